@@ -0,0 +1,341 @@
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use rustendo_core::disasm;
+use rustendo_core::Nes;
+
+use crate::parse_address;
+
+// Instructions kept from single-stepping, shown above the PC
+const HISTORY: usize = 8;
+
+// A run is shown at about the console's frame rate; emulating faster would only skip frames
+const RUN_FRAME_TIME: Duration = Duration::from_millis(16);
+
+const HELP: &str = "s step  f frame  c continue/pause  b breakpoint at PC  \
+                    PgUp/PgDn memory  : command  q quit";
+
+// A terminal debugger for the CPU, so it works over SSH and without a display. Commands typed
+// after ':' are `b <addr>` to toggle a breakpoint, `m <addr>` to show memory from an address and
+// `pc` to show memory from the PC.
+struct Debugger {
+    nes: Nes,
+    running: bool,
+    history: VecDeque<u16>, // PCs of the last instructions stepped through, oldest first
+    memory_address: u16,    // First address in the memory pane
+    command: Option<String>, // Being typed after ':'
+    message: String,        // What the last command did, or why it didn't
+}
+
+pub fn run(nes: Nes) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = Debugger::new(nes).run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl Debugger {
+    fn new(nes: Nes) -> Self {
+        Self {
+            nes,
+            running: false,
+            history: VecDeque::new(),
+            memory_address: 0x0000,
+            command: None,
+            message: String::new(),
+        }
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let started = Instant::now();
+            if self.running {
+                self.history.clear();
+                if self.nes.step_frame_until_breakpoint() {
+                    self.running = false;
+                    self.message = format!("Breakpoint at ${:04X}", self.pc());
+                }
+            }
+
+            // While running, keys are only checked for until the next frame is due
+            let timeout = if self.running {
+                RUN_FRAME_TIME.saturating_sub(started.elapsed())
+            } else {
+                Duration::from_secs(60)
+            };
+            if !event::poll(timeout)? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if let Some(command) = self.command.as_mut() {
+                match key.code {
+                    KeyCode::Enter => {
+                        let command = self.command.take().unwrap_or_default();
+                        self.run_command(&command);
+                    }
+                    KeyCode::Esc => self.command = None,
+                    KeyCode::Backspace => {
+                        command.pop();
+                    }
+                    KeyCode::Char(c) => command.push(c),
+                    _ => {}
+                }
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('s') if !self.running => self.step(),
+                KeyCode::Char('f') if !self.running => {
+                    self.history.clear();
+                    self.message = if self.nes.step_frame_until_breakpoint() {
+                        format!("Breakpoint at ${:04X}", self.pc())
+                    } else {
+                        format!("Frame {}", self.nes.frame())
+                    };
+                }
+                KeyCode::Char('c') => {
+                    self.running = !self.running;
+                    self.message.clear();
+                }
+                KeyCode::Char('b') => self.toggle_breakpoint(self.pc()),
+                KeyCode::PageUp => self.memory_address = self.memory_address.wrapping_sub(0x80),
+                KeyCode::PageDown => self.memory_address = self.memory_address.wrapping_add(0x80),
+                KeyCode::Char(':') => self.command = Some(String::new()),
+                _ => {}
+            }
+        }
+    }
+
+    fn pc(&self) -> u16 {
+        self.nes.cpu_registers().pc
+    }
+
+    fn step(&mut self) {
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(self.pc());
+        self.nes.step();
+        self.message.clear();
+    }
+
+    fn toggle_breakpoint(&mut self, address: u16) {
+        if self.nes.remove_breakpoint(address) {
+            self.message = format!("Removed the breakpoint at ${:04X}", address);
+        } else {
+            self.nes.add_breakpoint(address);
+            self.message = format!("Breakpoint set at ${:04X}", address);
+        }
+    }
+
+    fn run_command(&mut self, command: &str) {
+        let mut words = command.split_whitespace();
+        let name = words.next();
+        let address = words.next().and_then(parse_address);
+        match (name, address) {
+            (Some("b"), Some(address)) => self.toggle_breakpoint(address),
+            (Some("m"), Some(address)) => self.memory_address = address,
+            (Some("pc"), None) => self.memory_address = self.pc(),
+            (None, _) => {}
+            _ => self.message = format!("Unknown command: {}", command),
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [top, bottom] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(10)]).areas(main);
+        let [code, side] =
+            Layout::horizontal([Constraint::Min(0), Constraint::Length(34)]).areas(top);
+        let [registers, stack, breakpoints] = Layout::vertical([
+            Constraint::Length(6),
+            Constraint::Length(10),
+            Constraint::Min(0),
+        ])
+        .areas(side);
+        let [memory, ppu] =
+            Layout::horizontal([Constraint::Min(0), Constraint::Length(34)]).areas(bottom);
+
+        self.draw_disassembly(frame, code);
+        self.draw_registers(frame, registers);
+        self.draw_stack(frame, stack);
+        self.draw_breakpoints(frame, breakpoints);
+        self.draw_memory(frame, memory);
+        self.draw_ppu(frame, ppu);
+
+        let status_line = match &self.command {
+            Some(command) => Line::from(format!(":{}", command)),
+            None if self.running => Line::from("Running (c to pause)").bold(),
+            None if !self.message.is_empty() => Line::from(self.message.as_str()),
+            None => Line::from(HELP).dim(),
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+    }
+
+    // The instructions stepped through, the one at the PC and as many after it as fit
+    fn draw_disassembly(&self, frame: &mut Frame, area: Rect) {
+        let pc = self.pc();
+        let rows = area.height.saturating_sub(2) as usize;
+        let mut lines: Vec<Line> = self
+            .history
+            .iter()
+            .map(|&address| self.instruction_line(address, false))
+            .collect();
+        lines.push(self.instruction_line(pc, true));
+        let mut address = pc;
+        while lines.len() < rows {
+            address = address.wrapping_add(self.decode(address).size());
+            lines.push(self.instruction_line(address, false));
+        }
+        let skip = lines.len().saturating_sub(rows);
+        let lines = lines.split_off(skip);
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Disassembly ")),
+            area,
+        );
+    }
+
+    fn decode(&self, address: u16) -> disasm::Instruction {
+        disasm::decode(address, |address| self.nes.peek(address))
+    }
+
+    fn instruction_line(&self, address: u16, current: bool) -> Line<'static> {
+        let instruction = self.decode(address);
+        let (bytes, size) = instruction.bytes();
+        let hex: Vec<String> = bytes[..size]
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        let breakpoint = self.nes.breakpoints().any(|bp| bp == address);
+        let line = Line::from(vec![
+            Span::raw(if breakpoint { "● " } else { "  " }).red(),
+            Span::raw(format!("{:04X}  {:<9}", address, hex.join(" "))),
+            Span::raw(instruction.to_string()),
+        ]);
+        if current {
+            line.style(Style::new().reversed())
+        } else {
+            line
+        }
+    }
+
+    fn draw_registers(&self, frame: &mut Frame, area: Rect) {
+        let registers = self.nes.cpu_registers();
+        let flags: String = "NV-BDIZC"
+            .chars()
+            .enumerate()
+            .map(|(i, name)| {
+                if registers.status & (0x80 >> i) != 0 {
+                    name
+                } else {
+                    name.to_ascii_lowercase()
+                }
+            })
+            .collect();
+        let lines = vec![
+            Line::from(format!(
+                "PC ${:04X}  SP ${:02X}",
+                registers.pc, registers.sp
+            )),
+            Line::from(format!(
+                "A ${:02X}  X ${:02X}  Y ${:02X}",
+                registers.a, registers.x, registers.y
+            )),
+            Line::from(format!("P ${:02X}  {}", registers.status, flags)),
+            Line::from(format!("Cycle {}", self.nes.cycles())),
+        ];
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" CPU ")),
+            area,
+        );
+    }
+
+    // From the top of the stack down, most recent push first
+    fn draw_stack(&self, frame: &mut Frame, area: Rect) {
+        let sp = self.nes.cpu_registers().sp;
+        let rows = area.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = (sp as u16 + 1..=0xFF)
+            .take(rows)
+            .map(|offset| {
+                let address = 0x0100 + offset;
+                Line::from(format!("${:04X}  {:02X}", address, self.nes.peek(address)))
+            })
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Stack ")),
+            area,
+        );
+    }
+
+    fn draw_breakpoints(&self, frame: &mut Frame, area: Rect) {
+        let lines: Vec<Line> = self
+            .nes
+            .breakpoints()
+            .map(|address| Line::from(format!("${:04X}  {}", address, self.decode(address))))
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Breakpoints ")),
+            area,
+        );
+    }
+
+    fn draw_memory(&self, frame: &mut Frame, area: Rect) {
+        let rows = area.height.saturating_sub(2);
+        let lines: Vec<Line> = (0..rows)
+            .map(|row| {
+                let start = self.memory_address.wrapping_add(row * 16);
+                let bytes: Vec<String> = (0..16)
+                    .map(|i| format!("{:02X}", self.nes.peek(start.wrapping_add(i))))
+                    .collect();
+                Line::from(format!("{:04X}: {}", start, bytes.join(" ")))
+            })
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Memory ")),
+            area,
+        );
+    }
+
+    fn draw_ppu(&self, frame: &mut Frame, area: Rect) {
+        // The PPU runs behind the CPU until something needs it, so catch it up to show where
+        // it really is
+        let mut memory = self.nes.memory().borrow_mut();
+        memory.sync();
+        let ppu = memory.ppu();
+        let (scroll_x, scroll_y) = ppu.scroll();
+        let lines = vec![
+            Line::from(format!("Frame {}", ppu.frame_count())),
+            Line::from(format!("Scanline {}  dot {}", ppu.scanline(), ppu.cycle())),
+            Line::from(format!("Status ${:02X}", ppu.peek_register(0x2002))),
+            Line::from(format!(
+                "Rendering {}",
+                if ppu.rendering_enabled() { "on" } else { "off" }
+            )),
+            Line::from(format!(
+                "BG ${:04X}  sprites ${:04X} 8x{}",
+                ppu.background_pattern_table(),
+                ppu.sprite_pattern_table(),
+                ppu.sprite_height()
+            )),
+            Line::from(format!("Scroll {}, {}", scroll_x, scroll_y)),
+        ];
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" PPU ")),
+            area,
+        );
+    }
+}
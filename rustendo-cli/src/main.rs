@@ -0,0 +1,745 @@
+mod debugger;
+mod test_suite;
+
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal};
+use std::path::Path;
+use std::process;
+
+use rustendo_core::cheat::Cheat;
+use rustendo_core::checksum::crc32;
+use rustendo_core::debug::{
+    self, NAMETABLES_HEIGHT, NAMETABLES_WIDTH, PATTERN_TABLE_SIZE, SPRITE_PREVIEW_HEIGHT,
+    SPRITE_PREVIEW_WIDTH,
+};
+use rustendo_core::disasm;
+use rustendo_core::game_db::GameDb;
+use rustendo_core::mapper;
+use rustendo_core::movie::Movie;
+use rustendo_core::{Nes, Region, Rom};
+use tracing_subscriber::EnvFilter;
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} [--log <filter>] <command> ...  (log filter in RUST_LOG syntax)",
+        program
+    );
+    eprintln!(
+        "       {} rom-info [--db <games.db>] <path/to/rom/file.nes>",
+        program
+    );
+    eprintln!(
+        "       {} chr-dump [--palette <0-7>] [--frames <n>] <rom.nes> <out.png>",
+        program
+    );
+    eprintln!(
+        "       {} nametable-dump [--frames <n>] <rom.nes> <out.png>",
+        program
+    );
+    eprintln!(
+        "       {} oam-dump [--frames <n>] [--png <preview.png>] <rom.nes> <out.json>",
+        program
+    );
+    eprintln!(
+        "       {} hexdump [--frames <n>] <rom.nes> <start> <end>  (CPU addresses in hex)",
+        program
+    );
+    eprintln!(
+        "       {} disasm [--frames <n>] [--count <n>] <rom.nes> [<start>]  (reset vector if no start)",
+        program
+    );
+    eprintln!(
+        "       {} debug [--frames <n>] <rom.nes>  (terminal debugger)",
+        program
+    );
+    eprintln!(
+        "       {} extract [--colors <c0,c1,c2,c3>] [--palette <0-7>] [--frames <n>] \
+         <rom.nes> <out-dir>",
+        program
+    );
+    eprintln!(
+        "       {} fix-header --db <games.db> <rom.nes> <out.nes>",
+        program
+    );
+    eprintln!(
+        "       {} replay [--frames <n>] <rom.nes> <input.txt> [out.png]",
+        program
+    );
+    eprintln!(
+        "       {} cheat <code>...  (Game Genie, AAAA:VV or AAAA?CC:VV)",
+        program
+    );
+    eprintln!(
+        "       {} test-suite [--frames <n>] <dir>  (test ROMs, with a {} for picture checks)",
+        program,
+        test_suite::HASHES_FILE
+    );
+    process::exit(1);
+}
+
+fn load_rom(path: &str) -> Rom {
+    match Rom::load_from_file(path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Error loading ROM: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn load_game_db(path: &str) -> GameDb {
+    let result = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| GameDb::parse(&text).map_err(|e| e.to_string()));
+    match result {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Error loading game database {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+// Print what the header says about the ROM, corrected by the game database if there is one and
+// it knows the dump
+fn rom_info(program: &str, args: &[String]) {
+    let mut db = None;
+    let mut paths = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--db" => match iter.next() {
+                Some(path) => db = Some(load_game_db(path)),
+                None => usage(program),
+            },
+            _ if !arg.starts_with("--") => paths.push(arg.as_str()),
+            _ => usage(program),
+        }
+    }
+    let [path] = paths[..] else {
+        usage(program);
+    };
+
+    let mut rom = load_rom(path);
+    let sha1: String = rom
+        .sha1()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    println!("CRC32:     {:08X}", rom.crc32());
+    println!("SHA-1:     {}", sha1);
+    if let Some(entry) = db.as_ref().and_then(|db| db.lookup(&rom)) {
+        println!("Title:     {}", entry.title);
+        println!("Board:     {}", entry.board);
+        if entry.apply(&mut rom) {
+            println!("Header:    corrected from the database");
+        }
+    }
+    println!("PRG-ROM:   {} KB", rom.prg_rom.len() / 1024);
+    println!("CHR-ROM:   {} KB", rom.chr_rom.len() / 1024);
+    println!(
+        "PRG-RAM:   {} KB{}",
+        rom.prg_ram_size / 1024,
+        if rom.battery { " (battery)" } else { "" }
+    );
+    println!(
+        "Region:    {}",
+        match rom.region {
+            Region::Ntsc => "NTSC",
+            Region::Pal => "PAL",
+            Region::Dendy => "Dendy",
+        }
+    );
+    match mapper::info(rom.mapper) {
+        Some(info) => println!("Mapper:    {} ({})", rom.mapper, info.name),
+        None => println!("Mapper:    {} (not supported)", rom.mapper),
+    }
+    println!(
+        "Mirroring: {}",
+        if mapper::has_four_screen_vram(&rom) {
+            "four-screen"
+        } else if rom.mirroring == 0 {
+            "horizontal"
+        } else {
+            "vertical"
+        }
+    );
+}
+
+// Write a copy of a ROM whose header says what the game database knows about the dump
+fn fix_header(program: &str, args: &[String]) {
+    let mut db = None;
+    let mut paths = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--db" => match iter.next() {
+                Some(path) => db = Some(load_game_db(path)),
+                None => usage(program),
+            },
+            _ if !arg.starts_with("--") => paths.push(arg.as_str()),
+            _ => usage(program),
+        }
+    }
+    let ([rom_path, out_path], Some(db)) = (&paths[..], db) else {
+        usage(program);
+    };
+
+    let mut rom = load_rom(rom_path);
+    let Some(entry) = db.lookup(&rom) else {
+        eprintln!(
+            "{} (CRC32 {:08X}) is not in the database",
+            rom_path,
+            rom.crc32()
+        );
+        process::exit(1);
+    };
+    if entry.apply(&mut rom) {
+        println!("Corrected the header of {}", entry.title);
+    } else {
+        println!("The header of {} was already correct", entry.title);
+    }
+    if let Err(e) = fs::write(out_path, rom.to_bytes()) {
+        eprintln!("Error writing {}: {}", out_path, e);
+        process::exit(1);
+    }
+}
+
+// Load a ROM and run it for `frames` frames, for tools that look at the state it ends up in
+fn run_rom(path: &str, frames: u32) -> Nes {
+    let mut nes = match Nes::new(&load_rom(path)) {
+        Ok(nes) => nes,
+        Err(e) => {
+            eprintln!("Error loading ROM: {}", e);
+            process::exit(1);
+        }
+    };
+    for _ in 0..frames {
+        nes.step_frame();
+    }
+    nes
+}
+
+fn save_png(path: &str, width: usize, height: usize, rgba: &[u8]) {
+    if let Err(e) = write_png(Path::new(path), width, height, rgba) {
+        eprintln!("Error writing {}: {}", path, e);
+        process::exit(1);
+    }
+}
+
+fn write_png(path: &Path, width: usize, height: usize, rgba: &[u8]) -> Result<(), Box<dyn Error>> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(rgba)?;
+    Ok(())
+}
+
+// Write both pattern tables side by side as a 256x128 PNG. With --palette the game runs for a
+// while first (60 frames unless --frames says otherwise) so its palette RAM is filled in.
+fn chr_dump(program: &str, args: &[String]) {
+    let mut palette = None;
+    let mut frames = None;
+    let mut paths = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--palette" => match iter.next().and_then(|value| value.parse::<usize>().ok()) {
+                Some(index) if index < 8 => palette = Some(index),
+                _ => usage(program),
+            },
+            "--frames" => match iter.next().and_then(|value| value.parse::<u32>().ok()) {
+                Some(count) => frames = Some(count),
+                None => usage(program),
+            },
+            _ if !arg.starts_with("--") => paths.push(arg.as_str()),
+            _ => usage(program),
+        }
+    }
+    let [rom_path, png_path] = paths[..] else {
+        usage(program);
+    };
+
+    let frames = frames.unwrap_or(if palette.is_some() { 60 } else { 0 });
+    let nes = run_rom(rom_path, frames);
+
+    let memory = nes.memory().borrow();
+    let ppu = memory.ppu();
+    let colors = palette.map_or(debug::GRAYSCALE, |index| debug::ppu_palette(ppu, index));
+    let tables = [0, 1].map(|table| debug::pattern_table(ppu, table, colors));
+
+    let row_bytes = PATTERN_TABLE_SIZE * 4;
+    let mut sheet = Vec::with_capacity(tables[0].len() * 2);
+    for row in 0..PATTERN_TABLE_SIZE {
+        for table in &tables {
+            sheet.extend_from_slice(&table[row * row_bytes..(row + 1) * row_bytes]);
+        }
+    }
+
+    save_png(png_path, PATTERN_TABLE_SIZE * 2, PATTERN_TABLE_SIZE, &sheet);
+}
+
+// Four NES colours in hex, like 0F,16,27,30
+fn parse_colors(text: &str) -> Option<[u8; 4]> {
+    let colors: Vec<u8> = text
+        .split(',')
+        .map(|color| {
+            u8::from_str_radix(color.trim(), 16)
+                .ok()
+                .filter(|&c| c < 0x40)
+        })
+        .collect::<Option<_>>()?;
+    colors.try_into().ok()
+}
+
+// Split a ROM into <name>.prg and <name>.chr and draw all of its CHR-ROM as <name>.png, for ROM
+// hacking. The tiles are grey unless --colors gives four NES colours, or --palette takes one of
+// the game's palettes after running it for a while (60 frames unless --frames says otherwise).
+fn extract(program: &str, args: &[String]) {
+    let mut colors = None;
+    let mut palette = None;
+    let mut frames = None;
+    let mut paths = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--colors" => match iter.next().and_then(|value| parse_colors(value)) {
+                Some(value) => colors = Some(value),
+                None => usage(program),
+            },
+            "--palette" => match iter.next().and_then(|value| value.parse::<usize>().ok()) {
+                Some(index) if index < 8 => palette = Some(index),
+                _ => usage(program),
+            },
+            "--frames" => match iter.next().and_then(|value| value.parse::<u32>().ok()) {
+                Some(count) => frames = Some(count),
+                None => usage(program),
+            },
+            _ if !arg.starts_with("--") => paths.push(arg.as_str()),
+            _ => usage(program),
+        }
+    }
+    let [rom_path, out_dir] = paths[..] else {
+        usage(program);
+    };
+
+    let rom = load_rom(rom_path);
+    let name = Path::new(rom_path)
+        .file_stem()
+        .map_or("rom".into(), |stem| stem.to_string_lossy());
+    let out_dir = Path::new(out_dir);
+    let write = |extension: &str, data: &[u8]| {
+        let path = out_dir.join(format!("{}.{}", name, extension));
+        if let Err(e) = fs::write(&path, data) {
+            eprintln!("Error writing {}: {}", path.display(), e);
+            process::exit(1);
+        }
+        println!("Wrote {}", path.display());
+    };
+    if let Err(e) = fs::create_dir_all(out_dir) {
+        eprintln!("Error creating {}: {}", out_dir.display(), e);
+        process::exit(1);
+    }
+
+    write("prg", &rom.prg_rom);
+    if rom.chr_rom.is_empty() {
+        println!("No CHR-ROM: the game draws its tiles into CHR-RAM");
+        return;
+    }
+    write("chr", &rom.chr_rom);
+
+    let nes = run_rom(
+        rom_path,
+        frames.unwrap_or(if palette.is_some() { 60 } else { 0 }),
+    );
+    let memory = nes.memory().borrow();
+    let ppu = memory.ppu();
+    let colors = match (colors, palette) {
+        (Some(colors), _) => colors,
+        (None, Some(index)) => debug::ppu_palette(ppu, index),
+        (None, None) => debug::GRAYSCALE,
+    };
+    let sheet = debug::chr_sheet(&rom.chr_rom, ppu.palette(), colors);
+    let path = out_dir.join(format!("{}.png", name));
+    save_png(
+        &path.to_string_lossy(),
+        PATTERN_TABLE_SIZE,
+        sheet.len() / (PATTERN_TABLE_SIZE * 4),
+        &sheet,
+    );
+    println!("Wrote {}", path.display());
+}
+
+// Write all four nametables as a 512x480 PNG after running the game for a while (60 frames
+// unless --frames says otherwise), with the scroll position outlined
+fn nametable_dump(program: &str, args: &[String]) {
+    let mut frames = 60;
+    let mut paths = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--frames" => match iter.next().and_then(|value| value.parse::<u32>().ok()) {
+                Some(count) => frames = count,
+                None => usage(program),
+            },
+            _ if !arg.starts_with("--") => paths.push(arg.as_str()),
+            _ => usage(program),
+        }
+    }
+    let [rom_path, png_path] = paths[..] else {
+        usage(program);
+    };
+
+    let nes = run_rom(rom_path, frames);
+    let pixels = debug::nametables(nes.memory().borrow().ppu());
+    save_png(png_path, NAMETABLES_WIDTH, NAMETABLES_HEIGHT, &pixels);
+}
+
+// Write the 64 OAM entries as a JSON array after running the game for a while (60 frames
+// unless --frames says otherwise), and optionally a PNG preview of every sprite
+fn oam_dump(program: &str, args: &[String]) {
+    let mut frames = 60;
+    let mut preview = None;
+    let mut paths = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--frames" => match iter.next().and_then(|value| value.parse::<u32>().ok()) {
+                Some(count) => frames = count,
+                None => usage(program),
+            },
+            "--png" => match iter.next() {
+                Some(path) => preview = Some(path.as_str()),
+                None => usage(program),
+            },
+            _ if !arg.starts_with("--") => paths.push(arg.as_str()),
+            _ => usage(program),
+        }
+    }
+    let [rom_path, json_path] = paths[..] else {
+        usage(program);
+    };
+
+    let nes = run_rom(rom_path, frames);
+    let memory = nes.memory().borrow();
+    let entries: Vec<String> = debug::sprites(memory.ppu())
+        .iter()
+        .map(|sprite| {
+            format!(
+                "  {{\"index\": {}, \"x\": {}, \"y\": {}, \"tile\": {}, \"palette\": {}, \
+                 \"behind_background\": {}, \"flip_horizontal\": {}, \"flip_vertical\": {}}}",
+                sprite.index,
+                sprite.x,
+                sprite.y,
+                sprite.tile,
+                sprite.palette,
+                sprite.behind_background,
+                sprite.flip_horizontal,
+                sprite.flip_vertical
+            )
+        })
+        .collect();
+    if let Err(e) = fs::write(json_path, format!("[\n{}\n]\n", entries.join(",\n"))) {
+        eprintln!("Error writing {}: {}", json_path, e);
+        process::exit(1);
+    }
+
+    if let Some(png_path) = preview {
+        let pixels = debug::sprite_preview(memory.ppu());
+        save_png(
+            png_path,
+            SPRITE_PREVIEW_WIDTH,
+            SPRITE_PREVIEW_HEIGHT,
+            &pixels,
+        );
+    }
+}
+
+// An address like 8000, $8000 or 0x8000
+fn parse_address(text: &str) -> Option<u16> {
+    let digits = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+fn hexdump(program: &str, args: &[String]) {
+    let mut frames = 60;
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--frames" => match iter.next().and_then(|value| value.parse::<u32>().ok()) {
+                Some(count) => frames = count,
+                None => usage(program),
+            },
+            _ if !arg.starts_with("--") => positional.push(arg.as_str()),
+            _ => usage(program),
+        }
+    }
+    let [rom_path, start, end] = positional[..] else {
+        usage(program);
+    };
+    let (Some(start), Some(end)) = (parse_address(start), parse_address(end)) else {
+        usage(program);
+    };
+    if end < start {
+        usage(program);
+    }
+
+    // Peeking leaves registers like $2002 and $4016 as they were
+    let nes = run_rom(rom_path, frames);
+    let bytes = nes.peek_range(start..=end);
+    for (row, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let text: String = chunk
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        println!(
+            "{:04X}: {:<47}  |{}|",
+            start as usize + row * 16,
+            hex.join(" "),
+            text
+        );
+    }
+}
+
+// Disassemble `count` instructions from a CPU address, by default where the reset vector points,
+// after running the ROM for a while so the mapper has banked in what it's going to
+fn disassemble(program: &str, args: &[String]) {
+    let mut frames = 0;
+    let mut count = 32;
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--frames" => match iter.next().and_then(|value| value.parse::<u32>().ok()) {
+                Some(value) => frames = value,
+                None => usage(program),
+            },
+            "--count" => match iter.next().and_then(|value| value.parse::<usize>().ok()) {
+                Some(value) => count = value,
+                None => usage(program),
+            },
+            _ if !arg.starts_with("--") => positional.push(arg.as_str()),
+            _ => usage(program),
+        }
+    }
+    let (rom_path, start) = match positional[..] {
+        [rom_path] => (rom_path, None),
+        [rom_path, start] => match parse_address(start) {
+            Some(start) => (rom_path, Some(start)),
+            None => usage(program),
+        },
+        _ => usage(program),
+    };
+
+    let nes = run_rom(rom_path, frames);
+    let mut address =
+        start.unwrap_or_else(|| u16::from_le_bytes([nes.peek(0xFFFC), nes.peek(0xFFFD)]));
+    for _ in 0..count {
+        let instruction = disasm::decode(address, |address| nes.peek(address));
+        let (bytes, size) = instruction.bytes();
+        let hex: Vec<String> = bytes[..size]
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        let marker = if instruction.official { " " } else { "*" };
+        println!(
+            "{:04X}  {:<8} {}{}",
+            address,
+            hex.join(" "),
+            marker,
+            instruction
+        );
+        address = address.wrapping_add(instruction.size());
+    }
+}
+
+fn debug(program: &str, args: &[String]) {
+    let mut frames = 0;
+    let mut rom_path = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--frames" => match iter.next().and_then(|value| value.parse::<u32>().ok()) {
+                Some(count) => frames = count,
+                None => usage(program),
+            },
+            _ if rom_path.is_none() && !arg.starts_with("--") => rom_path = Some(arg.as_str()),
+            _ => usage(program),
+        }
+    }
+    let Some(rom_path) = rom_path else {
+        usage(program);
+    };
+    if let Err(e) = debugger::run(run_rom(rom_path, frames)) {
+        eprintln!("Terminal error: {}", e);
+        process::exit(1);
+    }
+}
+
+// Play an input recording from power-on and print the CRC32 of the final frame, so a run can
+// be checked against a known-good one. Runs until the last recorded input unless --frames
+// says otherwise, and can write the frame out as a PNG.
+fn replay(program: &str, args: &[String]) {
+    let mut frames = None;
+    let mut paths = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--frames" => match iter.next().and_then(|value| value.parse::<u64>().ok()) {
+                Some(count) => frames = Some(count),
+                None => usage(program),
+            },
+            _ if !arg.starts_with("--") => paths.push(arg.as_str()),
+            _ => usage(program),
+        }
+    }
+    let (rom_path, movie_path, png_path) = match paths[..] {
+        [rom, movie] => (rom, movie, None),
+        [rom, movie, png] => (rom, movie, Some(png)),
+        _ => usage(program),
+    };
+
+    let movie = match fs::read_to_string(movie_path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| Movie::parse(&text).map_err(|e| e.to_string()))
+    {
+        Ok(movie) => movie,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", movie_path, e);
+            process::exit(1);
+        }
+    };
+    let mut nes = run_rom(rom_path, 0);
+    if let Err(e) = nes.play_movie(&movie) {
+        eprintln!("Error playing {}: {}", movie_path, e);
+        process::exit(1);
+    }
+    let frames = frames.unwrap_or(movie.length() + 1);
+    while nes.frame() < frames {
+        nes.step_frame();
+    }
+
+    let hash = nes.frame_hash();
+    let overscan = nes.overscan();
+    let (width, height) = (overscan.width(), overscan.height());
+    let frame = nes.output_frame();
+    println!(
+        "Frame {}: CRC32 {:08X}, hash {:016X}",
+        frames,
+        crc32(frame),
+        hash
+    );
+    if let Some(path) = png_path {
+        save_png(path, width, height, frame);
+    }
+}
+
+// Run a directory of test ROMs and print how each did, exiting with an error unless they all
+// passed. Each gets --frames frames to report a result, a minute by default.
+fn run_test_suite(program: &str, args: &[String]) {
+    let mut frames = 3600;
+    let mut dir = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--frames" => match iter.next().and_then(|value| value.parse::<u64>().ok()) {
+                Some(count) => frames = count,
+                None => usage(program),
+            },
+            _ if dir.is_none() && !arg.starts_with("--") => dir = Some(arg.as_str()),
+            _ => usage(program),
+        }
+    }
+    let Some(dir) = dir else {
+        usage(program);
+    };
+    match test_suite::run(Path::new(dir), frames) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("Error running the tests in {}: {}", dir, e);
+            process::exit(1);
+        }
+    }
+}
+
+// Show what each cheat code does
+fn decode_cheats(program: &str, codes: &[String]) {
+    if codes.is_empty() {
+        usage(program);
+    }
+    for code in codes {
+        match Cheat::decode(code) {
+            Ok(cheat) => match cheat.compare {
+                Some(compare) => println!(
+                    "{}: ${:04X} = {:02X} if {:02X}",
+                    code, cheat.address, cheat.value, compare
+                ),
+                None => println!("{}: ${:04X} = {:02X}", code, cheat.address, cheat.value),
+            },
+            Err(e) => println!("{}: {}", code, e),
+        }
+    }
+}
+
+// Send the core's log events (targets cpu, ppu, apu and mapper) to stderr. `filter` is in
+// RUST_LOG syntax, e.g. `cpu=trace,mapper=debug`, and takes precedence over RUST_LOG; with
+// neither only warnings and errors are shown.
+fn init_logging(filter: Option<&str>) {
+    let filter = filter
+        .map(str::to_string)
+        .or_else(|| env::var("RUST_LOG").ok())
+        .unwrap_or_else(|| "warn".to_string());
+    let filter = match EnvFilter::try_new(&filter) {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("Invalid log filter {}: {}", filter, e);
+            process::exit(1);
+        }
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    // `--log <filter>` goes before the command and applies to all of them
+    let log = match args.get(1).map(String::as_str) {
+        Some("--log") if args.len() > 2 => Some(args.drain(1..3).nth(1).unwrap()),
+        Some("--log") => usage(&args[0]),
+        _ => None,
+    };
+    init_logging(log.as_deref());
+    match args.get(1).map(String::as_str) {
+        Some("rom-info") => rom_info(&args[0], &args[2..]),
+        Some("chr-dump") => chr_dump(&args[0], &args[2..]),
+        Some("nametable-dump") => nametable_dump(&args[0], &args[2..]),
+        Some("oam-dump") => oam_dump(&args[0], &args[2..]),
+        Some("hexdump") => hexdump(&args[0], &args[2..]),
+        Some("disasm") => disassemble(&args[0], &args[2..]),
+        Some("debug") => debug(&args[0], &args[2..]),
+        Some("fix-header") => fix_header(&args[0], &args[2..]),
+        Some("extract") => extract(&args[0], &args[2..]),
+        Some("replay") => replay(&args[0], &args[2..]),
+        Some("cheat") => decode_cheats(&args[0], &args[2..]),
+        Some("test-suite") => run_test_suite(&args[0], &args[2..]),
+        _ => usage(&args[0]),
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use rustendo_core::checksum::crc32;
+use rustendo_core::{Nes, Rom};
+
+// Where blargg's test ROMs report, in cartridge RAM: a status byte, a signature that says the
+// rest is valid, then the text they print as a zero-terminated string
+const STATUS: u16 = 0x6000;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const TEXT: u16 = 0x6004;
+const TEXT_END: u16 = 0x7FFF;
+
+// Status values below $80 are the final result, 0 for a pass
+const RUNNING: u8 = 0x80;
+const NEEDS_RESET: u8 = 0x81;
+
+// The ROM asks for the reset button to be pressed no sooner than 100ms after it says so
+const RESET_DELAY_FRAMES: u64 = 6;
+
+// Lists the ROMs judged by their picture instead, one per line: the path relative to the suite's
+// directory, the frame to stop at, and the CRC-32 of that frame as `rustendo replay` prints it
+pub const HASHES_FILE: &str = "hashes.txt";
+
+enum Outcome {
+    Pass,
+    Fail(String),
+    NoResult, // Still running when the frame limit came
+    Error(String),
+}
+
+struct Expected {
+    frame: u64,
+    crc32: u32,
+}
+
+// Run every .nes file under `dir` and print a table of how each did. Returns whether they all
+// passed.
+pub fn run(dir: &Path, max_frames: u64) -> io::Result<bool> {
+    let expected = load_hashes(dir)?;
+    let mut roms = Vec::new();
+    find_roms(dir, &mut roms)?;
+    roms.sort();
+    if roms.is_empty() {
+        println!("No .nes files in {}", dir.display());
+        return Ok(false);
+    }
+
+    let name_width = roms
+        .iter()
+        .map(|path| relative_name(dir, path).len())
+        .max()
+        .unwrap_or(0)
+        .max(3);
+    println!(
+        "{:<name_width$}  {:<9}  {:>6}  Details",
+        "ROM", "Result", "Frames"
+    );
+    let (mut passed, mut failed, mut unfinished) = (0, 0, 0);
+    // Panics are reported in the table instead
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    for path in &roms {
+        let name = relative_name(dir, path);
+        let (outcome, frames) = match expected.get(&name) {
+            Some(expected) => guarded(|| run_hashed(path, expected)),
+            None => guarded(|| run_blargg(path, max_frames)),
+        };
+        let (result, details) = match outcome {
+            Outcome::Pass => {
+                passed += 1;
+                ("pass", String::new())
+            }
+            Outcome::Fail(details) => {
+                failed += 1;
+                ("FAIL", details)
+            }
+            Outcome::NoResult => {
+                unfinished += 1;
+                ("no result", "never reported one".to_string())
+            }
+            Outcome::Error(details) => {
+                failed += 1;
+                ("error", details)
+            }
+        };
+        let row = format!(
+            "{:<name_width$}  {:<9}  {:>6}  {}",
+            name, result, frames, details
+        );
+        println!("{}", row.trim_end());
+    }
+    panic::set_hook(hook);
+    println!(
+        "\n{} passed, {} failed, {} without a result, out of {}",
+        passed,
+        failed,
+        unfinished,
+        roms.len()
+    );
+    Ok(passed == roms.len())
+}
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_roms(&path, roms)?;
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("nes"))
+        {
+            roms.push(path);
+        }
+    }
+    Ok(())
+}
+
+// With forward slashes, as the hashes file has them
+fn relative_name(dir: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(dir).unwrap_or(path);
+    let parts: Vec<_> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect();
+    parts.join("/")
+}
+
+fn load_hashes(dir: &Path) -> io::Result<BTreeMap<String, Expected>> {
+    let path = dir.join(HASHES_FILE);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e),
+    };
+    let mut hashes = BTreeMap::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let entry = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            (Some(name), Some(frame), Some(crc), None) => frame
+                .parse()
+                .ok()
+                .zip(u32::from_str_radix(crc, 16).ok())
+                .map(|(frame, crc32)| (name.to_string(), Expected { frame, crc32 })),
+            _ => None,
+        };
+        let Some((name, expected)) = entry else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: invalid line {}", path.display(), index + 1),
+            ));
+        };
+        hashes.insert(name, expected);
+    }
+    Ok(hashes)
+}
+
+// A panic, like the one for an opcode the CPU doesn't implement, fails that ROM alone
+fn guarded(run: impl FnOnce() -> (Outcome, u64)) -> (Outcome, u64) {
+    panic::catch_unwind(AssertUnwindSafe(run)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| {
+                payload
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+            })
+            .unwrap_or_default();
+        (Outcome::Error(format!("panicked: {}", message)), 0)
+    })
+}
+
+fn power_on(path: &Path) -> Result<Nes, String> {
+    let rom = Rom::load_from_file(path).map_err(|e| e.to_string())?;
+    Nes::new(&rom).map_err(|e| e.to_string())
+}
+
+// Run to the frame the hash was taken at and compare
+fn run_hashed(path: &Path, expected: &Expected) -> (Outcome, u64) {
+    let mut nes = match power_on(path) {
+        Ok(nes) => nes,
+        Err(e) => return (Outcome::Error(e), 0),
+    };
+    while nes.frame() < expected.frame {
+        nes.step_frame();
+    }
+    let crc = crc32(nes.output_frame());
+    let outcome = if crc == expected.crc32 {
+        Outcome::Pass
+    } else {
+        Outcome::Fail(format!(
+            "frame CRC32 {:08X}, expected {:08X}",
+            crc, expected.crc32
+        ))
+    };
+    (outcome, nes.frame())
+}
+
+// Run until the ROM reports a result at $6000, pressing reset when it asks
+fn run_blargg(path: &Path, max_frames: u64) -> (Outcome, u64) {
+    let mut nes = match power_on(path) {
+        Ok(nes) => nes,
+        Err(e) => return (Outcome::Error(e), 0),
+    };
+    let mut reset_at = None;
+    while nes.frame() < max_frames {
+        nes.step_frame();
+        if nes.peek_range(STATUS + 1..=STATUS + 3) != SIGNATURE {
+            continue;
+        }
+        match nes.peek(STATUS) {
+            RUNNING => {}
+            NEEDS_RESET => match reset_at {
+                None => reset_at = Some(nes.frame() + RESET_DELAY_FRAMES),
+                Some(frame) if nes.frame() >= frame => {
+                    nes.reset();
+                    reset_at = None;
+                }
+                Some(_) => {}
+            },
+            0 => return (Outcome::Pass, nes.frame()),
+            code => {
+                let text = report_text(&nes);
+                let details = if text.is_empty() {
+                    format!("code {}", code)
+                } else {
+                    format!("code {}: {}", code, text)
+                };
+                return (Outcome::Fail(details), nes.frame());
+            }
+        }
+    }
+    (Outcome::NoResult, nes.frame())
+}
+
+// What the ROM printed, on one line
+fn report_text(nes: &Nes) -> String {
+    let bytes: Vec<u8> = nes
+        .peek_range(TEXT..=TEXT_END)
+        .into_iter()
+        .take_while(|&byte| byte != 0)
+        .collect();
+    let text = String::from_utf8_lossy(&bytes);
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines.join(" / ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    // NROM whose first instruction is RLA, one of the unofficial opcodes the CPU panics on
+    fn unofficial_opcode_rom() -> Vec<u8> {
+        let mut file = b"NES\x1A\x02\x01\x00\x00".to_vec();
+        file.extend([0; 8]);
+        let mut prg = vec![0xEA; 0x8000];
+        prg[..3].copy_from_slice(&[0x2F, 0x00, 0x02]);
+        prg[0x7FFC..0x7FFE].copy_from_slice(&[0x00, 0x80]);
+        file.extend(prg);
+        file.extend([0; 0x2000]);
+        file
+    }
+
+    #[test]
+    fn panics_fail_the_rom_and_the_run_goes_on() {
+        let dir = env::temp_dir().join(format!("rustendo-test-suite-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rla.nes");
+        fs::write(&path, unofficial_opcode_rom()).unwrap();
+
+        match guarded(|| run_blargg(&path, 10)) {
+            (Outcome::Error(details), _) => {
+                assert!(details.contains("Invalid opcode"), "{}", details)
+            }
+            _ => panic!("the ROM should have been reported as an error"),
+        }
+        assert!(!run(&dir, 10).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,366 @@
+use alloc::vec::Vec;
+use tracing::trace;
+
+mod dmc;
+mod envelope;
+mod fds;
+mod filter;
+mod frame_counter;
+mod length_counter;
+mod noise;
+mod pulse;
+mod resampler;
+mod triangle;
+
+use dmc::Dmc;
+use fds::Fds;
+use frame_counter::FrameCounter;
+use noise::Noise;
+use pulse::Pulse;
+use resampler::Resampler;
+use triangle::Triangle;
+
+use crate::region::Region;
+
+pub use filter::{AudioFilter, NES_FILTERS};
+
+// Full-scale FDS output relative to the APU mix, roughly 2.4 times a pulse channel at full
+// volume as measured on hardware
+const FDS_LEVEL: f32 = 0.36;
+
+pub const CHANNELS: usize = 5;
+
+// Short channel names in register order, for labelling per-channel output
+pub const CHANNEL_NAMES: [&str; CHANNELS] = ["pulse1", "pulse2", "triangle", "noise", "dmc"];
+
+// With the `serde` feature everything that affects the sound, down to the timer and sequencer
+// positions, is saved. The resampler and the sample buffers belong to the host and are left out,
+// so a restored APU produces no samples until a rate is set again.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct APU {
+    pulse_1: Pulse,              // Pulse 1, $4000-$4003
+    pulse_2: Pulse,              // Pulse 2, $4004-$4007
+    triangle: Triangle,          // Triangle, $4008-$400B
+    noise: Noise,                // Noise, $400C-$400F
+    dmc: Dmc,                    // DMC, $4010-$4013
+    frame_counter: FrameCounter, // $4017
+    cycles: u64,                 // CPU cycles elapsed; pulse timers run on every other one
+    region: Region,              // Sets the clock rate and the timer tables
+    fds: Option<Fds>,            // Famicom Disk System sound, $4040-$4092, when present
+    fds_volume: f32,             // User volume for the FDS channel, 1.0 by default
+    filters: Vec<AudioFilter>,   // Output filter chain, the console's by default
+    #[cfg_attr(feature = "serde", serde(skip))]
+    resampler: Option<Resampler>, // Converts to the host rate; no samples until one is set
+    #[cfg_attr(feature = "serde", serde(skip))]
+    audio_buffer: Vec<f32>, // Generated samples, waiting to be taken
+    #[cfg_attr(feature = "serde", serde(skip))]
+    stem_resamplers: Vec<Resampler>, // One per channel while stems are being collected
+    #[cfg_attr(feature = "serde", serde(skip))]
+    stem_buffers: [Vec<f32>; CHANNELS], // Per-channel samples, waiting to be taken
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl APU {
+    pub fn new() -> Self {
+        Self {
+            pulse_1: Pulse::new(true),
+            pulse_2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            frame_counter: FrameCounter::default(),
+            cycles: 0,
+            region: Region::Ntsc,
+            fds: None,
+            fds_volume: 1.0,
+            filters: NES_FILTERS.to_vec(),
+            resampler: None,
+            audio_buffer: Vec::new(),
+            stem_resamplers: Vec::new(),
+            stem_buffers: Default::default(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.pulse_1 = Pulse::new(true);
+        self.pulse_2 = Pulse::new(false);
+        self.triangle = Triangle::default();
+        self.noise = Noise::new();
+        self.dmc = Dmc::new();
+        self.frame_counter = FrameCounter::default();
+        if self.fds.is_some() {
+            self.fds = Some(Fds::new());
+        }
+        self.use_region_tables();
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.use_region_tables();
+        // The resamplers convert from the CPU clock, which just changed
+        self.set_sample_rate(self.sample_rate());
+    }
+
+    // Dendy famiclones use the NTSC tables, only the clock differs
+    fn use_region_tables(&mut self) {
+        let pal = self.region == Region::Pal;
+        self.frame_counter.set_pal(pal);
+        self.noise.set_pal(pal);
+        self.dmc.set_pal(pal);
+    }
+
+    // Add the FDS expansion sound unit, for Famicom Disk System images
+    pub fn enable_fds(&mut self) {
+        self.fds.get_or_insert_with(Fds::new);
+    }
+
+    pub fn has_fds(&self) -> bool {
+        self.fds.is_some()
+    }
+
+    pub fn fds_volume(&self) -> f32 {
+        self.fds_volume
+    }
+
+    // Take over the state of an APU from a save state. The output side (sample rate, filters,
+    // FDS volume and samples not yet taken) stays as it is.
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore(&mut self, mut saved: APU) {
+        saved.fds_volume = self.fds_volume;
+        saved.filters = core::mem::take(&mut self.filters);
+        saved.resampler = self.resampler.take();
+        saved.audio_buffer = core::mem::take(&mut self.audio_buffer);
+        saved.stem_resamplers = core::mem::take(&mut self.stem_resamplers);
+        saved.stem_buffers = core::mem::take(&mut self.stem_buffers);
+        *self = saved;
+    }
+
+    pub fn set_fds_volume(&mut self, volume: f32) {
+        self.fds_volume = volume.max(0.0);
+    }
+
+    // Read an FDS sound register, if the unit is present and the address is readable
+    pub fn read_fds(&self, address: u16) -> Option<u8> {
+        self.fds.as_ref()?.read_register(address)
+    }
+
+    pub fn write_fds(&mut self, address: u16, value: u8) {
+        if let Some(fds) = self.fds.as_mut() {
+            fds.write_register(address, value);
+        }
+    }
+
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.resampler.as_ref().map(Resampler::rate)
+    }
+
+    // Start producing mono samples at `rate` Hz, or stop with `None`
+    pub fn set_sample_rate(&mut self, rate: Option<u32>) {
+        let clock = self.region.cpu_frequency();
+        self.resampler = rate.map(|rate| Resampler::new(rate, clock, &self.filters));
+        self.audio_buffer.clear();
+        self.set_stems(!self.stem_resamplers.is_empty());
+    }
+
+    pub fn filters(&self) -> &[AudioFilter] {
+        &self.filters
+    }
+
+    // Replace the filters applied to the output, e.g. with `NES_FILTERS` or nothing at all
+    pub fn set_filters(&mut self, filters: &[AudioFilter]) {
+        self.filters = filters.to_vec();
+        let rate = self.sample_rate();
+        if rate.is_some() {
+            self.set_sample_rate(rate);
+        }
+    }
+
+    // Also produce each channel on its own, as it would sound with the others silent. Needs a
+    // sample rate to be set.
+    pub fn set_stems(&mut self, enabled: bool) {
+        self.stem_resamplers.clear();
+        for buffer in &mut self.stem_buffers {
+            buffer.clear();
+        }
+        if let (true, Some(rate)) = (enabled, self.sample_rate()) {
+            self.stem_resamplers = (0..CHANNELS)
+                .map(|_| Resampler::new(rate, self.region.cpu_frequency(), &self.filters))
+                .collect();
+        }
+    }
+
+    // Move the samples of one channel (an index into `CHANNEL_NAMES`) generated since the last
+    // call to the end of `samples`
+    pub fn take_stem_samples(&mut self, channel: usize, samples: &mut Vec<f32>) {
+        samples.append(&mut self.stem_buffers[channel]);
+    }
+
+    // Move the samples generated since the last call to the end of `samples`
+    pub fn take_samples(&mut self, samples: &mut Vec<f32>) {
+        samples.append(&mut self.audio_buffer);
+    }
+
+    // Handle a CPU write to $4000-$4013, $4015 or $4017. Writing $4015 enables or silences
+    // the channels and acknowledges the DMC interrupt.
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        trace!(target: "apu", "${:04X} = {:02X}", address, value);
+        match address {
+            0x4000..=0x4003 => self.pulse_1.write_register(address - 0x4000, value),
+            0x4004..=0x4007 => self.pulse_2.write_register(address - 0x4004, value),
+            0x4008..=0x400B => self.triangle.write_register(address - 0x4008, value),
+            0x400C..=0x400F => self.noise.write_register(address - 0x400C, value),
+            0x4010..=0x4013 => self.dmc.write_register(address - 0x4010, value),
+            0x4015 => {
+                self.pulse_1.set_enabled(value & 0x01 != 0);
+                self.pulse_2.set_enabled(value & 0x02 != 0);
+                self.triangle.set_enabled(value & 0x04 != 0);
+                self.noise.set_enabled(value & 0x08 != 0);
+                self.dmc.set_enabled(value & 0x10 != 0);
+            }
+            0x4017 => self.frame_counter.write(value, self.cycles & 1 == 1),
+            _ => {}
+        }
+    }
+
+    // Read $4015: which channels are still playing and which interrupts are pending. Reading
+    // acknowledges the frame interrupt but not the DMC one.
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_counter.clear_irq();
+        status
+    }
+
+    // $4015 without the read side effect
+    pub fn peek_status(&self) -> u8 {
+        (self.pulse_1.length_active() as u8)
+            | (self.pulse_2.length_active() as u8) << 1
+            | (self.triangle.length_active() as u8) << 2
+            | (self.noise.length_active() as u8) << 3
+            | (self.dmc.active() as u8) << 4
+            | (self.frame_counter.irq() as u8) << 6
+            | (self.dmc.irq() as u8) << 7
+    }
+
+    // Whether the frame counter or the DMC is asserting the CPU's IRQ line
+    pub fn irq(&self) -> bool {
+        self.frame_counter.irq() || self.dmc.irq()
+    }
+
+    // CPU cycles until the APU next raises an IRQ or the DMC needs the bus, at most. While a
+    // DMC sample plays that's any cycle.
+    pub(crate) fn cycles_until_event(&self) -> u64 {
+        if self.dmc.busy() {
+            return 1;
+        }
+        self.frame_counter.cycles_until_event()
+    }
+
+    // The address of the next DMC sample byte, once, when the DMC wants it fetched by DMA
+    pub fn take_dmc_request(&mut self) -> Option<u16> {
+        self.dmc.take_fetch_request()
+    }
+
+    // Hand the DMC the sample byte fetched for its last request
+    pub fn load_dmc_sample(&mut self, sample: u8) {
+        self.dmc.load_sample(sample);
+    }
+
+    // Advance by one CPU cycle
+    pub fn tick(&mut self) {
+        self.cycles += 1;
+
+        let clock = self.frame_counter.tick();
+        if clock.quarter {
+            self.pulse_1.clock_quarter_frame();
+            self.pulse_2.clock_quarter_frame();
+            self.triangle.clock_quarter_frame();
+            self.noise.clock_quarter_frame();
+        }
+        if clock.half {
+            self.pulse_1.clock_half_frame();
+            self.pulse_2.clock_half_frame();
+            self.triangle.clock_half_frame();
+            self.noise.clock_half_frame();
+        }
+
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        if let Some(fds) = self.fds.as_mut() {
+            fds.clock();
+        }
+        if self.cycles & 1 == 0 {
+            self.pulse_1.clock_timer();
+            self.pulse_2.clock_timer();
+        }
+
+        if self.resampler.is_some() {
+            let level = self.output();
+            if let Some(resampler) = self.resampler.as_mut() {
+                resampler.clock(level, &mut self.audio_buffer);
+            }
+        }
+
+        if !self.stem_resamplers.is_empty() {
+            let levels = self.channel_outputs();
+            for ((resampler, buffer), level) in self
+                .stem_resamplers
+                .iter_mut()
+                .zip(&mut self.stem_buffers)
+                .zip(levels)
+            {
+                resampler.clock(level, buffer);
+            }
+        }
+    }
+
+    // The mixed output of all channels, 0.0-1.0, using the nonlinear mixer approximation
+    pub fn output(&self) -> f32 {
+        let pulse = (self.pulse_1.output() + self.pulse_2.output()) as f32;
+        let tnd = self.triangle.output() as f32 / 8227.0
+            + self.noise.output() as f32 / 12241.0
+            + self.dmc.output() as f32 / 22638.0;
+        let fds = self
+            .fds
+            .as_ref()
+            .map_or(0.0, |fds| fds.output() * FDS_LEVEL * self.fds_volume);
+        mix_pulse(pulse) + mix_tnd(tnd) + fds
+    }
+
+    // Each channel's contribution through the mixer with the others silent, in
+    // `CHANNEL_NAMES` order
+    pub fn channel_outputs(&self) -> [f32; CHANNELS] {
+        [
+            mix_pulse(self.pulse_1.output() as f32),
+            mix_pulse(self.pulse_2.output() as f32),
+            mix_tnd(self.triangle.output() as f32 / 8227.0),
+            mix_tnd(self.noise.output() as f32 / 12241.0),
+            mix_tnd(self.dmc.output() as f32 / 22638.0),
+        ]
+    }
+}
+
+fn mix_pulse(pulse: f32) -> f32 {
+    if pulse == 0.0 {
+        0.0
+    } else {
+        95.88 / (8128.0 / pulse + 100.0)
+    }
+}
+
+fn mix_tnd(tnd: f32) -> f32 {
+    if tnd == 0.0 {
+        0.0
+    } else {
+        159.79 / (1.0 / tnd + 100.0)
+    }
+}
@@ -0,0 +1,179 @@
+use tracing::debug;
+
+// Output rates selected by the low four bits of $4010, in CPU cycles per bit
+const RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const PAL_RATES: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+// The delta modulation channel, $4010-$4013. Samples are 1-bit deltas read from PRG space one
+// byte at a time through DMA, each bit moving a 7-bit output level up or down by 2. Games also
+// write the level directly through $4011 to play raw PCM.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dmc {
+    pal: bool,
+    irq_enabled: bool,
+    looping: bool,
+    irq: bool,
+    timer_period: u16,
+    timer: u16,
+    level: u8, // 0-127
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    fetch_pending: bool, // A DMA fetch for the sample buffer has been requested
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dmc {
+    pub fn new() -> Self {
+        Self {
+            pal: false,
+            irq_enabled: false,
+            looping: false,
+            irq: false,
+            timer_period: RATES[0],
+            timer: 0,
+            level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            fetch_pending: false,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+        }
+    }
+
+    // `register` is the address offset within the channel, 0-3
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.irq_enabled = value & 0x80 != 0;
+                self.looping = value & 0x40 != 0;
+                let rates = if self.pal { &PAL_RATES } else { &RATES };
+                self.timer_period = rates[value as usize & 0x0F];
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            1 => self.level = value & 0x7F,
+            2 => self.sample_address = 0xC000 | (value as u16) << 6,
+            _ => self.sample_length = (value as u16) << 4 | 1,
+        }
+    }
+
+    // Bit 4 of $4015 stops the sample or, if it had finished, starts it again
+    pub fn set_pal(&mut self, pal: bool) {
+        self.pal = pal;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    // Whether the sample still has bytes left to fetch
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq
+    }
+
+    // Whether the DMC may ask for a fetch or take a fetched byte soon
+    pub fn busy(&self) -> bool {
+        self.active() || self.fetch_pending
+    }
+
+    // The address to fetch when the sample buffer needs refilling, returned once per fetch
+    pub fn take_fetch_request(&mut self) -> Option<u16> {
+        if self.sample_buffer.is_some() || self.bytes_remaining == 0 || self.fetch_pending {
+            return None;
+        }
+        self.fetch_pending = true;
+        Some(self.current_address)
+    }
+
+    // Receive the byte fetched for the last request
+    pub fn load_sample(&mut self, sample: u8) {
+        self.fetch_pending = false;
+        if self.bytes_remaining == 0 {
+            return; // Disabled while the fetch was in flight
+        }
+
+        self.sample_buffer = Some(sample);
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                debug!(target: "apu", "DMC IRQ");
+                self.irq = true;
+            }
+        }
+    }
+
+    // Clocked every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if self.shift_register & 1 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    // Current output level, 0-127
+    pub fn output(&self) -> u8 {
+        self.level
+    }
+}
@@ -0,0 +1,51 @@
+// Volume envelope shared by the pulse and noise channels: either a constant volume or a
+// sawtooth that decays from 15 to 0, optionally looping
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Envelope {
+    start: bool, // Set by writes to the channel's length register
+    looping: bool,
+    constant: bool,
+    period: u8, // Also the constant volume
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    // The low six bits of $4000/$4004/$400C: --LC VVVV
+    pub fn write_control(&mut self, value: u8) {
+        self.looping = value & 0x20 != 0;
+        self.constant = value & 0x10 != 0;
+        self.period = value & 0x0F;
+    }
+
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    // Clocked on quarter frames
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.period;
+        } else if self.divider == 0 {
+            self.divider = self.period;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn volume(&self) -> u8 {
+        if self.constant {
+            self.period
+        } else {
+            self.decay
+        }
+    }
+}
@@ -0,0 +1,212 @@
+// Mod table entries ($4088) as changes to the mod counter; 4 resets it instead
+const MOD_STEPS: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+
+// Master volume ($4089 bits 0-1) as a fraction of full scale, multiplied by 30
+const MASTER_VOLUMES: [u16; 4] = [30, 20, 15, 12];
+
+// One of the two FDS envelopes ($4080 for volume, $4084 for the mod depth)
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct FdsEnvelope {
+    disabled: bool, // Gain is set directly from the speed bits
+    increase: bool,
+    speed: u8,
+    gain: u8,
+    timer: u32,
+}
+
+impl FdsEnvelope {
+    fn write(&mut self, value: u8) {
+        self.disabled = value & 0x80 != 0;
+        self.increase = value & 0x40 != 0;
+        self.speed = value & 0x3F;
+        if self.disabled {
+            self.gain = self.speed;
+        }
+        self.timer = 0;
+    }
+
+    fn clock(&mut self, master_speed: u8) {
+        if self.disabled {
+            return;
+        }
+        self.timer += 1;
+        if self.timer >= 8 * master_speed as u32 * (self.speed as u32 + 1) {
+            self.timer = 0;
+            if self.increase && self.gain < 32 {
+                self.gain += 1;
+            } else if !self.increase && self.gain > 0 {
+                self.gain -= 1;
+            }
+        }
+    }
+}
+
+// The Famicom Disk System's expansion sound: a 64-step, 6-bit wavetable voice whose pitch is
+// bent by a second, modulation wavetable. Registers live at $4040-$4092.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fds {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_arrays"))]
+    wave: [u8; 64],
+    wave_write: bool,       // $4089 bit 7, which also freezes the wave
+    master_volume: u8,      // $4089 bits 0-1
+    master_speed: u8,       // $408A, scales both envelope periods
+    frequency: u16,         // $4082/$4083
+    wave_halted: bool,      // $4083 bit 7
+    envelopes_halted: bool, // $4083 bit 6
+    wave_accumulator: u32,  // Bits 16-21 are the wave position
+    volume: FdsEnvelope,
+    output_gain: u8, // Volume gain latched at the start of each wave cycle
+    modulator: FdsEnvelope,
+    mod_table: [u8; 32],
+    mod_position: u8, // 0-63; each table entry lasts two steps
+    mod_counter: i8,  // 7-bit signed
+    mod_frequency: u16,
+    mod_halted: bool,
+    mod_accumulator: u32,
+}
+
+impl Default for Fds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fds {
+    pub fn new() -> Self {
+        Self {
+            wave: [0; 64],
+            wave_write: false,
+            master_volume: 0,
+            master_speed: 0xE8,
+            frequency: 0,
+            wave_halted: true,
+            envelopes_halted: true,
+            wave_accumulator: 0,
+            volume: FdsEnvelope::default(),
+            output_gain: 0,
+            modulator: FdsEnvelope::default(),
+            mod_table: [0; 32],
+            mod_position: 0,
+            mod_counter: 0,
+            mod_frequency: 0,
+            mod_halted: true,
+            mod_accumulator: 0,
+        }
+    }
+
+    pub fn read_register(&self, address: u16) -> Option<u8> {
+        match address {
+            0x4040..=0x407F => Some(self.wave[address as usize - 0x4040] | 0x40),
+            0x4090 => Some(self.volume.gain | 0x40),
+            0x4092 => Some(self.modulator.gain | 0x40),
+            _ => None,
+        }
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x4040..=0x407F if self.wave_write => {
+                self.wave[address as usize - 0x4040] = value & 0x3F;
+            }
+            0x4080 => self.volume.write(value),
+            0x4082 => self.frequency = (self.frequency & 0x0F00) | value as u16,
+            0x4083 => {
+                self.frequency = (self.frequency & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.wave_halted = value & 0x80 != 0;
+                self.envelopes_halted = value & 0x40 != 0;
+                if self.wave_halted {
+                    self.wave_accumulator = 0;
+                }
+            }
+            0x4084 => self.modulator.write(value),
+            0x4085 => self.mod_counter = ((value << 1) as i8) >> 1,
+            0x4086 => self.mod_frequency = (self.mod_frequency & 0x0F00) | value as u16,
+            0x4087 => {
+                self.mod_frequency = (self.mod_frequency & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.mod_halted = value & 0x80 != 0;
+                if self.mod_halted {
+                    self.mod_accumulator = 0;
+                }
+            }
+            0x4088 if self.mod_halted => {
+                // Each write fills the two steps at the current position
+                self.mod_table[self.mod_position as usize / 2] = value & 0x07;
+                self.mod_position = (self.mod_position + 2) & 0x3F;
+            }
+            0x4089 => {
+                self.wave_write = value & 0x80 != 0;
+                self.master_volume = value & 0x03;
+            }
+            0x408A => self.master_speed = value,
+            _ => {}
+        }
+    }
+
+    // Clocked every CPU cycle
+    pub fn clock(&mut self) {
+        if !self.envelopes_halted && !self.wave_halted && self.master_speed > 0 {
+            self.volume.clock(self.master_speed);
+            self.modulator.clock(self.master_speed);
+        }
+
+        if !self.mod_halted {
+            self.mod_accumulator += self.mod_frequency as u32;
+            if self.mod_accumulator >= 0x10000 {
+                self.mod_accumulator -= 0x10000;
+                self.step_modulator();
+            }
+        }
+
+        if !self.wave_halted && !self.wave_write {
+            let previous = self.wave_accumulator >> 16;
+            self.wave_accumulator = (self.wave_accumulator + self.pitch() as u32) & 0x3F_FFFF;
+            let position = self.wave_accumulator >> 16;
+            if position < previous {
+                self.output_gain = self.volume.gain.min(32);
+            }
+        }
+    }
+
+    fn step_modulator(&mut self) {
+        let entry = self.mod_table[self.mod_position as usize / 2];
+        self.mod_counter = if entry == 4 {
+            0
+        } else {
+            // Wrap within 7 bits
+            (self.mod_counter.wrapping_add(MOD_STEPS[entry as usize]) << 1) >> 1
+        };
+        self.mod_position = (self.mod_position + 1) & 0x3F;
+    }
+
+    // The wave frequency bent by the modulator, following the hardware's odd rounding
+    fn pitch(&self) -> u16 {
+        let mut temp = self.mod_counter as i32 * self.modulator.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if self.mod_counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        temp *= self.frequency as i32;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        (self.frequency as i32 + temp).clamp(0, 0xFFFF) as u16
+    }
+
+    // Current output, 0.0-1.0
+    pub fn output(&self) -> f32 {
+        let sample = self.wave[(self.wave_accumulator >> 16) as usize & 0x3F] as u16;
+        let level = sample * self.output_gain as u16 * MASTER_VOLUMES[self.master_volume as usize];
+        level as f32 / (63.0 * 32.0 * 30.0)
+    }
+}
@@ -0,0 +1,72 @@
+use alloc::vec::Vec;
+use core::f32::consts::PI;
+
+// A first-order filter stage applied to the resampled output, with its cutoff in Hz
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AudioFilter {
+    HighPass(f32),
+    LowPass(f32),
+}
+
+// The console's analog output stage: two high-pass filters and a low-pass filter
+pub const NES_FILTERS: [AudioFilter; 3] = [
+    AudioFilter::HighPass(90.0),
+    AudioFilter::HighPass(440.0),
+    AudioFilter::LowPass(14_000.0),
+];
+
+#[derive(Debug, Clone, Copy)]
+struct Stage {
+    high_pass: bool,
+    factor: f32,
+    input: f32, // Previous input and output
+    output: f32,
+}
+
+// A chain of RC filters, run once per output sample
+#[derive(Debug, Clone, Default)]
+pub struct FilterChain {
+    stages: Vec<Stage>,
+}
+
+impl FilterChain {
+    pub fn new(filters: &[AudioFilter], rate: u32) -> Self {
+        let dt = 1.0 / rate as f32;
+        let stages = filters
+            .iter()
+            .map(|filter| {
+                let (high_pass, cutoff) = match *filter {
+                    AudioFilter::HighPass(cutoff) => (true, cutoff),
+                    AudioFilter::LowPass(cutoff) => (false, cutoff),
+                };
+                let rc = 1.0 / (2.0 * PI * cutoff.max(1.0));
+                Stage {
+                    high_pass,
+                    factor: if high_pass {
+                        rc / (rc + dt)
+                    } else {
+                        dt / (rc + dt)
+                    },
+                    input: 0.0,
+                    output: 0.0,
+                }
+            })
+            .collect();
+        Self { stages }
+    }
+
+    pub fn process(&mut self, mut sample: f32) -> f32 {
+        for stage in &mut self.stages {
+            let output = if stage.high_pass {
+                stage.factor * (stage.output + sample - stage.input)
+            } else {
+                stage.output + stage.factor * (sample - stage.output)
+            };
+            stage.input = sample;
+            stage.output = output;
+            sample = output;
+        }
+        sample
+    }
+}
@@ -0,0 +1,124 @@
+use tracing::debug;
+
+// When the sequence steps happen, in CPU cycles: the first three quarter frames, then the
+// IRQ cycles around the end of the four-step sequence and the end of the five-step sequence,
+// each followed by the cycle the sequence starts over on
+const NTSC_STEPS: [u16; 8] = [7457, 14913, 22371, 29828, 29829, 29830, 37281, 37282];
+const PAL_STEPS: [u16; 8] = [8313, 16627, 24939, 33252, 33253, 33254, 41565, 41566];
+
+// Sequencer that clocks the envelopes (quarter frames) and the length counters and sweeps
+// (half frames), configured through $4017. Step times are in CPU cycles.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameCounter {
+    five_step: bool,
+    irq_inhibit: bool,
+    irq: bool,        // Raised at the end of every four-step sequence
+    cycle: u16,       // CPU cycles into the current sequence
+    reset_delay: u8,  // CPU cycles until a $4017 write restarts the sequence
+    pending_mode: u8, // The value written to $4017, applied after the delay
+    pal: bool,        // Use the slower PAL step times
+}
+
+// Which units a frame counter clock drives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FrameClock {
+    pub quarter: bool,
+    pub half: bool,
+}
+
+impl FrameCounter {
+    pub fn set_pal(&mut self, pal: bool) {
+        self.pal = pal;
+    }
+
+    pub fn write(&mut self, value: u8, odd_cycle: bool) {
+        self.irq_inhibit = value & 0x40 != 0;
+        if self.irq_inhibit {
+            self.irq = false;
+        }
+        self.pending_mode = value;
+        self.reset_delay = if odd_cycle { 4 } else { 3 };
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq
+    }
+
+    pub fn clear_irq(&mut self) {
+        self.irq = false;
+    }
+
+    // CPU cycles until the sequence next raises the IRQ or restarts after a $4017 write
+    pub fn cycles_until_event(&self) -> u64 {
+        if self.reset_delay > 0 {
+            return self.reset_delay as u64;
+        }
+        if self.five_step || self.irq_inhibit {
+            return u64::MAX;
+        }
+        let steps = if self.pal { &PAL_STEPS } else { &NTSC_STEPS };
+        steps[3..=5]
+            .iter()
+            .find(|&&step| step > self.cycle)
+            .map_or(1, |&step| (step - self.cycle) as u64)
+    }
+
+    // Advance by one CPU cycle
+    pub fn tick(&mut self) -> FrameClock {
+        let mut clock = FrameClock::default();
+
+        if self.reset_delay > 0 {
+            self.reset_delay -= 1;
+            if self.reset_delay == 0 {
+                self.five_step = self.pending_mode & 0x80 != 0;
+                self.cycle = 0;
+                if self.five_step {
+                    // Entering five-step mode clocks everything immediately
+                    return FrameClock {
+                        quarter: true,
+                        half: true,
+                    };
+                }
+                return clock;
+            }
+        }
+
+        self.cycle += 1;
+        let steps = if self.pal { &PAL_STEPS } else { &NTSC_STEPS };
+        let step = steps.iter().position(|&cycle| cycle == self.cycle);
+        match (self.five_step, step) {
+            (_, Some(0)) | (_, Some(2)) => clock.quarter = true,
+            (_, Some(1)) => {
+                clock.quarter = true;
+                clock.half = true;
+            }
+            (false, Some(3)) => self.raise_irq(),
+            (false, Some(4)) => {
+                clock.quarter = true;
+                clock.half = true;
+                self.raise_irq();
+            }
+            (false, Some(5)) => {
+                self.raise_irq();
+                self.cycle = 0;
+            }
+            (true, Some(6)) => {
+                clock.quarter = true;
+                clock.half = true;
+            }
+            (true, Some(7)) => self.cycle = 0,
+            _ => {}
+        }
+        clock
+    }
+
+    fn raise_irq(&mut self) {
+        if !self.irq_inhibit {
+            if !self.irq {
+                debug!(target: "apu", "Frame IRQ");
+            }
+            self.irq = true;
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+
+// Timer periods selected by the low four bits of $400E, in CPU cycles
+const PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const PAL_PERIODS: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+// The noise channel, $400C-$400F: a 15-bit linear feedback shift register whose low bit
+// gates the envelope
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Noise {
+    pal: bool,
+    short_mode: bool, // Feed back from bit 6 instead of bit 1, giving a 93-step metallic loop
+    timer_period: u16,
+    timer: u16,
+    shift_register: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Self {
+            pal: false,
+            short_mode: false,
+            timer_period: PERIODS[0],
+            timer: 0,
+            shift_register: 1, // Loaded with 1 at power on
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+
+    // `register` is the address offset within the channel, 0-3
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.length.set_halted(value & 0x20 != 0);
+                self.envelope.write_control(value);
+            }
+            1 => {}
+            2 => {
+                self.short_mode = value & 0x80 != 0;
+                let periods = if self.pal { &PAL_PERIODS } else { &PERIODS };
+                self.timer_period = periods[value as usize & 0x0F];
+            }
+            _ => {
+                self.length.load(value >> 3);
+                self.envelope.restart();
+            }
+        }
+    }
+
+    pub fn set_pal(&mut self, pal: bool) {
+        self.pal = pal;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length.set_enabled(enabled);
+    }
+
+    pub fn length_active(&self) -> bool {
+        self.length.active()
+    }
+
+    // Clocked every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    // Current output level, 0-15
+    pub fn output(&self) -> u8 {
+        if !self.length.active() || self.shift_register & 1 != 0 {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
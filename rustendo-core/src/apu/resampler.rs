@@ -0,0 +1,87 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::f64::consts::PI;
+
+use super::filter::{AudioFilter, FilterChain};
+
+const TAPS: usize = 16; // Width of the band-limited impulse, in output samples
+const PHASES: usize = 64; // Sub-sample positions the impulse is precomputed for
+const CUTOFF: f64 = 0.45; // Low-pass cutoff as a fraction of the output sample rate
+
+// Band-limited synthesis from the CPU clock down to the host rate. The mixer output only
+// changes in steps, so instead of filtering every cycle each step is added to the output as a
+// windowed-sinc impulse placed at its exact sub-sample time, and the impulses are integrated
+// back into a signal as samples are read out. Nothing above the cutoff survives to alias.
+pub struct Resampler {
+    rate: u32,
+    clock: u32,               // Input samples per second, one per CPU cycle
+    kernel: Vec<[f32; TAPS]>, // One impulse per phase, each summing to 1
+    time: u32,                // Time since the last output sample, in 1/clock samples
+    pending: VecDeque<f32>,   // Impulses not yet integrated, starting at the next sample
+    level: f32,               // Input level the impulses have reached so far
+    integrator: f32,
+    filters: FilterChain, // Applied to each output sample
+}
+
+impl Resampler {
+    pub fn new(rate: u32, clock: u32, filters: &[AudioFilter]) -> Self {
+        let rate = rate.clamp(1, clock);
+        Self {
+            rate,
+            clock,
+            kernel: (0..PHASES).map(impulse).collect(),
+            time: 0,
+            pending: VecDeque::from(alloc::vec![0.0; TAPS]),
+            level: 0.0,
+            integrator: 0.0,
+            filters: FilterChain::new(filters, rate),
+        }
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    // Feed the mixer output for one CPU cycle, pushing any completed samples to `output`
+    pub fn clock(&mut self, level: f32, output: &mut Vec<f32>) {
+        let delta = level - self.level;
+        if delta != 0.0 {
+            self.level = level;
+            let phase = (self.time as u64 * PHASES as u64 / self.clock as u64) as usize;
+            for (pending, tap) in self.pending.iter_mut().zip(&self.kernel[phase]) {
+                *pending += delta * tap;
+            }
+        }
+
+        self.time += self.rate;
+        if self.time >= self.clock {
+            self.time -= self.clock;
+            self.integrator += self.pending.pop_front().unwrap_or(0.0);
+            self.pending.push_back(0.0);
+
+            output.push(self.filters.process(self.integrator));
+        }
+    }
+}
+
+// The windowed-sinc impulse for a step `phase / PHASES` of a sample after the start of the
+// current output sample, centred in the kernel
+fn impulse(phase: usize) -> [f32; TAPS] {
+    let offset = phase as f64 / PHASES as f64;
+    let mut taps = [0.0; TAPS];
+    for (i, tap) in taps.iter_mut().enumerate() {
+        let x = i as f64 - (TAPS / 2) as f64 - offset;
+        let sinc = if x == 0.0 {
+            2.0 * CUTOFF
+        } else {
+            libm::sin(2.0 * PI * CUTOFF * x) / (PI * x)
+        };
+        // Blackman window over the kernel width
+        let w = (i as f64 + 1.0 - offset) / (TAPS + 1) as f64;
+        let window = 0.42 - 0.5 * libm::cos(2.0 * PI * w) + 0.08 * libm::cos(4.0 * PI * w);
+        *tap = (sinc * window) as f32;
+    }
+
+    let sum: f32 = taps.iter().sum();
+    taps.map(|tap| tap / sum)
+}
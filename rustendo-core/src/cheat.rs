@@ -0,0 +1,346 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::fmt::Write;
+
+// Game Genie letters, in the order of the nibble values they stand for
+const GAME_GENIE_LETTERS: &[u8; 16] = b"APZLGITYEOXUKSVN";
+
+// A read substitution: reads from `address` return `value` instead, or only when the byte that
+// is really there equals `compare`. Game Genie codes patch PRG-ROM (and the compare byte keeps
+// them to the right bank on bank-switched boards); raw codes can name any address, so a code
+// for RAM pins that location the way a Pro Action Replay does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cheat {
+    pub address: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CheatError {
+    InvalidCode,        // Neither a 6/8-letter Game Genie code nor AAAA:VV or AAAA?CC:VV
+    InvalidLine(usize), // In a cheat file, 1-based
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheatError::InvalidCode => {
+                f.write_str("not a Game Genie code or a raw code (AAAA:VV or AAAA?CC:VV)")
+            }
+            CheatError::InvalidLine(line) => write!(f, "invalid cheat on line {}", line),
+        }
+    }
+}
+
+impl core::error::Error for CheatError {}
+
+impl Cheat {
+    // Decode a Game Genie code like SXIOPO or a raw code like 075A:09, case-insensitively
+    pub fn decode(code: &str) -> Result<Self, CheatError> {
+        let code = code.trim();
+        if code.contains(':') {
+            decode_raw(code)
+        } else {
+            decode_game_genie(code)
+        }
+        .ok_or(CheatError::InvalidCode)
+    }
+
+    fn apply(&self, address: u16, value: u8) -> Option<u8> {
+        if address != self.address || self.compare.is_some_and(|compare| compare != value) {
+            return None;
+        }
+        Some(self.value)
+    }
+}
+
+// The letters are nibbles whose bits are shuffled into a 15-bit address in $8000-$FFFF, the
+// value and, for 8-letter codes, the compare byte
+fn decode_game_genie(code: &str) -> Option<Cheat> {
+    let n: Vec<u16> = code
+        .bytes()
+        .map(|letter| {
+            let letter = letter.to_ascii_uppercase();
+            GAME_GENIE_LETTERS
+                .iter()
+                .position(|&l| l == letter)
+                .map(|nibble| nibble as u16)
+        })
+        .collect::<Option<_>>()?;
+    if n.len() != 6 && n.len() != 8 {
+        return None;
+    }
+
+    let address = 0x8000
+        | (n[3] & 7) << 12
+        | (n[5] & 7) << 8
+        | (n[4] & 8) << 8
+        | (n[2] & 7) << 4
+        | (n[1] & 8) << 4
+        | (n[4] & 7)
+        | (n[3] & 8);
+    let value = (n[1] & 7) << 4 | (n[0] & 8) << 4 | (n[0] & 7);
+    let (value, compare) = if n.len() == 6 {
+        (value | (n[5] & 8), None)
+    } else {
+        let compare = (n[7] & 7) << 4 | (n[6] & 8) << 4 | (n[6] & 7) | (n[5] & 8);
+        (value | (n[7] & 8), Some(compare as u8))
+    };
+    Some(Cheat {
+        address,
+        value: value as u8,
+        compare,
+    })
+}
+
+// AAAA:VV, or AAAA?CC:VV to only replace CC, all in hex
+fn decode_raw(code: &str) -> Option<Cheat> {
+    let (target, value) = code.split_once(':')?;
+    let (address, compare) = match target.split_once('?') {
+        Some((address, compare)) => (address, Some(hex_byte(compare)?)),
+        None => (target, None),
+    };
+    if address.is_empty() || address.len() > 4 {
+        return None;
+    }
+    Some(Cheat {
+        address: u16::from_str_radix(address, 16).ok()?,
+        value: hex_byte(value)?,
+        compare,
+    })
+}
+
+fn hex_byte(text: &str) -> Option<u8> {
+    if text.is_empty() || text.len() > 2 {
+        return None;
+    }
+    u8::from_str_radix(text, 16).ok()
+}
+
+// A cheat as the user entered it, which can be switched off without forgetting it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CheatEntry {
+    pub code: String, // Upper case
+    pub name: String, // What it does, empty if nobody said
+    pub cheat: Cheat,
+    pub enabled: bool,
+}
+
+// The active cheats, consulted on every CPU read. Codes are identified by their text.
+//
+// The text format, used for a game's `.cht` file, has one cheat per line: the code, then what
+// it does. A `-` before the code keeps it switched off, and lines starting with `#` are
+// comments, e.g.
+//
+//     SXIOPO Infinite lives
+//     -075A:09 Start with 9 lives
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cheats {
+    entries: Vec<CheatEntry>,
+}
+
+impl Cheats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, CheatError> {
+        let mut cheats = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (enabled, line) = match line.strip_prefix('-') {
+                Some(line) => (false, line.trim_start()),
+                None => (true, line),
+            };
+            let (code, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            if cheats.contains(code) {
+                continue;
+            }
+            cheats
+                .add(code)
+                .map_err(|_| CheatError::InvalidLine(index + 1))?;
+            if let Some(entry) = cheats.entries.last_mut() {
+                entry.name = name.trim().into();
+                entry.enabled = enabled;
+            }
+        }
+        Ok(cheats)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        // Writing to a String can't fail
+        let _ = writeln!(
+            text,
+            "# Cheat code, then what it does; '-' switches one off"
+        );
+        for entry in &self.entries {
+            let off = if entry.enabled { "" } else { "-" };
+            let line = format!("{}{} {}", off, entry.code, entry.name);
+            let _ = writeln!(text, "{}", line.trim_end());
+        }
+        text
+    }
+
+    // Add an enabled cheat, or re-enable it if it is already in the list
+    pub fn add(&mut self, code: &str) -> Result<(), CheatError> {
+        let cheat = Cheat::decode(code)?;
+        let code = code.trim().to_ascii_uppercase();
+        match self.entries.iter_mut().find(|entry| entry.code == code) {
+            Some(entry) => entry.enabled = true,
+            None => self.entries.push(CheatEntry {
+                code,
+                name: String::new(),
+                cheat,
+                enabled: true,
+            }),
+        }
+        Ok(())
+    }
+
+    // Returns whether the code was in the list
+    pub fn remove(&mut self, code: &str) -> bool {
+        let code = code.trim().to_ascii_uppercase();
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.code != code);
+        self.entries.len() != len
+    }
+
+    // Returns whether the code was in the list
+    pub fn set_enabled(&mut self, code: &str, enabled: bool) -> bool {
+        let code = code.trim().to_ascii_uppercase();
+        match self.entries.iter_mut().find(|entry| entry.code == code) {
+            Some(entry) => {
+                entry.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn contains(&self, code: &str) -> bool {
+        let code = code.trim().to_ascii_uppercase();
+        self.entries.iter().any(|entry| entry.code == code)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CheatEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // The value a read of `address` returns once the enabled cheats have had their say
+    pub(crate) fn apply(&self, address: u16, value: u8) -> u8 {
+        self.entries
+            .iter()
+            .filter(|entry| entry.enabled)
+            .find_map(|entry| entry.cheat.apply(address, value))
+            .unwrap_or(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cheat(address: u16, value: u8, compare: Option<u8>) -> Cheat {
+        Cheat {
+            address,
+            value,
+            compare,
+        }
+    }
+
+    #[test]
+    fn game_genie_codes() {
+        assert_eq!(Cheat::decode("GOSSIP"), Ok(cheat(0xD1DD, 0x14, None)));
+        assert_eq!(Cheat::decode("sxiopo"), Ok(cheat(0x91D9, 0xAD, None)));
+        assert_eq!(
+            Cheat::decode("ZEXPYGLA"),
+            Ok(cheat(0x94A7, 0x02, Some(0x03)))
+        );
+        for code in ["SXIOP", "SXIOPOO", "SXIOPQ", ""] {
+            assert_eq!(
+                Cheat::decode(code),
+                Err(CheatError::InvalidCode),
+                "{}",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn raw_codes() {
+        assert_eq!(Cheat::decode(" 075a:09 "), Ok(cheat(0x075A, 0x09, None)));
+        assert_eq!(Cheat::decode("C0:1"), Ok(cheat(0x00C0, 0x01, None)));
+        assert_eq!(
+            Cheat::decode("8123?A9:EA"),
+            Ok(cheat(0x8123, 0xEA, Some(0xA9)))
+        );
+        for code in [
+            "12345:00", ":00", "0000:", "0000:100", "0000?:00", "00G0:00",
+        ] {
+            assert_eq!(
+                Cheat::decode(code),
+                Err(CheatError::InvalidCode),
+                "{}",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn reads_with_and_without_a_compare() {
+        let mut cheats = Cheats::new();
+        cheats.add("075A:09").unwrap();
+        cheats.add("8123?A9:EA").unwrap();
+        assert_eq!(cheats.apply(0x075A, 0x03), 0x09);
+        assert_eq!(cheats.apply(0x075B, 0x03), 0x03);
+        assert_eq!(cheats.apply(0x8123, 0xA9), 0xEA);
+        // Another bank is mapped in
+        assert_eq!(cheats.apply(0x8123, 0x60), 0x60);
+        assert!(cheats.set_enabled("075a:09", false));
+        assert_eq!(cheats.apply(0x075A, 0x03), 0x03);
+    }
+
+    #[test]
+    fn cheat_files() {
+        let text =
+            "# Comment\n\nSXIOPO Infinite lives\n-075a:09  Start with 9 lives\nSXIOPO Again\n";
+        let cheats = Cheats::parse(text).unwrap();
+        let entries: Vec<_> = cheats
+            .iter()
+            .map(|entry| (entry.code.as_str(), entry.name.as_str(), entry.enabled))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("SXIOPO", "Infinite lives", true),
+                ("075A:09", "Start with 9 lives", false)
+            ]
+        );
+        assert_eq!(Cheats::parse(&cheats.to_text()), Ok(cheats));
+        assert_eq!(
+            Cheats::parse("SXIOPO\nBADCODE Lives\n"),
+            Err(CheatError::InvalidLine(2))
+        );
+    }
+}
@@ -0,0 +1,95 @@
+// The hashes ROM databases identify dumps by, and one for telling frames apart. The first two
+// are only run once per loaded ROM, so they favour brevity over speed.
+
+// CRC-32 as used by zip and No-Intro (reflected, polynomial $EDB88320)
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = crc >> 1 ^ 0xEDB8_8320 & mask;
+        }
+    }
+    !crc
+}
+
+// 64-bit FNV-1a, which is quick, stable across platforms and versions, and plenty to tell two
+// frames apart
+pub fn fnv1a64(data: impl IntoIterator<Item = u8>) -> u64 {
+    let mut hash = 0xCBF2_9CE4_8422_2325u64;
+    for byte in data {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01B3);
+    }
+    hash
+}
+
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [
+        0x6745_2301,
+        0xEFCD_AB89,
+        0x98BA_DCFE,
+        0x1032_5476,
+        0xC3D2_E1F0,
+    ];
+
+    // The message is followed by a 1 bit, zeros up to 8 bytes short of a block boundary, and
+    // its length in bits
+    let remainder = data.len() % 64;
+    let (blocks, rest) = data.split_at(data.len() - remainder);
+    let mut tail = [0u8; 128];
+    tail[..remainder].copy_from_slice(rest);
+    tail[remainder] = 0x80;
+    let tail_len = if remainder < 56 { 64 } else { 128 };
+    let bit_length = (data.len() as u64).wrapping_mul(8);
+    tail[tail_len - 8..tail_len].copy_from_slice(&bit_length.to_be_bytes());
+
+    for block in blocks
+        .chunks_exact(64)
+        .chain(tail[..tail_len].chunks_exact(64))
+    {
+        sha1_block(&mut state, block);
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn sha1_block(state: &mut [u32; 5], block: &[u8]) {
+    let mut w = [0u32; 80];
+    for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e] = *state;
+    for (i, &word) in w.iter().enumerate() {
+        let (f, k) = match i {
+            0..=19 => (b & c | !b & d, 0x5A82_7999),
+            20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+            40..=59 => (b & c | b & d | c & d, 0x8F1B_BCDC),
+            _ => (b ^ c ^ d, 0xCA62_C1D6),
+        };
+        let temp = a
+            .rotate_left(5)
+            .wrapping_add(f)
+            .wrapping_add(e)
+            .wrapping_add(k)
+            .wrapping_add(word);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+
+    for (word, value) in state.iter_mut().zip([a, b, c, d, e]) {
+        *word = word.wrapping_add(value);
+    }
+}
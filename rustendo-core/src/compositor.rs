@@ -0,0 +1,174 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::palette::{PixelFormat, DEFAULT_PALETTE};
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const PIXELS: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
+
+// Layers in the order they are stacked, bottom first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    Game,
+    Osd,
+    InputDisplay,
+    Debug,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 4] = [Layer::Game, Layer::Osd, Layer::InputDisplay, Layer::Debug];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+struct LayerState {
+    visible: bool,
+    opacity: u8,     // Multiplied with each pixel's own alpha
+    pixels: Vec<u8>, // RGBA, unused for the game layer which comes from the PPU
+}
+
+// Stacks the game frame and the overlay layers into one RGBA image for the front end
+pub struct Compositor {
+    layers: [LayerState; 4],
+    output: Vec<u8>,
+}
+
+impl Default for Compositor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compositor {
+    pub fn new() -> Self {
+        let layer = |pixels| LayerState {
+            visible: true,
+            opacity: 0xFF,
+            pixels,
+        };
+        Self {
+            layers: [
+                layer(Vec::new()),
+                layer(vec![0; PIXELS * 4]),
+                layer(vec![0; PIXELS * 4]),
+                layer(vec![0; PIXELS * 4]),
+            ],
+            output: vec![0; PIXELS * 4],
+        }
+    }
+
+    pub fn is_visible(&self, layer: Layer) -> bool {
+        self.layers[layer.index()].visible
+    }
+
+    pub fn set_visible(&mut self, layer: Layer, visible: bool) {
+        self.layers[layer.index()].visible = visible;
+    }
+
+    pub fn set_opacity(&mut self, layer: Layer, opacity: u8) {
+        self.layers[layer.index()].opacity = opacity;
+    }
+
+    // Make an overlay layer fully transparent again
+    pub fn clear(&mut self, layer: Layer) {
+        self.layers[layer.index()].pixels.fill(0);
+    }
+
+    pub fn set_pixel(&mut self, layer: Layer, x: usize, y: usize, rgba: [u8; 4]) {
+        if x >= SCREEN_WIDTH || y >= SCREEN_HEIGHT {
+            return;
+        }
+        let pixels = &mut self.layers[layer.index()].pixels;
+        if pixels.is_empty() {
+            return;
+        }
+        let offset = (y * SCREEN_WIDTH + x) * 4;
+        pixels[offset..offset + 4].copy_from_slice(&rgba);
+    }
+
+    pub fn fill_rect(
+        &mut self,
+        layer: Layer,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+        rgba: [u8; 4],
+    ) {
+        for row in y..(y + h).min(SCREEN_HEIGHT) {
+            for column in x..(x + w).min(SCREEN_WIDTH) {
+                self.set_pixel(layer, column, row, rgba);
+            }
+        }
+    }
+
+    // Copy a `w`x`h` RGBA image onto a layer with its top-left corner at (x, y)
+    pub fn blit(&mut self, layer: Layer, x: usize, y: usize, w: usize, h: usize, rgba: &[u8]) {
+        for row in 0..h {
+            for column in 0..w {
+                let offset = (row * w + column) * 4;
+                if let Some(pixel) = rgba.get(offset..offset + 4) {
+                    self.set_pixel(layer, x + column, y + row, pixel.try_into().unwrap());
+                }
+            }
+        }
+    }
+
+    // Blend all visible layers over the game frame and return the RGBA result
+    pub fn compose(&mut self, game: &[u8], format: PixelFormat) -> &[u8] {
+        let size = format.bytes_per_pixel();
+        let game_layer = &self.layers[Layer::Game.index()];
+        for (i, out) in self.output.chunks_exact_mut(4).enumerate() {
+            let pixel = if game_layer.visible {
+                game.get(i * size..(i + 1) * size)
+                    .map_or([0, 0, 0, 0xFF], |bytes| decode(format, bytes))
+            } else {
+                [0, 0, 0, 0xFF]
+            };
+            out.copy_from_slice(&pixel);
+        }
+
+        for layer in &self.layers[1..] {
+            if !layer.visible || layer.opacity == 0 {
+                continue;
+            }
+            for (out, src) in self
+                .output
+                .chunks_exact_mut(4)
+                .zip(layer.pixels.chunks_exact(4))
+            {
+                let alpha = src[3] as u32 * layer.opacity as u32 / 0xFF;
+                if alpha == 0 {
+                    continue;
+                }
+                for channel in 0..3 {
+                    out[channel] = ((src[channel] as u32 * alpha
+                        + out[channel] as u32 * (0xFF - alpha))
+                        / 0xFF) as u8;
+                }
+            }
+        }
+
+        &self.output
+    }
+}
+
+fn decode(format: PixelFormat, bytes: &[u8]) -> [u8; 4] {
+    match format {
+        PixelFormat::Rgba8888 => [bytes[0], bytes[1], bytes[2], 0xFF],
+        PixelFormat::Bgra8888 => [bytes[2], bytes[1], bytes[0], 0xFF],
+        PixelFormat::Rgb565 => {
+            let value = u16::from_le_bytes([bytes[0], bytes[1]]);
+            let r = (value >> 11) as u8 & 0x1F;
+            let g = (value >> 5) as u8 & 0x3F;
+            let b = value as u8 & 0x1F;
+            [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2, 0xFF]
+        }
+        PixelFormat::Indexed => {
+            let [r, g, b] = DEFAULT_PALETTE[bytes[0] as usize & 0x3F];
+            [r, g, b, 0xFF]
+        }
+    }
+}
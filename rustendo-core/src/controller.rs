@@ -0,0 +1,153 @@
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Controller {
+    buttons: [bool; 8], // Button states (A, B, Select, Start, Up, Down, Left, Right)
+    turbo: [bool; 8],   // Buttons held down on autofire, pressed only while `turbo_on`
+    turbo_on: bool,     // Which half of the autofire cycle the current frame is in
+    strobe: bool,       // Strobe state for handling button presses
+    index: usize,       // Current button index for reading button states in a serial manner
+}
+
+impl Default for Controller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Controller {
+    pub fn new() -> Self {
+        Self {
+            buttons: [false; 8],
+            turbo: [false; 8],
+            turbo_on: false,
+            strobe: false,
+            index: 0,
+        }
+    }
+
+    pub fn press_button(&mut self, button: usize) {
+        self.buttons[button] = true;
+    }
+
+    pub fn release_button(&mut self, button: usize) {
+        self.buttons[button] = false;
+    }
+
+    // Hold or let go of a button on autofire, which presses and releases it at the rate set with
+    // `Nes::set_turbo_rate`
+    pub fn set_turbo(&mut self, button: usize, held: bool) {
+        self.turbo[button] = held;
+    }
+
+    // Buttons held on autofire, as a bitmask like `buttons`
+    pub fn turbo(&self) -> u8 {
+        (0..8).fold(0, |mask, button| {
+            mask | (self.turbo[button] as u8) << button
+        })
+    }
+
+    pub(crate) fn set_turbo_on(&mut self, on: bool) {
+        self.turbo_on = on;
+    }
+
+    // Take the shift register's position from a save state. The buttons stay as the player is
+    // holding them now.
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore(&mut self, saved: &Controller) {
+        self.turbo_on = saved.turbo_on;
+        self.strobe = saved.strobe;
+        self.index = saved.index;
+    }
+
+    fn pressed(&self, button: usize) -> bool {
+        self.buttons[button] || self.turbo[button] && self.turbo_on
+    }
+
+    // The buttons the console sees pressed, autofire included, as a bitmask (bit 0 = A ...
+    // bit 7 = Right)
+    pub fn buttons(&self) -> u8 {
+        (0..8).fold(0, |mask, button| {
+            mask | (self.pressed(button) as u8) << button
+        })
+    }
+
+    // Replace all button states at once from a bitmask (bit 0 = A ... bit 7 = Right)
+    pub fn set_buttons(&mut self, buttons: u8) {
+        for (i, button) in self.buttons.iter_mut().enumerate() {
+            *button = buttons & (1 << i) != 0;
+        }
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.strobe = value & 0x01 != 0;
+        if self.strobe {
+            self.index = 0;
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        let button_state = self.peek();
+
+        if self.strobe {
+            self.index = 0;
+        } else {
+            self.index = (self.index + 1).min(self.buttons.len());
+        }
+
+        button_state
+    }
+
+    // The bit the next read returns, without shifting. Once all eight buttons have been
+    // shifted out, standard controllers read back 1s.
+    pub fn peek(&self) -> u8 {
+        match self.index {
+            0..=7 => self.pressed(self.index) as u8,
+            _ => 1,
+        }
+    }
+}
+
+// The NES Four Score / Four Player adapter. Each port shifts out 24 bits: the controller
+// plugged into it (1 or 2), then the one behind it (3 or 4), then a signature that tells games
+// the adapter is there. Reads after that return 1s.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FourScore {
+    strobe: bool,
+    index: [usize; 2], // Bits shifted out of $4016 and $4017
+}
+
+// The signatures, read least significant bit first: the 20th bit on $4016, the 19th on $4017
+const FOUR_SCORE_SIGNATURES: [u8; 2] = [0x08, 0x04];
+
+impl FourScore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.strobe = value & 0x01 != 0;
+        if self.strobe {
+            self.index = [0, 0];
+        }
+    }
+
+    // Read port 0 ($4016) or 1 ($4017), with `near` and `far` the controllers in its chain
+    pub fn read(&mut self, port: usize, near: &Controller, far: &Controller) -> u8 {
+        let bit = self.peek(port, near, far);
+        if !self.strobe {
+            self.index[port] = (self.index[port] + 1).min(24);
+        }
+        bit
+    }
+
+    pub fn peek(&self, port: usize, near: &Controller, far: &Controller) -> u8 {
+        let (byte, bit) = match self.index[port] {
+            _ if self.strobe => (near.buttons(), 0),
+            index @ 0..=7 => (near.buttons(), index),
+            index @ 8..=15 => (far.buttons(), index - 8),
+            index @ 16..=23 => (FOUR_SCORE_SIGNATURES[port], index - 16),
+            _ => return 1,
+        };
+        byte >> bit & 0x01
+    }
+}
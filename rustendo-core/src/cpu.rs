@@ -1,31 +1,78 @@
-use crate::memory::Memory;
-use std::cell::RefCell;
+use crate::cpu_bus::CpuBus;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::fmt;
+use tracing::{debug, trace};
 
 const CARRY_FLAG: u8 = 0b0000_0001;
-pub struct CPU<'a> {
+
+// Everything the CPU keeps between instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Registers {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub pc: u16,
+    pub sp: u8,
+    pub status: u8,
+}
+
+// One line in the style of CPU trace logs: `PC:C000 A:00 X:00 Y:00 P:24 SP:FD`
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+            self.pc, self.a, self.x, self.y, self.status, self.sp
+        )
+    }
+}
+
+pub struct CPU {
     a: u8,                       // Accumulator
     x: u8,                       // X register
     y: u8,                       // Y register
     pc: u16,                     // Program Counter
     sp: u8,                      // Stack Pointer
     status: u8,                  // Status register (flags)
-    memory: &'a RefCell<Memory>, // Reference to the shared Memory struct
+    memory: Rc<RefCell<CpuBus>>, // The CPU bus, shared with Nes
 }
 
-impl<'a> CPU<'a> {
-    pub fn new(memory: &'a RefCell<Memory>) -> Self {
-        println!("{}", memory.borrow().read_word(0xFFFC));
+impl CPU {
+    pub fn new(memory: Rc<RefCell<CpuBus>>) -> Self {
+        let pc = memory.borrow_mut().read_word(0xFFFC);
         Self {
             a: 0,
             x: 0,
             y: 0,
-            pc: memory.borrow().read_word(0xFFFC),
+            pc,
             sp: 0xFD,
             status: 0x24,
             memory,
         }
     }
 
+    pub fn registers(&self) -> Registers {
+        Registers {
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            pc: self.pc,
+            sp: self.sp,
+            status: self.status,
+        }
+    }
+
+    pub fn set_registers(&mut self, registers: Registers) {
+        self.a = registers.a;
+        self.x = registers.x;
+        self.y = registers.y;
+        self.pc = registers.pc;
+        self.sp = registers.sp;
+        self.status = registers.status;
+    }
+
     pub fn reset(&mut self) {
         self.a = 0;
         self.x = 0;
@@ -34,25 +81,18 @@ impl<'a> CPU<'a> {
         self.status = 0x24;
 
         // Fetch the reset vector address from the memory and set the Program Counter
-        self.pc = self.memory.borrow().read_word(0xFFFC);
+        self.pc = self.memory.borrow_mut().read_word(0xFFFC);
+        debug!(target: "cpu", "Reset, starting at {:04X}", self.pc);
     }
 
-    pub fn debug_print(&self) {
-        println!("=== CPU State ===");
-        println!("PC:     {:#06x}", self.pc);
-        println!("A:      {:#04x}", self.a);
-        println!("X:      {:#04x}", self.x);
-        println!("Y:      {:#04x}", self.y);
-        println!("SP:     {:#04x}", self.sp);
-        // println!("Status: {:#010b}", self.status);
-        // println!("  Carry: {}", (self.status & 0b00000001) != 0);
-        // println!("  Zero:  {}", (self.status & 0b00000010) != 0);
-        // println!("  Interrupt Disable: {}", (self.status & 0b00000100) != 0);
-        // println!("  Decimal Mode: {}", (self.status & 0b00001000) != 0);
-        // println!("  Break: {}", (self.status & 0b00010000) != 0);
-        // println!("  Overflow: {}", (self.status & 0b01000000) != 0);
-        // println!("  Negative: {}", (self.status & 0b10000000) != 0);
-        println!("=================");
+    // The reset button: unlike at power-on the registers keep their values, apart from the
+    // stack pointer moving down three as if the PC and status had been pushed
+    pub fn soft_reset(&mut self) -> usize {
+        self.sp = self.sp.wrapping_sub(3);
+        self.status |= 0x04;
+        self.pc = self.memory.borrow_mut().read_word(0xFFFC);
+        debug!(target: "cpu", "Soft reset, starting at {:04X}", self.pc);
+        7
     }
 
     fn update_carry_flag(&mut self, value: bool) {
@@ -105,13 +145,46 @@ impl<'a> CPU<'a> {
         }
     }
 
-    fn branch_ticks(&mut self, old_pc: u16, new_pc: u16) -> u8 {
-        let crossed_page_boundary = (old_pc & 0xFF00) != (new_pc & 0xFF00);
-        if crossed_page_boundary {
-            // Add extra cycle if a page boundary is crossed
-            2
+    // The operand of an Absolute,X or Absolute,Y instruction plus `index`
+    fn absolute_indexed(&mut self, index: u8) -> u16 {
+        let base = self.memory.borrow_mut().read_word(self.pc);
+        self.pc = self.pc.wrapping_add(2);
+        self.indexed(base, index)
+    }
+
+    // The pointer at the zero page operand plus Y, for (Indirect),Y
+    fn indirect_indexed(&mut self) -> u16 {
+        let pointer = self.memory.borrow_mut().read_byte(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        let base = self.memory.borrow_mut().read_word_zero_page(pointer as u16);
+        self.indexed(base, self.y)
+    }
+
+    // When adding the index carries into the high byte, the CPU first reads the address
+    // without the carry, which takes the extra cycle these reads are known for
+    fn indexed(&mut self, base: u16, index: u8) -> u16 {
+        let address = base.wrapping_add(index as u16);
+        if base & 0xFF00 != address & 0xFF00 {
+            self.memory
+                .borrow_mut()
+                .read_byte(base & 0xFF00 | address & 0x00FF);
+        }
+        address
+    }
+
+    // Branches take a cycle more when taken, and another when that lands on a different page
+    fn branch(&mut self, taken: bool) -> usize {
+        let offset = self.memory.borrow_mut().read_byte(self.pc) as i8;
+        self.pc = self.pc.wrapping_add(1);
+        if !taken {
+            return 2;
+        }
+        let old_pc = self.pc;
+        self.pc = self.pc.wrapping_add(offset as u16);
+        if old_pc & 0xFF00 != self.pc & 0xFF00 {
+            4
         } else {
-            1
+            3
         }
     }
 
@@ -126,18 +199,14 @@ impl<'a> CPU<'a> {
         self.a = temp as u8;
     }
 
+    // A + !value + C: a set carry means no borrow
     fn sbc(&mut self, value: u8) {
-        let carry = if self.status & 0x01 == 1 { 0 } else { 1 };
-        let result = self.a as u16 + ((!value) & 0xFF) as u16 + carry as u16;
-        self.set_carry_flag(result > 0xFF);
-        self.set_overflow_flag((self.a as u16 ^ result) & (value as u16 ^ result) & 0x80 != 0);
-        self.a = result as u8;
-        self.update_zero_and_negative_flags(self.a);
+        self.adc(!value);
     }
 
     fn ror(&mut self, value: u8) -> u8 {
-        let carry = (value & 1) << 7;
-        let result = (value >> 1) | carry;
+        let result = (value >> 1) | (self.status & CARRY_FLAG) << 7;
+        self.set_carry_flag(value & 0x01 != 0);
         self.update_zero_and_negative_flags(result);
         result
     }
@@ -189,7 +258,7 @@ impl<'a> CPU<'a> {
 
     fn pop_byte_from_stack(&mut self) -> u8 {
         self.sp = self.sp.wrapping_add(1);
-        self.memory.borrow().read_byte(0x0100 | self.sp as u16)
+        self.memory.borrow_mut().read_byte(0x0100 | self.sp as u16)
     }
 
     fn push_word_to_stack(&mut self, value: u16) {
@@ -205,43 +274,71 @@ impl<'a> CPU<'a> {
 
     fn pop_word_from_stack(&mut self) -> u16 {
         self.sp = self.sp.wrapping_add(1);
-        let low_byte = self.memory.borrow().read_byte(0x0100 | self.sp as u16);
+        let low_byte = self.memory.borrow_mut().read_byte(0x0100 | self.sp as u16);
         self.sp = self.sp.wrapping_add(1);
-        let high_byte = self.memory.borrow().read_byte(0x0100 | self.sp as u16);
+        let high_byte = self.memory.borrow_mut().read_byte(0x0100 | self.sp as u16);
         ((high_byte as u16) << 8) | low_byte as u16
     }
 
+    // Service a non-maskable interrupt, returning the cycles it took
+    pub fn nmi(&mut self) -> usize {
+        debug!(target: "cpu", "NMI at {:04X}", self.pc);
+        self.push_word_to_stack(self.pc);
+        self.push_byte_to_stack((self.status & !0x10) | 0x20);
+        self.status |= 0x04;
+        self.pc = self.memory.borrow_mut().read_word(0xFFFA);
+        7
+    }
+
+    // Service a maskable interrupt unless the interrupt disable flag is set, returning the
+    // cycles it took
+    pub fn irq(&mut self) -> usize {
+        if self.status & 0x04 != 0 {
+            return 0;
+        }
+        debug!(target: "cpu", "IRQ at {:04X}", self.pc);
+        self.push_word_to_stack(self.pc);
+        self.push_byte_to_stack((self.status & !0x10) | 0x20);
+        self.status |= 0x04;
+        self.pc = self.memory.borrow_mut().read_word(0xFFFE);
+        7
+    }
+
+    // `execute` has already stepped past the opcode
     fn invalid_opcode(&mut self) {
+        let address = self.pc.wrapping_sub(1);
         panic!(
             "Invalid opcode: 0x{:02X} at 0x{:04X}",
-            self.memory.borrow().read_byte(self.pc),
-            self.pc
+            self.memory.borrow().peek(address),
+            address
         );
     }
 
     pub fn execute(&mut self) -> usize {
-        let opcode = self.memory.borrow().read_byte(self.pc);
-        self.debug_print();
-        println!("opcode: {:#02x}", opcode);
-        println!("");
-        self.pc += 1;
+        let opcode = self.memory.borrow_mut().read_byte(self.pc);
+        trace!(target: "cpu", "{} OP:{:02X}", self.registers(), opcode);
+        self.pc = self.pc.wrapping_add(1);
 
         match opcode {
             0x00 => {
                 // BRK
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 self.push_word_to_stack(self.pc);
                 self.push_byte_to_stack(self.status | 0x10);
                 self.status |= 0x04;
-                self.pc = self.memory.borrow().read_word(0xFFFE);
+                self.pc = self.memory.borrow_mut().read_word(0xFFFE);
                 7
             }
             0x01 => {
                 // ORA Indirect,X
-                let addr = self.memory.borrow().read_byte(self.pc).wrapping_add(self.x) as u16;
-                self.pc += 1;
+                let addr = self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.x) as u16;
+                self.pc = self.pc.wrapping_add(1);
                 let indirect_addr = self.memory.borrow_mut().read_word_zero_page(addr);
-                self.a |= self.memory.borrow().read_byte(indirect_addr);
+                self.a |= self.memory.borrow_mut().read_byte(indirect_addr);
                 self.update_zero_and_negative_flags(self.a);
                 6
             }
@@ -255,22 +352,22 @@ impl<'a> CPU<'a> {
             }
             0x04 => {
                 // NOP Zero Page
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 3
             }
             0x05 => {
                 // ORA Zero Page
-                let addr = self.memory.borrow().read_byte(self.pc) as u16;
-                self.pc += 1;
-                self.a |= self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_byte(self.pc) as u16;
+                self.pc = self.pc.wrapping_add(1);
+                self.a |= self.memory.borrow_mut().read_byte(addr);
                 self.update_zero_and_negative_flags(self.a);
                 3
             }
             0x06 => {
                 // ASL Zero Page
-                let addr = self.memory.borrow().read_byte(self.pc) as u16;
-                self.pc += 1;
-                let mut value = self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_byte(self.pc) as u16;
+                self.pc = self.pc.wrapping_add(1);
+                let mut value = self.memory.borrow_mut().read_byte(addr);
                 self.set_carry_flag(value & 0x80 != 0);
                 value <<= 1;
                 self.memory.borrow_mut().write_byte(addr, value);
@@ -288,8 +385,8 @@ impl<'a> CPU<'a> {
             }
             0x09 => {
                 // ORA Immediate
-                self.a |= self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                self.a |= self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 self.update_zero_and_negative_flags(self.a);
                 2
             }
@@ -306,22 +403,22 @@ impl<'a> CPU<'a> {
             }
             0x0C => {
                 // NOP Absolute
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
                 4
             }
             0x0D => {
                 // ORA Absolute
-                let addr = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                self.a |= self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                self.a |= self.memory.borrow_mut().read_byte(addr);
                 self.update_zero_and_negative_flags(self.a);
                 4
             }
             0x0E => {
                 // ASL Absolute
-                let addr = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                let mut value = self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                let mut value = self.memory.borrow_mut().read_byte(addr);
                 self.set_carry_flag(value & 0x80 != 0);
                 value <<= 1;
                 self.memory.borrow_mut().write_byte(addr, value);
@@ -334,28 +431,13 @@ impl<'a> CPU<'a> {
             }
             0x10 => {
                 // BPL (Branch if Positive)
-                let offset = self.memory.borrow().read_byte(self.pc) as i8;
-                self.pc += 1;
-                if self.status & 0x80 == 0 {
-                    let old_pc = self.pc;
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                    if (old_pc & 0xFF00) != (self.pc & 0xFF00) {
-                        // Add an extra cycle if a page boundary is crossed
-                        return 3;
-                    }
-                }
-                2
+                self.branch(self.status & 0x80 == 0)
             }
             0x11 => {
                 // ORA Indirect,Y
-                let base_addr = self.memory.borrow().read_byte(self.pc) as u16;
-                self.pc += 1;
-                let addr = self
-                    .memory
-                    .borrow_mut()
-                    .read_word_zero_page(base_addr)
-                    .wrapping_add(self.y as u16);
-                self.a |= self.memory.borrow().read_byte(addr);
+                let address = self.indirect_indexed();
+                let value = self.memory.borrow_mut().read_byte(address);
+                self.a |= value;
                 self.update_zero_and_negative_flags(self.a);
                 5
             }
@@ -369,22 +451,30 @@ impl<'a> CPU<'a> {
             }
             0x14 => {
                 // NOP Zero Page,X
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 4
             }
             0x15 => {
                 // ORA Zero Page,X
-                let addr = (self.memory.borrow().read_byte(self.pc).wrapping_add(self.x)) as u16;
-                self.pc += 1;
-                self.a |= self.memory.borrow().read_byte(addr);
+                let addr = (self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.x)) as u16;
+                self.pc = self.pc.wrapping_add(1);
+                self.a |= self.memory.borrow_mut().read_byte(addr);
                 self.update_zero_and_negative_flags(self.a);
                 4
             }
             0x16 => {
                 // ASL Zero Page,X
-                let addr = (self.memory.borrow().read_byte(self.pc).wrapping_add(self.x)) as u16;
-                self.pc += 1;
-                let mut value = self.memory.borrow().read_byte(addr);
+                let addr = (self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.x)) as u16;
+                self.pc = self.pc.wrapping_add(1);
+                let mut value = self.memory.borrow_mut().read_byte(addr);
                 self.set_carry_flag(value & 0x80 != 0);
                 value <<= 1;
                 self.memory.borrow_mut().write_byte(addr, value);
@@ -402,13 +492,9 @@ impl<'a> CPU<'a> {
             }
             0x19 => {
                 // ORA Absolute,Y
-                let addr = self
-                    .memory
-                    .borrow()
-                    .read_word(self.pc)
-                    .wrapping_add(self.y as u16);
-                self.pc += 2;
-                self.a |= self.memory.borrow().read_byte(addr);
+                let address = self.absolute_indexed(self.y);
+                let value = self.memory.borrow_mut().read_byte(address);
+                self.a |= value;
                 self.update_zero_and_negative_flags(self.a);
                 4
             }
@@ -422,18 +508,14 @@ impl<'a> CPU<'a> {
             }
             0x1C => {
                 // NOP Absolute,X
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
                 4
             }
             0x1D => {
                 // ORA Absolute,X
-                let addr = self
-                    .memory
-                    .borrow()
-                    .read_word(self.pc)
-                    .wrapping_add(self.x as u16);
-                self.pc += 2;
-                self.a |= self.memory.borrow().read_byte(addr);
+                let address = self.absolute_indexed(self.x);
+                let value = self.memory.borrow_mut().read_byte(address);
+                self.a |= value;
                 self.update_zero_and_negative_flags(self.a);
                 4
             }
@@ -441,11 +523,11 @@ impl<'a> CPU<'a> {
                 // ASL Absolute,X
                 let addr = self
                     .memory
-                    .borrow()
+                    .borrow_mut()
                     .read_word(self.pc)
                     .wrapping_add(self.x as u16);
-                self.pc += 2;
-                let mut value = self.memory.borrow().read_byte(addr);
+                self.pc = self.pc.wrapping_add(2);
+                let mut value = self.memory.borrow_mut().read_byte(addr);
                 self.set_carry_flag(value & 0x80 != 0);
                 value <<= 1;
                 self.memory.borrow_mut().write_byte(addr, value);
@@ -458,19 +540,23 @@ impl<'a> CPU<'a> {
             }
             0x20 => {
                 // JSR (Jump to Subroutine)
-                let target_addr = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                self.push_byte_to_stack(((self.pc - 1) >> 8) as u8);
-                self.push_byte_to_stack((self.pc - 1) as u8);
+                let target_addr = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                self.push_byte_to_stack((self.pc.wrapping_sub(1) >> 8) as u8);
+                self.push_byte_to_stack(self.pc.wrapping_sub(1) as u8);
                 self.pc = target_addr;
                 6
             }
             0x21 => {
                 // AND Indirect,X
-                let base_addr = self.memory.borrow().read_byte(self.pc).wrapping_add(self.x) as u16;
-                self.pc += 1;
+                let base_addr = self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.x) as u16;
+                self.pc = self.pc.wrapping_add(1);
                 let addr = self.memory.borrow_mut().read_word_zero_page(base_addr);
-                self.a &= self.memory.borrow().read_byte(addr);
+                self.a &= self.memory.borrow_mut().read_byte(addr);
                 self.update_zero_and_negative_flags(self.a);
                 6
             }
@@ -486,9 +572,9 @@ impl<'a> CPU<'a> {
             }
             0x24 => {
                 // BIT Zero Page
-                let addr = self.memory.borrow().read_byte(self.pc) as u16;
-                self.pc += 1;
-                let value = self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_byte(self.pc) as u16;
+                self.pc = self.pc.wrapping_add(1);
+                let value = self.memory.borrow_mut().read_byte(addr);
                 self.set_zero_flag((self.a & value) == 0);
                 self.set_overflow_flag(value & 0x40 != 0);
                 self.set_negative_flag(value & 0x80 != 0);
@@ -496,17 +582,17 @@ impl<'a> CPU<'a> {
             }
             0x25 => {
                 // AND Zero Page
-                let addr = self.memory.borrow().read_byte(self.pc) as u16;
-                self.pc += 1;
-                self.a &= self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_byte(self.pc) as u16;
+                self.pc = self.pc.wrapping_add(1);
+                self.a &= self.memory.borrow_mut().read_byte(addr);
                 self.update_zero_and_negative_flags(self.a);
                 3
             }
             0x26 => {
                 // ROL Zero Page
-                let addr = self.memory.borrow().read_byte(self.pc) as u16;
-                self.pc += 1;
-                let mut value = self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_byte(self.pc) as u16;
+                self.pc = self.pc.wrapping_add(1);
+                let mut value = self.memory.borrow_mut().read_byte(addr);
                 let carry = (value & 0x80) != 0;
                 value = (value << 1) | (self.status & 0x01);
                 self.set_carry_flag(carry);
@@ -521,13 +607,13 @@ impl<'a> CPU<'a> {
             0x28 => {
                 // PLP (Pull Processor Status)
                 self.sp = self.sp.wrapping_add(1);
-                self.status = self.memory.borrow().read_byte(0x0100 | self.sp as u16) | 0x20;
+                self.status = self.memory.borrow_mut().read_byte(0x0100 | self.sp as u16) | 0x20;
                 4
             }
             0x29 => {
                 // AND Immediate
-                self.a &= self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                self.a &= self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 self.update_zero_and_negative_flags(self.a);
                 2
             }
@@ -546,9 +632,9 @@ impl<'a> CPU<'a> {
             }
             0x2C => {
                 // BIT Absolute
-                let addr = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                let value = self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                let value = self.memory.borrow_mut().read_byte(addr);
                 self.set_zero_flag((self.a & value) == 0);
                 self.set_overflow_flag(value & 0x40 != 0);
                 self.set_negative_flag(value & 0x80 != 0);
@@ -556,17 +642,17 @@ impl<'a> CPU<'a> {
             }
             0x2D => {
                 // AND Absolute
-                let addr = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                self.a &= self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                self.a &= self.memory.borrow_mut().read_byte(addr);
                 self.update_zero_and_negative_flags(self.a);
                 4
             }
             0x2E => {
                 // ROL Absolute
-                let addr = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                let mut value = self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                let mut value = self.memory.borrow_mut().read_byte(addr);
                 let carry = (value & 0x80) != 0;
                 value = (value << 1) | (self.status & 0x01);
                 self.set_carry_flag(carry);
@@ -581,56 +667,52 @@ impl<'a> CPU<'a> {
             }
             0x30 => {
                 // BMI (Branch if Minus)
-                let offset = self.memory.borrow().read_byte(self.pc) as i8;
-                self.pc += 1;
-                if self.status & 0x80 != 0 {
-                    let old_pc = self.pc;
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                    self.branch_ticks(old_pc, self.pc);
-                }
-                2
+                self.branch(self.status & 0x80 != 0)
             }
             0x31 => {
                 // AND Indirect,Y
-                let base_addr = self.memory.borrow().read_byte(self.pc) as u16;
-                self.pc += 1;
-                let addr = self
-                    .memory
-                    .borrow_mut()
-                    .read_word_zero_page(base_addr)
-                    .wrapping_add(self.y as u16);
-                self.a &= self.memory.borrow().read_byte(addr);
+                let address = self.indirect_indexed();
+                let value = self.memory.borrow_mut().read_byte(address);
+                self.a &= value;
                 self.update_zero_and_negative_flags(self.a);
                 5
             }
             0x32 => {
                 // Future Extension / Unofficial Opcode
-                self.invalid_opcode()
+                self.invalid_opcode();
                 2
             }
             0x33 => {
                 // Unofficial Opcode
-                self.invalid_opcode()
+                self.invalid_opcode();
                 8
             }
             0x34 => {
                 // Unofficial Opcode
-                self.invalid_opcode()
+                self.invalid_opcode();
                 4
             }
             0x35 => {
                 // AND Zero Page,X
-                let addr = (self.memory.borrow().read_byte(self.pc).wrapping_add(self.x)) as u16;
-                self.pc += 1;
-                self.a &= self.memory.borrow().read_byte(addr);
+                let addr = (self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.x)) as u16;
+                self.pc = self.pc.wrapping_add(1);
+                self.a &= self.memory.borrow_mut().read_byte(addr);
                 self.update_zero_and_negative_flags(self.a);
                 4
             }
             0x36 => {
                 // ROL Zero Page,X
-                let addr = (self.memory.borrow().read_byte(self.pc).wrapping_add(self.x)) as u16;
-                self.pc += 1;
-                let mut value = self.memory.borrow().read_byte(addr);
+                let addr = (self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.x)) as u16;
+                self.pc = self.pc.wrapping_add(1);
+                let mut value = self.memory.borrow_mut().read_byte(addr);
                 let carry = (value & 0x80) != 0;
                 value = (value << 1) | (self.status & 0x01);
                 self.set_carry_flag(carry);
@@ -640,7 +722,7 @@ impl<'a> CPU<'a> {
             }
             0x37 => {
                 // Unofficial Opcode
-                self.invalid_opcode()
+                self.invalid_opcode();
                 6
             }
             0x38 => {
@@ -650,103 +732,97 @@ impl<'a> CPU<'a> {
             }
             0x39 => {
                 // AND Absolute,Y
-                let addr = self
-                    .memory
-                    .borrow()
-                    .read_word(self.pc)
-                    .wrapping_add(self.y as u16);
-                self.pc += 2;
-                self.a &= self.memory.borrow().read_byte(addr);
+                let address = self.absolute_indexed(self.y);
+                let value = self.memory.borrow_mut().read_byte(address);
+                self.a &= value;
                 self.update_zero_and_negative_flags(self.a);
                 4
             }
             0x3A => {
                 // Future Extension / Unofficial Opcode
-                self.invalid_opcode()
+                self.invalid_opcode();
                 2
             }
             0x3B => {
                 // Unofficial Opcode
-                self.invalid_opcode()
+                self.invalid_opcode();
                 7
             }
             0x3C => {
                 // Unofficial Opcode
-                self.invalid_opcode()
+                self.invalid_opcode();
                 4
             }
             0x3D => {
                 // AND Absolute,X
-                let addr = self
-                    .memory
-                    .borrow()
-                    .read_word(self.pc)
-                    .wrapping_add(self.x as u16);
-                self.pc += 2;
-                self.a &= self.memory.borrow().read_byte(addr);
+                let address = self.absolute_indexed(self.x);
+                let value = self.memory.borrow_mut().read_byte(address);
+                self.a &= value;
                 self.update_zero_and_negative_flags(self.a);
                 4
             }
             0x3E => {
                 // ROL (Rotate Left) - Absolute,X
-                let addr = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
+                let addr = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
                 let address = addr.wrapping_add(self.x as u16);
-                let value = self.memory.borrow().read_byte(address);
+                let value = self.memory.borrow_mut().read_byte(address);
                 let result = self.rotate_left(value);
                 self.memory.borrow_mut().write_byte(address, result);
                 7
             }
             0x3F => {
                 // Unofficial Opcode
-                self.invalid_opcode()
+                self.invalid_opcode();
                 7
             }
             0x40 => {
                 // RTI (Return from Interrupt)
                 self.status = self.pop_byte_from_stack() | 0x20;
-                let lo = self.pop_byte_from_stack() as u16;
-                let hi = self.pop_byte_from_stack() as u16;
-                self.pc = hi << 8 | lo;
+                self.pc = self.pop_word_from_stack();
                 6
             }
             0x41 => {
                 // EOR Indirect,X
-                let base_addr = self.memory.borrow().read_byte(self.pc).wrapping_add(self.x) as u16;
-                self.pc += 1;
+                let base_addr = self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.x) as u16;
+                self.pc = self.pc.wrapping_add(1);
                 let addr = self.memory.borrow_mut().read_word_zero_page(base_addr);
-                self.a ^= self.memory.borrow().read_byte(addr);
+                self.a ^= self.memory.borrow_mut().read_byte(addr);
                 self.update_zero_and_negative_flags(self.a);
                 6
             }
             0x42 => {
                 // Future Extension / Unofficial Opcode
-                self.invalid_opcode()
+                self.invalid_opcode();
                 2
             }
             0x43 => {
                 // Unofficial Opcode
-                self.invalid_opcode()
+                self.invalid_opcode();
                 8
             }
             0x44 => {
                 // Unofficial Opcode
-                self.invalid_opcode()
+                self.invalid_opcode();
                 3
             }
             0x45 => {
                 // EOR Zero Page
-                let addr = self.memory.borrow().read_byte(self.pc) as u16;
-                self.pc += 1;
-                self.a ^= self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_byte(self.pc) as u16;
+                self.pc = self.pc.wrapping_add(1);
+                self.a ^= self.memory.borrow_mut().read_byte(addr);
                 self.update_zero_and_negative_flags(self.a);
                 3
             }
             0x46 => {
                 // LSR Zero Page
-                let addr = self.memory.borrow().read_byte(self.pc) as u16;
-                self.pc += 1;
-                let mut value = self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_byte(self.pc) as u16;
+                self.pc = self.pc.wrapping_add(1);
+                let mut value = self.memory.borrow_mut().read_byte(addr);
                 self.set_carry_flag(value & 0x01 != 0);
                 value >>= 1;
                 self.memory.borrow_mut().write_byte(addr, value);
@@ -755,7 +831,7 @@ impl<'a> CPU<'a> {
             }
             0x47 => {
                 // Unofficial Opcode
-                self.invalid_opcode()
+                self.invalid_opcode();
                 5
             }
             0x48 => {
@@ -765,8 +841,8 @@ impl<'a> CPU<'a> {
             }
             0x49 => {
                 // EOR Immediate
-                self.a ^= self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                self.a ^= self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 self.update_zero_and_negative_flags(self.a);
                 2
             }
@@ -775,32 +851,32 @@ impl<'a> CPU<'a> {
                 self.set_carry_flag(self.a & 0x01 != 0);
                 self.a >>= 1;
                 self.update_zero_and_negative_flags(self.a);
-                7
+                2
             }
             0x4B => {
                 // Unofficial Opcode
-                self.invalid_opcode()
+                self.invalid_opcode();
                 2
             }
             0x4C => {
                 // JMP Absolute
-                let addr = self.memory.borrow().read_word(self.pc);
+                let addr = self.memory.borrow_mut().read_word(self.pc);
                 self.pc = addr;
                 3
             }
             0x4D => {
                 // EOR Absolute
-                let addr = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                self.a ^= self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                self.a ^= self.memory.borrow_mut().read_byte(addr);
                 self.update_zero_and_negative_flags(self.a);
                 4
             }
             0x4E => {
                 // LSR Absolute
-                let addr = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                let mut value = self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                let mut value = self.memory.borrow_mut().read_byte(addr);
                 self.set_carry_flag(value & 0x01 != 0);
                 value >>= 1;
                 self.memory.borrow_mut().write_byte(addr, value);
@@ -809,256 +885,246 @@ impl<'a> CPU<'a> {
             }
             0x4F => {
                 // Unofficial Opcode
-                self.invalid_opcode()
+                self.invalid_opcode();
                 6
             }
             0x50 => {
                 // BVC (Branch if Overflow Clear)
-                let offset = self.memory.borrow().read_byte(self.pc) as i8;
-                self.pc += 1;
-                if self.status & 0x40 == 0 {
-                    let old_pc = self.pc;
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                    self.branch_ticks(old_pc, self.pc);
-                    // Add the additional cycles to the cycle count
-                }
-                2
+                self.branch(self.status & 0x40 == 0)
             }
             0x51 => {
                 // EOR (Exclusive OR) - (Indirect), Y
-                let base = self.memory.borrow().read_byte(self.pc);
-                let addr = self
-                    .memory
-                    .borrow_mut()
-                    .read_word_zero_page(base as u16)
-                    .wrapping_add(self.y as u16);
-                let value = self.memory.borrow().read_byte(addr);
+                let address = self.indirect_indexed();
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.a ^= value;
                 self.update_zero_and_negative_flags(self.a);
-                self.pc += 1;
-                // Add 5 cycles (+1 if page crossed)
                 5
             }
             0x55 => {
                 // EOR (Exclusive OR) - Zero Page, X
-                let addr = (self.memory.borrow().read_byte(self.pc) + self.x) as u16;
-                let value = self.memory.borrow().read_byte(addr);
+                let addr = self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.x) as u16;
+                let value = self.memory.borrow_mut().read_byte(addr);
                 self.a ^= value;
                 self.update_zero_and_negative_flags(self.a);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 // Add 4 cycles
                 4
             }
             0x56 => {
                 // LSR (Logical Shift Right) - Zero Page, X
-                let addr = (self.memory.borrow().read_byte(self.pc) + self.x) as u16;
-                let value = self.memory.borrow().read_byte(addr);
+                let addr = self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.x) as u16;
+                let value = self.memory.borrow_mut().read_byte(addr);
                 self.set_carry_flag(value & 1 != 0);
                 let result = value >> 1;
                 self.memory.borrow_mut().write_byte(addr, result);
                 self.update_zero_and_negative_flags(result);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 // Add 6 cycles
                 6
             }
             0x58 => {
                 // CLI (Clear Interrupt Disable)
                 self.status &= !0x04;
-                self.pc += 1;
                 // Add 2 cycles
                 2
             }
             0x59 => {
                 // EOR (Exclusive OR) - Absolute, Y
-                let lo = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let hi = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let addr = ((hi as u16) << 8 | (lo as u16)).wrapping_add(self.y as u16);
-                let value = self.memory.borrow().read_byte(addr);
+                let address = self.absolute_indexed(self.y);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.a ^= value;
                 self.update_zero_and_negative_flags(self.a);
-                // Add 4 cycles (+1 if page crossed)
                 4
             }
             0x5D => {
                 // EOR (Exclusive OR) - Absolute, X
-                let lo = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let hi = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let addr = ((hi as u16) << 8 | (lo as u16)).wrapping_add(self.x as u16);
-                let value = self.memory.borrow().read_byte(addr);
+                let address = self.absolute_indexed(self.x);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.a ^= value;
                 self.update_zero_and_negative_flags(self.a);
-                // Add 4 cycles (+1 if page crossed)
                 4
             }
+            0x5E => {
+                // LSR (Logical Shift Right) - Absolute,X
+                let base = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                let address = base.wrapping_add(self.x as u16);
+                let value = self.memory.borrow_mut().read_byte(address);
+                self.set_carry_flag(value & 0x01 != 0);
+                let result = value >> 1;
+                self.memory.borrow_mut().write_byte(address, result);
+                self.update_zero_and_negative_flags(result);
+                7
+            }
             0x60 => {
                 // RTS (Return from Subroutine)
                 let lo = self.pop_byte_from_stack();
                 let hi = self.pop_byte_from_stack();
                 self.pc = (hi as u16) << 8 | (lo as u16);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 6
             }
             0x61 => {
                 // ADC (Add with Carry) - (Indirect, X)
-                let base = self.memory.borrow().read_byte(self.pc).wrapping_add(self.x);
+                let base = self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.x);
                 let addr = self.memory.borrow_mut().read_word_zero_page(base as u16);
-                let value = self.memory.borrow().read_byte(addr);
+                let value = self.memory.borrow_mut().read_byte(addr);
                 self.adc(value);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 6
             }
             0x65 => {
                 // ADC (Add with Carry) - Zero Page
-                let addr = self.memory.borrow().read_byte(self.pc) as u16;
-                let value = self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_byte(self.pc) as u16;
+                let value = self.memory.borrow_mut().read_byte(addr);
                 self.adc(value);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 3
             }
             0x66 => {
                 // ROR (Rotate Right) - Zero Page
-                let addr = self.memory.borrow().read_byte(self.pc) as u16;
-                let value = self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_byte(self.pc) as u16;
+                let value = self.memory.borrow_mut().read_byte(addr);
                 let carry = (value & 1) != 0;
-                let result = (value >> 1) | ((self.status as u8 & 0x01) << 7);
+                let result = (value >> 1) | ((self.status & 0x01) << 7);
                 self.memory.borrow_mut().write_byte(addr, result);
                 self.set_carry_flag(carry);
                 self.update_zero_and_negative_flags(result);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 5
             }
             0x68 => {
                 // PLA (Pull Accumulator)
                 self.a = self.pop_byte_from_stack();
                 self.update_zero_and_negative_flags(self.a);
-                self.pc += 1;
                 4
             }
             0x69 => {
                 // ADC (Add with Carry) - Immediate
-                let value = self.memory.borrow().read_byte(self.pc);
+                let value = self.memory.borrow_mut().read_byte(self.pc);
                 self.adc(value);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 2
             }
             0x6A => {
                 // ROR (Rotate Right) - Accumulator
                 let carry = (self.a & 1) != 0;
-                self.a = (self.a >> 1) | ((self.status as u8 & 0x01) << 7);
+                self.a = (self.a >> 1) | ((self.status & 0x01) << 7);
                 self.set_carry_flag(carry);
                 self.update_zero_and_negative_flags(self.a);
-                self.pc += 1;
                 2
             }
             0x6B => {
                 // ARR (unofficial)
-                let value = self.memory.borrow().read_byte(self.pc);
+                let value = self.memory.borrow_mut().read_byte(self.pc);
                 self.a &= value;
                 self.a = self.a.rotate_right(1);
                 self.update_zero_and_negative_flags(self.a);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 2
             }
             0x6C => {
                 // JMP (Jump) - Indirect
-                let lo = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let hi = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let lo = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let hi = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let ptr = (hi as u16) << 8 | (lo as u16);
-                let addr_lo = self.memory.borrow().read_byte(ptr);
+                let addr_lo = self.memory.borrow_mut().read_byte(ptr);
                 let addr_hi = self
                     .memory
-                    .borrow()
+                    .borrow_mut()
                     .read_byte((ptr & 0xFF00) | ((ptr + 1) & 0xFF));
                 self.pc = (addr_hi as u16) << 8 | (addr_lo as u16);
                 5
             }
             0x6D => {
                 // ADC (Absolute)
-                let addr = self.memory.borrow().read_word(self.pc);
-                let value = self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_word(self.pc);
+                let value = self.memory.borrow_mut().read_byte(addr);
                 self.adc(value);
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
                 4
             }
             0x6E => {
                 // ROR (Rotate Right) Absolute
-                let addr = self.memory.borrow().read_word(self.pc);
-                let value = self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_word(self.pc);
+                let value = self.memory.borrow_mut().read_byte(addr);
                 let result = self.ror(value);
                 self.memory.borrow_mut().write_byte(addr, result);
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
                 6
             }
             0x6F => {
                 // RRA (unofficial)
-                let addr = self.memory.borrow().read_word(self.pc);
-                let value = self.memory.borrow().read_byte(addr);
+                let addr = self.memory.borrow_mut().read_word(self.pc);
+                let value = self.memory.borrow_mut().read_byte(addr);
                 let result = self.ror(value);
                 self.memory.borrow_mut().write_byte(addr, result);
                 self.adc(result);
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
                 6
             }
             0x70 => {
                 // BVS (Branch if Overflow Set)
-                let offset = self.memory.borrow().read_byte(self.pc) as i8;
-                self.pc += 1;
-                if self.status & 0x40 != 0 {
-                    let old_pc = self.pc;
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                    self.branch_ticks(old_pc, self.pc);
-                    // Add the additional cycles to the cycle count
-                }
-                2
+                self.branch(self.status & 0x40 != 0)
             }
             0x71 => {
                 // ADC (Add with Carry) - (Indirect), Y
-                let base = self.memory.borrow().read_byte(self.pc);
-                let addr = self
-                    .memory
-                    .borrow_mut()
-                    .read_word_zero_page(base as u16)
-                    .wrapping_add(self.y as u16);
-                let value = self.memory.borrow().read_byte(addr);
+                let address = self.indirect_indexed();
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.adc(value);
-                self.pc += 1;
                 5
             }
             0x75 => {
                 // ADC (Add with Carry) - Zero Page, X
-                let addr = (self.memory.borrow().read_byte(self.pc) + self.x) as u16;
-                let value = self.memory.borrow().read_byte(addr);
+                let addr = self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.x) as u16;
+                let value = self.memory.borrow_mut().read_byte(addr);
                 self.adc(value);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 4
             }
             0x76 => {
                 // ROR (Rotate Right) - Zero Page, X
-                let addr = (self.memory.borrow().read_byte(self.pc) + self.x) as u16;
-                let value = self.memory.borrow().read_byte(addr);
+                let addr = self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.x) as u16;
+                let value = self.memory.borrow_mut().read_byte(addr);
                 let carry = (value & 1) != 0;
-                let result = (value >> 1) | ((self.status as u8 & 0x01) << 7);
+                let result = (value >> 1) | ((self.status & 0x01) << 7);
                 self.memory.borrow_mut().write_byte(addr, result);
                 self.set_carry_flag(carry);
                 self.update_zero_and_negative_flags(result);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 6
             }
             0x77 => {
                 // RRA (Rotate Right then ADC) - Zero Page,X
-                let base = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let base = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let address = self
                     .memory
                     .borrow_mut()
-                    .read_word_zero_page((base as u16 + self.x as u16) % 0xFF);
-                let value = self.memory.borrow().read_byte(address);
+                    .read_word_zero_page(base.wrapping_add(self.x) as u16);
+                let value = self.memory.borrow_mut().read_byte(address);
                 let rotated_value = self.rotate_right(value);
                 self.memory.borrow_mut().write_byte(address, rotated_value);
                 self.adc(rotated_value);
@@ -1067,64 +1133,68 @@ impl<'a> CPU<'a> {
             0x78 => {
                 // SEI (Set Interrupt Disable)
                 self.status |= 0x04;
-                self.pc += 1;
                 2
             }
             0x79 => {
                 // ADC (Add with Carry) - Absolute, Y
-                let lo = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let hi = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let addr = ((hi as u16) << 8 | (lo as u16)).wrapping_add(self.y as u16);
-                let value = self.memory.borrow().read_byte(addr);
+                let address = self.absolute_indexed(self.y);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.adc(value);
-
                 4
             }
             0x7D => {
                 // ADC (Add with Carry) - Absolute, X
-                let lo = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let hi = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let addr = ((hi as u16) << 8 | (lo as u16)).wrapping_add(self.x as u16);
-                let value = self.memory.borrow().read_byte(addr);
+                let address = self.absolute_indexed(self.x);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.adc(value);
                 4
             }
+            0x7E => {
+                // ROR (Rotate Right) - Absolute,X
+                let base = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                let address = base.wrapping_add(self.x as u16);
+                let value = self.memory.borrow_mut().read_byte(address);
+                let result = self.ror(value);
+                self.memory.borrow_mut().write_byte(address, result);
+                7
+            }
             0x80 => {
                 // NOP (No Operation) - Immediate
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 2
             }
             0x81 => {
                 // STA (Store Accumulator) - (Indirect, X)
-                let base = self.memory.borrow().read_byte(self.pc).wrapping_add(self.x);
+                let base = self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.x);
                 let addr = self.memory.borrow_mut().read_word_zero_page(base as u16);
                 self.memory.borrow_mut().write_byte(addr, self.a);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 6
             }
             0x84 => {
                 // STY (Store Y Register) - Zero Page
-                let addr = self.memory.borrow().read_byte(self.pc) as u16;
+                let addr = self.memory.borrow_mut().read_byte(self.pc) as u16;
                 self.memory.borrow_mut().write_byte(addr, self.y);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 3
             }
             0x85 => {
                 // STA (Store Accumulator) - Zero Page
-                let addr = self.memory.borrow().read_byte(self.pc) as u16;
+                let addr = self.memory.borrow_mut().read_byte(self.pc) as u16;
                 self.memory.borrow_mut().write_byte(addr, self.a);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 3
             }
             0x86 => {
                 // STX (Store X Register) - Zero Page
-                let addr = self.memory.borrow().read_byte(self.pc) as u16;
+                let addr = self.memory.borrow_mut().read_byte(self.pc) as u16;
                 self.memory.borrow_mut().write_byte(addr, self.x);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 3
             }
             0x88 => {
@@ -1137,97 +1207,99 @@ impl<'a> CPU<'a> {
                 // TXA (Transfer X to Accumulator)
                 self.a = self.x;
                 self.update_zero_and_negative_flags(self.a);
-                self.pc += 1;
                 2
             }
             0x8C => {
                 // STY (Store Y Register) - Absolute
-                let lo = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let hi = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let lo = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let hi = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let addr = (hi as u16) << 8 | (lo as u16);
                 self.memory.borrow_mut().write_byte(addr, self.y);
                 4
             }
             0x8D => {
                 // STA (Store Accumulator) - Absolute
-                let lo = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let hi = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let lo = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let hi = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let addr = (hi as u16) << 8 | (lo as u16);
                 self.memory.borrow_mut().write_byte(addr, self.a);
                 4
             }
             0x8E => {
                 // STX (Store X Register) - Absolute
-                let lo = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let hi = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let lo = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let hi = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let addr = (hi as u16) << 8 | (lo as u16);
                 self.memory.borrow_mut().write_byte(addr, self.x);
                 4
             }
             0x90 => {
                 // BCC (Branch if Carry Clear)
-                let offset = self.memory.borrow().read_byte(self.pc) as i8;
-                self.pc += 1;
-                if self.status & 0x01 == 0 {
-                    let old_pc = self.pc;
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                    self.branch_ticks(old_pc, self.pc);
-                }
-                // Add 1 cycle if branch not taken, 1 or 2 cycles if taken (depending on same or different page)
-                2
+                self.branch(self.status & 0x01 == 0)
             }
             0x91 => {
                 // STA (Store Accumulator) - (Indirect), Y
-                let base = self.memory.borrow().read_byte(self.pc);
+                let base = self.memory.borrow_mut().read_byte(self.pc);
                 let addr = self
                     .memory
                     .borrow_mut()
                     .read_word_zero_page(base as u16)
                     .wrapping_add(self.y as u16);
                 self.memory.borrow_mut().write_byte(addr, self.a);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 6
             }
             0x94 => {
                 // STY (Store Y Register) - Zero Page, X
-                let addr = (self.memory.borrow().read_byte(self.pc).wrapping_add(self.x)) as u16;
+                let addr = (self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.x)) as u16;
                 self.memory.borrow_mut().write_byte(addr, self.y);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 4
             }
             0x95 => {
                 // STA (Store Accumulator) - Zero Page, X
-                let addr = (self.memory.borrow().read_byte(self.pc).wrapping_add(self.x)) as u16;
+                let addr = (self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.x)) as u16;
                 self.memory.borrow_mut().write_byte(addr, self.a);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 4
             }
             0x96 => {
                 // STX (Store X Register) - Zero Page, Y
-                let addr = (self.memory.borrow().read_byte(self.pc).wrapping_add(self.y)) as u16;
+                let addr = (self
+                    .memory
+                    .borrow_mut()
+                    .read_byte(self.pc)
+                    .wrapping_add(self.y)) as u16;
                 self.memory.borrow_mut().write_byte(addr, self.x);
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
                 4
             }
             0x98 => {
                 // TYA (Transfer Y to Accumulator)
                 self.a = self.y;
                 self.update_zero_and_negative_flags(self.a);
-                self.pc += 1;
                 2
             }
             0x99 => {
                 // STA (Store Accumulator) - Absolute, Y
-                let lo = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let hi = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let lo = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let hi = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let addr = ((hi as u16) << 8 | (lo as u16)).wrapping_add(self.y as u16);
                 self.memory.borrow_mut().write_byte(addr, self.a);
                 5
@@ -1235,15 +1307,14 @@ impl<'a> CPU<'a> {
             0x9A => {
                 // TXS (Transfer X to Stack Pointer)
                 self.sp = self.x;
-                self.pc += 1;
                 2
             }
             0x9D => {
                 // STA (Store Accumulator) - Absolute, X
-                let lo = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let hi = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let lo = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let hi = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let addr = ((hi as u16) << 8 | (lo as u16)).wrapping_add(self.x as u16);
                 self.memory.borrow_mut().write_byte(addr, self.a);
                 5
@@ -1260,27 +1331,27 @@ impl<'a> CPU<'a> {
             }
             0xA0 => {
                 // LDY (Load Y Register) - Immediate
-                self.y = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                self.y = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 self.update_zero_and_negative_flags(self.y);
                 2
             }
             0xA1 => {
                 // LDA (Load Accumulator) - Indirect,X
-                let base = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let base = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let address = self
                     .memory
                     .borrow_mut()
-                    .read_word_zero_page(((base + self.x) % 0xFF) as u16);
-                self.a = self.memory.borrow().read_byte(address);
+                    .read_word_zero_page(base.wrapping_add(self.x) as u16);
+                self.a = self.memory.borrow_mut().read_byte(address);
                 self.update_zero_and_negative_flags(self.a);
                 6
             }
             0xA2 => {
                 // LDX (Load X Register) - Immediate
-                self.x = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                self.x = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 self.update_zero_and_negative_flags(self.x);
                 2
             }
@@ -1291,25 +1362,25 @@ impl<'a> CPU<'a> {
             }
             0xA4 => {
                 // LDY (Load Y Register) - Zero Page
-                let address = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                self.y = self.memory.borrow().read_byte(address as u16);
+                let address = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                self.y = self.memory.borrow_mut().read_byte(address as u16);
                 self.update_zero_and_negative_flags(self.y);
                 3
             }
             0xA5 => {
                 // LDA (Load Accumulator) - Zero Page
-                let address = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                self.a = self.memory.borrow().read_byte(address as u16);
+                let address = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                self.a = self.memory.borrow_mut().read_byte(address as u16);
                 self.update_zero_and_negative_flags(self.a);
                 3
             }
             0xA6 => {
                 // LDX (Load X Register) - Zero Page
-                let address = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                self.x = self.memory.borrow().read_byte(address as u16);
+                let address = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                self.x = self.memory.borrow_mut().read_byte(address as u16);
                 self.update_zero_and_negative_flags(self.x);
                 3
             }
@@ -1326,8 +1397,8 @@ impl<'a> CPU<'a> {
             }
             0xA9 => {
                 // LDA (Load Accumulator) - Immediate
-                self.a = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                self.a = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 self.update_zero_and_negative_flags(self.a);
                 2
             }
@@ -1344,25 +1415,25 @@ impl<'a> CPU<'a> {
             }
             0xAC => {
                 // LDY (Load Y Register) - Absolute
-                let address = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                self.y = self.memory.borrow().read_byte(address);
+                let address = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                self.y = self.memory.borrow_mut().read_byte(address);
                 self.update_zero_and_negative_flags(self.y);
                 4
             }
             0xAD => {
                 // LDA (Load Accumulator) - Absolute
-                let address = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                self.a = self.memory.borrow().read_byte(address);
+                let address = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                self.a = self.memory.borrow_mut().read_byte(address);
                 self.update_zero_and_negative_flags(self.a);
                 4
             }
             0xAE => {
                 // LDX (Load X Register) - Absolute
-                let address = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                self.x = self.memory.borrow().read_byte(address);
+                let address = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                self.x = self.memory.borrow_mut().read_byte(address);
                 self.update_zero_and_negative_flags(self.x);
                 4
             }
@@ -1373,25 +1444,13 @@ impl<'a> CPU<'a> {
             }
             0xB0 => {
                 // BCS (Branch if Carry Set)
-                let offset = self.memory.borrow().read_byte(self.pc) as i8;
-                self.pc += 1;
-                if self.status & 0x01 != 0 {
-                    let old_pc = self.pc;
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                    self.branch_ticks(old_pc, self.pc);
-                }
-                2
+                self.branch(self.status & 0x01 != 0)
             }
             0xB1 => {
                 // LDA (Load Accumulator) - Indirect,Y
-                let base = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let address = self
-                    .memory
-                    .borrow_mut()
-                    .read_word_zero_page(base as u16)
-                    .wrapping_add(self.y as u16);
-                self.a = self.memory.borrow().read_byte(address);
+                let address = self.indirect_indexed();
+                let value = self.memory.borrow_mut().read_byte(address);
+                self.a = value;
                 self.update_zero_and_negative_flags(self.a);
                 5
             }
@@ -1407,28 +1466,28 @@ impl<'a> CPU<'a> {
             }
             0xB4 => {
                 // LDY (Load Y Register) - Zero Page,X
-                let base = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let address = (base + self.x) % 0xFF;
-                self.y = self.memory.borrow().read_byte(address as u16);
+                let base = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let address = base.wrapping_add(self.x);
+                self.y = self.memory.borrow_mut().read_byte(address as u16);
                 self.update_zero_and_negative_flags(self.y);
                 4
             }
             0xB5 => {
                 // LDA (Load Accumulator) - Zero Page,X
-                let base = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let address = (base + self.x) % 0xFF;
-                self.a = self.memory.borrow().read_byte(address as u16);
+                let base = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let address = base.wrapping_add(self.x);
+                self.a = self.memory.borrow_mut().read_byte(address as u16);
                 self.update_zero_and_negative_flags(self.a);
                 4
             }
             0xB6 => {
                 // LDX (Load X Register) - Zero Page,Y
-                let base = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let address = (base + self.y) % 0xFF;
-                self.x = self.memory.borrow().read_byte(address as u16);
+                let base = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let address = base.wrapping_add(self.y);
+                self.x = self.memory.borrow_mut().read_byte(address as u16);
                 self.update_zero_and_negative_flags(self.x);
                 4
             }
@@ -1444,10 +1503,9 @@ impl<'a> CPU<'a> {
             }
             0xB9 => {
                 // LDA (Load Accumulator) - Absolute,Y
-                let base = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                let address = base.wrapping_add(self.y as u16);
-                self.a = self.memory.borrow().read_byte(address);
+                let address = self.absolute_indexed(self.y);
+                let value = self.memory.borrow_mut().read_byte(address);
+                self.a = value;
                 self.update_zero_and_negative_flags(self.a);
                 4
             }
@@ -1464,28 +1522,25 @@ impl<'a> CPU<'a> {
             }
             0xBC => {
                 // LDY (Load Y Register) - Absolute,X
-                let base = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                let address = base.wrapping_add(self.x as u16);
-                self.y = self.memory.borrow().read_byte(address);
+                let address = self.absolute_indexed(self.x);
+                let value = self.memory.borrow_mut().read_byte(address);
+                self.y = value;
                 self.update_zero_and_negative_flags(self.y);
                 4
             }
             0xBD => {
                 // LDA (Load Accumulator) - Absolute,X
-                let base = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                let address = base.wrapping_add(self.x as u16);
-                self.a = self.memory.borrow().read_byte(address);
+                let address = self.absolute_indexed(self.x);
+                let value = self.memory.borrow_mut().read_byte(address);
+                self.a = value;
                 self.update_zero_and_negative_flags(self.a);
                 4
             }
             0xBE => {
                 // LDX (Load X Register) - Absolute,Y
-                let base = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                let address = base.wrapping_add(self.y as u16);
-                self.x = self.memory.borrow().read_byte(address);
+                let address = self.absolute_indexed(self.y);
+                let value = self.memory.borrow_mut().read_byte(address);
+                self.x = value;
                 self.update_zero_and_negative_flags(self.x);
                 4
             }
@@ -1496,20 +1551,20 @@ impl<'a> CPU<'a> {
             }
             0xC0 => {
                 // CPY (Compare Y Register) - Immediate
-                let value = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let value = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 self.compare(self.y, value);
                 2
             }
             0xC1 => {
                 // CMP (Compare Accumulator) - Indirect,X
-                let base = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let base = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let address = self
                     .memory
                     .borrow_mut()
-                    .read_word_zero_page(((base + self.x) % 0xFF) as u16);
-                let value = self.memory.borrow().read_byte(address);
+                    .read_word_zero_page(base.wrapping_add(self.x) as u16);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.compare(self.a, value);
                 6
             }
@@ -1525,27 +1580,27 @@ impl<'a> CPU<'a> {
             }
             0xC4 => {
                 // CPY (Compare Y Register) - Zero Page
-                let address = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let value = self.memory.borrow().read_byte(address as u16);
+                let address = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let value = self.memory.borrow_mut().read_byte(address as u16);
                 self.compare(self.y, value);
-                4
+                3
             }
             0xC5 => {
                 // CMP (Compare Accumulator) - Zero Page
-                let address = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let value = self.memory.borrow().read_byte(address as u16);
+                let address = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let value = self.memory.borrow_mut().read_byte(address as u16);
                 self.compare(self.a, value);
                 3
             }
             0xC6 => {
                 // DEC (Decrement Memory) - Zero Page
-                let address = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let address = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let value = self
                     .memory
-                    .borrow()
+                    .borrow_mut()
                     .read_byte(address as u16)
                     .wrapping_sub(1);
                 self.memory.borrow_mut().write_byte(address as u16, value);
@@ -1565,8 +1620,8 @@ impl<'a> CPU<'a> {
             }
             0xC9 => {
                 // CMP (Compare Accumulator) - Immediate
-                let value = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let value = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 self.compare(self.a, value);
                 2
             }
@@ -1583,25 +1638,25 @@ impl<'a> CPU<'a> {
             }
             0xCC => {
                 // CPY (Compare Y Register) - Absolute
-                let address = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                let value = self.memory.borrow().read_byte(address);
+                let address = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.compare(self.y, value);
                 4
             }
             0xCD => {
                 // CMP (Compare Accumulator) - Absolute
-                let address = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                let value = self.memory.borrow().read_byte(address);
+                let address = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.compare(self.a, value);
                 4
             }
             0xCE => {
                 // DEC (Decrement Memory) - Absolute
-                let address = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                let value = self.memory.borrow().read_byte(address).wrapping_sub(1);
+                let address = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                let value = self.memory.borrow_mut().read_byte(address).wrapping_sub(1);
                 self.memory.borrow_mut().write_byte(address, value);
                 self.update_zero_and_negative_flags(value);
                 6
@@ -1613,25 +1668,12 @@ impl<'a> CPU<'a> {
             }
             0xD0 => {
                 // BNE (Branch if Not Equal)
-                let offset = self.memory.borrow().read_byte(self.pc) as i8;
-                self.pc += 1;
-                if self.status & 0x02 == 0 {
-                    let old_pc = self.pc;
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                    self.branch_ticks(old_pc, self.pc);
-                }
-                2
+                self.branch(self.status & 0x02 == 0)
             }
             0xD1 => {
                 // CMP (Compare Accumulator) - Indirect,Y
-                let base = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let address = self
-                    .memory
-                    .borrow_mut()
-                    .read_word_zero_page(base as u16)
-                    .wrapping_add(self.y as u16);
-                let value = self.memory.borrow().read_byte(address);
+                let address = self.indirect_indexed();
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.compare(self.a, value);
                 5
             }
@@ -1652,21 +1694,21 @@ impl<'a> CPU<'a> {
             }
             0xD5 => {
                 // CMP (Compare Accumulator) - Zero Page,X
-                let base = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let address = (base + self.x) % 0xFF;
-                let value = self.memory.borrow().read_byte(address as u16);
+                let base = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let address = base.wrapping_add(self.x);
+                let value = self.memory.borrow_mut().read_byte(address as u16);
                 self.compare(self.a, value);
                 4
             }
             0xD6 => {
                 // DEC (Decrement Memory) - Zero Page,X
-                let base = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let address = (base + self.x) % 0xFF;
+                let base = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let address = base.wrapping_add(self.x);
                 let value = self
                     .memory
-                    .borrow()
+                    .borrow_mut()
                     .read_byte(address as u16)
                     .wrapping_sub(1);
                 self.memory.borrow_mut().write_byte(address as u16, value);
@@ -1685,10 +1727,8 @@ impl<'a> CPU<'a> {
             }
             0xD9 => {
                 // CMP (Compare Accumulator) - Absolute,Y
-                let base = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                let address = base.wrapping_add(self.y as u16);
-                let value = self.memory.borrow().read_byte(address);
+                let address = self.absolute_indexed(self.y);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.compare(self.a, value);
                 4
             }
@@ -1702,6 +1742,13 @@ impl<'a> CPU<'a> {
                 self.invalid_opcode();
                 7
             }
+            0xDD => {
+                // CMP (Compare Accumulator) - Absolute,X
+                let address = self.absolute_indexed(self.x);
+                let value = self.memory.borrow_mut().read_byte(address);
+                self.compare(self.a, value);
+                4
+            }
             0xDC => {
                 // Invalid opcode
                 self.invalid_opcode();
@@ -1709,10 +1756,10 @@ impl<'a> CPU<'a> {
             }
             0xDE => {
                 // DEC (Decrement Memory) - Absolute,X
-                let base = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
+                let base = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
                 let address = base.wrapping_add(self.x as u16);
-                let value = self.memory.borrow().read_byte(address).wrapping_sub(1);
+                let value = self.memory.borrow_mut().read_byte(address).wrapping_sub(1);
                 self.memory.borrow_mut().write_byte(address, value);
                 self.update_zero_and_negative_flags(value);
                 7
@@ -1724,20 +1771,20 @@ impl<'a> CPU<'a> {
             }
             0xE0 => {
                 // CPX (Compare X Register) - Immediate
-                let value = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let value = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 self.compare(self.x, value);
                 2
             }
             0xE1 => {
                 // SBC (Subtract with Carry) - Indexed Indirect,X
-                let base = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let base = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let address = self
                     .memory
                     .borrow_mut()
-                    .read_word_zero_page(((base + self.x) % 0xFF) as u16);
-                let value = self.memory.borrow().read_byte(address);
+                    .read_word_zero_page(base.wrapping_add(self.x) as u16);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.sbc(value);
                 6
             }
@@ -1753,25 +1800,25 @@ impl<'a> CPU<'a> {
             }
             0xE4 => {
                 // CPX (Compare X Register) - Zero Page
-                let address = self.memory.borrow().read_byte(self.pc) as u16;
-                self.pc += 1;
-                let value = self.memory.borrow().read_byte(address);
+                let address = self.memory.borrow_mut().read_byte(self.pc) as u16;
+                self.pc = self.pc.wrapping_add(1);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.compare(self.x, value);
                 3
             }
             0xE5 => {
                 // SBC (Subtract with Carry) - Zero Page
-                let address = self.memory.borrow().read_byte(self.pc) as u16;
-                self.pc += 1;
-                let value = self.memory.borrow().read_byte(address);
+                let address = self.memory.borrow_mut().read_byte(self.pc) as u16;
+                self.pc = self.pc.wrapping_add(1);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.sbc(value);
                 3
             }
             0xE6 => {
                 // INC (Increment Memory) - Zero Page
-                let address = self.memory.borrow().read_byte(self.pc) as u16;
-                self.pc += 1;
-                let value = self.memory.borrow().read_byte(address).wrapping_add(1);
+                let address = self.memory.borrow_mut().read_byte(self.pc) as u16;
+                self.pc = self.pc.wrapping_add(1);
+                let value = self.memory.borrow_mut().read_byte(address).wrapping_add(1);
                 self.memory.borrow_mut().write_byte(address, value);
                 self.update_zero_and_negative_flags(value);
                 5
@@ -1789,8 +1836,8 @@ impl<'a> CPU<'a> {
             }
             0xE9 => {
                 // SBC (Subtract with Carry) - Immediate
-                let value = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let value = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 self.sbc(value);
                 2
             }
@@ -1805,25 +1852,25 @@ impl<'a> CPU<'a> {
             }
             0xEC => {
                 // CPX (Compare X Register) - Absolute
-                let address = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                let value = self.memory.borrow().read_byte(address);
+                let address = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.compare(self.x, value);
                 4
             }
             0xED => {
                 // SBC (Subtract with Carry) - Absolute
-                let address = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                let value = self.memory.borrow().read_byte(address);
+                let address = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.sbc(value);
                 4
             }
             0xEE => {
                 // INC (Increment Memory) - Absolute
-                let address = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
-                let value = self.memory.borrow().read_byte(address).wrapping_add(1);
+                let address = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+                let value = self.memory.borrow_mut().read_byte(address).wrapping_add(1);
                 self.memory.borrow_mut().write_byte(address, value);
                 self.update_zero_and_negative_flags(value);
                 6
@@ -1835,25 +1882,12 @@ impl<'a> CPU<'a> {
             }
             0xF0 => {
                 // BEQ (Branch if Equal)
-                let offset = self.memory.borrow().read_byte(self.pc) as i8;
-                self.pc += 1;
-                if self.status & 0x02 != 0 {
-                    let old_pc = self.pc;
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                    self.branch_ticks(old_pc, self.pc);
-                }
-                2
+                self.branch(self.status & 0x02 != 0)
             }
             0xF1 => {
                 // SBC (Subtract with Carry) - Indirect Indexed,Y
-                let base = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
-                let address = self
-                    .memory
-                    .borrow_mut()
-                    .read_word_zero_page(base as u16)
-                    .wrapping_add(self.y as u16);
-                let value = self.memory.borrow().read_byte(address);
+                let address = self.indirect_indexed();
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.sbc(value);
                 5
             }
@@ -1874,19 +1908,19 @@ impl<'a> CPU<'a> {
             }
             0xF5 => {
                 // SBC (Subtract with Carry) - Zero Page,X
-                let base = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let base = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let address = (base.wrapping_add(self.x)) as u16;
-                let value = self.memory.borrow().read_byte(address);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.sbc(value);
                 4
             }
             0xF6 => {
                 // INC (Increment Memory) - Zero Page,X
-                let base = self.memory.borrow().read_byte(self.pc);
-                self.pc += 1;
+                let base = self.memory.borrow_mut().read_byte(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let address = (base.wrapping_add(self.x)) as u16;
-                let value = self.memory.borrow().read_byte(address).wrapping_add(1);
+                let value = self.memory.borrow_mut().read_byte(address).wrapping_add(1);
                 self.memory.borrow_mut().write_byte(address, value);
                 self.update_zero_and_negative_flags(value);
                 6
@@ -1903,13 +1937,8 @@ impl<'a> CPU<'a> {
             }
             0xF9 => {
                 // SBC (Subtract with Carry) - Absolute,Y
-                let address = self
-                    .memory
-                    .borrow()
-                    .read_word(self.pc)
-                    .wrapping_add(self.y as u16);
-                self.pc += 2;
-                let value = self.memory.borrow().read_byte(address);
+                let address = self.absolute_indexed(self.y);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.sbc(value);
                 4
             }
@@ -1930,18 +1959,17 @@ impl<'a> CPU<'a> {
             }
             0xFD => {
                 // SBC (Subtract with Carry) - Absolute, X
-                let addr = self.memory.borrow().read_word(self.pc) + self.x as u16;
-                let value = self.memory.borrow().read_byte(addr);
+                let address = self.absolute_indexed(self.x);
+                let value = self.memory.borrow_mut().read_byte(address);
                 self.sbc(value);
-                self.pc += 2;
                 4
             }
             0xFE => {
                 // INC (Increment Memory) - Absolute,X
-                let base_address = self.memory.borrow().read_word(self.pc);
-                self.pc += 2;
+                let base_address = self.memory.borrow_mut().read_word(self.pc);
+                self.pc = self.pc.wrapping_add(2);
                 let address = base_address.wrapping_add(self.x as u16);
-                let value = self.memory.borrow().read_byte(address).wrapping_add(1);
+                let value = self.memory.borrow_mut().read_byte(address).wrapping_add(1);
                 self.memory.borrow_mut().write_byte(address, value);
                 self.update_zero_and_negative_flags(value);
                 7
@@ -1956,3 +1984,47 @@ impl<'a> CPU<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::nes::Nes;
+    use crate::test_rom;
+
+    // Implied instructions are one byte long; each result is stored to zero page for checking
+    #[test]
+    fn implied_instructions_take_one_byte() {
+        let program = [
+            0x78, // SEI
+            0xA9, 0x40, 0x8D, 0x17, 0x40, // LDA #$40; STA $4017
+            0xA2, 0x05, 0x8A, 0x85, 0x00, // LDX #5; TXA; STA $00
+            0xA0, 0x06, 0x98, 0x85, 0x01, // LDY #6; TYA; STA $01
+            0xA2, 0xFF, 0x9A, // LDX #$FF; TXS
+            0xA9, 0x07, 0x48, 0xA9, 0x00, 0x68, 0x85,
+            0x02, // LDA #7; PHA; LDA #0; PLA; STA $02
+            0x38, 0xA9, 0x02, 0x6A, 0x85, 0x03, // SEC; LDA #2; ROR; STA $03
+            0x58, // CLI
+            0xA9, 0x09, 0x85, 0x04, // LDA #9; STA $04
+            0x4C, 0x26, 0x80, // JMP *
+        ];
+        let mut nes = Nes::new(&test_rom::nrom(&program, &[])).unwrap();
+        nes.step_frame();
+        assert_eq!(nes.peek_range(0x00..=0x04), [5, 6, 7, 0x81, 9]);
+    }
+
+    // $FFFF holds $C0, the IRQ vector's high byte: CPY #imm, whose operand is at $0000
+    #[test]
+    fn program_counter_wraps_past_ffff() {
+        let mut program = Vec::new();
+        // LDA #9; STA $11; JMP $0006 at $0001, then JMP $FFFF
+        for (address, value) in [0xA9, 0x09, 0x85, 0x11, 0x4C, 0x06, 0x00]
+            .into_iter()
+            .enumerate()
+        {
+            program.extend([0xA9, value, 0x85, address as u8 + 1]);
+        }
+        program.extend([0x4C, 0xFF, 0xFF]);
+        let mut nes = Nes::new(&test_rom::nrom(&program, &[])).unwrap();
+        nes.step_frame();
+        assert_eq!(nes.peek_range(0x11..=0x11), [9]);
+    }
+}
@@ -0,0 +1,778 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use core::ops::RangeInclusive;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+use tracing::trace;
+
+use crate::apu::APU;
+use crate::cheat::Cheats;
+use crate::controller::{Controller, FourScore};
+use crate::dma::Dma;
+use crate::keyboard::FamilyKeyboard;
+use crate::mapper::{self, MapperError, SharedMapper};
+use crate::nes::CONTROLLER_PORTS;
+use crate::ppu::PPU;
+#[cfg(feature = "std")]
+use crate::profile::{self, Profile};
+use crate::rom::Rom;
+use crate::scheduler::{Device, Scheduler};
+
+pub type A12Listener = Box<dyn FnMut()>;
+
+pub type AccessHook = Box<dyn FnMut(BusAccess)>;
+
+// Who drove a bus access
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AccessSource {
+    #[default]
+    Cpu,
+    Dma,      // OAM DMA and DMC sample fetches
+    Debugger, // Writes made by cheats and tools
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+// One read or write on the CPU bus, as reported to the access hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusAccess {
+    pub kind: AccessKind,
+    pub address: u16,
+    pub value: u8,
+    pub source: AccessSource,
+}
+
+// What reads return where nothing drives the data bus: $4018-$401F, cartridge space the board
+// leaves unmapped (often $4020-$5FFF, and $6000-$7FFF without PRG-RAM), or no cartridge at all
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnmappedReads {
+    // The last value on the bus, like the real console. Some games rely on it, e.g. for copy
+    // protection.
+    #[default]
+    OpenBus,
+    Zero,
+}
+
+// $4016/$4017 drive bits 0-4: a controller's serial data on bit 0 and the expansion port's lines
+// on bits 1-4, which read 0 with nothing plugged in. Bits 5-7 aren't connected and keep what was
+// last on the bus, usually $40 from the address, so reads give exactly $40 or $41 (Paperboy
+// checks for that).
+const PORT_DRIVEN_BITS: u8 = 0x1F;
+
+// The Famicom's second controller has a microphone, read as bit 2 of $4016
+const MICROPHONE_BIT: u8 = 0x04;
+
+// With the `serde` feature the console side can be saved: RAM, the PPU and APU, DMA and the
+// controller ports. The cartridge is saved on its own, and the hooks, cheats and settings
+// belong to the session, so `restore` keeps the current ones.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuBus {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_arrays"))]
+    ram: [u8; 0x800], // 2KB of internal RAM
+    // Saved in sections of their own
+    #[cfg_attr(feature = "serde", serde(skip))]
+    ppu: PPU, // PPU, reached through its registers at $2000-$3FFF
+    #[cfg_attr(feature = "serde", serde(skip))]
+    apu: APU, // APU, clocked once per CPU cycle
+    dma: Dma,                         // Pending OAM and DMC DMA transfers
+    cycles: u64,                      // CPU cycles elapsed, one per bus access
+    open_bus: u8,                     // Last value on the data bus, read back from nowhere
+    apu_and_io_registers: [u8; 0x18], // APU and I/O registers
+    #[cfg_attr(feature = "serde", serde(skip))]
+    cartridge: Option<SharedMapper>, // Everything from $4020 up, also wired to the PPU
+    #[cfg_attr(feature = "serde", serde(skip))]
+    a12_listener: Option<A12Listener>, // Told about every filtered PPU A12 rising edge
+    #[cfg_attr(feature = "serde", serde(skip))]
+    unmapped_reads: UnmappedReads, // Open bus or 0 where nothing answers
+    #[cfg_attr(feature = "serde", serde(skip))]
+    access_hook: Option<AccessHook>, // Told about every read and write, when set
+    #[cfg_attr(feature = "serde", serde(skip))]
+    access_source: AccessSource, // Who the current accesses are made for
+    #[cfg_attr(feature = "serde", serde(skip))]
+    cheats: Cheats, // Substitutions applied to CPU reads
+    // Read at $4016/$4017, strobed by writing $4016
+    controllers: [Controller; CONTROLLER_PORTS],
+    four_score: Option<FourScore>, // Chains controllers 3 and 4 behind 1 and 2 when connected
+    #[cfg_attr(feature = "serde", serde(skip))]
+    microphone: bool, // Whether the Famicom microphone is picking up sound
+    // Scanned through $4016 and $4017, when plugged into the expansion port
+    family_keyboard: Option<FamilyKeyboard>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    synced: u64, // The cycle the PPU, APU and cartridge have been run up to
+    #[cfg_attr(feature = "serde", serde(skip))]
+    scheduler: Scheduler, // When each of them next has to be caught up
+    #[cfg_attr(feature = "serde", serde(skip))]
+    next_event: u64, // The earliest of those; 0 to reschedule on the next cycle
+    #[cfg_attr(feature = "serde", serde(skip))]
+    overclock: u64, // CPU cycles the devices sit out at the start of each vblank
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pause_left: u64, // Of the current vblank's
+    #[cfg_attr(feature = "serde", serde(skip))]
+    paused: u64, // All the cycles sat out, which the devices' clock is behind the CPU's
+    #[cfg_attr(feature = "serde", serde(skip))]
+    nmi_since: Option<u64>, // The CPU cycle the PPU's pending NMI was raised on
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    profile: Option<Profile>, // Time spent in each part, while profiling
+}
+
+impl Default for CpuBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CpuBus {
+    pub fn new() -> Self {
+        Self {
+            ram: [0; 0x800],
+            ppu: PPU::new(),
+            apu: APU::new(),
+            dma: Dma::new(),
+            cycles: 0,
+            open_bus: 0,
+            apu_and_io_registers: [0; 0x18],
+            controllers: core::array::from_fn(|_| Controller::new()),
+            four_score: None,
+            microphone: false,
+            family_keyboard: None,
+            cartridge: None,
+            a12_listener: None,
+            unmapped_reads: UnmappedReads::default(),
+            access_hook: None,
+            access_source: AccessSource::Cpu,
+            cheats: Cheats::new(),
+            synced: 0,
+            scheduler: Scheduler::new(),
+            next_event: 0,
+            overclock: 0,
+            pause_left: 0,
+            paused: 0,
+            nmi_since: None,
+            #[cfg(feature = "std")]
+            profile: None,
+        }
+    }
+
+    // Insert the cartridge described by the ROM, failing if its mapper is not supported
+    pub fn load_rom(&mut self, rom: &Rom) -> Result<(), MapperError> {
+        let cartridge = mapper::create(rom)?;
+        self.ppu.set_region(rom.region);
+        self.apu.set_region(rom.region);
+        self.ppu
+            .connect_cartridge(Rc::clone(&cartridge), mapper::has_four_screen_vram(rom));
+        self.a12_listener = None;
+        if cartridge.borrow().counts_a12_rises() {
+            let counter = Rc::clone(&cartridge);
+            self.set_a12_listener(move || counter.borrow_mut().a12_rise());
+        }
+        self.cartridge = Some(cartridge);
+        self.next_event = 0;
+        Ok(())
+    }
+
+    // Take over the console state from a save state. The peripherals plugged in and the buttons
+    // held stay as they are now; only their shift registers are restored.
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore(&mut self, saved: CpuBus) {
+        self.ram = saved.ram;
+        self.dma = saved.dma;
+        self.cycles = saved.cycles;
+        self.synced = saved.cycles;
+        self.next_event = 0;
+        (self.pause_left, self.paused) = (0, 0);
+        self.open_bus = saved.open_bus;
+        self.apu_and_io_registers = saved.apu_and_io_registers;
+        for (controller, saved) in self.controllers.iter_mut().zip(&saved.controllers) {
+            controller.restore(saved);
+        }
+        if let (Some(four_score), Some(saved)) = (self.four_score.as_mut(), saved.four_score) {
+            *four_score = saved;
+        }
+        if let (Some(keyboard), Some(saved)) = (
+            self.family_keyboard.as_mut(),
+            saved.family_keyboard.as_ref(),
+        ) {
+            keyboard.restore(saved);
+        }
+    }
+
+    pub fn cartridge(&self) -> Option<&SharedMapper> {
+        self.cartridge.as_ref()
+    }
+
+    pub fn controller(&self, port: usize) -> Option<&Controller> {
+        self.controllers.get(port)
+    }
+
+    pub fn controller_mut(&mut self, port: usize) -> Option<&mut Controller> {
+        self.controllers.get_mut(port)
+    }
+
+    pub(crate) fn set_turbo_on(&mut self, on: bool) {
+        for controller in &mut self.controllers {
+            controller.set_turbo_on(on);
+        }
+    }
+
+    pub fn four_score(&self) -> bool {
+        self.four_score.is_some()
+    }
+
+    // Connect or disconnect the Four Score. Without it controllers 3 and 4 aren't read.
+    pub fn set_four_score(&mut self, connected: bool) {
+        if connected != self.four_score.is_some() {
+            self.four_score = connected.then(FourScore::new);
+        }
+    }
+
+    pub fn microphone(&self) -> bool {
+        self.microphone
+    }
+
+    // Games like the Famicom Zelda and Kid Icarus react to blowing into the microphone; only
+    // its level matters, as a single bit
+    pub fn set_microphone(&mut self, active: bool) {
+        self.microphone = active;
+    }
+
+    // What a read of $4016 (port 0) or $4017 (port 1) puts on the bus
+    fn read_port(&mut self, port: usize) -> u8 {
+        let data = self.read_controller(port) | self.expansion_lines(port);
+        data & PORT_DRIVEN_BITS | self.open_bus & !PORT_DRIVEN_BITS
+    }
+
+    fn peek_port(&self, port: usize) -> u8 {
+        let data = self.peek_controller(port) | self.expansion_lines(port);
+        data & PORT_DRIVEN_BITS | self.open_bus & !PORT_DRIVEN_BITS
+    }
+
+    pub fn family_keyboard(&self) -> Option<&FamilyKeyboard> {
+        self.family_keyboard.as_ref()
+    }
+
+    pub fn family_keyboard_mut(&mut self) -> Option<&mut FamilyKeyboard> {
+        self.family_keyboard.as_mut()
+    }
+
+    // Plug the Family BASIC keyboard into the expansion port or take it out
+    pub fn set_family_keyboard(&mut self, connected: bool) {
+        if connected != self.family_keyboard.is_some() {
+            self.family_keyboard = connected.then(FamilyKeyboard::new);
+        }
+    }
+
+    // Bits 1-4 of a port read
+    fn expansion_lines(&self, port: usize) -> u8 {
+        match port {
+            0 if self.microphone => MICROPHONE_BIT,
+            1 => self
+                .family_keyboard
+                .as_ref()
+                .map_or(0, FamilyKeyboard::read),
+            _ => 0,
+        }
+    }
+
+    fn read_controller(&mut self, port: usize) -> u8 {
+        let [first, second, third, fourth] = &mut self.controllers;
+        match self.four_score.as_mut() {
+            Some(four_score) if port == 0 => four_score.read(0, first, third),
+            Some(four_score) => four_score.read(1, second, fourth),
+            None if port == 0 => first.read(),
+            None => second.read(),
+        }
+    }
+
+    fn peek_controller(&self, port: usize) -> u8 {
+        match &self.four_score {
+            Some(four_score) => {
+                four_score.peek(port, &self.controllers[port], &self.controllers[port + 2])
+            }
+            None => self.controllers[port].peek(),
+        }
+    }
+
+    // Register the cartridge logic that counts PPU A12 rising edges (the MMC3 scanline counter
+    // and its relatives). It is called right after the PPU dot that caused the edge.
+    pub fn set_a12_listener(&mut self, listener: impl FnMut() + 'static) {
+        self.a12_listener = Some(Box::new(listener));
+        self.next_event = 0;
+    }
+
+    // Register a function that is called after every read and write on the bus, e.g. for a
+    // code/data logger. Side-effect-free peeks are not reported. Without a hook tracing costs
+    // nothing but a check.
+    pub fn set_access_hook(&mut self, hook: impl FnMut(BusAccess) + 'static) {
+        self.access_hook = Some(Box::new(hook));
+    }
+
+    pub fn clear_access_hook(&mut self) {
+        self.access_hook = None;
+    }
+
+    fn trace(&mut self, kind: AccessKind, address: u16, value: u8, source: AccessSource) {
+        if let Some(hook) = self.access_hook.as_mut() {
+            hook(BusAccess {
+                kind,
+                address,
+                value,
+                source,
+            });
+        }
+    }
+
+    pub fn cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cheats
+    }
+
+    pub fn unmapped_reads(&self) -> UnmappedReads {
+        self.unmapped_reads
+    }
+
+    pub fn set_unmapped_reads(&mut self, unmapped_reads: UnmappedReads) {
+        self.unmapped_reads = unmapped_reads;
+    }
+
+    // The value read from an address nothing responds to
+    fn unmapped(&self) -> u8 {
+        match self.unmapped_reads {
+            UnmappedReads::OpenBus => self.open_bus,
+            UnmappedReads::Zero => 0,
+        }
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        &self.ppu
+    }
+
+    // Caught up with the CPU, and rescheduled in case of changes
+    pub fn ppu_mut(&mut self) -> &mut PPU {
+        self.sync();
+        self.next_event = 0;
+        &mut self.ppu
+    }
+
+    pub fn apu(&self) -> &APU {
+        &self.apu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut APU {
+        self.sync();
+        self.next_event = 0;
+        &mut self.apu
+    }
+
+    pub fn dma_mut(&mut self) -> &mut Dma {
+        &mut self.dma
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn overclock(&self) -> u64 {
+        self.overclock
+    }
+
+    // Give the CPU `cycles` more each frame: when vblank starts the PPU, APU and cartridge stop
+    // for that long while the CPU carries on. Games that slow down get more time before the
+    // next frame, and nothing else about the frame or the sound changes.
+    pub fn set_overclock(&mut self, cycles: u64) {
+        self.overclock = cycles;
+    }
+
+    // The cycle the PPU, APU and cartridge are at when caught up: the CPU's, less the cycles
+    // they sat out
+    fn device_cycles(&self) -> u64 {
+        self.cycles - self.paused
+    }
+
+    // Advance the rest of the system by one CPU cycle (three PPU dots, or 3.2 on PAL). The
+    // PPU, APU and cartridge don't actually run until one of them is due to do something the
+    // CPU can see, like the vblank NMI or an IRQ, or the CPU accesses one of them; then they
+    // are caught up in one go. Either way they are at the right cycle whenever it matters.
+    pub fn tick(&mut self) {
+        self.cycles += 1;
+        if self.pause_left > 0 {
+            self.pause_left -= 1;
+            self.paused += 1;
+            return;
+        }
+        if self.device_cycles() >= self.next_event {
+            self.sync();
+            self.schedule();
+        }
+    }
+
+    // Run the PPU, APU and cartridge up to the CPU's cycle. Between events they don't affect
+    // each other, so each one runs its share of cycles in a single loop.
+    pub fn sync(&mut self) {
+        let now = self.device_cycles();
+        if self.synced == now {
+            return;
+        }
+        let cycles = self.synced + 1..=now;
+        self.synced = now;
+        #[cfg(feature = "std")]
+        if self.profile.is_some() {
+            self.sync_profiled(cycles);
+            return;
+        }
+        let count = cycles.end() - cycles.start() + 1;
+        self.run_ppu(cycles);
+        self.run_apu(count);
+        self.run_cartridge(count);
+        self.watch_nmi();
+    }
+
+    #[cfg(feature = "std")]
+    fn sync_profiled(&mut self, cycles: RangeInclusive<u64>) {
+        let count = cycles.end() - cycles.start() + 1;
+        let mut start = Instant::now();
+        self.run_ppu(cycles);
+        let ppu = profile::lap(&mut start);
+        self.run_apu(count);
+        let apu = profile::lap(&mut start);
+        self.run_cartridge(count);
+        let cartridge = profile::lap(&mut start);
+        if let Some(profile) = self.profile.as_mut() {
+            profile.ppu += ppu;
+            profile.apu += apu;
+            profile.cartridge += cartridge;
+        }
+        self.watch_nmi();
+    }
+
+    // Ask each device when it next needs to be caught up
+    fn schedule(&mut self) {
+        // A12 listeners are the cartridge's own counters and may raise an IRQ on any edge
+        let ppu = if self.a12_listener.is_some() {
+            1
+        } else {
+            let region = self.ppu.region();
+            region.cycles_for_dots(self.ppu.dots_until_event())
+        };
+        let cartridge = self.cartridge.as_ref().map_or(u64::MAX, |cartridge| {
+            cartridge.borrow().cycles_until_event()
+        });
+        let now = self.device_cycles();
+        self.scheduler
+            .schedule(Device::Ppu, now.saturating_add(ppu));
+        self.scheduler.schedule(
+            Device::Apu,
+            now.saturating_add(self.apu.cycles_until_event()),
+        );
+        self.scheduler
+            .schedule(Device::Cartridge, now.saturating_add(cartridge));
+        self.next_event = self.scheduler.next();
+    }
+
+    fn run_ppu(&mut self, cycles: RangeInclusive<u64>) {
+        let region = self.ppu.region();
+        for cycle in cycles {
+            for dot in 0..region.dots_per_cycle(cycle) {
+                self.ppu.step();
+                for _ in 0..self.ppu.take_a12_rises() {
+                    if let Some(listener) = self.a12_listener.as_mut() {
+                        listener();
+                    }
+                }
+                // The CPU samples its NMI input a dot into each cycle, so an NMI raised on the
+                // first dot counts as raised the cycle before
+                if dot == 0 && self.nmi_since.is_none() && self.ppu.nmi_pending() {
+                    self.nmi_since = Some(cycle + self.paused - 1);
+                }
+            }
+        }
+        // Vblank is one of the PPU's events, so it only ever starts in a run's last cycle
+        if self.ppu.take_vblank_started() {
+            self.pause_left = self.overclock;
+        }
+    }
+
+    fn run_apu(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            self.apu.tick();
+            if let Some(sample) = self.dma.take_dmc_sample() {
+                self.apu.load_dmc_sample(sample);
+            }
+            if let Some(address) = self.apu.take_dmc_request() {
+                self.dma.request_dmc(address);
+            }
+        }
+    }
+
+    fn run_cartridge(&mut self, cycles: u64) {
+        if let Some(cartridge) = self.cartridge.as_ref() {
+            let mut cartridge = cartridge.borrow_mut();
+            for _ in 0..cycles {
+                cartridge.cpu_tick();
+            }
+        }
+    }
+
+    // Note the cycle the PPU raises its NMI on, or forget it if a $2002 read or $2000 write has
+    // taken it back
+    fn watch_nmi(&mut self) {
+        if !self.ppu.nmi_pending() {
+            self.nmi_since = None;
+        } else if self.nmi_since.is_none() {
+            self.nmi_since = Some(self.cycles);
+        }
+    }
+
+    // The PPU raises its NMI on an event, so there's no catching up to do first. The CPU polls
+    // for interrupts before an instruction's last cycle: one raised on that cycle waits until
+    // after the next instruction.
+    pub fn take_nmi(&mut self) -> bool {
+        self.watch_nmi();
+        match self.nmi_since {
+            Some(cycle) if cycle < self.cycles => {
+                self.nmi_since = None;
+                self.ppu.take_nmi()
+            }
+            _ => false,
+        }
+    }
+
+    // The finished frame flag, which is also only raised on an event
+
+    pub fn take_frame_complete(&mut self) -> bool {
+        self.ppu.take_frame_complete()
+    }
+
+    // Start timing each part of the console from zero, or stop with false
+    #[cfg(feature = "std")]
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profile = enabled.then(Profile::default);
+    }
+
+    #[cfg(feature = "std")]
+    pub fn profile(&self) -> Option<Profile> {
+        self.profile
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn add_step_time(&mut self, time: Duration) {
+        if let Some(profile) = self.profile.as_mut() {
+            profile.total += time;
+        }
+    }
+
+    // What the reset button does outside the CPU: the APU falls silent and the PPU stops drawing
+    // and generating NMIs. RAM and the cartridge are left alone.
+    pub fn reset(&mut self) {
+        self.sync();
+        self.next_event = 0;
+        self.apu.write_register(0x4015, 0x00);
+        self.ppu.write_register(0x2000, 0x00);
+        self.ppu.write_register(0x2001, 0x00);
+    }
+
+    // Whether anything is asserting the CPU's IRQ line
+    pub fn irq(&self) -> bool {
+        self.apu.irq()
+            || self
+                .cartridge
+                .as_ref()
+                .is_some_and(|cartridge| cartridge.borrow().irq_pending())
+    }
+
+    // DMA reads happen on even ("get") cycles and writes on odd ("put") cycles
+    fn on_get_cycle(&self) -> bool {
+        self.cycles & 1 == 0
+    }
+
+    // Perform any DMA that was requested during the last instruction, returning the cycles the
+    // CPU was halted for.
+    pub fn run_dma(&mut self) -> usize {
+        let start = self.cycles;
+        self.access_source = AccessSource::Dma;
+
+        if let Some(page) = self.dma.take_oam_page() {
+            self.tick(); // Halt
+            if !self.on_get_cycle() {
+                self.tick(); // Alignment
+            }
+
+            for offset in 0..=0xFF {
+                if let Some(address) = self.dma.take_dmc_address() {
+                    // The DMC steals the get cycle, and OAM DMA then needs a put cycle to
+                    // realign before its own get, so an overlapping DMC fetch usually costs 2
+                    let sample = self.read_byte(address);
+                    self.dma.finish_dmc(sample);
+                    self.tick();
+                }
+
+                let value = self.read_byte((page as u16) << 8 | offset);
+                self.write_byte(0x2004, value);
+            }
+
+            if let Some(address) = self.dma.take_dmc_address() {
+                // Requested during the final put: the CPU is still halted, so only the dummy
+                // and alignment cycles are needed before the get (3 cycles instead of 4)
+                self.dmc_fetch(address);
+            }
+        }
+
+        if let Some(address) = self.dma.take_dmc_address() {
+            self.tick(); // Halt
+            self.dmc_fetch(address);
+        }
+
+        self.access_source = AccessSource::Cpu;
+        (self.cycles - start) as usize
+    }
+
+    fn dmc_fetch(&mut self, address: u16) {
+        self.tick(); // Dummy cycle
+        if !self.on_get_cycle() {
+            self.tick(); // Alignment
+        }
+        let sample = self.read_byte(address);
+        self.dma.finish_dmc(sample);
+    }
+
+    pub fn read_byte(&mut self, address: u16) -> u8 {
+        self.tick();
+        // Registers with read side effects and mapper counters need their device up to date
+        let synced = matches!(address, 0x2000..=0x3FFF | 0x4015 | 0x4020..=0x5FFF);
+        if synced {
+            self.sync();
+        }
+        let value = match address {
+            0x0000..=0x1FFF => self.ram[address as usize % 0x800],
+            0x2000..=0x3FFF => {
+                let value = self.ppu.read_register(address);
+                self.watch_nmi();
+                value
+            }
+            0x4015 => self.apu.read_status(),
+            0x4016 | 0x4017 => self.read_port(address as usize - 0x4016),
+            0x4000..=0x4017 => self.apu_and_io_registers[address as usize - 0x4000],
+            0x4018..=0x401F => self.unmapped(), // Unused
+            0x4040..=0x4092 if self.apu.has_fds() => self
+                .apu
+                .read_fds(address)
+                .unwrap_or_else(|| self.unmapped()),
+            0x4020..=0xFFFF => self
+                .cartridge
+                .as_ref()
+                .and_then(|cartridge| cartridge.borrow_mut().cpu_read(address))
+                .unwrap_or_else(|| self.unmapped()),
+        };
+        if synced {
+            self.schedule();
+        }
+        let value = self.cheats.apply(address, value);
+        self.open_bus = value;
+        self.trace(AccessKind::Read, address, value, self.access_source);
+        value
+    }
+
+    // Read without ticking the rest of the system or triggering any read side effects, for
+    // debuggers and tools
+    pub fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x1FFF => self.ram[address as usize % 0x800],
+            0x2000..=0x3FFF => self.ppu.peek_register(address),
+            0x4015 => self.apu.peek_status(),
+            0x4016 | 0x4017 => self.peek_port(address as usize - 0x4016),
+            0x4000..=0x4017 => self.apu_and_io_registers[address as usize - 0x4000],
+            0x4018..=0x401F => self.unmapped(), // Unused
+            0x4040..=0x4092 if self.apu.has_fds() => self
+                .apu
+                .read_fds(address)
+                .unwrap_or_else(|| self.unmapped()),
+            0x4020..=0xFFFF => self
+                .cartridge
+                .as_ref()
+                .and_then(|cartridge| cartridge.borrow_mut().cpu_peek(address))
+                .unwrap_or_else(|| self.unmapped()),
+        }
+    }
+
+    // Write internal RAM or the cartridge's PRG-RAM at $6000-$7FFF without ticking, for
+    // cheats. PRG-RAM is written directly, so board registers in that window stay untouched
+    // and protection doesn't apply. Other addresses are ignored.
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        if let 0x0000..=0x1FFF | 0x6000..=0x7FFF = address {
+            self.trace(AccessKind::Write, address, value, AccessSource::Debugger);
+        }
+        match address {
+            0x0000..=0x1FFF => self.ram[address as usize & 0x07FF] = value,
+            0x6000..=0x7FFF => {
+                if let Some(cartridge) = &self.cartridge {
+                    cartridge.borrow_mut().write_prg_ram(address, value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn write_byte(&mut self, addr: u16, value: u8) {
+        self.tick();
+        // Anything from $2000 up may change when a device's next event is
+        let synced = addr >= 0x2000;
+        if synced {
+            self.sync();
+        }
+        self.open_bus = value;
+        self.trace(AccessKind::Write, addr, value, self.access_source);
+        match addr {
+            0x0000..=0x1FFF => self.ram[addr as usize & 0x07FF] = value,
+            0x2000..=0x3FFF => {
+                self.ppu.write_register(addr, value);
+                self.watch_nmi();
+            }
+            0x4014 => self.dma.start_oam(value),
+            0x4000..=0x4013 | 0x4015 | 0x4017 => {
+                self.apu.write_register(addr, value);
+                self.apu_and_io_registers[addr as usize & 0x001F] = value;
+            }
+            0x4016 => {
+                for controller in &mut self.controllers {
+                    controller.write(value);
+                }
+                if let Some(four_score) = self.four_score.as_mut() {
+                    four_score.write(value);
+                }
+                if let Some(keyboard) = self.family_keyboard.as_mut() {
+                    keyboard.write(value);
+                }
+            }
+            0x4040..=0x4092 if self.apu.has_fds() => self.apu.write_fds(addr, value),
+            0x4020..=0xFFFF => {
+                if let Some(cartridge) = &self.cartridge {
+                    trace!(target: "mapper", "${:04X} = {:02X}", addr, value);
+                    cartridge.borrow_mut().cpu_write(addr, value);
+                }
+            }
+            _ => {} // $4018-$401F: nothing listens
+        }
+        if synced {
+            self.schedule();
+        }
+    }
+
+    pub fn read_word(&mut self, address: u16) -> u16 {
+        let low = self.read_byte(address) as u16;
+        let high = self.read_byte(address.wrapping_add(1)) as u16;
+        (high << 8) | low
+    }
+
+    pub fn read_word_zero_page(&mut self, addr: u16) -> u16 {
+        let lo = self.read_byte(addr & 0xFF) as u16;
+        let hi = self.read_byte((addr + 1) & 0xFF) as u16;
+        (hi << 8) | lo
+    }
+}
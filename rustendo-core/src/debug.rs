@@ -0,0 +1,433 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::apu::{CHANNELS, CHANNEL_NAMES};
+use crate::compositor::{Compositor, Layer};
+use crate::cpu::Registers;
+use crate::cpu_bus::CpuBus;
+use crate::disasm;
+use crate::osd;
+use crate::palette::Palette;
+use crate::ppu::{PPU, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub const PATTERN_TABLE_SIZE: usize = 128; // Each table is 16x16 tiles of 8x8 pixels
+
+// NES colours for a plain black-to-white ramp, for looking at CHR without the game's palettes
+pub const GRAYSCALE: [u8; 4] = [0x0F, 0x00, 0x10, 0x30];
+
+// The four colours of one of the eight palettes in palette RAM (0-3 background, 4-7 sprites)
+pub fn ppu_palette(ppu: &PPU, index: usize) -> [u8; 4] {
+    let base = 0x3F00 + (index as u16 & 0x07) * 4;
+    [0, 1, 2, 3].map(|entry| ppu.peek(base + entry) & 0x3F)
+}
+
+// Render pattern table 0 ($0000) or 1 ($1000) as a 128x128 RGBA tile sheet, drawing each
+// 2-bit pixel with the given NES colours
+pub fn pattern_table(ppu: &PPU, table: usize, colors: [u8; 4]) -> Vec<u8> {
+    let base = (table as u16 & 0x01) * 0x1000;
+    let data: Vec<u8> = (0..0x1000).map(|offset| ppu.peek(base + offset)).collect();
+    chr_sheet(&data, ppu.palette(), colors)
+}
+
+// Render raw CHR data (e.g. a whole CHR-ROM) as an RGBA tile sheet 128 pixels wide, 16 tiles
+// to a row in the order they are stored, so each 4KB bank is one 128x128 block
+pub fn chr_sheet(chr: &[u8], palette: &Palette, colors: [u8; 4]) -> Vec<u8> {
+    let rgba = colors.map(|color| {
+        let [r, g, b] = palette.color(color as u16);
+        [r, g, b, 0xFF]
+    });
+    let tiles = chr.len() / 16;
+    let mut pixels = vec![0; tiles.div_ceil(16) * 8 * PATTERN_TABLE_SIZE * 4];
+
+    for (tile, planes) in chr.chunks_exact(16).enumerate() {
+        let tile_x = (tile % 16) * 8;
+        let tile_y = (tile / 16) * 8;
+        for row in 0..8 {
+            let (low, high) = (planes[row], planes[row + 8]);
+            for column in 0..8 {
+                let bit = 7 - column;
+                let value = ((low >> bit) & 0x01) | (((high >> bit) & 0x01) << 1);
+                let offset = ((tile_y + row) * PATTERN_TABLE_SIZE + tile_x + column) * 4;
+                pixels[offset..offset + 4].copy_from_slice(&rgba[value as usize]);
+            }
+        }
+    }
+    pixels
+}
+
+// Draw both pattern tables side by side across the top of the debug layer
+pub fn overlay_pattern_tables(ppu: &PPU, compositor: &mut Compositor, colors: [u8; 4]) {
+    for table in 0..2 {
+        let pixels = pattern_table(ppu, table, colors);
+        compositor.blit(
+            Layer::Debug,
+            table * PATTERN_TABLE_SIZE,
+            0,
+            PATTERN_TABLE_SIZE,
+            PATTERN_TABLE_SIZE,
+            &pixels,
+        );
+    }
+}
+
+pub const NAMETABLES_WIDTH: usize = 512; // Four 256x240 nametables in a 2x2 grid
+pub const NAMETABLES_HEIGHT: usize = 480;
+
+const SCROLL_OUTLINE: [u8; 4] = [0xFF, 0x00, 0xFF, 0xFF];
+
+// Render all four nametables ($2000 top left, $2400 top right, $2800 bottom left, $2C00 bottom
+// right) as a 512x480 RGBA image, with the 256x240 area the current scroll shows outlined
+pub fn nametables(ppu: &PPU) -> Vec<u8> {
+    let mut pixels = vec![0; NAMETABLES_WIDTH * NAMETABLES_HEIGHT * 4];
+    let pattern_table = ppu.background_pattern_table();
+
+    for table in 0..4u16 {
+        let base = 0x2000 + table * 0x400;
+        let origin_x = (table as usize & 0x01) * 256;
+        let origin_y = (table as usize >> 1) * 240;
+        for tile_row in 0..30u16 {
+            for tile_column in 0..32u16 {
+                let tile = ppu.peek(base + tile_row * 32 + tile_column) as u16;
+                let attribute = ppu.peek(base + 0x3C0 + (tile_row / 4) * 8 + tile_column / 4);
+                let shift = ((tile_row & 0x02) << 1) | (tile_column & 0x02);
+                let palette = (attribute >> shift) & 0x03;
+
+                for row in 0..8u16 {
+                    let low = ppu.peek(pattern_table + tile * 16 + row);
+                    let high = ppu.peek(pattern_table + tile * 16 + row + 8);
+                    for column in 0..8 {
+                        let bit = 7 - column;
+                        let value = ((low >> bit) & 0x01) | (((high >> bit) & 0x01) << 1);
+                        // Colour 0 of every background palette is the shared backdrop
+                        let entry = if value == 0 {
+                            0x3F00
+                        } else {
+                            0x3F00 + palette as u16 * 4 + value as u16
+                        };
+                        let [r, g, b] = ppu.palette().color(ppu.peek(entry) as u16 & 0x3F);
+                        let x = origin_x + tile_column as usize * 8 + column as usize;
+                        let y = origin_y + tile_row as usize * 8 + row as usize;
+                        let offset = (y * NAMETABLES_WIDTH + x) * 4;
+                        pixels[offset..offset + 4].copy_from_slice(&[r, g, b, 0xFF]);
+                    }
+                }
+            }
+        }
+    }
+
+    // The visible area wraps around the edges of the nametable space
+    let (scroll_x, scroll_y) = ppu.scroll();
+    let mut outline = |x: usize, y: usize| {
+        let x = (scroll_x + x) % NAMETABLES_WIDTH;
+        let y = (scroll_y + y) % NAMETABLES_HEIGHT;
+        let offset = (y * NAMETABLES_WIDTH + x) * 4;
+        pixels[offset..offset + 4].copy_from_slice(&SCROLL_OUTLINE);
+    };
+    for x in 0..256 {
+        outline(x, 0);
+        outline(x, 239);
+    }
+    for y in 0..240 {
+        outline(0, y);
+        outline(255, y);
+    }
+    pixels
+}
+
+// Draw the nametable view onto the debug layer at half size so it fills the screen
+pub fn overlay_nametables(ppu: &PPU, compositor: &mut Compositor) {
+    let pixels = nametables(ppu);
+    for y in 0..NAMETABLES_HEIGHT / 2 {
+        for x in 0..NAMETABLES_WIDTH / 2 {
+            let offset = (y * 2 * NAMETABLES_WIDTH + x * 2) * 4;
+            let pixel = pixels[offset..offset + 4].try_into().unwrap();
+            compositor.set_pixel(Layer::Debug, x, y, pixel);
+        }
+    }
+}
+
+// One decoded OAM entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sprite {
+    pub index: usize,
+    pub x: u8,
+    pub y: u8, // Top of the sprite minus one, as stored in OAM
+    pub tile: u8,
+    pub palette: u8, // 4-7, the sprite half of palette RAM
+    pub behind_background: bool,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+}
+
+pub fn sprites(ppu: &PPU) -> Vec<Sprite> {
+    ppu.oam()
+        .chunks_exact(4)
+        .enumerate()
+        .map(|(index, entry)| Sprite {
+            index,
+            x: entry[3],
+            y: entry[0],
+            tile: entry[1],
+            palette: 4 + (entry[2] & 0x03),
+            behind_background: entry[2] & 0x20 != 0,
+            flip_horizontal: entry[2] & 0x40 != 0,
+            flip_vertical: entry[2] & 0x80 != 0,
+        })
+        .collect()
+}
+
+pub const SPRITE_PREVIEW_WIDTH: usize = 64; // 8x8 grid of 8x16 cells, one per OAM entry
+pub const SPRITE_PREVIEW_HEIGHT: usize = 128;
+
+// Render every sprite with its own palette and flips into a grid of cells in OAM order, as
+// RGBA with transparent pixels left at zero alpha. 8x8 sprites use the top half of their cell.
+pub fn sprite_preview(ppu: &PPU) -> Vec<u8> {
+    let mut pixels = vec![0; SPRITE_PREVIEW_WIDTH * SPRITE_PREVIEW_HEIGHT * 4];
+    let height = ppu.sprite_height();
+
+    for sprite in sprites(ppu) {
+        let cell_x = (sprite.index % 8) * 8;
+        let cell_y = (sprite.index / 8) * 16;
+        for row in 0..height {
+            let source_row = if sprite.flip_vertical {
+                height - 1 - row
+            } else {
+                row
+            };
+            // 8x16 sprites take their pattern table from bit 0 of the tile number
+            let address = if height == 16 {
+                let table = (sprite.tile as u16 & 0x01) * 0x1000;
+                let tile = (sprite.tile as u16 & 0xFE) + (source_row as u16 / 8);
+                table + tile * 16 + (source_row as u16 & 0x07)
+            } else {
+                ppu.sprite_pattern_table() + sprite.tile as u16 * 16 + source_row as u16
+            };
+            let low = ppu.peek(address);
+            let high = ppu.peek(address + 8);
+
+            for column in 0..8 {
+                let bit = if sprite.flip_horizontal {
+                    column
+                } else {
+                    7 - column
+                };
+                let value = ((low >> bit) & 0x01) | (((high >> bit) & 0x01) << 1);
+                if value == 0 {
+                    continue;
+                }
+                let entry = 0x3F00 + sprite.palette as u16 * 4 + value as u16;
+                let [r, g, b] = ppu.palette().color(ppu.peek(entry) as u16 & 0x3F);
+                let offset = ((cell_y + row) * SPRITE_PREVIEW_WIDTH + cell_x + column) * 4;
+                pixels[offset..offset + 4].copy_from_slice(&[r, g, b, 0xFF]);
+            }
+        }
+    }
+    pixels
+}
+
+// Draw the sprite preview onto the top-left corner of the debug layer
+pub fn overlay_sprites(ppu: &PPU, compositor: &mut Compositor) {
+    compositor.blit(
+        Layer::Debug,
+        0,
+        0,
+        SPRITE_PREVIEW_WIDTH,
+        SPRITE_PREVIEW_HEIGHT,
+        &sprite_preview(ppu),
+    );
+}
+
+const SWATCH_WIDTH: usize = 16;
+const SWATCH_HEIGHT: usize = 9;
+const SWATCH_GAP: usize = 1;
+pub const PALETTE_VIEW_WIDTH: usize = 4 * (SWATCH_WIDTH + SWATCH_GAP); // Four colours per row
+pub const PALETTE_VIEW_HEIGHT: usize = 8 * (SWATCH_HEIGHT + SWATCH_GAP); // One row per palette
+
+// 3x5 pixel hex digits, one row per byte with the leftmost pixel in bit 2
+const HEX_FONT: [[u8; 5]; 16] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+    [0b010, 0b101, 0b111, 0b101, 0b101],
+    [0b110, 0b101, 0b110, 0b101, 0b110],
+    [0b011, 0b100, 0b100, 0b100, 0b011],
+    [0b110, 0b101, 0b101, 0b101, 0b110],
+    [0b111, 0b100, 0b111, 0b100, 0b111],
+    [0b111, 0b100, 0b111, 0b100, 0b100],
+];
+
+// Render the eight palettes as rows of four swatches, each labelled with its colour index
+pub fn palette_view(ppu: &PPU) -> Vec<u8> {
+    let mut pixels = vec![0; PALETTE_VIEW_WIDTH * PALETTE_VIEW_HEIGHT * 4];
+    let mut put = |x: usize, y: usize, rgba: [u8; 4]| {
+        let offset = (y * PALETTE_VIEW_WIDTH + x) * 4;
+        pixels[offset..offset + 4].copy_from_slice(&rgba);
+    };
+
+    for (entry, color) in ppu.palette_ram().into_iter().enumerate() {
+        let color = color & 0x3F;
+        let left = (entry % 4) * (SWATCH_WIDTH + SWATCH_GAP);
+        let top = (entry / 4) * (SWATCH_HEIGHT + SWATCH_GAP);
+        let [r, g, b] = ppu.palette().color(color as u16);
+        for y in 0..SWATCH_HEIGHT {
+            for x in 0..SWATCH_WIDTH {
+                put(left + x, top + y, [r, g, b, 0xFF]);
+            }
+        }
+
+        // Dark text on light colours and the other way round
+        let luma = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+        let text = if luma > 0x80 {
+            [0x00, 0x00, 0x00, 0xFF]
+        } else {
+            [0xFF, 0xFF, 0xFF, 0xFF]
+        };
+        for (digit, value) in [color >> 4, color & 0x0F].into_iter().enumerate() {
+            let glyph_left = left + 4 + digit * 4;
+            for (row, bits) in HEX_FONT[value as usize].iter().enumerate() {
+                for column in 0..3 {
+                    if bits & (0b100 >> column) != 0 {
+                        put(glyph_left + column, top + 2 + row, text);
+                    }
+                }
+            }
+        }
+    }
+    pixels
+}
+
+// Draw the palette view onto the top-left corner of the debug layer
+pub fn overlay_palettes(ppu: &PPU, compositor: &mut Compositor) {
+    compositor.blit(
+        Layer::Debug,
+        0,
+        0,
+        PALETTE_VIEW_WIDTH,
+        PALETTE_VIEW_HEIGHT,
+        &palette_view(ppu),
+    );
+}
+
+// Text views start below the lines TVs crop by default
+const TEXT_LEFT: usize = 4;
+const TEXT_TOP: usize = 10;
+const LINE_HEIGHT: usize = osd::GLYPH_HEIGHT + 2;
+const TEXT_COLOR: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+const BACKDROP: [u8; 4] = [0x00, 0x00, 0x00, 0xC0]; // Dims the game behind text views
+
+const DISASSEMBLY_LINES: usize = 16;
+
+const SCOPE_COLORS: [[u8; 4]; CHANNELS] = [
+    [0xFF, 0x60, 0x60, 0xFF],
+    [0xFF, 0xB0, 0x40, 0xFF],
+    [0x60, 0xD0, 0xFF, 0xFF],
+    [0xC0, 0xC0, 0xC0, 0xFF],
+    [0x80, 0xFF, 0x80, 0xFF],
+];
+
+// Dim the picture and write `lines` down it, for pages of text. Front ends use it for views of
+// their own.
+pub fn overlay_text(compositor: &mut Compositor, lines: &[String]) {
+    compositor.clear(Layer::Debug);
+    compositor.fill_rect(Layer::Debug, 0, 0, SCREEN_WIDTH, SCREEN_HEIGHT, BACKDROP);
+    for (row, line) in lines.iter().enumerate() {
+        let y = TEXT_TOP + row * LINE_HEIGHT;
+        osd::draw_text(compositor, Layer::Debug, TEXT_LEFT, y, line, TEXT_COLOR);
+    }
+}
+
+// The CPU registers, flags and stack, and the instructions from the PC on, as lines of text.
+// Unofficial opcodes are marked with `*`.
+pub fn cpu_lines(registers: Registers, bus: &CpuBus) -> Vec<String> {
+    let flags: String = "NV-BDIZC"
+        .chars()
+        .enumerate()
+        .map(|(i, name)| {
+            if registers.status & (0x80 >> i) != 0 {
+                name
+            } else {
+                '.'
+            }
+        })
+        .collect();
+    let stack: Vec<String> = (registers.sp as u16 + 1..=0xFF)
+        .take(8)
+        .map(|offset| format!("{:02X}", bus.peek(0x0100 + offset)))
+        .collect();
+    let mut lines = vec![
+        format!("{}", registers),
+        format!("FLAGS {}  CYCLE {}", flags, bus.cycles()),
+        format!("STACK {}", stack.join(" ")),
+        String::new(),
+    ];
+    let mut address = registers.pc;
+    for _ in 0..DISASSEMBLY_LINES {
+        let instruction = disasm::decode(address, |address| bus.peek(address));
+        let marker = if instruction.official { ' ' } else { '*' };
+        lines.push(format!("{:04X} {}{}", address, marker, instruction));
+        address = address.wrapping_add(instruction.size());
+    }
+    lines
+}
+
+pub fn overlay_cpu(registers: Registers, bus: &CpuBus, compositor: &mut Compositor) {
+    overlay_text(compositor, &cpu_lines(registers, bus));
+}
+
+// A scope per APU channel, across the samples taken over the last frame (each channel's
+// output after the mixer, as `APU::channel_outputs` gives it). Each is scaled to its own peak.
+pub fn overlay_apu(scope: &[[f32; CHANNELS]], compositor: &mut Compositor) {
+    compositor.clear(Layer::Debug);
+    compositor.fill_rect(Layer::Debug, 0, 0, SCREEN_WIDTH, SCREEN_HEIGHT, BACKDROP);
+    let lane_height = (SCREEN_HEIGHT - 2 * TEXT_TOP) / CHANNELS;
+    let wave_height = lane_height - LINE_HEIGHT - 2;
+    for (channel, name) in CHANNEL_NAMES.iter().enumerate() {
+        let top = TEXT_TOP + channel * lane_height;
+        osd::draw_text(compositor, Layer::Debug, TEXT_LEFT, top, name, TEXT_COLOR);
+        if scope.is_empty() {
+            continue;
+        }
+        let peak = scope
+            .iter()
+            .map(|levels| levels[channel])
+            .fold(f32::EPSILON, f32::max);
+        let bottom = top + LINE_HEIGHT + wave_height - 1;
+        for x in 0..SCREEN_WIDTH {
+            let level = scope[x * scope.len() / SCREEN_WIDTH][channel] / peak;
+            let y = bottom - (level * (wave_height - 1) as f32) as usize;
+            compositor.set_pixel(Layer::Debug, x, y, SCOPE_COLORS[channel]);
+        }
+    }
+}
+
+// A debug view that the emulator redraws onto the debug layer after every frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugView {
+    PatternTables([u8; 4]), // Drawn with these four NES colours
+    Nametables,
+    Sprites,
+    Palettes,
+    Cpu, // Registers, stack and disassembly
+    Apu, // Channel scopes
+}
+
+// Draw one of the PPU views. `Nes` draws the CPU and APU ones, which need more than the PPU.
+pub fn overlay(view: DebugView, ppu: &PPU, compositor: &mut Compositor) {
+    compositor.clear(Layer::Debug);
+    match view {
+        DebugView::PatternTables(colors) => overlay_pattern_tables(ppu, compositor, colors),
+        DebugView::Nametables => overlay_nametables(ppu, compositor),
+        DebugView::Sprites => overlay_sprites(ppu, compositor),
+        DebugView::Palettes => overlay_palettes(ppu, compositor),
+        DebugView::Cpu | DebugView::Apu => {}
+    }
+}
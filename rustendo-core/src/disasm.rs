@@ -0,0 +1,404 @@
+use core::fmt;
+
+use AddressingMode::*;
+
+// How an instruction finds its operand, which also gives its length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingMode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX, // ($nn,X)
+    IndirectY, // ($nn),Y
+    Relative,  // Branches: a signed offset from the next instruction
+}
+
+impl AddressingMode {
+    // Bytes after the opcode
+    pub fn operand_size(self) -> u16 {
+        match self {
+            AddressingMode::Implied | AddressingMode::Accumulator => 0,
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Opcode {
+    mnemonic: &'static str,
+    mode: AddressingMode,
+    official: bool,
+}
+
+const fn op(mnemonic: &'static str, mode: AddressingMode, official: bool) -> Opcode {
+    Opcode {
+        mnemonic,
+        mode,
+        official,
+    }
+}
+
+// Every opcode, the unofficial ones under their usual names (JAM locks the CPU up)
+const OPCODES: [Opcode; 256] = [
+    // $0x
+    op("BRK", Implied, true),
+    op("ORA", IndirectX, true),
+    op("JAM", Implied, false),
+    op("SLO", IndirectX, false),
+    op("NOP", ZeroPage, false),
+    op("ORA", ZeroPage, true),
+    op("ASL", ZeroPage, true),
+    op("SLO", ZeroPage, false),
+    op("PHP", Implied, true),
+    op("ORA", Immediate, true),
+    op("ASL", Accumulator, true),
+    op("ANC", Immediate, false),
+    op("NOP", Absolute, false),
+    op("ORA", Absolute, true),
+    op("ASL", Absolute, true),
+    op("SLO", Absolute, false),
+    // $1x
+    op("BPL", Relative, true),
+    op("ORA", IndirectY, true),
+    op("JAM", Implied, false),
+    op("SLO", IndirectY, false),
+    op("NOP", ZeroPageX, false),
+    op("ORA", ZeroPageX, true),
+    op("ASL", ZeroPageX, true),
+    op("SLO", ZeroPageX, false),
+    op("CLC", Implied, true),
+    op("ORA", AbsoluteY, true),
+    op("NOP", Implied, false),
+    op("SLO", AbsoluteY, false),
+    op("NOP", AbsoluteX, false),
+    op("ORA", AbsoluteX, true),
+    op("ASL", AbsoluteX, true),
+    op("SLO", AbsoluteX, false),
+    // $2x
+    op("JSR", Absolute, true),
+    op("AND", IndirectX, true),
+    op("JAM", Implied, false),
+    op("RLA", IndirectX, false),
+    op("BIT", ZeroPage, true),
+    op("AND", ZeroPage, true),
+    op("ROL", ZeroPage, true),
+    op("RLA", ZeroPage, false),
+    op("PLP", Implied, true),
+    op("AND", Immediate, true),
+    op("ROL", Accumulator, true),
+    op("ANC", Immediate, false),
+    op("BIT", Absolute, true),
+    op("AND", Absolute, true),
+    op("ROL", Absolute, true),
+    op("RLA", Absolute, false),
+    // $3x
+    op("BMI", Relative, true),
+    op("AND", IndirectY, true),
+    op("JAM", Implied, false),
+    op("RLA", IndirectY, false),
+    op("NOP", ZeroPageX, false),
+    op("AND", ZeroPageX, true),
+    op("ROL", ZeroPageX, true),
+    op("RLA", ZeroPageX, false),
+    op("SEC", Implied, true),
+    op("AND", AbsoluteY, true),
+    op("NOP", Implied, false),
+    op("RLA", AbsoluteY, false),
+    op("NOP", AbsoluteX, false),
+    op("AND", AbsoluteX, true),
+    op("ROL", AbsoluteX, true),
+    op("RLA", AbsoluteX, false),
+    // $4x
+    op("RTI", Implied, true),
+    op("EOR", IndirectX, true),
+    op("JAM", Implied, false),
+    op("SRE", IndirectX, false),
+    op("NOP", ZeroPage, false),
+    op("EOR", ZeroPage, true),
+    op("LSR", ZeroPage, true),
+    op("SRE", ZeroPage, false),
+    op("PHA", Implied, true),
+    op("EOR", Immediate, true),
+    op("LSR", Accumulator, true),
+    op("ALR", Immediate, false),
+    op("JMP", Absolute, true),
+    op("EOR", Absolute, true),
+    op("LSR", Absolute, true),
+    op("SRE", Absolute, false),
+    // $5x
+    op("BVC", Relative, true),
+    op("EOR", IndirectY, true),
+    op("JAM", Implied, false),
+    op("SRE", IndirectY, false),
+    op("NOP", ZeroPageX, false),
+    op("EOR", ZeroPageX, true),
+    op("LSR", ZeroPageX, true),
+    op("SRE", ZeroPageX, false),
+    op("CLI", Implied, true),
+    op("EOR", AbsoluteY, true),
+    op("NOP", Implied, false),
+    op("SRE", AbsoluteY, false),
+    op("NOP", AbsoluteX, false),
+    op("EOR", AbsoluteX, true),
+    op("LSR", AbsoluteX, true),
+    op("SRE", AbsoluteX, false),
+    // $6x
+    op("RTS", Implied, true),
+    op("ADC", IndirectX, true),
+    op("JAM", Implied, false),
+    op("RRA", IndirectX, false),
+    op("NOP", ZeroPage, false),
+    op("ADC", ZeroPage, true),
+    op("ROR", ZeroPage, true),
+    op("RRA", ZeroPage, false),
+    op("PLA", Implied, true),
+    op("ADC", Immediate, true),
+    op("ROR", Accumulator, true),
+    op("ARR", Immediate, false),
+    op("JMP", Indirect, true),
+    op("ADC", Absolute, true),
+    op("ROR", Absolute, true),
+    op("RRA", Absolute, false),
+    // $7x
+    op("BVS", Relative, true),
+    op("ADC", IndirectY, true),
+    op("JAM", Implied, false),
+    op("RRA", IndirectY, false),
+    op("NOP", ZeroPageX, false),
+    op("ADC", ZeroPageX, true),
+    op("ROR", ZeroPageX, true),
+    op("RRA", ZeroPageX, false),
+    op("SEI", Implied, true),
+    op("ADC", AbsoluteY, true),
+    op("NOP", Implied, false),
+    op("RRA", AbsoluteY, false),
+    op("NOP", AbsoluteX, false),
+    op("ADC", AbsoluteX, true),
+    op("ROR", AbsoluteX, true),
+    op("RRA", AbsoluteX, false),
+    // $8x
+    op("NOP", Immediate, false),
+    op("STA", IndirectX, true),
+    op("NOP", Immediate, false),
+    op("SAX", IndirectX, false),
+    op("STY", ZeroPage, true),
+    op("STA", ZeroPage, true),
+    op("STX", ZeroPage, true),
+    op("SAX", ZeroPage, false),
+    op("DEY", Implied, true),
+    op("NOP", Immediate, false),
+    op("TXA", Implied, true),
+    op("XAA", Immediate, false),
+    op("STY", Absolute, true),
+    op("STA", Absolute, true),
+    op("STX", Absolute, true),
+    op("SAX", Absolute, false),
+    // $9x
+    op("BCC", Relative, true),
+    op("STA", IndirectY, true),
+    op("JAM", Implied, false),
+    op("AHX", IndirectY, false),
+    op("STY", ZeroPageX, true),
+    op("STA", ZeroPageX, true),
+    op("STX", ZeroPageY, true),
+    op("SAX", ZeroPageY, false),
+    op("TYA", Implied, true),
+    op("STA", AbsoluteY, true),
+    op("TXS", Implied, true),
+    op("TAS", AbsoluteY, false),
+    op("SHY", AbsoluteX, false),
+    op("STA", AbsoluteX, true),
+    op("SHX", AbsoluteY, false),
+    op("AHX", AbsoluteY, false),
+    // $Ax
+    op("LDY", Immediate, true),
+    op("LDA", IndirectX, true),
+    op("LDX", Immediate, true),
+    op("LAX", IndirectX, false),
+    op("LDY", ZeroPage, true),
+    op("LDA", ZeroPage, true),
+    op("LDX", ZeroPage, true),
+    op("LAX", ZeroPage, false),
+    op("TAY", Implied, true),
+    op("LDA", Immediate, true),
+    op("TAX", Implied, true),
+    op("LAX", Immediate, false),
+    op("LDY", Absolute, true),
+    op("LDA", Absolute, true),
+    op("LDX", Absolute, true),
+    op("LAX", Absolute, false),
+    // $Bx
+    op("BCS", Relative, true),
+    op("LDA", IndirectY, true),
+    op("JAM", Implied, false),
+    op("LAX", IndirectY, false),
+    op("LDY", ZeroPageX, true),
+    op("LDA", ZeroPageX, true),
+    op("LDX", ZeroPageY, true),
+    op("LAX", ZeroPageY, false),
+    op("CLV", Implied, true),
+    op("LDA", AbsoluteY, true),
+    op("TSX", Implied, true),
+    op("LAS", AbsoluteY, false),
+    op("LDY", AbsoluteX, true),
+    op("LDA", AbsoluteX, true),
+    op("LDX", AbsoluteY, true),
+    op("LAX", AbsoluteY, false),
+    // $Cx
+    op("CPY", Immediate, true),
+    op("CMP", IndirectX, true),
+    op("NOP", Immediate, false),
+    op("DCP", IndirectX, false),
+    op("CPY", ZeroPage, true),
+    op("CMP", ZeroPage, true),
+    op("DEC", ZeroPage, true),
+    op("DCP", ZeroPage, false),
+    op("INY", Implied, true),
+    op("CMP", Immediate, true),
+    op("DEX", Implied, true),
+    op("AXS", Immediate, false),
+    op("CPY", Absolute, true),
+    op("CMP", Absolute, true),
+    op("DEC", Absolute, true),
+    op("DCP", Absolute, false),
+    // $Dx
+    op("BNE", Relative, true),
+    op("CMP", IndirectY, true),
+    op("JAM", Implied, false),
+    op("DCP", IndirectY, false),
+    op("NOP", ZeroPageX, false),
+    op("CMP", ZeroPageX, true),
+    op("DEC", ZeroPageX, true),
+    op("DCP", ZeroPageX, false),
+    op("CLD", Implied, true),
+    op("CMP", AbsoluteY, true),
+    op("NOP", Implied, false),
+    op("DCP", AbsoluteY, false),
+    op("NOP", AbsoluteX, false),
+    op("CMP", AbsoluteX, true),
+    op("DEC", AbsoluteX, true),
+    op("DCP", AbsoluteX, false),
+    // $Ex
+    op("CPX", Immediate, true),
+    op("SBC", IndirectX, true),
+    op("NOP", Immediate, false),
+    op("ISC", IndirectX, false),
+    op("CPX", ZeroPage, true),
+    op("SBC", ZeroPage, true),
+    op("INC", ZeroPage, true),
+    op("ISC", ZeroPage, false),
+    op("INX", Implied, true),
+    op("SBC", Immediate, true),
+    op("NOP", Implied, true),
+    op("SBC", Immediate, false),
+    op("CPX", Absolute, true),
+    op("SBC", Absolute, true),
+    op("INC", Absolute, true),
+    op("ISC", Absolute, false),
+    // $Fx
+    op("BEQ", Relative, true),
+    op("SBC", IndirectY, true),
+    op("JAM", Implied, false),
+    op("ISC", IndirectY, false),
+    op("NOP", ZeroPageX, false),
+    op("SBC", ZeroPageX, true),
+    op("INC", ZeroPageX, true),
+    op("ISC", ZeroPageX, false),
+    op("SED", Implied, true),
+    op("SBC", AbsoluteY, true),
+    op("NOP", Implied, false),
+    op("ISC", AbsoluteY, false),
+    op("NOP", AbsoluteX, false),
+    op("SBC", AbsoluteX, true),
+    op("INC", AbsoluteX, true),
+    op("ISC", AbsoluteX, false),
+];
+
+// One decoded instruction, which formats as assembly (`LDA ($20),Y`, `BNE $C012`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: u8,
+    pub operand: u16, // Zero-extended for one-byte operands
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    pub official: bool,
+}
+
+impl Instruction {
+    // Bytes including the opcode
+    pub fn size(&self) -> u16 {
+        1 + self.mode.operand_size()
+    }
+
+    // The instruction's bytes, as many of the three as it uses
+    pub fn bytes(&self) -> ([u8; 3], usize) {
+        let [low, high] = self.operand.to_le_bytes();
+        ([self.opcode, low, high], self.size() as usize)
+    }
+
+    // Where a branch goes if taken
+    pub fn branch_target(&self) -> Option<u16> {
+        (self.mode == Relative).then(|| {
+            let offset = self.operand as u8 as i8;
+            self.address.wrapping_add(2).wrapping_add(offset as u16)
+        })
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = self.operand;
+        write!(f, "{}", self.mnemonic)?;
+        match self.mode {
+            Implied => Ok(()),
+            Accumulator => write!(f, " A"),
+            Immediate => write!(f, " #${:02X}", operand),
+            ZeroPage => write!(f, " ${:02X}", operand),
+            ZeroPageX => write!(f, " ${:02X},X", operand),
+            ZeroPageY => write!(f, " ${:02X},Y", operand),
+            Absolute => write!(f, " ${:04X}", operand),
+            AbsoluteX => write!(f, " ${:04X},X", operand),
+            AbsoluteY => write!(f, " ${:04X},Y", operand),
+            Indirect => write!(f, " (${:04X})", operand),
+            IndirectX => write!(f, " (${:02X},X)", operand),
+            IndirectY => write!(f, " (${:02X}),Y", operand),
+            Relative => write!(f, " ${:04X}", self.branch_target().unwrap_or(0)),
+        }
+    }
+}
+
+// Decode the instruction at `address`, reading its bytes through `read` (e.g. `CpuBus::peek`,
+// so nothing is disturbed)
+pub fn decode(address: u16, mut read: impl FnMut(u16) -> u8) -> Instruction {
+    let opcode = read(address);
+    let Opcode {
+        mnemonic,
+        mode,
+        official,
+    } = OPCODES[opcode as usize];
+    let operand = match mode.operand_size() {
+        0 => 0,
+        1 => read(address.wrapping_add(1)) as u16,
+        _ => u16::from_le_bytes([read(address.wrapping_add(1)), read(address.wrapping_add(2))]),
+    };
+    Instruction {
+        address,
+        opcode,
+        operand,
+        mnemonic,
+        mode,
+        official,
+    }
+}
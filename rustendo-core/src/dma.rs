@@ -0,0 +1,56 @@
+// Pending DMA transfers. Both units halt the CPU and share its bus, so the actual transfers are
+// run by `CpuBus::run_dma` where the get/put cycle alignment and their interaction is handled.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dma {
+    oam_page: Option<u8>,     // Page written to $4014, waiting to be copied to OAM
+    dmc_address: Option<u16>, // Sample byte the DMC channel asked for
+    dmc_sample: Option<u8>,   // Fetched sample byte, waiting for the DMC channel to collect it
+}
+
+impl Default for Dma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dma {
+    pub fn new() -> Self {
+        Self {
+            oam_page: None,
+            dmc_address: None,
+            dmc_sample: None,
+        }
+    }
+
+    pub fn start_oam(&mut self, page: u8) {
+        self.oam_page = Some(page);
+    }
+
+    pub fn request_dmc(&mut self, address: u16) {
+        self.dmc_address = Some(address);
+    }
+
+    pub fn take_oam_page(&mut self) -> Option<u8> {
+        self.oam_page.take()
+    }
+
+    pub fn take_dmc_address(&mut self) -> Option<u16> {
+        self.dmc_address.take()
+    }
+
+    pub fn dmc_pending(&self) -> bool {
+        self.dmc_address.is_some()
+    }
+
+    pub fn pending(&self) -> bool {
+        self.oam_page.is_some() || self.dmc_address.is_some()
+    }
+
+    pub fn finish_dmc(&mut self, sample: u8) {
+        self.dmc_sample = Some(sample);
+    }
+
+    pub fn take_dmc_sample(&mut self) -> Option<u8> {
+        self.dmc_sample.take()
+    }
+}
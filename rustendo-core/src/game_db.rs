@@ -0,0 +1,142 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use core::fmt;
+
+use crate::region::Region;
+use crate::rom::Rom;
+
+// A database of known dumps, keyed by the CRC-32 of their PRG-ROM followed by their CHR-ROM,
+// that gives each game its title and board and corrects headers that get them wrong.
+//
+// The text format has one game per line, with `#` starting a comment:
+//
+//     <crc32>;<mapper>[.<submapper>];<H|V|4>;<PRG-RAM KB>[b];<NTSC|PAL|Dendy>;<board>;<title>
+//
+// e.g. `1A2B3C4D;1;H;8b;NTSC;SNROM;Some Game`, where H/V/4 is horizontal, vertical or
+// four-screen mirroring and a `b` after the PRG-RAM size marks it as battery-backed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameEntry {
+    pub crc32: u32,
+    pub title: String,
+    pub board: String,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: u8, // 0 horizontal, 1 vertical, as in the header
+    pub four_screen: bool,
+    pub prg_ram_size: usize,
+    pub battery: bool,
+    pub region: Region,
+}
+
+impl GameEntry {
+    // Overwrite whatever the header said about the board, returning whether anything changed
+    pub fn apply(&self, rom: &mut Rom) -> bool {
+        let header = board_fields(rom);
+        rom.mapper = self.mapper;
+        rom.submapper = self.submapper;
+        rom.mirroring = self.mirroring;
+        rom.four_screen = self.four_screen;
+        rom.prg_ram_size = self.prg_ram_size;
+        rom.battery = self.battery;
+        rom.region = self.region;
+        header != board_fields(rom)
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(7, ';').map(str::trim);
+        let crc32 = u32::from_str_radix(fields.next()?, 16).ok()?;
+        let mapper = fields.next()?;
+        let (mapper, submapper) = mapper.split_once('.').unwrap_or((mapper, "0"));
+        let (mapper, submapper) = (mapper.parse().ok()?, submapper.parse().ok()?);
+        let (mirroring, four_screen) = match fields.next()? {
+            "H" => (0, false),
+            "V" => (1, false),
+            "4" => (0, true),
+            _ => return None,
+        };
+        let ram = fields.next()?;
+        let (ram, battery) = match ram.strip_suffix('b') {
+            Some(ram) => (ram, true),
+            None => (ram, false),
+        };
+        let prg_ram_size = ram.parse::<usize>().ok()? * 1024;
+        let region = match fields.next()? {
+            "NTSC" => Region::Ntsc,
+            "PAL" => Region::Pal,
+            "Dendy" => Region::Dendy,
+            _ => return None,
+        };
+        let board = fields.next()?.to_string();
+        let title = fields.next()?.to_string();
+        Some(Self {
+            crc32,
+            title,
+            board,
+            mapper,
+            submapper,
+            mirroring,
+            four_screen,
+            prg_ram_size,
+            battery,
+            region,
+        })
+    }
+}
+
+// What the database can correct about a header
+fn board_fields(rom: &Rom) -> (u16, u8, u8, bool, usize, bool, Region) {
+    (
+        rom.mapper,
+        rom.submapper,
+        rom.mirroring,
+        rom.four_screen,
+        rom.prg_ram_size,
+        rom.battery,
+        rom.region,
+    )
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct GameDbError {
+    pub line: usize, // 1-based
+}
+
+impl fmt::Display for GameDbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid game database entry on line {}", self.line)
+    }
+}
+
+impl core::error::Error for GameDbError {}
+
+#[derive(Debug, Clone, Default)]
+pub struct GameDb {
+    entries: BTreeMap<u32, GameEntry>,
+}
+
+impl GameDb {
+    pub fn parse(text: &str) -> Result<Self, GameDbError> {
+        let mut entries = BTreeMap::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let entry = GameEntry::parse(line).ok_or(GameDbError { line: index + 1 })?;
+            entries.insert(entry.crc32, entry);
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn lookup(&self, rom: &Rom) -> Option<&GameEntry> {
+        self.entries.get(&rom.crc32())
+    }
+}
@@ -0,0 +1,15 @@
+// Something that drives the controllers from code rather than from OS events: a bot, a test
+// script or a netplay peer. `Nes` polls it for every port at the start of each frame, when
+// games read their input, so it is asked once per frame and port.
+pub trait InputSource {
+    // The buttons held on `port` during `frame`, as a bitmask (bit 0 = A ... bit 7 = Right), or
+    // None to leave that controller as it is
+    fn poll(&mut self, frame: u64, port: usize) -> Option<u8>;
+}
+
+// Any `FnMut(frame, port) -> Option<u8>` closure works as a source
+impl<F: FnMut(u64, usize) -> Option<u8>> InputSource for F {
+    fn poll(&mut self, frame: u64, port: usize) -> Option<u8> {
+        self(frame, port)
+    }
+}
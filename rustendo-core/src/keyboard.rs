@@ -0,0 +1,101 @@
+// The Family BASIC keyboard (HVC-007), plugged into the Famicom's expansion port. Its 72 keys
+// form a matrix of 9 rows by 2 columns of 4 keys. Writing $4016 selects a row and column and
+// reading $4017 returns that column's keys on bits 1-4, low while a key is down.
+//
+// $4016 writes: bit 0 returns to row 0, bit 1 selects the column (the row advances when it goes
+// from 1 back to 0) and bit 2 enables the matrix. Disabled, every key reads as down.
+pub const KEYBOARD_ROWS: usize = 9;
+
+// Key names in matrix order: row by row, column 0 then 1, each from $4017 bit 4 down to bit 1
+#[rustfmt::skip]
+pub const KEY_NAMES: [&str; 72] = [
+    "]", "[", "RETURN", "F8", "STOP", "YEN", "RSHIFT", "KANA",
+    ";", ":", "@", "F7", "^", "-", "/", "_",
+    "K", "L", "O", "F6", "0", "P", ",", ".",
+    "J", "U", "I", "F5", "8", "9", "N", "M",
+    "H", "G", "Y", "F4", "6", "7", "V", "B",
+    "D", "R", "T", "F3", "4", "5", "C", "F",
+    "A", "S", "W", "F2", "3", "E", "Z", "X",
+    "CTR", "Q", "ESC", "F1", "2", "1", "GRPH", "LSHIFT",
+    "LEFT", "RIGHT", "UP", "CLR", "INS", "DEL", "SPACE", "DOWN",
+];
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FamilyKeyboard {
+    keys: [[bool; 4]; 18], // Held down, by row * 2 + column and then as in `KEY_NAMES`
+    row: usize,            // Selected row, `KEYBOARD_ROWS` once scanning has gone past the last
+    column: usize,         // Selected column, 0 or 1
+    enabled: bool,         // Matrix enable bit of the last $4016 write
+}
+
+impl Default for FamilyKeyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FamilyKeyboard {
+    pub fn new() -> Self {
+        Self {
+            keys: [[false; 4]; 18],
+            row: 0,
+            column: 0,
+            enabled: false,
+        }
+    }
+
+    // The index of a key in `KEY_NAMES`, by name in any case
+    pub fn key_index(name: &str) -> Option<usize> {
+        KEY_NAMES
+            .iter()
+            .position(|key| key.eq_ignore_ascii_case(name))
+    }
+
+    pub fn press_key(&mut self, key: usize) {
+        self.keys[key / 4][key % 4] = true;
+    }
+
+    pub fn release_key(&mut self, key: usize) {
+        self.keys[key / 4][key % 4] = false;
+    }
+
+    pub fn release_all(&mut self) {
+        self.keys = [[false; 4]; 18];
+    }
+
+    pub fn pressed(&self, key: usize) -> bool {
+        self.keys[key / 4][key % 4]
+    }
+
+    // Take the scanning position from a save state, keeping the keys held now
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore(&mut self, saved: &FamilyKeyboard) {
+        self.row = saved.row;
+        self.column = saved.column;
+        self.enabled = saved.enabled;
+    }
+
+    pub fn write(&mut self, value: u8) {
+        let column = (value >> 1 & 0x01) as usize;
+        if value & 0x01 != 0 {
+            self.row = 0;
+        } else if self.column == 1 && column == 0 {
+            self.row = (self.row + 1).min(KEYBOARD_ROWS);
+        }
+        self.column = column;
+        self.enabled = value & 0x04 != 0;
+    }
+
+    // Bits 1-4 of a $4017 read. Past the last row every key reads as down, which is how
+    // programs tell the keyboard is there.
+    pub fn read(&self) -> u8 {
+        if !self.enabled || self.row >= KEYBOARD_ROWS {
+            return 0;
+        }
+        self.keys[self.row * 2 + self.column]
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &down)| bits | (!down as u8) << (4 - i))
+    }
+}
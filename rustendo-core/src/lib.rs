@@ -0,0 +1,52 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![allow(clippy::upper_case_acronyms)]
+
+extern crate alloc;
+
+pub mod apu;
+pub mod cheat;
+pub mod checksum;
+pub mod compositor;
+pub mod controller;
+pub mod cpu;
+pub mod cpu_bus;
+pub mod debug;
+pub mod disasm;
+pub mod dma;
+pub mod game_db;
+pub mod input;
+pub mod keyboard;
+pub mod mapper;
+pub mod movie;
+pub mod nes;
+#[cfg(feature = "std")]
+pub mod netplay;
+pub mod ntsc;
+pub mod osd;
+pub mod overscan;
+pub mod palette;
+pub mod patch;
+pub mod ppu;
+pub mod ppu_bus;
+#[cfg(feature = "std")]
+pub mod profile;
+pub mod ram_search;
+pub mod region;
+pub mod rom;
+mod scheduler;
+#[cfg(feature = "serde")]
+mod serde_arrays;
+#[cfg(feature = "serde")]
+pub mod state;
+#[cfg(test)]
+mod test_rom;
+#[cfg(feature = "std")]
+pub mod wav;
+pub mod zip;
+
+pub use nes::Nes;
+pub use overscan::Overscan;
+pub use palette::{Palette, PixelFormat};
+pub use ppu::{LayerVisibility, RenderMode};
+pub use region::Region;
+pub use rom::Rom;
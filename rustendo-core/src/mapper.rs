@@ -0,0 +1,315 @@
+use alloc::rc::Rc;
+#[cfg(feature = "serde")]
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+use tracing::info;
+
+use crate::rom::Rom;
+#[cfg(feature = "serde")]
+use crate::state::{self, StateError};
+
+mod action53;
+mod bnrom;
+mod camerica;
+mod cartridge_memory;
+mod color_dreams;
+mod gxrom;
+mod mmc1;
+mod mmc3;
+mod namco108;
+mod namco163;
+mod nrom;
+mod prg_ram;
+mod unrom512;
+mod vrc4;
+mod vrc6;
+mod vrc_irq;
+
+use action53::Action53;
+use bnrom::Bnrom;
+use camerica::Camerica;
+use color_dreams::ColorDreams;
+use gxrom::Gxrom;
+use mmc1::Mmc1;
+use mmc3::Mmc3;
+use namco108::Namco108;
+use namco163::Namco163;
+use nrom::Nrom;
+use unrom512::Unrom512;
+use vrc4::Vrc4;
+use vrc6::Vrc6;
+
+// How the PPU's two nametables of internal RAM are arranged in $2000-$2FFF
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Mirroring {
+    Horizontal,        // $2000/$2400 share a table, as do $2800/$2C00
+    Vertical,          // $2000/$2800 share a table, as do $2400/$2C00
+    SingleScreenLower, // All four show the first table
+    SingleScreenUpper, // All four show the second table
+    FourScreen,        // Extra RAM on the cartridge gives each its own table
+}
+
+// How the board currently guards its PRG-RAM, for debuggers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrgRamProtection {
+    pub enabled: bool,       // Disabled RAM is neither read nor written
+    pub read_protected: u8,  // Bit n makes window n read back as 0
+    pub write_protected: u8, // Bit n protects window n from writes
+    pub window: usize,       // Window size in bytes, from the start of the RAM
+}
+
+// Saving and restoring a board's state, banks and RAM included, for save states. With the
+// `serde` feature every board that derives Serialize and Deserialize, and implements
+// CartridgeRom, gets it.
+pub trait MapperState {
+    #[cfg(feature = "serde")]
+    fn save_state(&self) -> Vec<u8>;
+
+    // Leaves the board untouched if the state doesn't fit it
+    #[cfg(feature = "serde")]
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError>;
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned + CartridgeRom> MapperState for T {
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut board: T = state::decode(state).ok_or(StateError::InvalidSection("cartridge"))?;
+        board.take_rom(self);
+        *self = board;
+        Ok(())
+    }
+}
+
+// States leave out the cartridge's ROM, which the game can't change, to stay small. Loading one
+// takes the ROM from the board it replaces: the state's header has already checked that both
+// are the same game.
+#[cfg(feature = "serde")]
+pub trait CartridgeRom {
+    fn take_rom(&mut self, board: &mut Self);
+}
+
+#[cfg(not(feature = "serde"))]
+impl<T> MapperState for T {}
+
+// The cartridge board: it decodes CPU accesses to $4020-$FFFF and PPU accesses to the pattern
+// tables at $0000-$1FFF, and wires up the nametables
+pub trait Mapper: MapperState {
+    // `None` where the board drives nothing onto the data bus, leaving it open
+    fn cpu_read(&mut self, address: u16) -> Option<u8>;
+
+    // `cpu_read` without side effects, for debuggers. Boards whose reads change state override
+    // it.
+    fn cpu_peek(&mut self, address: u16) -> Option<u8> {
+        self.cpu_read(address)
+    }
+    fn cpu_write(&mut self, address: u16, value: u8);
+    fn ppu_read(&mut self, address: u16) -> u8;
+    fn ppu_write(&mut self, address: u16, value: u8);
+    fn mirroring(&self) -> Mirroring;
+
+    // Whether the board is asserting the CPU's IRQ line
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    // Called once per CPU cycle, for boards that count cycles
+    fn cpu_tick(&mut self) {}
+
+    // Boards that count filtered rising edges of PPU A12, like the MMC3's scanline counter,
+    // say so here to have `a12_rise` called right after the PPU dot that caused each one
+    fn counts_a12_rises(&self) -> bool {
+        false
+    }
+
+    fn a12_rise(&mut self) {}
+
+    // CPU cycles until `cpu_tick` next does something the CPU can see, like raising an IRQ.
+    // The bus runs the ticks in batches until then, so an estimate may be early but never late.
+    fn cycles_until_event(&self) -> u64 {
+        u64::MAX
+    }
+
+    // Write PRG-RAM at $6000-$7FFF directly, past the board's registers and its enable and
+    // protection bits, for frozen cheats. Boards without PRG-RAM ignore it.
+    fn write_prg_ram(&mut self, _address: u16, _value: u8) {}
+
+    // For boards that can disable or write-protect their PRG-RAM
+    fn prg_ram_protection(&self) -> Option<PrgRamProtection> {
+        None
+    }
+
+    // Memory the board keeps across power cycles (battery-backed RAM or self-written flash),
+    // if it has any
+    fn save_data(&self) -> Option<&[u8]> {
+        None
+    }
+
+    // Restore `save_data` from an earlier run; data of the wrong size is ignored
+    fn load_save_data(&mut self, _data: &[u8]) {}
+
+    // Whether `save_data` changed since the last call
+    fn take_save_changed(&mut self) -> bool {
+        false
+    }
+}
+
+// A mapper shared between the CPU bus and the PPU bus
+pub type SharedMapper = Rc<RefCell<dyn Mapper>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapperError {
+    Unsupported(u16),
+}
+
+impl fmt::Display for MapperError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapperError::Unsupported(number) => write!(f, "mapper {} is not supported", number),
+        }
+    }
+}
+
+impl core::error::Error for MapperError {}
+
+pub struct MapperInfo {
+    pub number: u16,
+    pub name: &'static str,
+    create: fn(&Rom) -> SharedMapper,
+}
+
+// Every supported board, by iNES mapper number
+pub const MAPPERS: &[MapperInfo] = &[
+    MapperInfo {
+        number: 0,
+        name: "NROM",
+        create: |rom| Rc::new(RefCell::new(Nrom::new(rom))),
+    },
+    MapperInfo {
+        number: 1,
+        name: "MMC1",
+        create: |rom| Rc::new(RefCell::new(Mmc1::new(rom))),
+    },
+    MapperInfo {
+        number: 4,
+        name: "MMC3/MMC6",
+        create: |rom| Rc::new(RefCell::new(Mmc3::new(rom))),
+    },
+    MapperInfo {
+        number: 11,
+        name: "Color Dreams",
+        create: |rom| Rc::new(RefCell::new(ColorDreams::new(rom))),
+    },
+    MapperInfo {
+        number: 19,
+        name: "Namco 129/163",
+        create: |rom| Rc::new(RefCell::new(Namco163::new(rom))),
+    },
+    MapperInfo {
+        number: 21,
+        name: "VRC4a/VRC4c",
+        create: |rom| Rc::new(RefCell::new(Vrc4::new(rom))),
+    },
+    MapperInfo {
+        number: 22,
+        name: "VRC2a",
+        create: |rom| Rc::new(RefCell::new(Vrc4::new(rom))),
+    },
+    MapperInfo {
+        number: 23,
+        name: "VRC2b/VRC4e/VRC4f",
+        create: |rom| Rc::new(RefCell::new(Vrc4::new(rom))),
+    },
+    MapperInfo {
+        number: 24,
+        name: "VRC6a",
+        create: |rom| Rc::new(RefCell::new(Vrc6::new(rom))),
+    },
+    MapperInfo {
+        number: 25,
+        name: "VRC2c/VRC4b/VRC4d",
+        create: |rom| Rc::new(RefCell::new(Vrc4::new(rom))),
+    },
+    MapperInfo {
+        number: 26,
+        name: "VRC6b",
+        create: |rom| Rc::new(RefCell::new(Vrc6::new(rom))),
+    },
+    MapperInfo {
+        number: 28,
+        name: "Action 53",
+        create: |rom| Rc::new(RefCell::new(Action53::new(rom))),
+    },
+    MapperInfo {
+        number: 30,
+        name: "UNROM-512",
+        create: |rom| Rc::new(RefCell::new(Unrom512::new(rom))),
+    },
+    MapperInfo {
+        number: 34,
+        name: "BNROM/NINA-001",
+        create: |rom| Rc::new(RefCell::new(Bnrom::new(rom))),
+    },
+    MapperInfo {
+        number: 66,
+        name: "GxROM",
+        create: |rom| Rc::new(RefCell::new(Gxrom::new(rom))),
+    },
+    MapperInfo {
+        number: 71,
+        name: "Camerica/Codemasters",
+        create: |rom| Rc::new(RefCell::new(Camerica::new(rom))),
+    },
+    MapperInfo {
+        number: 206,
+        name: "Namco 108 (DxROM)",
+        create: |rom| Rc::new(RefCell::new(Namco108::new(rom))),
+    },
+];
+
+pub fn info(number: u16) -> Option<&'static MapperInfo> {
+    MAPPERS.iter().find(|info| info.number == number)
+}
+
+// Build the board the ROM header asks for
+pub fn create(rom: &Rom) -> Result<SharedMapper, MapperError> {
+    let info = info(rom.mapper).ok_or(MapperError::Unsupported(rom.mapper))?;
+    info!(
+        target: "mapper",
+        "Mapper {} ({}), {}KB PRG-ROM, {}KB CHR-ROM",
+        info.number,
+        info.name,
+        rom.prg_rom.len() / 1024,
+        rom.chr_rom.len() / 1024
+    );
+    Ok((info.create)(rom))
+}
+
+// The mirroring selected by the header, for boards with fixed nametable wiring
+pub fn header_mirroring(rom: &Rom) -> Mirroring {
+    if rom.mirroring != 0 {
+        Mirroring::Vertical
+    } else {
+        Mirroring::Horizontal
+    }
+}
+
+// Whether the board carries 2KB of nametable RAM of its own (header bit 3), making all four
+// nametables distinct whatever its mirroring control says. Mapper 30 uses the bit for
+// something else.
+pub fn has_four_screen_vram(rom: &Rom) -> bool {
+    rom.four_screen && rom.mapper != 30
+}
+
+// The index of the byte at `offset` into the `bank`th `size`-byte bank of `memory`. Bank
+// numbers wrap around the banks that exist, the way unconnected high bank lines behave, and a
+// memory smaller than one bank repeats. `memory` must not be empty.
+fn bank_index(memory: &[u8], size: usize, bank: usize, offset: usize) -> usize {
+    let banks = (memory.len() / size).max(1);
+    ((bank % banks) * size + offset % size) % memory.len()
+}
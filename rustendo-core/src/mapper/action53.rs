@@ -0,0 +1,189 @@
+use super::cartridge_memory::CartridgeMemory;
+#[cfg(feature = "serde")]
+use super::CartridgeRom;
+use super::{bank_index, Mapper, Mirroring};
+use crate::rom::Rom;
+
+// Action 53 (mapper 28), the homebrew multicart board. A write to $5000-$5FFF picks one of
+// four registers and writes to $8000-$FFFF set it:
+//
+//   $00  CHR-RAM bank (bits 0-1), and the single-screen page (bit 4) in single-screen modes
+//   $01  inner PRG bank (bits 0-3), likewise with the single-screen page in bit 4
+//   $80  mode: mirroring (bits 0-1), PRG layout (bits 2-3), game size (bits 4-5)
+//   $81  outer PRG bank, in 32KB units
+//
+// The outer bank places a game in the ROM and the inner bank switches within it, with the game
+// size deciding how many of the inner bank's bits are used. Each game then sees a 32KB bank
+// or an UNROM-style switchable 16KB half next to a fixed one.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Action53 {
+    prg_rom: CartridgeMemory,
+    chr: CartridgeMemory,
+    register: u8, // Selected by $5000-$5FFF, $00, $01, $80 or $81
+    chr_bank: u8,
+    inner_bank: u8,
+    mode: u8,
+    outer_bank: u8,
+    mirroring: Mirroring,
+}
+
+impl Action53 {
+    pub fn new(rom: &Rom) -> Self {
+        Self {
+            prg_rom: CartridgeMemory::new(rom.prg_rom.clone(), false),
+            chr: CartridgeMemory::chr(rom, 0x8000),
+            register: 0,
+            chr_bank: 0,
+            inner_bank: 0,
+            mode: 0,
+            // The menu lives in the last 32KB, which the board starts up in
+            outer_bank: 0xFF,
+            mirroring: Mirroring::SingleScreenLower,
+        }
+    }
+
+    fn prg_bank(&self, address: u16) -> usize {
+        let a14 = (address >> 14 & 0x01) as usize;
+        let outer = (self.outer_bank as usize) << 1;
+        let layout = self.mode >> 2 & 0x03;
+        let mask = (2 << (self.mode >> 4 & 0x03)) - 1;
+        match layout {
+            // UNROM-style, with the first half fixed for layout 2 and the second for layout 3
+            2 | 3 if a14 == (layout & 0x01) as usize => outer | a14,
+            2 | 3 => outer & !mask | self.inner_bank as usize & mask,
+            _ => outer & !mask | ((self.inner_bank as usize) << 1 | a14) & mask,
+        }
+    }
+
+    fn write_register(&mut self, value: u8) {
+        match self.register {
+            0x00 | 0x01 => {
+                if self.register == 0x00 {
+                    self.chr_bank = value & 0x03;
+                } else {
+                    self.inner_bank = value & 0x0F;
+                }
+                if self.mode & 0x02 == 0 {
+                    self.set_mirroring(value >> 4 & 0x01);
+                }
+            }
+            0x80 => {
+                self.mode = value & 0x3F;
+                self.set_mirroring(value & 0x03);
+            }
+            _ => self.outer_bank = value,
+        }
+    }
+
+    fn set_mirroring(&mut self, mode: u8) {
+        self.mode = self.mode & !0x03 | mode;
+        self.mirroring = match mode {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        };
+    }
+
+    fn chr_index(&self, address: u16) -> usize {
+        bank_index(&self.chr, 0x2000, self.chr_bank as usize, address as usize)
+    }
+}
+
+impl Mapper for Action53 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => {
+                let bank = self.prg_bank(address);
+                Some(self.prg_rom[bank_index(&self.prg_rom, 0x4000, bank, address as usize)])
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x5000..=0x5FFF => self.register = value & 0x81,
+            0x8000..=0xFFFF => self.write_register(value),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_index(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr.is_writable() {
+            let index = self.chr_index(address);
+            self.chr[index] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(feature = "serde")]
+impl CartridgeRom for Action53 {
+    fn take_rom(&mut self, board: &mut Self) {
+        self.prg_rom.take_rom(&mut board.prg_rom);
+        self.chr.take_rom(&mut board.chr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom;
+
+    fn write(board: &mut Action53, register: u8, value: u8) {
+        board.cpu_write(0x5000, register);
+        board.cpu_write(0x8000, value);
+    }
+
+    fn board() -> Action53 {
+        Action53::new(&test_rom::banked(28, 0x20000, 0))
+    }
+
+    #[test]
+    fn starts_in_the_last_32kb() {
+        let mut board = board();
+        assert_eq!(
+            [board.cpu_read(0x8000), board.cpu_read(0xC000)],
+            [Some(12), Some(14)]
+        );
+        assert_eq!(board.mirroring(), Mirroring::SingleScreenLower);
+    }
+
+    #[test]
+    fn unrom_games_inside_the_outer_bank() {
+        let mut board = board();
+        write(&mut board, 0x81, 2);
+        // 64KB game, second half fixed, vertical mirroring
+        write(&mut board, 0x80, 0x1E);
+        write(&mut board, 0x01, 0x12);
+        assert_eq!(
+            [board.cpu_read(0x8000), board.cpu_read(0xC000)],
+            [Some(12), Some(10)]
+        );
+        // Only the game size's bits of the inner bank count
+        write(&mut board, 0x01, 0x05);
+        assert_eq!(board.cpu_read(0x8000), Some(10));
+        assert_eq!(board.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn single_screen_page_and_chr_ram_banks() {
+        let mut board = board();
+        write(&mut board, 0x00, 0x13);
+        assert_eq!(board.mirroring(), Mirroring::SingleScreenUpper);
+        board.ppu_write(0x0000, 0x42);
+        write(&mut board, 0x00, 0x00);
+        assert_eq!(board.ppu_read(0x0000), 0);
+        assert_eq!(board.mirroring(), Mirroring::SingleScreenLower);
+        write(&mut board, 0x00, 0x03);
+        assert_eq!(board.ppu_read(0x0000), 0x42);
+    }
+}
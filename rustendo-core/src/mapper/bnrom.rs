@@ -0,0 +1,166 @@
+use super::cartridge_memory::CartridgeMemory;
+use super::prg_ram::PrgRam;
+#[cfg(feature = "serde")]
+use super::CartridgeRom;
+use super::{bank_index, header_mirroring, Mapper, Mirroring};
+use crate::rom::Rom;
+
+// Mapper 34 covers two unrelated boards. BNROM (submapper 2) selects a 32KB PRG bank by
+// writing anywhere in $8000-$FFFF and has CHR-RAM. NINA-001 (submapper 1) has 8KB of PRG-RAM
+// with registers at $7FFD (32KB PRG bank), $7FFE and $7FFF (4KB CHR banks). Without a
+// submapper, more than 8KB of CHR-ROM means NINA-001, as BNROM never has any.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bnrom {
+    prg_rom: CartridgeMemory,
+    prg_ram: PrgRam,
+    chr: CartridgeMemory,
+    nina: bool,
+    mirroring: Mirroring,
+    prg_bank: u8,
+    chr_banks: [u8; 2], // NINA-001 only
+}
+
+impl Bnrom {
+    pub fn new(rom: &Rom) -> Self {
+        let nina = match rom.submapper {
+            1 => true,
+            2 => false,
+            _ => rom.chr_rom.len() > 0x2000,
+        };
+        Self {
+            prg_rom: CartridgeMemory::new(rom.prg_rom.clone(), false),
+            prg_ram: PrgRam::new(rom, if nina { rom.prg_ram_size } else { 0 }),
+            chr: CartridgeMemory::chr(rom, 0x2000),
+            nina,
+            mirroring: header_mirroring(rom),
+            prg_bank: 0,
+            chr_banks: [0, 1],
+        }
+    }
+
+    fn chr_index(&self, address: u16) -> usize {
+        if self.nina {
+            let bank = self.chr_banks[address as usize >> 12 & 0x01];
+            bank_index(&self.chr, 0x1000, bank as usize, address as usize)
+        } else {
+            address as usize % self.chr.len()
+        }
+    }
+}
+
+impl Mapper for Bnrom {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF if self.nina => self.prg_ram.read(address),
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => {
+                let index = bank_index(
+                    &self.prg_rom,
+                    0x8000,
+                    self.prg_bank as usize,
+                    address as usize,
+                );
+                Some(self.prg_rom[index])
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if self.nina => {
+                self.prg_ram.write(address, value);
+                match address {
+                    0x7FFD => self.prg_bank = value & 0x01,
+                    0x7FFE => self.chr_banks[0] = value & 0x0F,
+                    0x7FFF => self.chr_banks[1] = value & 0x0F,
+                    _ => {}
+                }
+            }
+            0x8000..=0xFFFF if !self.nina => self.prg_bank = value,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_index(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr.is_writable() {
+            let index = self.chr_index(address);
+            self.chr[index] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn write_prg_ram(&mut self, address: u16, value: u8) {
+        self.prg_ram.force_write(address, value);
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        self.prg_ram.save_data()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.prg_ram.load_save_data(data);
+    }
+
+    fn take_save_changed(&mut self) -> bool {
+        self.prg_ram.take_changed()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl CartridgeRom for Bnrom {
+    fn take_rom(&mut self, board: &mut Self) {
+        self.prg_rom.take_rom(&mut board.prg_rom);
+        self.chr.take_rom(&mut board.chr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom;
+
+    #[test]
+    fn bnrom() {
+        let mut board = Bnrom::new(&test_rom::banked(34, 0x20000, 0));
+        assert_eq!(board.cpu_read(0xE000), Some(3));
+        board.cpu_write(0x8000, 2);
+        assert_eq!(board.cpu_read(0x8000), Some(8));
+        assert_eq!(board.cpu_read(0xE000), Some(11));
+        // No RAM, and CHR-RAM
+        board.cpu_write(0x6000, 0x42);
+        assert_eq!(board.cpu_read(0x6000), None);
+        board.ppu_write(0x1234, 0x42);
+        assert_eq!(board.ppu_read(0x1234), 0x42);
+    }
+
+    #[test]
+    fn nina_001() {
+        let mut board = Bnrom::new(&test_rom::banked(34, 0x10000, 0x10000));
+        assert_eq!([board.ppu_read(0x0000), board.ppu_read(0x1000)], [0, 4]);
+        board.cpu_write(0x7FFD, 1);
+        board.cpu_write(0x7FFE, 3);
+        board.cpu_write(0x7FFF, 5);
+        assert_eq!(board.cpu_read(0x8000), Some(4));
+        assert_eq!([board.ppu_read(0x0000), board.ppu_read(0x1000)], [12, 20]);
+        // The registers are in the RAM, and writes to ROM do nothing
+        assert_eq!(board.cpu_read(0x7FFD), Some(1));
+        board.cpu_write(0x8000, 0);
+        assert_eq!(board.cpu_read(0x8000), Some(4));
+    }
+
+    #[test]
+    fn submapper_decides() {
+        let mut rom = test_rom::banked(34, 0x10000, 0x2000);
+        rom.submapper = 1;
+        let mut board = Bnrom::new(&rom);
+        board.cpu_write(0x7FFD, 1);
+        assert_eq!(board.cpu_read(0x8000), Some(4));
+    }
+}
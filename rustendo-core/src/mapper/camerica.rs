@@ -0,0 +1,127 @@
+use super::cartridge_memory::CartridgeMemory;
+#[cfg(feature = "serde")]
+use super::CartridgeRom;
+use super::{bank_index, header_mirroring, Mapper, Mirroring};
+use crate::rom::Rom;
+
+// Camerica/Codemasters (mapper 71): like UxROM, a 16KB PRG bank at $8000 with the last one
+// fixed at $C000, but selected by writes to $C000-$FFFF. The Fire Hawk board (submapper 1)
+// also picks a single-screen nametable with bit 4 of writes to $9000-$9FFF. Other boards have
+// nothing there, so for plain iNES headers that control is switched on by the first such write.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Camerica {
+    prg_rom: CartridgeMemory,
+    chr: CartridgeMemory,
+    mirroring: Mirroring,
+    prg_bank: u8,
+}
+
+impl Camerica {
+    pub fn new(rom: &Rom) -> Self {
+        Self {
+            prg_rom: CartridgeMemory::new(rom.prg_rom.clone(), false),
+            chr: CartridgeMemory::chr(rom, 0x2000),
+            mirroring: if rom.submapper == 1 {
+                Mirroring::SingleScreenLower
+            } else {
+                header_mirroring(rom)
+            },
+            prg_bank: 0,
+        }
+    }
+
+    fn prg_index(&self, address: u16) -> usize {
+        let bank = match address {
+            0x8000..=0xBFFF => self.prg_bank as usize,
+            _ => self.prg_rom.len() / 0x4000 - 1,
+        };
+        bank_index(&self.prg_rom, 0x4000, bank, address as usize)
+    }
+}
+
+impl Mapper for Camerica {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0xFFFF if self.prg_rom.len() >= 0x4000 => {
+                Some(self.prg_rom[self.prg_index(address)])
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x9000..=0x9FFF => {
+                self.mirroring = if value & 0x10 == 0 {
+                    Mirroring::SingleScreenLower
+                } else {
+                    Mirroring::SingleScreenUpper
+                };
+            }
+            0xC000..=0xFFFF => self.prg_bank = value & 0x0F,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[address as usize % self.chr.len()]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr.is_writable() {
+            self.chr[address as usize & 0x1FFF] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(feature = "serde")]
+impl CartridgeRom for Camerica {
+    fn take_rom(&mut self, board: &mut Self) {
+        self.prg_rom.take_rom(&mut board.prg_rom);
+        self.chr.take_rom(&mut board.chr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom;
+
+    #[test]
+    fn last_bank_fixed_at_c000() {
+        let mut board = Camerica::new(&test_rom::banked(71, 0x20000, 0));
+        assert_eq!(
+            [board.cpu_read(0x8000), board.cpu_read(0xC000)],
+            [Some(0), Some(14)]
+        );
+        board.cpu_write(0xC000, 3);
+        assert_eq!(
+            [board.cpu_read(0x8000), board.cpu_read(0xA000)],
+            [Some(6), Some(7)]
+        );
+        assert_eq!(board.cpu_read(0xE000), Some(15));
+        // $8000-$BFFF has no bank register
+        board.cpu_write(0x8000, 1);
+        assert_eq!(board.cpu_read(0x8000), Some(6));
+    }
+
+    #[test]
+    fn fire_hawk_mirroring() {
+        let mut board = Camerica::new(&test_rom::banked(71, 0x20000, 0));
+        assert_eq!(board.mirroring(), Mirroring::Horizontal);
+        board.cpu_write(0x9000, 0x10);
+        assert_eq!(board.mirroring(), Mirroring::SingleScreenUpper);
+
+        let mut rom = test_rom::banked(71, 0x20000, 0);
+        rom.submapper = 1;
+        let mut board = Camerica::new(&rom);
+        assert_eq!(board.mirroring(), Mirroring::SingleScreenLower);
+        board.cpu_write(0x9FFF, 0x10);
+        board.cpu_write(0x9000, 0x00);
+        assert_eq!(board.mirroring(), Mirroring::SingleScreenLower);
+    }
+}
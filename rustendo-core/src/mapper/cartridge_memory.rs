@@ -0,0 +1,70 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
+
+use crate::rom::Rom;
+
+// PRG or CHR memory on the board: the game's ROM, or RAM (or flash) it can write. Save states
+// only carry the writable kind; ROM is taken back from the board being replaced when a state
+// loads.
+pub struct CartridgeMemory {
+    data: Vec<u8>,
+    writable: bool,
+}
+
+impl CartridgeMemory {
+    pub fn new(data: Vec<u8>, writable: bool) -> Self {
+        Self { data, writable }
+    }
+
+    // The cartridge's CHR-ROM, or `ram_size` bytes of CHR-RAM for boards that have none
+    pub fn chr(rom: &Rom, ram_size: usize) -> Self {
+        if rom.chr_rom.is_empty() {
+            Self::new(vec![0; ram_size], true)
+        } else {
+            Self::new(rom.chr_rom.clone(), false)
+        }
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    // Fill in ROM left out of a save state from `old`, the memory it replaces
+    #[cfg(feature = "serde")]
+    pub fn take_rom(&mut self, old: &mut Self) {
+        if !self.writable {
+            self.data = core::mem::take(&mut old.data);
+        }
+    }
+}
+
+impl Deref for CartridgeMemory {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl DerefMut for CartridgeMemory {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for CartridgeMemory {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data: &[u8] = if self.writable { &self.data } else { &[] };
+        (self.writable, data).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CartridgeMemory {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (writable, data) = <(bool, Vec<u8>)>::deserialize(deserializer)?;
+        Ok(Self { data, writable })
+    }
+}
@@ -0,0 +1,92 @@
+use super::cartridge_memory::CartridgeMemory;
+#[cfg(feature = "serde")]
+use super::CartridgeRom;
+use super::{bank_index, header_mirroring, Mapper, Mirroring};
+use crate::rom::Rom;
+
+// Mapper 11: a single register anywhere in $8000-$FFFF selects a 32KB PRG bank (bits 0-1) and
+// an 8KB CHR bank (bits 4-7)
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ColorDreams {
+    prg_rom: CartridgeMemory,
+    chr: CartridgeMemory,
+    mirroring: Mirroring,
+    prg_bank: usize,
+    chr_bank: usize,
+}
+
+impl ColorDreams {
+    pub fn new(rom: &Rom) -> Self {
+        Self {
+            prg_rom: CartridgeMemory::new(rom.prg_rom.clone(), false),
+            chr: CartridgeMemory::chr(rom, 0x2000),
+            mirroring: header_mirroring(rom),
+            prg_bank: 0,
+            chr_bank: 0,
+        }
+    }
+
+    fn chr_index(&self, address: u16) -> usize {
+        bank_index(&self.chr, 0x2000, self.chr_bank, address as usize)
+    }
+}
+
+impl Mapper for ColorDreams {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => {
+                let index = bank_index(&self.prg_rom, 0x8000, self.prg_bank, address as usize);
+                Some(self.prg_rom[index])
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if address >= 0x8000 {
+            self.prg_bank = (value & 0x03) as usize;
+            self.chr_bank = (value >> 4) as usize;
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_index(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr.is_writable() {
+            let index = self.chr_index(address);
+            self.chr[index] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(feature = "serde")]
+impl CartridgeRom for ColorDreams {
+    fn take_rom(&mut self, board: &mut Self) {
+        self.prg_rom.take_rom(&mut board.prg_rom);
+        self.chr.take_rom(&mut board.chr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom;
+
+    #[test]
+    fn banks() {
+        let mut board = ColorDreams::new(&test_rom::banked(11, 0x20000, 0x20000));
+        board.cpu_write(0x8000, 0xF3);
+        assert_eq!(board.cpu_read(0x8000), Some(12));
+        assert_eq!(board.cpu_read(0xE000), Some(15));
+        assert_eq!([board.ppu_read(0x0000), board.ppu_read(0x1C00)], [120, 127]);
+        board.cpu_write(0xFFFF, 0x11);
+        assert_eq!(board.cpu_read(0x8000), Some(4));
+        assert_eq!(board.ppu_read(0x0000), 8);
+    }
+}
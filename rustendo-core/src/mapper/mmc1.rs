@@ -0,0 +1,239 @@
+use super::cartridge_memory::CartridgeMemory;
+use super::prg_ram::PrgRam;
+#[cfg(feature = "serde")]
+use super::CartridgeRom;
+use super::{bank_index, Mapper, Mirroring, PrgRamProtection};
+use crate::rom::Rom;
+
+// Nintendo MMC1 (mapper 1, the SxROM boards). Registers are written a bit at a time through
+// a serial port at $8000-$FFFF: five writes shift bit 0 in, and the fifth's address picks the
+// register (control, CHR bank 0, CHR bank 1 or PRG bank). A write with bit 7 set resets the
+// shift register. The second of two writes on consecutive cycles isn't ignored like it is on
+// the real chip.
+//
+// On SUROM boards, with 512KB of PRG-ROM, bit 4 of the CHR bank registers selects the 256KB
+// half the PRG banks come from.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mmc1 {
+    prg_rom: CartridgeMemory,
+    prg_ram: PrgRam,
+    chr: CartridgeMemory,
+    shift: u8, // Bits written so far, with a marker bit above them
+    control: u8,
+    chr_banks: [u8; 2],
+    prg_bank: u8,
+}
+
+impl Mmc1 {
+    pub fn new(rom: &Rom) -> Self {
+        Self {
+            prg_rom: CartridgeMemory::new(rom.prg_rom.clone(), false),
+            prg_ram: PrgRam::new(rom, rom.prg_ram_size),
+            chr: CartridgeMemory::chr(rom, 0x2000),
+            shift: 0x10,
+            // The last bank starts out fixed at $C000, where the vectors are
+            control: 0x0C,
+            chr_banks: [0; 2],
+            prg_bank: 0,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0x9FFF => self.control = value,
+            0xA000..=0xBFFF => self.chr_banks[0] = value,
+            0xC000..=0xDFFF => self.chr_banks[1] = value,
+            _ => {
+                self.prg_bank = value & 0x0F;
+                // MMC1B and later: bit 4 disables the RAM
+                self.prg_ram.set_enabled(value & 0x10 == 0);
+            }
+        }
+    }
+
+    fn prg_index(&self, address: u16) -> usize {
+        let outer = if self.prg_rom.len() > 0x40000 {
+            (self.chr_banks[0] & 0x10) as usize
+        } else {
+            0
+        };
+        let bank = self.prg_bank as usize;
+        let bank = match (self.control >> 2 & 0x03, address) {
+            // 32KB mode ignores the low bit
+            (0 | 1, _) => (bank & !0x01) | (address as usize >> 14 & 0x01),
+            (2, 0x8000..=0xBFFF) => 0,
+            (2, _) => bank,
+            (_, 0x8000..=0xBFFF) => bank,
+            _ => 0x0F,
+        };
+        bank_index(&self.prg_rom, 0x4000, outer | bank, address as usize)
+    }
+
+    fn chr_index(&self, address: u16) -> usize {
+        let bank = if self.control & 0x10 == 0 {
+            // 8KB mode ignores the low bit
+            (self.chr_banks[0] & !0x01) as usize | (address as usize >> 12)
+        } else {
+            self.chr_banks[address as usize >> 12] as usize
+        };
+        bank_index(&self.chr, 0x1000, bank, address as usize)
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF => self.prg_ram.read(address),
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => {
+                Some(self.prg_rom[self.prg_index(address)])
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF => self.prg_ram.write(address, value),
+            0x8000..=0xFFFF if value & 0x80 != 0 => {
+                self.shift = 0x10;
+                self.control |= 0x0C;
+            }
+            0x8000..=0xFFFF => {
+                let full = self.shift & 0x01 != 0;
+                self.shift = self.shift >> 1 | (value & 0x01) << 4;
+                if full {
+                    self.write_register(address, self.shift);
+                    self.shift = 0x10;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_index(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr.is_writable() {
+            let index = self.chr_index(address);
+            self.chr[index] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0x03 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn write_prg_ram(&mut self, address: u16, value: u8) {
+        self.prg_ram.force_write(address, value);
+    }
+
+    fn prg_ram_protection(&self) -> Option<PrgRamProtection> {
+        Some(self.prg_ram.protection())
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        self.prg_ram.save_data()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.prg_ram.load_save_data(data);
+    }
+
+    fn take_save_changed(&mut self) -> bool {
+        self.prg_ram.take_changed()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl CartridgeRom for Mmc1 {
+    fn take_rom(&mut self, board: &mut Self) {
+        self.prg_rom.take_rom(&mut board.prg_rom);
+        self.chr.take_rom(&mut board.chr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom;
+
+    // Shift a register's five bits in, lowest first
+    fn write(board: &mut Mmc1, address: u16, value: u8) {
+        for bit in 0..5 {
+            board.cpu_write(address, value >> bit & 0x01);
+        }
+    }
+
+    fn board() -> Mmc1 {
+        Mmc1::new(&test_rom::banked(1, 0x20000, 0x8000))
+    }
+
+    #[test]
+    fn last_bank_starts_fixed_at_c000() {
+        let mut board = board();
+        assert_eq!(board.cpu_read(0xC000), Some(14));
+        assert_eq!(board.cpu_read(0xE000), Some(15));
+        write(&mut board, 0xE000, 3);
+        assert_eq!(board.cpu_read(0x8000), Some(6));
+        assert_eq!(board.cpu_read(0xA000), Some(7));
+        assert_eq!(board.cpu_read(0xC000), Some(14));
+    }
+
+    #[test]
+    fn prg_modes() {
+        let mut board = board();
+        write(&mut board, 0xE000, 3);
+        // 32KB: the low bit is ignored
+        write(&mut board, 0x8000, 0x00);
+        assert_eq!(board.cpu_read(0x8000), Some(4));
+        assert_eq!(board.cpu_read(0xC000), Some(6));
+        // First bank fixed at $8000
+        write(&mut board, 0x8000, 0x08);
+        assert_eq!(board.cpu_read(0x8000), Some(0));
+        assert_eq!(board.cpu_read(0xC000), Some(6));
+    }
+
+    #[test]
+    fn chr_modes_and_mirroring() {
+        let mut board = board();
+        write(&mut board, 0xA000, 3);
+        write(&mut board, 0xC000, 5);
+        // 8KB mode: 3 selects the 4KB banks 2 and 3
+        assert_eq!(board.ppu_read(0x0000), 8);
+        assert_eq!(board.ppu_read(0x1000), 12);
+        write(&mut board, 0x8000, 0x12);
+        assert_eq!(board.ppu_read(0x0000), 12);
+        assert_eq!(board.ppu_read(0x1000), 20);
+        assert_eq!(board.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn bit_7_resets_the_shift_register() {
+        let mut board = board();
+        board.cpu_write(0xE000, 0x01);
+        board.cpu_write(0xE000, 0x01);
+        board.cpu_write(0x8000, 0x80);
+        write(&mut board, 0xE000, 2);
+        assert_eq!(board.cpu_read(0x8000), Some(4));
+    }
+
+    #[test]
+    fn prg_bank_bit_4_disables_the_ram() {
+        let mut board = board();
+        board.cpu_write(0x6000, 0x42);
+        assert_eq!(board.cpu_read(0x6000), Some(0x42));
+        write(&mut board, 0xE000, 0x10);
+        assert_eq!(board.cpu_read(0x6000), None);
+        board.cpu_write(0x6000, 0x43);
+        assert!(!board.prg_ram_protection().unwrap().enabled);
+        write(&mut board, 0xE000, 0x00);
+        assert_eq!(board.cpu_read(0x6000), Some(0x42));
+    }
+}
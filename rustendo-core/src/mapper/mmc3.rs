@@ -0,0 +1,339 @@
+use tracing::debug;
+
+use super::cartridge_memory::CartridgeMemory;
+use super::prg_ram::PrgRam;
+#[cfg(feature = "serde")]
+use super::CartridgeRom;
+use super::{bank_index, header_mirroring, Mapper, Mirroring, PrgRamProtection};
+use crate::rom::Rom;
+
+// Nintendo MMC3 (mapper 4, the TxROM boards) and MMC6 (NES 2.0 submapper 1). The Namco 108's
+// banking, with the PRG and CHR layouts switchable by bits 6 and 7 of the bank select, plus
+// mirroring control and a scanline counter clocked by rising edges of PPU A12.
+//
+// The MMC6 has 1KB of RAM of its own at $7000-$7FFF, repeating every 1KB, in two 512-byte
+// halves that can each be made readable and writable. $8000 bit 5 turns the RAM on, and
+// $A001 is ignored until it is.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mmc3 {
+    prg_rom: CartridgeMemory,
+    prg_ram: PrgRam,
+    chr: CartridgeMemory,
+    mmc6: bool,
+    bank_select: u8,
+    banks: [u8; 8], // R0-R5 are CHR, R6-R7 PRG
+    mirroring: Mirroring,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool, // Reload the counter from the latch on the next clock
+    irq_enabled: bool,
+    irq_pending: bool,
+}
+
+impl Mmc3 {
+    pub fn new(rom: &Rom) -> Self {
+        let mmc6 = rom.submapper == 1;
+        let prg_ram = if mmc6 {
+            // Off, with both halves locked
+            let mut prg_ram = PrgRam::new(rom, 0x400);
+            prg_ram.set_window(0x200);
+            prg_ram.set_enabled(false);
+            prg_ram.set_read_protected(0x03);
+            prg_ram.set_write_protected(0x03);
+            prg_ram
+        } else {
+            PrgRam::new(rom, rom.prg_ram_size)
+        };
+        Self {
+            prg_rom: CartridgeMemory::new(rom.prg_rom.clone(), false),
+            prg_ram,
+            chr: CartridgeMemory::chr(rom, 0x2000),
+            mmc6,
+            bank_select: 0,
+            banks: [0; 8],
+            mirroring: header_mirroring(rom),
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+        }
+    }
+
+    // Bit 6 of the bank select swaps $8000 and $C000
+    fn prg_index(&self, address: u16) -> usize {
+        let second_last = self.prg_rom.len() / 0x2000 - 2;
+        let swapped = self.bank_select & 0x40 != 0;
+        let bank = match address {
+            0x8000..=0x9FFF if swapped => second_last,
+            0x8000..=0x9FFF => self.banks[6] as usize,
+            0xA000..=0xBFFF => self.banks[7] as usize,
+            0xC000..=0xDFFF if swapped => self.banks[6] as usize,
+            0xC000..=0xDFFF => second_last,
+            _ => second_last + 1,
+        };
+        bank_index(&self.prg_rom, 0x2000, bank, address as usize)
+    }
+
+    // Bit 7 of the bank select swaps the 2KB and 1KB halves. R0 and R1 ignore their lowest bit
+    // and take PPU A10 in its place.
+    fn chr_index(&self, address: u16) -> usize {
+        let inverted = if self.bank_select & 0x80 != 0 {
+            address ^ 0x1000
+        } else {
+            address
+        } as usize;
+        let bank = match inverted {
+            0x0000..=0x0FFF => {
+                self.banks[inverted >> 11] as usize & !0x01 | (inverted >> 10 & 0x01)
+            }
+            _ => self.banks[2 + (inverted >> 10 & 0x03)] as usize,
+        };
+        bank_index(&self.chr, 0x400, bank, address as usize)
+    }
+
+    // $A001 on the MMC6: bits 4 and 5 let the lower half be read and written, bits 6 and 7
+    // the upper half. A half must be readable to be written.
+    fn write_mmc6_protection(&mut self, value: u8) {
+        let readable = (value >> 4 & 0x01) | (value >> 5 & 0x02);
+        let writable = (value >> 5 & 0x01) | (value >> 6 & 0x02);
+        self.prg_ram.set_read_protected(!readable & 0x03);
+        self.prg_ram
+            .set_write_protected(!(readable & writable) & 0x03);
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x6FFF if self.mmc6 => None,
+            // A locked half reads as 0, unless both are locked and the bus is left open
+            0x7000..=0x7FFF if self.mmc6 && self.prg_ram.protection().read_protected == 0x03 => {
+                None
+            }
+            0x6000..=0x7FFF => self.prg_ram.read(address),
+            0x8000..=0xFFFF if self.prg_rom.len() >= 0x4000 => {
+                Some(self.prg_rom[self.prg_index(address)])
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        let odd = address & 0x01 != 0;
+        match address {
+            0x6000..=0x6FFF if self.mmc6 => {}
+            0x6000..=0x7FFF => self.prg_ram.write(address, value),
+            0x8000..=0x9FFF if !odd => {
+                self.bank_select = value;
+                if self.mmc6 {
+                    self.prg_ram.set_enabled(value & 0x20 != 0);
+                }
+            }
+            0x8000..=0x9FFF => {
+                let register = self.bank_select as usize & 0x07;
+                self.banks[register] = value;
+            }
+            0xA000..=0xBFFF if !odd => {
+                self.mirroring = if value & 0x01 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            0xA000..=0xBFFF if self.mmc6 => {
+                if self.bank_select & 0x20 != 0 {
+                    self.write_mmc6_protection(value);
+                }
+            }
+            0xA000..=0xBFFF => {
+                self.prg_ram.set_enabled(value & 0x80 != 0);
+                self.prg_ram
+                    .set_write_protected(if value & 0x40 != 0 { 0xFF } else { 0 });
+            }
+            0xC000..=0xDFFF if !odd => self.irq_latch = value,
+            0xC000..=0xDFFF => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xE000..=0xFFFF if !odd => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            _ => self.irq_enabled = true,
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_index(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr.is_writable() {
+            let index = self.chr_index(address);
+            self.chr[index] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn counts_a12_rises(&self) -> bool {
+        true
+    }
+
+    // Once per scanline while rendering, with the usual pattern table layout
+    fn a12_rise(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+        if self.irq_counter == 0 && self.irq_enabled {
+            debug!(target: "mapper", "MMC3 IRQ");
+            self.irq_pending = true;
+        }
+    }
+
+    // The MMC6's 1KB sits at $7000-$7FFF only
+    fn write_prg_ram(&mut self, address: u16, value: u8) {
+        if !self.mmc6 || address >= 0x7000 {
+            self.prg_ram.force_write(address, value);
+        }
+    }
+
+    fn prg_ram_protection(&self) -> Option<PrgRamProtection> {
+        Some(self.prg_ram.protection())
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        self.prg_ram.save_data()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.prg_ram.load_save_data(data);
+    }
+
+    fn take_save_changed(&mut self) -> bool {
+        self.prg_ram.take_changed()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl CartridgeRom for Mmc3 {
+    fn take_rom(&mut self, board: &mut Self) {
+        self.prg_rom.take_rom(&mut board.prg_rom);
+        self.chr.take_rom(&mut board.chr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom;
+
+    fn board(submapper: u8) -> Mmc3 {
+        let mut rom = test_rom::banked(4, 0x20000, 0x20000);
+        rom.submapper = submapper;
+        Mmc3::new(&rom)
+    }
+
+    #[test]
+    fn prg_banks_swap_with_bit_6() {
+        let mut board = board(0);
+        board.cpu_write(0x8000, 6);
+        board.cpu_write(0x8001, 3);
+        board.cpu_write(0x8000, 7);
+        board.cpu_write(0x8001, 5);
+        assert_eq!(
+            [0x8000, 0xA000, 0xC000, 0xE000].map(|address| board.cpu_read(address)),
+            [Some(3), Some(5), Some(14), Some(15)]
+        );
+        board.cpu_write(0x8000, 0x40);
+        assert_eq!(
+            [0x8000, 0xA000, 0xC000, 0xE000].map(|address| board.cpu_read(address)),
+            [Some(14), Some(5), Some(3), Some(15)]
+        );
+    }
+
+    #[test]
+    fn chr_halves_swap_with_bit_7() {
+        let mut board = board(0);
+        for (register, bank) in [(0, 9), (2, 20)] {
+            board.cpu_write(0x8000, register);
+            board.cpu_write(0x8001, bank);
+        }
+        // R0 is a 2KB bank, so 9 becomes 8 and 9
+        assert_eq!([board.ppu_read(0x0000), board.ppu_read(0x0400)], [8, 9]);
+        assert_eq!(board.ppu_read(0x1000), 20);
+        board.cpu_write(0x8000, 0x80);
+        assert_eq!([board.ppu_read(0x1000), board.ppu_read(0x1400)], [8, 9]);
+        assert_eq!(board.ppu_read(0x0000), 20);
+    }
+
+    #[test]
+    fn a001_disables_and_write_protects_the_ram() {
+        let mut board = board(0);
+        board.cpu_write(0x6000, 0x42);
+        board.cpu_write(0xA001, 0xC0);
+        board.cpu_write(0x6000, 0x43);
+        assert_eq!(board.cpu_read(0x6000), Some(0x42));
+        assert_eq!(board.prg_ram_protection().unwrap().write_protected, 0xFF);
+        board.cpu_write(0xA001, 0x00);
+        assert_eq!(board.cpu_read(0x6000), None);
+        board.cpu_write(0xA001, 0x80);
+        board.cpu_write(0x6000, 0x43);
+        assert_eq!(board.cpu_read(0x6000), Some(0x43));
+    }
+
+    #[test]
+    fn mmc6_ram_halves() {
+        let mut board = board(1);
+        // Off until $8000 bit 5, and $A001 is ignored until then
+        board.cpu_write(0xA001, 0xF0);
+        board.cpu_write(0x7000, 0x42);
+        assert_eq!(board.cpu_read(0x7000), None);
+        board.cpu_write(0x8000, 0x20);
+        board.cpu_write(0xA001, 0xF0);
+        board.cpu_write(0x7000, 0x42);
+        board.cpu_write(0x7200, 0x43);
+        assert_eq!(board.cpu_read(0x6000), None);
+        // The 1KB repeats through $7000-$7FFF
+        assert_eq!(board.cpu_read(0x7C00), Some(0x42));
+        // Lower half read-only, upper half locked, which reads as 0
+        board.cpu_write(0xA001, 0x10);
+        board.cpu_write(0x7000, 0x44);
+        assert_eq!(board.cpu_read(0x7000), Some(0x42));
+        assert_eq!(board.cpu_read(0x7200), Some(0));
+        // Both locked leaves the bus open
+        board.cpu_write(0xA001, 0x00);
+        assert_eq!(board.cpu_read(0x7000), None);
+    }
+
+    #[test]
+    fn irq_after_latch_plus_one_scanlines() {
+        let mut board = board(0);
+        board.cpu_write(0xC000, 2);
+        board.cpu_write(0xC001, 0);
+        board.cpu_write(0xE001, 0);
+        board.a12_rise(); // Reloads to 2
+        board.a12_rise();
+        assert!(!board.irq_pending());
+        board.a12_rise();
+        assert!(board.irq_pending());
+        board.cpu_write(0xE000, 0);
+        assert!(!board.irq_pending());
+        // Reloads and counts down again
+        board.cpu_write(0xE001, 0);
+        board.a12_rise();
+        board.a12_rise();
+        board.a12_rise();
+        assert!(board.irq_pending());
+    }
+}
@@ -0,0 +1,136 @@
+use super::cartridge_memory::CartridgeMemory;
+#[cfg(feature = "serde")]
+use super::CartridgeRom;
+use super::{bank_index, header_mirroring, Mapper, Mirroring};
+use crate::rom::Rom;
+
+// Namco 108 / DxROM (mapper 206), the MMC3's predecessor: a bank select register at even
+// addresses in $8000-$9FFF and the selected bank's number at odd ones. Two 8KB PRG banks are
+// switchable with the last 16KB fixed at $C000; CHR is two 2KB banks then four 1KB banks.
+// There is no IRQ or mirroring control.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Namco108 {
+    prg_rom: CartridgeMemory,
+    chr: CartridgeMemory,
+    mirroring: Mirroring,
+    bank_select: u8,
+    banks: [u8; 8], // R0-R5 are CHR, R6-R7 PRG
+}
+
+impl Namco108 {
+    pub fn new(rom: &Rom) -> Self {
+        Self {
+            prg_rom: CartridgeMemory::new(rom.prg_rom.clone(), false),
+            chr: CartridgeMemory::chr(rom, 0x2000),
+            mirroring: header_mirroring(rom),
+            bank_select: 0,
+            banks: [0; 8],
+        }
+    }
+
+    fn prg_index(&self, address: u16) -> usize {
+        let last = self.prg_rom.len() / 0x2000 - 1;
+        let bank = match address {
+            0x8000..=0x9FFF => self.banks[6] as usize,
+            0xA000..=0xBFFF => self.banks[7] as usize,
+            0xC000..=0xDFFF => last - 1,
+            _ => last,
+        };
+        bank_index(&self.prg_rom, 0x2000, bank, address as usize)
+    }
+
+    // R0 and R1 ignore their lowest bit and take PPU A10 in its place
+    fn chr_index(&self, address: u16) -> usize {
+        let bank = match address {
+            0x0000..=0x0FFF => {
+                self.banks[address as usize >> 11] as usize & !0x01
+                    | (address as usize >> 10 & 0x01)
+            }
+            _ => self.banks[2 + (address as usize >> 10 & 0x03)] as usize,
+        };
+        bank_index(&self.chr, 0x400, bank, address as usize)
+    }
+}
+
+impl Mapper for Namco108 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0xFFFF if self.prg_rom.len() >= 0x4000 => {
+                Some(self.prg_rom[self.prg_index(address)])
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0x9FFF if address & 0x01 == 0 => self.bank_select = value & 0x07,
+            0x8000..=0x9FFF => {
+                let mask = if self.bank_select < 6 { 0x3F } else { 0x0F };
+                self.banks[self.bank_select as usize] = value & mask;
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_index(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr.is_writable() {
+            let index = self.chr_index(address);
+            self.chr[index] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(feature = "serde")]
+impl CartridgeRom for Namco108 {
+    fn take_rom(&mut self, board: &mut Self) {
+        self.prg_rom.take_rom(&mut board.prg_rom);
+        self.chr.take_rom(&mut board.chr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom;
+
+    fn write(board: &mut Namco108, register: u8, bank: u8) {
+        board.cpu_write(0x8000, register);
+        board.cpu_write(0x8001, bank);
+    }
+
+    #[test]
+    fn prg_banks() {
+        let mut board = Namco108::new(&test_rom::banked(206, 0x20000, 0x10000));
+        write(&mut board, 6, 3);
+        // The MMC3's swap bit isn't there, and PRG banks have four bits
+        write(&mut board, 0x47, 0x15);
+        assert_eq!(
+            [0x8000, 0xA000, 0xC000, 0xE000].map(|address| board.cpu_read(address)),
+            [Some(3), Some(5), Some(14), Some(15)]
+        );
+    }
+
+    #[test]
+    fn chr_banks() {
+        let mut board = Namco108::new(&test_rom::banked(206, 0x20000, 0x10000));
+        write(&mut board, 0, 9);
+        write(&mut board, 1, 12);
+        write(&mut board, 2, 20);
+        write(&mut board, 5, 0x6F);
+        // R0 and R1 are 2KB banks, so 9 becomes 8 and 9
+        assert_eq!(
+            [0x0000, 0x0400, 0x0800, 0x0C00].map(|address| board.ppu_read(address)),
+            [8, 9, 12, 13]
+        );
+        assert_eq!([board.ppu_read(0x1000), board.ppu_read(0x1C00)], [20, 47]);
+    }
+}
@@ -0,0 +1,300 @@
+use tracing::debug;
+
+use super::cartridge_memory::CartridgeMemory;
+use super::prg_ram::PrgRam;
+#[cfg(feature = "serde")]
+use super::CartridgeRom;
+use super::{bank_index, Mapper, Mirroring, PrgRamProtection};
+use crate::rom::Rom;
+
+// Namco 129/163 (mapper 19): three switchable 8KB PRG banks, eight 1KB CHR banks, four
+// nametable registers, 128 bytes of internal RAM behind an address port and a 15-bit CPU cycle
+// IRQ counter. The internal RAM also holds the expansion sound's waveforms.
+//
+// Nametable and CHR registers can point at the console's nametable RAM (values $E0 and up).
+// For the nametables only the arrangements expressible as `Mirroring` are followed, and CHR
+// registers always select CHR-ROM, which covers the games using the board.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Namco163 {
+    prg_rom: CartridgeMemory,
+    prg_ram: PrgRam,
+    chr: CartridgeMemory,
+    prg_banks: [u8; 3], // $8000, $A000 and $C000
+    chr_banks: [u8; 8],
+    nametables: [u8; 4],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_arrays"))]
+    internal_ram: [u8; 0x80],
+    ram_address: u8, // $F800: bits 0-6 address, bit 7 auto-increment
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+}
+
+impl Namco163 {
+    pub fn new(rom: &Rom) -> Self {
+        // Locked until $F800 unlocks it
+        let mut prg_ram = PrgRam::new(rom, rom.prg_ram_size);
+        prg_ram.set_write_protected(0x0F);
+        Self {
+            prg_rom: CartridgeMemory::new(rom.prg_rom.clone(), false),
+            prg_ram,
+            chr: CartridgeMemory::chr(rom, 0x2000),
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            nametables: [0xE0, 0xE0, 0xE1, 0xE1],
+            internal_ram: [0; 0x80],
+            ram_address: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+        }
+    }
+
+    fn prg_index(&self, address: u16) -> usize {
+        let bank = match address {
+            0x8000..=0xDFFF => self.prg_banks[(address as usize - 0x8000) / 0x2000] as usize,
+            _ => self.prg_rom.len() / 0x2000 - 1,
+        };
+        bank_index(&self.prg_rom, 0x2000, bank, address as usize)
+    }
+
+    fn chr_index(&self, address: u16) -> usize {
+        let bank = self.chr_banks[address as usize >> 10 & 0x07];
+        bank_index(&self.chr, 0x400, bank as usize, address as usize)
+    }
+
+    fn access_internal_ram(&mut self) -> usize {
+        let address = (self.ram_address & 0x7F) as usize;
+        if self.ram_address & 0x80 != 0 {
+            self.ram_address = 0x80 | self.ram_address.wrapping_add(1) & 0x7F;
+        }
+        address
+    }
+}
+
+impl Mapper for Namco163 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x4800..=0x4FFF => {
+                let address = self.access_internal_ram();
+                Some(self.internal_ram[address])
+            }
+            0x5000..=0x57FF => Some(self.irq_counter as u8),
+            0x5800..=0x5FFF => Some((self.irq_counter >> 8) as u8 | (self.irq_enabled as u8) << 7),
+            0x6000..=0x7FFF => self.prg_ram.read(address),
+            0x8000..=0xFFFF if self.prg_rom.len() >= 0x2000 => {
+                Some(self.prg_rom[self.prg_index(address)])
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_peek(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x4800..=0x4FFF => Some(self.internal_ram[(self.ram_address & 0x7F) as usize]),
+            _ => self.cpu_read(address),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4800..=0x4FFF => {
+                let address = self.access_internal_ram();
+                self.internal_ram[address] = value;
+            }
+            0x5000..=0x57FF => {
+                self.irq_counter = self.irq_counter & 0x7F00 | value as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = self.irq_counter & 0x00FF | ((value & 0x7F) as u16) << 8;
+                self.irq_enabled = value & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF => self.prg_ram.write(address, value),
+            0x8000..=0xBFFF => self.chr_banks[(address as usize - 0x8000) / 0x800] = value,
+            0xC000..=0xDFFF => self.nametables[(address as usize - 0xC000) / 0x800] = value,
+            0xE000..=0xF7FF => {
+                self.prg_banks[(address as usize - 0xE000) / 0x800] = value & 0x3F;
+            }
+            0xF800..=0xFFFF => {
+                self.ram_address = value;
+                // The same register unlocks PRG-RAM writes when its top nibble is 4
+                let protected = if value & 0xF0 == 0x40 {
+                    value & 0x0F
+                } else {
+                    0x0F
+                };
+                self.prg_ram.set_write_protected(protected);
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_index(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr.is_writable() {
+            let index = self.chr_index(address);
+            self.chr[index] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.nametables.map(|page| page & 0x01) {
+            [0, 1, 0, 1] => Mirroring::Vertical,
+            [0, 0, 0, 0] => Mirroring::SingleScreenLower,
+            [1, 1, 1, 1] => Mirroring::SingleScreenUpper,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn cpu_tick(&mut self) {
+        if self.irq_enabled && self.irq_counter < 0x7FFF {
+            self.irq_counter += 1;
+            if self.irq_counter == 0x7FFF {
+                debug!(target: "mapper", "Namco 163 IRQ");
+                self.irq_pending = true;
+            }
+        }
+    }
+
+    fn cycles_until_event(&self) -> u64 {
+        if self.irq_enabled && self.irq_counter < 0x7FFF {
+            (0x7FFF - self.irq_counter) as u64
+        } else {
+            u64::MAX
+        }
+    }
+
+    fn write_prg_ram(&mut self, address: u16, value: u8) {
+        self.prg_ram.force_write(address, value);
+    }
+
+    fn prg_ram_protection(&self) -> Option<PrgRamProtection> {
+        Some(self.prg_ram.protection())
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        self.prg_ram.save_data()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.prg_ram.load_save_data(data);
+    }
+
+    fn take_save_changed(&mut self) -> bool {
+        self.prg_ram.take_changed()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl CartridgeRom for Namco163 {
+    fn take_rom(&mut self, board: &mut Self) {
+        self.prg_rom.take_rom(&mut board.prg_rom);
+        self.chr.take_rom(&mut board.chr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom;
+
+    fn board() -> Namco163 {
+        Namco163::new(&test_rom::banked(19, 0x20000, 0x20000))
+    }
+
+    #[test]
+    fn prg_and_chr_banks() {
+        let mut board = board();
+        board.cpu_write(0xE000, 3);
+        board.cpu_write(0xE800, 5);
+        board.cpu_write(0xF000, 0x47);
+        assert_eq!(
+            [0x8000, 0xA000, 0xC000, 0xE000].map(|address| board.cpu_read(address)),
+            [Some(3), Some(5), Some(7), Some(15)]
+        );
+        board.cpu_write(0x8000, 9);
+        board.cpu_write(0xB800, 20);
+        assert_eq!([board.ppu_read(0x0000), board.ppu_read(0x1C00)], [9, 20]);
+    }
+
+    #[test]
+    fn nametable_registers() {
+        let mut board = board();
+        assert_eq!(board.mirroring(), Mirroring::Horizontal);
+        for (address, page) in [
+            (0xC000, 0xE0),
+            (0xC800, 0xE1),
+            (0xD000, 0xE0),
+            (0xD800, 0xE1),
+        ] {
+            board.cpu_write(address, page);
+        }
+        assert_eq!(board.mirroring(), Mirroring::Vertical);
+        board.cpu_write(0xC000, 0xE1);
+        board.cpu_write(0xD000, 0xE1);
+        assert_eq!(board.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn internal_ram_port() {
+        let mut board = board();
+        board.cpu_write(0xF800, 0xFF);
+        board.cpu_write(0x4800, 1);
+        board.cpu_write(0x4800, 2);
+        // Without auto-increment the address stays put, and peeking never moves it
+        board.cpu_write(0xF800, 0x7F);
+        assert_eq!(board.cpu_read(0x4800), Some(1));
+        assert_eq!(board.cpu_read(0x4800), Some(1));
+        board.cpu_write(0xF800, 0x80);
+        assert_eq!(board.cpu_peek(0x4800), Some(2));
+        assert_eq!(board.cpu_read(0x4800), Some(2));
+        assert_eq!(board.cpu_read(0x4800), Some(0));
+    }
+
+    #[test]
+    fn prg_ram_write_protection() {
+        let mut board = board();
+        board.cpu_write(0x6000, 0x42);
+        assert_eq!(board.cpu_read(0x6000), Some(0));
+        board.cpu_write(0xF800, 0x41);
+        board.cpu_write(0x6000, 0x42);
+        board.cpu_write(0x6800, 0x43);
+        assert_eq!(
+            [board.cpu_read(0x6000), board.cpu_read(0x6800)],
+            [Some(0), Some(0x43)]
+        );
+        board.cpu_write(0xF800, 0x40);
+        board.cpu_write(0x6000, 0x42);
+        assert_eq!(board.cpu_read(0x6000), Some(0x42));
+    }
+
+    #[test]
+    fn irq_counter() {
+        let mut board = board();
+        board.cpu_write(0x5000, 0xFD);
+        board.cpu_write(0x5800, 0xFF);
+        assert_eq!(board.cycles_until_event(), 2);
+        board.cpu_tick();
+        assert!(!board.irq_pending());
+        board.cpu_tick();
+        assert!(board.irq_pending());
+        // It stops at $7FFF
+        board.cpu_tick();
+        assert_eq!(
+            [board.cpu_read(0x5000), board.cpu_read(0x5800)],
+            [Some(0xFF), Some(0xFF)]
+        );
+        assert_eq!(board.cycles_until_event(), u64::MAX);
+        board.cpu_write(0x5000, 0);
+        assert!(!board.irq_pending());
+    }
+}
@@ -0,0 +1,123 @@
+use super::cartridge_memory::CartridgeMemory;
+use super::prg_ram::PrgRam;
+#[cfg(feature = "serde")]
+use super::CartridgeRom;
+use super::{header_mirroring, Mapper, Mirroring};
+use crate::rom::Rom;
+
+// Mapper 0: 16KB or 32KB of PRG-ROM and 8KB of CHR, no registers. A 16KB PRG-ROM (NROM-128)
+// appears twice, at $8000 and again at $C000 where the CPU finds its vectors.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Nrom {
+    prg_rom: CartridgeMemory,
+    prg_ram: PrgRam,
+    chr: CartridgeMemory, // Boards without CHR-ROM have 8KB of CHR-RAM instead
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(rom: &Rom) -> Self {
+        Self {
+            prg_rom: CartridgeMemory::new(rom.prg_rom.clone(), false),
+            prg_ram: PrgRam::new(rom, rom.prg_ram_size),
+            chr: CartridgeMemory::chr(rom, 0x2000),
+            mirroring: header_mirroring(rom),
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF => self.prg_ram.read(address),
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => {
+                Some(self.prg_rom[(address as usize - 0x8000) % self.prg_rom.len()])
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        // Writes anywhere else hit ROM and are ignored
+        if let 0x6000..=0x7FFF = address {
+            self.prg_ram.write(address, value);
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        // Undersized CHR-ROMs repeat to fill the pattern tables
+        self.chr[address as usize % self.chr.len()]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr.is_writable() {
+            self.chr[address as usize & 0x1FFF] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn write_prg_ram(&mut self, address: u16, value: u8) {
+        self.prg_ram.force_write(address, value);
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        self.prg_ram.save_data()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.prg_ram.load_save_data(data);
+    }
+
+    fn take_save_changed(&mut self) -> bool {
+        self.prg_ram.take_changed()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl CartridgeRom for Nrom {
+    fn take_rom(&mut self, board: &mut Self) {
+        self.prg_rom.take_rom(&mut board.prg_rom);
+        self.chr.take_rom(&mut board.chr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom;
+
+    #[test]
+    fn prg_rom_and_ram() {
+        let mut board = Nrom::new(&test_rom::banked(0, 0x8000, 0x2000));
+        assert_eq!(
+            [0x8000, 0xA000, 0xC000, 0xE000].map(|address| board.cpu_read(address)),
+            [Some(0), Some(1), Some(2), Some(3)]
+        );
+        board.cpu_write(0x8000, 0x42);
+        assert_eq!(board.cpu_read(0x8000), Some(0));
+        board.cpu_write(0x6000, 0x42);
+        assert_eq!(board.cpu_read(0x6000), Some(0x42));
+        assert_eq!(board.cpu_read(0x5000), None);
+    }
+
+    #[test]
+    fn nrom_128_appears_twice() {
+        let mut board = Nrom::new(&test_rom::banked(0, 0x4000, 0x2000));
+        assert_eq!(board.cpu_read(0xC000), Some(0));
+        assert_eq!(board.cpu_read(0xFFFF), Some(1));
+    }
+
+    #[test]
+    fn chr_rom_and_ram() {
+        let mut board = Nrom::new(&test_rom::banked(0, 0x8000, 0x2000));
+        board.ppu_write(0x1C00, 0x42);
+        assert_eq!(board.ppu_read(0x1C00), 7);
+
+        let mut board = Nrom::new(&test_rom::banked(0, 0x8000, 0));
+        board.ppu_write(0x1C00, 0x42);
+        assert_eq!(board.ppu_read(0x1C00), 0x42);
+    }
+}
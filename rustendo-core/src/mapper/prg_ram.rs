@@ -0,0 +1,122 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::PrgRamProtection;
+use crate::rom::Rom;
+
+// The cartridge's PRG-RAM at $6000-$7FFF. On boards with a battery its contents are the game's
+// save, so it also tracks whether it has been written to since it was last saved.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PrgRam {
+    data: Vec<u8>,
+    battery: bool,
+    changed: bool,
+    enabled: bool,
+    read_protected: u8,  // Bit n makes window n read back as 0
+    write_protected: u8, // Bit n protects window n from writes
+    window: usize,       // Bytes the board protects as a unit, 2KB unless it says otherwise
+}
+
+impl PrgRam {
+    // Dumps with a trainer get it preloaded at $7000, where it was copied on the original
+    // hardware
+    pub fn new(rom: &Rom, size: usize) -> Self {
+        let mut data = vec![0; size];
+        if size >= 0x2000 {
+            for (byte, &value) in data[0x1000..].iter_mut().zip(&rom.trainer) {
+                *byte = value;
+            }
+        }
+        Self {
+            data,
+            battery: rom.battery && size > 0,
+            changed: false,
+            enabled: true,
+            read_protected: 0,
+            write_protected: 0,
+            window: 0x800,
+        }
+    }
+
+    pub fn set_window(&mut self, window: usize) {
+        self.window = window;
+    }
+
+    // Disabled RAM ignores writes and leaves the bus open on reads
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn set_read_protected(&mut self, read_protected: u8) {
+        self.read_protected = read_protected;
+    }
+
+    pub fn set_write_protected(&mut self, write_protected: u8) {
+        self.write_protected = write_protected;
+    }
+
+    pub fn protection(&self) -> PrgRamProtection {
+        PrgRamProtection {
+            enabled: self.enabled,
+            read_protected: self.read_protected,
+            write_protected: self.write_protected,
+            window: self.window,
+        }
+    }
+
+    // `None` if the board has no PRG-RAM or it is disabled, leaving the bus open. Smaller RAMs
+    // repeat through $6000-$7FFF.
+    pub fn read(&self, address: u16) -> Option<u8> {
+        if self.data.is_empty() || !self.enabled {
+            return None;
+        }
+        let index = (address as usize - 0x6000) % self.data.len();
+        if self.protects(self.read_protected, index) {
+            return Some(0);
+        }
+        Some(self.data[index])
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        if self.data.is_empty() || !self.enabled {
+            return;
+        }
+        let index = (address as usize - 0x6000) % self.data.len();
+        if self.protects(self.write_protected, index) {
+            return;
+        }
+        self.changed |= self.battery && self.data[index] != value;
+        self.data[index] = value;
+    }
+
+    // `write` ignoring whether the RAM is enabled or protected
+    pub fn force_write(&mut self, address: u16, value: u8) {
+        if self.data.is_empty() {
+            return;
+        }
+        let index = (address as usize - 0x6000) % self.data.len();
+        self.changed |= self.battery && self.data[index] != value;
+        self.data[index] = value;
+    }
+
+    // Whether `bits` has the bit set for the window holding byte `index`
+    fn protects(&self, bits: u8, index: usize) -> bool {
+        let window = (index / self.window) as u32;
+        bits.checked_shr(window)
+            .is_some_and(|bits| bits & 0x01 != 0)
+    }
+
+    pub fn save_data(&self) -> Option<&[u8]> {
+        self.battery.then_some(&self.data[..])
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        if self.battery && data.len() == self.data.len() {
+            self.data.copy_from_slice(data);
+        }
+    }
+
+    pub fn take_changed(&mut self) -> bool {
+        core::mem::take(&mut self.changed)
+    }
+}
@@ -0,0 +1,269 @@
+use super::cartridge_memory::CartridgeMemory;
+#[cfg(feature = "serde")]
+use super::CartridgeRom;
+use super::{bank_index, header_mirroring, Mapper, Mirroring};
+use crate::rom::Rom;
+
+// The flash chip's software ID, as read in ID mode (SST39SF040)
+const FLASH_ID: [u8; 2] = [0xBF, 0xB7];
+
+// UNROM-512 (mapper 30), common in modern homebrew. Writes to $8000-$FFFF select a 16KB PRG
+// bank for $8000 (bits 0-4), one of four 8KB CHR-RAM banks (bits 5-6) and, on boards wired
+// for it, the single-screen nametable (bit 7). The last bank is fixed at $C000.
+//
+// With the battery bit set the PRG-ROM is a flash chip the game can rewrite to save its
+// progress. The register then only answers at $C000-$FFFF, and writes to $8000-$BFFF send
+// commands to the flash at the address formed by the selected bank and the low 14 bits.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Unrom512 {
+    prg: CartridgeMemory, // Flash on battery boards, otherwise ROM
+    chr: CartridgeMemory,
+    flash: bool,
+    one_screen: bool, // Header bits 0 and 3 say bit 7 picks the nametable
+    prg_bank: u8,
+    chr_bank: u8,
+    mirroring: Mirroring,
+    command: FlashCommand,
+    id_mode: bool,
+    changed: bool, // PRG rewritten since the last `take_save_changed`
+}
+
+// Progress through a flash command. Every command starts by writing $AA to $5555 and $55 to
+// $2AAA; erasing takes a second such unlock after the $80 prefix.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum FlashCommand {
+    Idle,
+    Unlocked1, // $AA written
+    Unlocked2, // $55 written, waiting for the command byte
+    Program,   // The next write programs a byte
+    EraseIdle,
+    EraseUnlocked1,
+    EraseUnlocked2, // Waiting for chip ($10) or sector ($30) erase
+}
+
+impl Unrom512 {
+    pub fn new(rom: &Rom) -> Self {
+        let one_screen = rom.four_screen && rom.mirroring == 0;
+        Self {
+            prg: CartridgeMemory::new(rom.prg_rom.clone(), rom.battery),
+            chr: CartridgeMemory::chr(rom, 0x8000),
+            flash: rom.battery,
+            one_screen,
+            prg_bank: 0,
+            chr_bank: 0,
+            mirroring: if one_screen {
+                Mirroring::SingleScreenLower
+            } else {
+                header_mirroring(rom)
+            },
+            command: FlashCommand::Idle,
+            id_mode: false,
+            changed: false,
+        }
+    }
+
+    fn prg_index(&self, address: u16) -> usize {
+        let bank = match address {
+            0x8000..=0xBFFF => self.prg_bank as usize,
+            _ => self.prg.len() / 0x4000 - 1,
+        };
+        bank_index(&self.prg, 0x4000, bank, address as usize)
+    }
+
+    fn chr_index(&self, address: u16) -> usize {
+        bank_index(&self.chr, 0x2000, self.chr_bank as usize, address as usize)
+    }
+
+    fn write_flash(&mut self, address: u16, value: u8) {
+        let index = self.prg_index(address);
+        let command_address = index & 0x7FFF;
+        self.command = match (self.command, command_address, value) {
+            (FlashCommand::Program, _, _) => {
+                // Programming can only clear bits
+                self.prg[index] &= value;
+                self.changed = true;
+                FlashCommand::Idle
+            }
+            (_, _, 0xF0) => {
+                self.id_mode = false;
+                FlashCommand::Idle
+            }
+            (FlashCommand::Idle, 0x5555, 0xAA) => FlashCommand::Unlocked1,
+            (FlashCommand::Unlocked1, 0x2AAA, 0x55) => FlashCommand::Unlocked2,
+            (FlashCommand::Unlocked2, 0x5555, 0xA0) => FlashCommand::Program,
+            (FlashCommand::Unlocked2, 0x5555, 0x80) => FlashCommand::EraseIdle,
+            (FlashCommand::Unlocked2, 0x5555, 0x90) => {
+                self.id_mode = true;
+                FlashCommand::Idle
+            }
+            (FlashCommand::EraseIdle, 0x5555, 0xAA) => FlashCommand::EraseUnlocked1,
+            (FlashCommand::EraseUnlocked1, 0x2AAA, 0x55) => FlashCommand::EraseUnlocked2,
+            (FlashCommand::EraseUnlocked2, 0x5555, 0x10) => {
+                self.prg.fill(0xFF);
+                self.changed = true;
+                FlashCommand::Idle
+            }
+            (FlashCommand::EraseUnlocked2, _, 0x30) => {
+                let sector = index & !0x0FFF;
+                let end = (sector + 0x1000).min(self.prg.len());
+                self.prg[sector..end].fill(0xFF);
+                self.changed = true;
+                FlashCommand::Idle
+            }
+            _ => FlashCommand::Idle,
+        };
+    }
+}
+
+impl Mapper for Unrom512 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0xFFFF if self.id_mode => Some(FLASH_ID[address as usize & 0x01]),
+            0x8000..=0xFFFF if self.prg.len() >= 0x4000 => Some(self.prg[self.prg_index(address)]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0xBFFF if self.flash => self.write_flash(address, value),
+            0x8000..=0xFFFF => {
+                self.prg_bank = value & 0x1F;
+                self.chr_bank = value >> 5 & 0x03;
+                if self.one_screen {
+                    self.mirroring = if value & 0x80 == 0 {
+                        Mirroring::SingleScreenLower
+                    } else {
+                        Mirroring::SingleScreenUpper
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_index(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr.is_writable() {
+            let index = self.chr_index(address);
+            self.chr[index] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        self.flash.then_some(&self.prg[..])
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        if self.flash && data.len() == self.prg.len() {
+            self.prg.copy_from_slice(data);
+        }
+    }
+
+    fn take_save_changed(&mut self) -> bool {
+        core::mem::take(&mut self.changed)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl CartridgeRom for Unrom512 {
+    fn take_rom(&mut self, board: &mut Self) {
+        self.prg.take_rom(&mut board.prg);
+        self.chr.take_rom(&mut board.chr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom;
+
+    fn board(battery: bool) -> Unrom512 {
+        let mut rom = test_rom::banked(30, 0x80000, 0);
+        rom.battery = battery;
+        Unrom512::new(&rom)
+    }
+
+    // A flash write to the chip's address `command_address`, through the bank register
+    fn flash_write(board: &mut Unrom512, command_address: usize, value: u8) {
+        board.cpu_write(0xC000, (command_address >> 14) as u8);
+        board.cpu_write(0x8000 | (command_address & 0x3FFF) as u16, value);
+    }
+
+    fn flash_command(board: &mut Unrom512, value: u8) {
+        flash_write(board, 0x5555, 0xAA);
+        flash_write(board, 0x2AAA, 0x55);
+        flash_write(board, 0x5555, value);
+    }
+
+    #[test]
+    fn banks() {
+        let mut board = board(false);
+        board.cpu_write(0x8000, 0x65);
+        assert_eq!(
+            [board.cpu_read(0x8000), board.cpu_read(0xC000)],
+            [Some(10), Some(62)]
+        );
+        board.ppu_write(0x0000, 0x42);
+        board.cpu_write(0x8000, 0x05);
+        assert_eq!(board.ppu_read(0x0000), 0);
+        board.cpu_write(0xFFFF, 0x60);
+        assert_eq!(board.ppu_read(0x0000), 0x42);
+        assert_eq!(board.save_data(), None);
+    }
+
+    #[test]
+    fn one_screen_boards() {
+        let mut rom = test_rom::banked(30, 0x80000, 0);
+        rom.four_screen = true;
+        let mut board = Unrom512::new(&rom);
+        assert_eq!(board.mirroring(), Mirroring::SingleScreenLower);
+        board.cpu_write(0xC000, 0x80);
+        assert_eq!(board.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn flash_programs_and_erases() {
+        let mut board = board(true);
+        // $8000-$BFFF no longer selects banks
+        board.cpu_write(0xC000, 3);
+        board.cpu_write(0x8000, 0);
+        assert_eq!(board.cpu_read(0x8000), Some(6));
+
+        flash_command(&mut board, 0xA0);
+        flash_write(&mut board, 0xC010, 0x02);
+        assert!(board.take_save_changed());
+        assert_eq!(board.cpu_read(0x8010), Some(6 & 0x02));
+        assert_eq!(board.save_data().unwrap()[0xC010], 0x02);
+
+        // Sector erase, which takes a second unlock
+        flash_command(&mut board, 0x80);
+        flash_write(&mut board, 0x5555, 0xAA);
+        flash_write(&mut board, 0x2AAA, 0x55);
+        flash_write(&mut board, 0xC123, 0x30);
+        board.cpu_write(0xC000, 3);
+        assert_eq!(board.cpu_read(0x8000), Some(0xFF));
+        assert_eq!(board.cpu_read(0x8FFF), Some(0xFF));
+        assert_eq!(board.cpu_read(0x9000), Some(6));
+    }
+
+    #[test]
+    fn flash_id_mode() {
+        let mut board = board(true);
+        flash_command(&mut board, 0x90);
+        assert_eq!(
+            [board.cpu_read(0x8000), board.cpu_read(0xC001)],
+            [Some(0xBF), Some(0xB7)]
+        );
+        flash_write(&mut board, 0, 0xF0);
+        assert_eq!(board.cpu_read(0xC000), Some(62));
+    }
+}
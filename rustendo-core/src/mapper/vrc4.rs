@@ -0,0 +1,287 @@
+use super::cartridge_memory::CartridgeMemory;
+use super::prg_ram::PrgRam;
+use super::vrc_irq::VrcIrq;
+#[cfg(feature = "serde")]
+use super::CartridgeRom;
+use super::{bank_index, Mapper, Mirroring};
+use crate::rom::Rom;
+
+// Konami VRC2 and VRC4 (mappers 21, 22, 23 and 25): two switchable 8KB PRG banks, eight 1KB
+// CHR banks and switchable mirroring. VRC4 adds a second PRG layout, single-screen mirroring,
+// wider CHR bank numbers and the cycle-counting IRQ.
+//
+// The boards connect the chip's two register select pins to different CPU address lines, which
+// is what the mapper numbers mostly tell apart. NES 2.0 submappers name the exact wiring; for
+// plain iNES headers both candidate lines are listened to, as games only ever drive one of them.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Vrc4 {
+    prg_rom: CartridgeMemory,
+    prg_ram: PrgRam,
+    chr: CartridgeMemory,
+    vrc2: bool,
+    lines: [u16; 2],    // Address lines wired to register select bits 0 and 1
+    chr_shift: u8,      // VRC2a ignores the lowest bit of CHR bank numbers
+    prg_banks: [u8; 2], // $8000-$8003 and $A000-$A003
+    prg_swap: bool,     // VRC4: $8000 and $C000 swapped, $9002 bit 1
+    chr_banks: [u16; 8],
+    mirroring: Mirroring,
+    irq: VrcIrq,
+}
+
+impl Vrc4 {
+    pub fn new(rom: &Rom) -> Self {
+        let lines = match (rom.mapper, rom.submapper) {
+            (21, 1) => [0x02, 0x04], // VRC4a
+            (21, 2) => [0x40, 0x80], // VRC4c
+            (21, _) => [0x42, 0x84],
+            (22, _) => [0x02, 0x01],           // VRC2a
+            (23, 1) | (23, 3) => [0x01, 0x02], // VRC4f, VRC2b
+            (23, 2) => [0x04, 0x08],           // VRC4e
+            (23, _) => [0x05, 0x0A],
+            (25, 1) | (25, 3) => [0x02, 0x01], // VRC4b, VRC2c
+            (25, 2) => [0x08, 0x04],           // VRC4d
+            _ => [0x0A, 0x05],
+        };
+        Self {
+            prg_rom: CartridgeMemory::new(rom.prg_rom.clone(), false),
+            prg_ram: PrgRam::new(rom, rom.prg_ram_size),
+            chr: CartridgeMemory::chr(rom, 0x2000),
+            vrc2: rom.mapper == 22 || rom.submapper == 3,
+            lines,
+            chr_shift: (rom.mapper == 22) as u8,
+            prg_banks: [0; 2],
+            prg_swap: false,
+            chr_banks: [0; 8],
+            mirroring: Mirroring::Vertical,
+            irq: VrcIrq::default(),
+        }
+    }
+
+    // The register an address selects, as $x000-$x003
+    fn register(&self, address: u16) -> u16 {
+        let low = (address & self.lines[0] != 0) as u16;
+        let high = (address & self.lines[1] != 0) as u16;
+        address & 0xF000 | high << 1 | low
+    }
+
+    fn prg_index(&self, address: u16) -> usize {
+        let last = self.prg_rom.len() / 0x2000 - 1;
+        let bank = match (address >> 13 & 0x03, self.prg_swap) {
+            (0, false) | (2, true) => self.prg_banks[0] as usize,
+            (0, true) | (2, false) => last - 1,
+            (1, _) => self.prg_banks[1] as usize,
+            _ => last,
+        };
+        bank_index(&self.prg_rom, 0x2000, bank, address as usize)
+    }
+
+    fn chr_index(&self, address: u16) -> usize {
+        let bank = self.chr_banks[address as usize >> 10 & 0x07] >> self.chr_shift;
+        bank_index(&self.chr, 0x400, bank as usize, address as usize)
+    }
+
+    fn write_chr_bank(&mut self, register: u16, value: u8) {
+        let slot = ((register >> 12) - 0xB) as usize * 2 + (register >> 1 & 0x01) as usize;
+        let bank = &mut self.chr_banks[slot];
+        if register & 0x01 == 0 {
+            *bank = *bank & 0x1F0 | (value & 0x0F) as u16;
+        } else {
+            let high = if self.vrc2 { 0x0F } else { 0x1F };
+            *bank = *bank & 0x0F | ((value & high) as u16) << 4;
+        }
+    }
+}
+
+impl Mapper for Vrc4 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF => self.prg_ram.read(address),
+            0x8000..=0xFFFF if self.prg_rom.len() >= 0x4000 => {
+                Some(self.prg_rom[self.prg_index(address)])
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            self.prg_ram.write(address, value);
+            return;
+        }
+
+        let register = self.register(address);
+        match register {
+            0x8000..=0x8003 => self.prg_banks[0] = value & 0x1F,
+            0x9000..=0x9003 if self.vrc2 => {
+                self.mirroring = if value & 0x01 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            0x9000 | 0x9001 => {
+                self.mirroring = match value & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0x9002 => self.prg_swap = value & 0x02 != 0,
+            0xA000..=0xA003 => self.prg_banks[1] = value & 0x1F,
+            0xB000..=0xEFFF => self.write_chr_bank(register, value),
+            0xF000..=0xFFFF if self.vrc2 => {}
+            0xF000 => self.irq.set_latch(self.irq.latch() & 0xF0 | value & 0x0F),
+            0xF001 => self.irq.set_latch(self.irq.latch() & 0x0F | value << 4),
+            0xF002 => self.irq.write_control(value),
+            0xF003 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_index(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr.is_writable() {
+            let index = self.chr_index(address);
+            self.chr[index] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn cpu_tick(&mut self) {
+        self.irq.clock();
+    }
+
+    fn cycles_until_event(&self) -> u64 {
+        self.irq.cycles_until_irq()
+    }
+
+    fn write_prg_ram(&mut self, address: u16, value: u8) {
+        self.prg_ram.force_write(address, value);
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        self.prg_ram.save_data()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.prg_ram.load_save_data(data);
+    }
+
+    fn take_save_changed(&mut self) -> bool {
+        self.prg_ram.take_changed()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl CartridgeRom for Vrc4 {
+    fn take_rom(&mut self, board: &mut Self) {
+        self.prg_rom.take_rom(&mut board.prg_rom);
+        self.chr.take_rom(&mut board.chr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom;
+
+    fn board(mapper: u16, submapper: u8) -> Vrc4 {
+        let mut rom = test_rom::banked(mapper, 0x20000, 0x40000);
+        rom.submapper = submapper;
+        Vrc4::new(&rom)
+    }
+
+    #[test]
+    fn prg_banks_and_swap() {
+        // Plain mapper 21 listens to A1 or A6 and A2 or A7
+        let mut board = board(21, 0);
+        board.cpu_write(0x8000, 3);
+        board.cpu_write(0xA000, 5);
+        assert_eq!(
+            [0x8000, 0xA000, 0xC000, 0xE000].map(|address| board.cpu_read(address)),
+            [Some(3), Some(5), Some(14), Some(15)]
+        );
+        board.cpu_write(0x9080, 0x02);
+        assert_eq!(
+            [board.cpu_read(0x8000), board.cpu_read(0xC000)],
+            [Some(14), Some(3)]
+        );
+        board.cpu_write(0x9004, 0x00);
+        assert_eq!(board.cpu_read(0x8000), Some(3));
+    }
+
+    #[test]
+    fn chr_banks_in_nibbles() {
+        // VRC4a: A1 and A2
+        let mut vrc4a = board(21, 1);
+        vrc4a.cpu_write(0xB000, 0x05);
+        vrc4a.cpu_write(0xB002, 0x11);
+        vrc4a.cpu_write(0xE006, 0x0F);
+        assert_eq!(
+            [vrc4a.ppu_read(0x0000), vrc4a.ppu_read(0x1C00)],
+            [0x15, 0xF0]
+        );
+
+        // VRC2a drops the lowest bit
+        let mut vrc2 = board(22, 0);
+        vrc2.cpu_write(0xB000, 0x05);
+        assert_eq!(vrc2.ppu_read(0x0000), 2);
+    }
+
+    #[test]
+    fn mirroring() {
+        let mut vrc4 = board(23, 1);
+        vrc4.cpu_write(0x9000, 0x03);
+        assert_eq!(vrc4.mirroring(), Mirroring::SingleScreenUpper);
+        // VRC2 has only the low bit
+        let mut vrc2 = board(23, 3);
+        vrc2.cpu_write(0x9000, 0x03);
+        assert_eq!(vrc2.mirroring(), Mirroring::Horizontal);
+        vrc2.cpu_write(0x9000, 0x02);
+        assert_eq!(vrc2.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn irq_in_cycle_mode() {
+        let mut board = board(23, 1);
+        board.cpu_write(0xF000, 0x0D);
+        board.cpu_write(0xF001, 0x0F);
+        board.cpu_write(0xF002, 0x06);
+        assert_eq!(board.cycles_until_event(), 3);
+        board.cpu_tick();
+        board.cpu_tick();
+        assert!(!board.irq_pending());
+        board.cpu_tick();
+        assert!(board.irq_pending());
+        // Acknowledging copies control bit 0, which was clear
+        board.cpu_write(0xF003, 0);
+        assert!(!board.irq_pending());
+        assert_eq!(board.cycles_until_event(), u64::MAX);
+    }
+
+    #[test]
+    fn irq_in_scanline_mode() {
+        let mut board = board(23, 1);
+        board.cpu_write(0xF000, 0x0F);
+        board.cpu_write(0xF001, 0x0F);
+        board.cpu_write(0xF002, 0x02);
+        assert_eq!(board.cycles_until_event(), 114);
+        for _ in 0..113 {
+            board.cpu_tick();
+        }
+        assert!(!board.irq_pending());
+        board.cpu_tick();
+        assert!(board.irq_pending());
+    }
+}
@@ -0,0 +1,245 @@
+use super::cartridge_memory::CartridgeMemory;
+use super::prg_ram::PrgRam;
+use super::vrc_irq::VrcIrq;
+#[cfg(feature = "serde")]
+use super::CartridgeRom;
+use super::{bank_index, Mapper, Mirroring, PrgRamProtection};
+use crate::rom::Rom;
+
+// Konami VRC6 (mappers 24 and 26, which swap the two register select lines): a 16KB and an 8KB
+// switchable PRG bank, eight CHR bank registers arranged by the banking mode in $B003, and the
+// VRC IRQ counter. Its sound registers at $9000-$B002 are not handled here.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Vrc6 {
+    prg_rom: CartridgeMemory,
+    prg_ram: PrgRam,
+    chr: CartridgeMemory,
+    swap_lines: bool, // Mapper 26 wires A0 and A1 the other way round
+    prg_16k: u8,      // $8000-$BFFF
+    prg_8k: u8,       // $C000-$DFFF
+    chr_banks: [u8; 8],
+    chr_mode: u8, // $B003 bits 0-1
+    mirroring: Mirroring,
+    irq: VrcIrq,
+}
+
+impl Vrc6 {
+    pub fn new(rom: &Rom) -> Self {
+        // Off until $B003 bit 7 turns it on
+        let mut prg_ram = PrgRam::new(rom, rom.prg_ram_size);
+        prg_ram.set_enabled(false);
+        Self {
+            prg_rom: CartridgeMemory::new(rom.prg_rom.clone(), false),
+            prg_ram,
+            chr: CartridgeMemory::chr(rom, 0x2000),
+            swap_lines: rom.mapper == 26,
+            prg_16k: 0,
+            prg_8k: 0,
+            chr_banks: [0; 8],
+            chr_mode: 0,
+            mirroring: Mirroring::Vertical,
+            irq: VrcIrq::default(),
+        }
+    }
+
+    fn prg_index(&self, address: u16) -> usize {
+        let (size, bank) = match address {
+            0x8000..=0xBFFF => (0x4000, self.prg_16k as usize),
+            0xC000..=0xDFFF => (0x2000, self.prg_8k as usize),
+            _ => (0x2000, self.prg_rom.len() / 0x2000 - 1),
+        };
+        bank_index(&self.prg_rom, size, bank, address as usize)
+    }
+
+    // Modes 1-3 use some registers as 2KB banks, with PPU A10 in place of the lowest bank bit
+    fn chr_index(&self, address: u16) -> usize {
+        let slot = address as usize >> 10 & 0x07;
+        let a10 = slot & 0x01;
+        let bank = match (self.chr_mode, slot) {
+            (0, _) => self.chr_banks[slot] as usize,
+            (1, _) => self.chr_banks[slot >> 1] as usize & !0x01 | a10,
+            (_, 0..=3) => self.chr_banks[slot] as usize,
+            _ => self.chr_banks[4 + (slot - 4) / 2] as usize & !0x01 | a10,
+        };
+        bank_index(&self.chr, 0x400, bank, address as usize)
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF => self.prg_ram.read(address),
+            0x8000..=0xFFFF if self.prg_rom.len() >= 0x4000 => {
+                Some(self.prg_rom[self.prg_index(address)])
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            self.prg_ram.write(address, value);
+            return;
+        }
+
+        let mut register = address & 0xF003;
+        if self.swap_lines {
+            register = register & 0xF000 | (register & 0x01) << 1 | (register & 0x02) >> 1;
+        }
+        match register {
+            0x8000..=0x8003 => self.prg_16k = value & 0x0F,
+            0xB003 => {
+                self.prg_ram.set_enabled(value & 0x80 != 0);
+                self.chr_mode = value & 0x03;
+                self.mirroring = match value >> 2 & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0xC000..=0xC003 => self.prg_8k = value & 0x1F,
+            0xD000..=0xD003 => self.chr_banks[(register & 0x03) as usize] = value,
+            0xE000..=0xE003 => self.chr_banks[4 + (register & 0x03) as usize] = value,
+            0xF000 => self.irq.set_latch(value),
+            0xF001 => self.irq.write_control(value),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_index(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr.is_writable() {
+            let index = self.chr_index(address);
+            self.chr[index] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn cpu_tick(&mut self) {
+        self.irq.clock();
+    }
+
+    fn cycles_until_event(&self) -> u64 {
+        self.irq.cycles_until_irq()
+    }
+
+    fn write_prg_ram(&mut self, address: u16, value: u8) {
+        self.prg_ram.force_write(address, value);
+    }
+
+    fn prg_ram_protection(&self) -> Option<PrgRamProtection> {
+        Some(self.prg_ram.protection())
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        self.prg_ram.save_data()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.prg_ram.load_save_data(data);
+    }
+
+    fn take_save_changed(&mut self) -> bool {
+        self.prg_ram.take_changed()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl CartridgeRom for Vrc6 {
+    fn take_rom(&mut self, board: &mut Self) {
+        self.prg_rom.take_rom(&mut board.prg_rom);
+        self.chr.take_rom(&mut board.chr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom;
+
+    fn board(mapper: u16) -> Vrc6 {
+        Vrc6::new(&test_rom::banked(mapper, 0x40000, 0x40000))
+    }
+
+    fn chr(board: &mut Vrc6) -> [u8; 8] {
+        core::array::from_fn(|slot| board.ppu_read(slot as u16 * 0x400))
+    }
+
+    #[test]
+    fn prg_banks() {
+        let mut board = board(24);
+        board.cpu_write(0x8000, 3);
+        board.cpu_write(0xC000, 9);
+        assert_eq!(
+            [0x8000, 0xA000, 0xC000, 0xE000].map(|address| board.cpu_read(address)),
+            [Some(6), Some(7), Some(9), Some(31)]
+        );
+    }
+
+    #[test]
+    fn chr_modes() {
+        let mut board = board(24);
+        for (slot, address) in [
+            0xD000, 0xD001, 0xD002, 0xD003, 0xE000, 0xE001, 0xE002, 0xE003,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            board.cpu_write(address, 10 + slot as u8);
+        }
+        assert_eq!(chr(&mut board), [10, 11, 12, 13, 14, 15, 16, 17]);
+        board.cpu_write(0xB003, 0x01);
+        assert_eq!(chr(&mut board), [10, 11, 10, 11, 12, 13, 12, 13]);
+        board.cpu_write(0xB003, 0x02);
+        assert_eq!(chr(&mut board), [10, 11, 12, 13, 14, 15, 14, 15]);
+    }
+
+    #[test]
+    fn mapper_26_swaps_a0_and_a1() {
+        let mut board = board(26);
+        board.cpu_write(0xD001, 5);
+        board.cpu_write(0xD002, 6);
+        assert_eq!(&chr(&mut board)[..3], [0, 6, 5]);
+    }
+
+    #[test]
+    fn b003_ram_and_mirroring() {
+        let mut board = board(24);
+        board.cpu_write(0x6000, 0x42);
+        assert_eq!(board.cpu_read(0x6000), None);
+        board.cpu_write(0xB003, 0x84);
+        board.cpu_write(0x6000, 0x42);
+        assert_eq!(board.cpu_read(0x6000), Some(0x42));
+        assert_eq!(board.mirroring(), Mirroring::Horizontal);
+        board.cpu_write(0xB003, 0x0C);
+        assert_eq!(board.mirroring(), Mirroring::SingleScreenUpper);
+        assert!(!board.prg_ram_protection().unwrap().enabled);
+    }
+
+    #[test]
+    fn irq() {
+        let mut board = board(24);
+        board.cpu_write(0xF000, 0xFE);
+        board.cpu_write(0xF001, 0x07);
+        board.cpu_tick();
+        assert!(!board.irq_pending());
+        board.cpu_tick();
+        assert!(board.irq_pending());
+        // Control bit 0 keeps it counting after the acknowledge
+        board.cpu_write(0xF002, 0);
+        assert!(!board.irq_pending());
+        assert_eq!(board.cycles_until_event(), 2);
+    }
+}
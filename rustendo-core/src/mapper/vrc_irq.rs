@@ -0,0 +1,86 @@
+use tracing::debug;
+
+// The IRQ counter shared by Konami's VRC4, VRC6 and VRC7. An 8-bit counter counts up from a
+// reloadable latch and raises an IRQ when it overflows. In scanline mode a prescaler clocks it
+// every 341/3 CPU cycles, i.e. once per scanline; in cycle mode it counts every CPU cycle.
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16, // Counts down by 3 each CPU cycle; at 0 the counter is clocked
+    enabled: bool,
+    enable_after_ack: bool, // Control bit 0, copied to `enabled` on acknowledge
+    cycle_mode: bool,
+    pending: bool,
+}
+
+impl VrcIrq {
+    pub fn latch(&self) -> u8 {
+        self.latch
+    }
+
+    pub fn set_latch(&mut self, latch: u8) {
+        self.latch = latch;
+    }
+
+    pub fn write_control(&mut self, value: u8) {
+        self.enable_after_ack = value & 0x01 != 0;
+        self.enabled = value & 0x02 != 0;
+        self.cycle_mode = value & 0x04 != 0;
+        self.pending = false;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = 341;
+        }
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    pub fn pending(&self) -> bool {
+        self.pending
+    }
+
+    // CPU cycles until the counter next overflows, at most
+    pub fn cycles_until_irq(&self) -> u64 {
+        if !self.enabled {
+            return u64::MAX;
+        }
+        let clocks = 0x100 - self.counter as u64;
+        if self.cycle_mode {
+            return clocks;
+        }
+        // The prescaler clocks the counter every 113 or 114 cycles
+        let first = (self.prescaler.max(1) as u64).div_ceil(3);
+        first + (clocks - 1) * 113
+    }
+
+    // Advance by one CPU cycle
+    pub fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.cycle_mode {
+            self.clock_counter();
+        } else {
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += 341;
+                self.clock_counter();
+            }
+        }
+    }
+
+    fn clock_counter(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            debug!(target: "mapper", "VRC IRQ");
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
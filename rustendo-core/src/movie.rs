@@ -0,0 +1,179 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::fmt::Write;
+
+use crate::nes::InputError;
+
+const MAGIC: &str = "rustendo input 1";
+
+// Every controller state change in a run, with the frame it was latched on. Played back from
+// power-on with the same ROM and settings (region, Four Score), it reproduces the run exactly.
+//
+// The text format is a `rustendo input 1` line, a `crc32 <hex>` line naming the ROM, then one
+// `<frame> <port> <buttons>` line per change, the buttons as a hex bitmask (bit 0 = A ...
+// bit 7 = Right). `#` starts a comment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_crc32: u32,          // `Rom::crc32` of the game it was recorded on
+    pub inputs: Vec<MovieInput>, // In frame order
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovieInput {
+    pub frame: u64,
+    pub port: usize,
+    pub buttons: u8,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MovieError {
+    NotAMovie,          // The first line isn't `rustendo input 1`
+    InvalidLine(usize), // 1-based
+    WrongRom(u32, u32), // Recorded on, being played on
+    Input(InputError),  // Playback started after the movie's first frame
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::NotAMovie => write!(f, "not a rustendo input recording"),
+            MovieError::InvalidLine(line) => write!(f, "invalid input on line {}", line),
+            MovieError::WrongRom(recorded, loaded) => write!(
+                f,
+                "recorded on a ROM with CRC32 {:08X}, but this one is {:08X}",
+                recorded, loaded
+            ),
+            MovieError::Input(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl core::error::Error for MovieError {}
+
+impl From<InputError> for MovieError {
+    fn from(e: InputError) -> Self {
+        MovieError::Input(e)
+    }
+}
+
+impl Movie {
+    pub fn new(rom_crc32: u32) -> Self {
+        Self {
+            rom_crc32,
+            inputs: Vec::new(),
+        }
+    }
+
+    // The last frame with an input change, or 0 for an empty movie
+    pub fn length(&self) -> u64 {
+        self.inputs.last().map_or(0, |input| input.frame)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, MovieError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.split('#').next().unwrap_or("").trim()))
+            .filter(|(_, line)| !line.is_empty());
+        if lines.next().map(|(_, line)| line) != Some(MAGIC) {
+            return Err(MovieError::NotAMovie);
+        }
+
+        let (number, line) = lines.next().ok_or(MovieError::NotAMovie)?;
+        let rom_crc32 = line
+            .strip_prefix("crc32 ")
+            .and_then(|crc| u32::from_str_radix(crc.trim(), 16).ok())
+            .ok_or(MovieError::InvalidLine(number))?;
+
+        let mut inputs: Vec<MovieInput> = Vec::new();
+        for (number, line) in lines {
+            let input = parse_input(line).ok_or(MovieError::InvalidLine(number))?;
+            if inputs.last().is_some_and(|last| last.frame > input.frame) {
+                return Err(MovieError::InvalidLine(number));
+            }
+            inputs.push(input);
+        }
+        Ok(Self { rom_crc32, inputs })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        // Writing to a String can't fail
+        let _ = writeln!(text, "{}", MAGIC);
+        let _ = writeln!(text, "crc32 {:08X}", self.rom_crc32);
+        let _ = writeln!(text, "# frame port buttons");
+        for input in &self.inputs {
+            let _ = writeln!(text, "{} {} {:02X}", input.frame, input.port, input.buttons);
+        }
+        text
+    }
+}
+
+fn parse_input(line: &str) -> Option<MovieInput> {
+    let mut fields = line.split_whitespace();
+    let input = MovieInput {
+        frame: fields.next()?.parse().ok()?,
+        port: fields.next()?.parse().ok()?,
+        buttons: u8::from_str_radix(fields.next()?, 16).ok()?,
+    };
+    fields.next().is_none().then_some(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(frame: u64, port: usize, buttons: u8) -> MovieInput {
+        MovieInput {
+            frame,
+            port,
+            buttons,
+        }
+    }
+
+    #[test]
+    fn parse_and_write() {
+        let text = "rustendo input 1\n# Comment\ncrc32 3f0e2a1b\n\n0 0 00\n12 0 81 # A and Right\n12 1 08\n";
+        let movie = Movie::parse(text).unwrap();
+        assert_eq!(movie.rom_crc32, 0x3F0E_2A1B);
+        assert_eq!(
+            movie.inputs,
+            [input(0, 0, 0x00), input(12, 0, 0x81), input(12, 1, 0x08)]
+        );
+        assert_eq!(movie.length(), 12);
+        assert_eq!(Movie::parse(&movie.to_text()), Ok(movie));
+        assert_eq!(Movie::new(0).length(), 0);
+    }
+
+    #[test]
+    fn invalid_movies() {
+        assert_eq!(Movie::parse(""), Err(MovieError::NotAMovie));
+        assert_eq!(
+            Movie::parse("rustendo input 2\ncrc32 0\n"),
+            Err(MovieError::NotAMovie)
+        );
+        assert_eq!(
+            Movie::parse("rustendo input 1\n"),
+            Err(MovieError::NotAMovie)
+        );
+        assert_eq!(
+            Movie::parse("rustendo input 1\ncrc32 xyz\n"),
+            Err(MovieError::InvalidLine(2))
+        );
+        for line in ["1 0", "1 0 100", "1 0 01 02", "-1 0 01", "1 0 GG"] {
+            let text = alloc::format!("rustendo input 1\ncrc32 0\n\n{}\n", line);
+            assert_eq!(
+                Movie::parse(&text),
+                Err(MovieError::InvalidLine(4)),
+                "{}",
+                line
+            );
+        }
+        // Inputs out of frame order
+        assert_eq!(
+            Movie::parse("rustendo input 1\ncrc32 0\n5 0 01\n4 0 00\n"),
+            Err(MovieError::InvalidLine(4))
+        );
+    }
+}
@@ -0,0 +1,1202 @@
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::{Ref, RefCell, RefMut};
+use core::fmt;
+use core::ops::RangeInclusive;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::Path;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+#[cfg(feature = "serde")]
+use crate::apu::APU;
+#[cfg(feature = "std")]
+use crate::apu::CHANNEL_NAMES;
+use crate::apu::{AudioFilter, CHANNELS};
+use crate::cheat::{CheatError, Cheats};
+use crate::checksum;
+use crate::compositor::{Compositor, Layer};
+use crate::controller::Controller;
+use crate::cpu::Registers;
+use crate::cpu::CPU;
+use crate::cpu_bus::{BusAccess, CpuBus, UnmappedReads};
+use crate::debug::{self, DebugView};
+use crate::disasm::{self, Instruction};
+use crate::input::InputSource;
+use crate::keyboard::FamilyKeyboard;
+use crate::mapper::{MapperError, PrgRamProtection};
+use crate::movie::{Movie, MovieError, MovieInput};
+use crate::ntsc::NtscFilter;
+use crate::osd;
+use crate::overscan::Overscan;
+use crate::palette::{Palette, PixelFormat};
+#[cfg(feature = "serde")]
+use crate::ppu::PPU;
+use crate::ppu::{LayerVisibility, RenderMode, DOTS_PER_SCANLINE, SCREEN_HEIGHT, SCREEN_WIDTH};
+#[cfg(feature = "std")]
+use crate::profile::Profile;
+use crate::region::Region;
+use crate::rom::Rom;
+#[cfg(feature = "serde")]
+use crate::state::{Section, StateError, StateReader, StateWriter};
+#[cfg(feature = "std")]
+use crate::wav::WavWriter;
+
+// Controllers 1 and 2, and 3 and 4 when a Four Score is connected
+pub const CONTROLLER_PORTS: usize = 4;
+
+pub type FrameCallback = Box<dyn FnMut(&[u8])>;
+
+// Two frames pressed, two released: 15 presses a second on NTSC
+pub const DEFAULT_TURBO_RATE: u64 = 2;
+
+// Frames an on-screen message stays up, two seconds on NTSC
+const MESSAGE_FRAMES: u64 = 120;
+
+// When frozen addresses are forced back to their values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FreezeTiming {
+    // After every instruction, so the game never gets to act on another value
+    #[default]
+    Instruction,
+    // Once at the start of each frame, which is enough for most cheats
+    Frame,
+}
+
+// Sample rate used for recordings when the embedder has not picked one
+#[cfg(feature = "std")]
+pub const DEFAULT_RECORDING_RATE: u32 = 44_100;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum InputError {
+    FrameAlreadyPassed { frame: u64, current: u64 },
+    InvalidPort(usize),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputError::FrameAlreadyPassed { frame, current } => write!(
+                f,
+                "cannot queue input for frame {} (current frame is {})",
+                frame, current
+            ),
+            InputError::InvalidPort(port) => write!(f, "invalid controller port {}", port),
+        }
+    }
+}
+
+impl core::error::Error for InputError {}
+
+// An instruction as the CPU was about to run it, kept by `Nes::set_instruction_history`
+#[derive(Debug, Clone, Copy)]
+pub struct TracedInstruction {
+    pub cycle: u64,
+    pub registers: Registers,
+    pub instruction: Instruction,
+}
+
+// `20 00 80  JSR $8000     PC:C004 A:00 X:00 Y:00 P:24 SP:FD CYC:27`
+impl fmt::Display for TracedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (bytes, size) = self.instruction.bytes();
+        for byte in &bytes[..size] {
+            write!(f, "{:02X} ", byte)?;
+        }
+        let instruction = format!("{}", self.instruction);
+        write!(
+            f,
+            "{:pad$} {:13} {} CYC:{}",
+            "",
+            instruction,
+            self.registers,
+            self.cycle,
+            pad = (3 - size) * 3
+        )
+    }
+}
+
+// Controller states being recorded into a movie
+struct InputRecording {
+    movie: Movie,
+    last: [u8; CONTROLLER_PORTS], // Last recorded state of each port, all released to begin with
+    frame: Option<u64>,           // Last frame whose states were recorded
+}
+
+#[cfg(feature = "std")]
+struct AudioRecording {
+    mix: WavWriter,
+    stems: Vec<WavWriter>,    // Per channel, in `CHANNEL_NAMES` order
+    keep_samples: bool,       // Whether the embedder also takes the samples
+    error: Option<io::Error>, // First write error, reported when stopping
+}
+
+pub struct Nes {
+    memory: Rc<RefCell<CpuBus>>,
+    cpu: CPU,
+    input_queue: BTreeMap<u64, Vec<(usize, u8)>>, // Frame -> (port, buttons) in queue order
+    input_source: Option<Box<dyn InputSource>>,   // Polled for every port each frame
+    inputs_applied: Option<u64>,                  // Last frame whose queued inputs were latched
+    turbo_rate: u64,                              // Frames autofire holds a button down, then up
+    rom_crc32: u32,                               // Identifies the game in movies and states
+    input_recording: Option<InputRecording>,      // Controller changes being recorded
+    frame_callback: Option<FrameCallback>,        // Called with each frame once it completes
+    compositor: Compositor,                       // Overlays stacked on top of the game frame
+    debug_view: Option<DebugView>,                // Redrawn onto the debug layer every frame
+    scope: Vec<[f32; CHANNELS]>,                  // Channel levels over the frame, for the APU view
+    scope_recording: bool,                        // Keep `scope` for `take_scope`, without the view
+    message_frames: u64,                          // Frames left before the OSD message is cleared
+    ntsc_filter: Option<NtscFilter>,              // Composite video simulation, when enabled
+    filtered_frame: Vec<u8>,                      // RGBA output of the NTSC filter
+    overscan: Overscan,                           // Edges trimmed from the exported frame
+    frozen: BTreeMap<u16, u8>,                    // Address -> value forced by cheats
+    breakpoints: BTreeSet<u16>, // Addresses `step_frame_until_breakpoint` stops at
+    freeze_timing: FreezeTiming, // When the frozen values are written back
+    output: Vec<u8>,            // Cropped RGBA frame handed to front ends
+    audio_samples: Vec<f32>,    // Recorded samples not yet taken
+    instructions: u64,          // Executed since the console was created
+    history: VecDeque<TracedInstruction>, // The last instructions run, oldest first
+    history_len: usize,         // How many `history` keeps, 0 for none
+    overclock: u32,             // Extra scanlines of CPU time per frame
+    // WAV files the audio is streamed to
+    #[cfg(feature = "std")]
+    audio_recording: Option<AudioRecording>,
+}
+
+impl Nes {
+    // Fails if the ROM needs a mapper that is not supported
+    pub fn new(rom: &Rom) -> Result<Self, MapperError> {
+        let memory = Rc::new(RefCell::new(CpuBus::new()));
+        memory.borrow_mut().load_rom(rom)?;
+
+        Ok(Self {
+            cpu: CPU::new(Rc::clone(&memory)),
+            memory,
+            input_queue: BTreeMap::new(),
+            input_source: None,
+            inputs_applied: None,
+            turbo_rate: DEFAULT_TURBO_RATE,
+            rom_crc32: rom.crc32(),
+            input_recording: None,
+            frame_callback: None,
+            compositor: Compositor::new(),
+            debug_view: None,
+            scope: Vec::new(),
+            scope_recording: false,
+            message_frames: 0,
+            ntsc_filter: None,
+            filtered_frame: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+            overscan: Overscan::default(),
+            frozen: BTreeMap::new(),
+            breakpoints: BTreeSet::new(),
+            freeze_timing: FreezeTiming::default(),
+            output: Vec::new(),
+            audio_samples: Vec::new(),
+            instructions: 0,
+            history: VecDeque::new(),
+            history_len: 0,
+            overclock: 0,
+            #[cfg(feature = "std")]
+            audio_recording: None,
+        })
+    }
+
+    pub fn region(&self) -> Region {
+        self.memory.borrow().ppu().region()
+    }
+
+    // Extra scanlines' worth of CPU time each frame, 0 for none
+    pub fn overclock(&self) -> u32 {
+        self.overclock
+    }
+
+    // Let the CPU run for `scanlines` more lines at the start of each vblank while the rest of
+    // the console waits, so games that slow down have more time for each frame. The picture
+    // and sound stay as they were; games that time things with the CPU may run differently.
+    pub fn set_overclock(&mut self, scanlines: u32) {
+        self.overclock = scanlines;
+        let dots = scanlines as u64 * DOTS_PER_SCANLINE as u64;
+        let cycles = if scanlines > 0 {
+            self.region().cycles_for_dots(dots)
+        } else {
+            0
+        };
+        self.memory.borrow_mut().set_overclock(cycles);
+    }
+
+    // CPU cycles since power-on
+    pub fn cycles(&self) -> u64 {
+        self.memory.borrow().cycles()
+    }
+
+    // CPU instructions executed since the console was created, not counting interrupts
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    // Keep the last `len` instructions the CPU ran, with the registers before each, for crash
+    // reports and debuggers. 0, the default, stops keeping them.
+    pub fn set_instruction_history(&mut self, len: usize) {
+        self.history_len = len;
+        while self.history.len() > len {
+            self.history.pop_front();
+        }
+    }
+
+    // The instructions kept by `set_instruction_history`, oldest first
+    pub fn instruction_history(&self) -> impl Iterator<Item = &TracedInstruction> + '_ {
+        self.history.iter()
+    }
+
+    // Time how long each part of the console takes from now on, from zero, or stop with false.
+    // This slows emulation down noticeably.
+    #[cfg(feature = "std")]
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.memory.borrow_mut().set_profiling(enabled);
+    }
+
+    // The time spent so far, while profiling
+    #[cfg(feature = "std")]
+    pub fn profile(&self) -> Option<Profile> {
+        self.memory.borrow().profile()
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.memory.borrow().ppu().frame_count() as u64
+    }
+
+    // A hash of the last completed frame, taken over its palette indices and emphasis bits so it
+    // doesn't depend on the palette, pixel format, overscan, NTSC filter or overlays. It stays
+    // the same between builds and platforms, for comparing runs against known-good ones.
+    pub fn frame_hash(&self) -> u64 {
+        let memory = self.memory.borrow();
+        let indices = memory.ppu().frame_indices();
+        checksum::fnv1a64(indices.iter().flat_map(|index| index.to_le_bytes()))
+    }
+
+    // Register a function that receives every completed frame, exactly once per frame
+    pub fn set_frame_callback(&mut self, callback: impl FnMut(&[u8]) + 'static) {
+        self.frame_callback = Some(Box::new(callback));
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.memory.borrow_mut().ppu_mut().set_palette(palette);
+    }
+
+    // Hide the background, the sprites, or all sprites but one palette in the rendered frame
+    pub fn set_layer_visibility(&mut self, visibility: LayerVisibility) {
+        self.memory
+            .borrow_mut()
+            .ppu_mut()
+            .set_layer_visibility(visibility);
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.memory.borrow_mut().ppu_mut().set_render_mode(mode);
+    }
+
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.memory.borrow_mut().ppu_mut().set_pixel_format(format);
+    }
+
+    // Borrow the current frame as 256x240 pixels in the configured pixel format
+    pub fn with_frame<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        f(self.memory.borrow().ppu().frame())
+    }
+
+    pub fn compositor(&self) -> &Compositor {
+        &self.compositor
+    }
+
+    pub fn compositor_mut(&mut self) -> &mut Compositor {
+        &mut self.compositor
+    }
+
+    pub fn ntsc_filter_enabled(&self) -> bool {
+        self.ntsc_filter.is_some()
+    }
+
+    // Run the game frame through the NTSC composite filter before compositing
+    pub fn set_ntsc_filter(&mut self, enabled: bool) {
+        if enabled != self.ntsc_filter.is_some() {
+            self.ntsc_filter = enabled.then(NtscFilter::new);
+        }
+    }
+
+    // The current frame (NTSC filtered if enabled) with all visible overlay layers blended on
+    // top, as RGBA pixels
+    pub fn composited_frame(&mut self) -> &[u8] {
+        let memory = self.memory.borrow();
+        let ppu = memory.ppu();
+        match &self.ntsc_filter {
+            Some(filter) => {
+                filter.apply(
+                    ppu.frame_indices(),
+                    ppu.frame_count(),
+                    &mut self.filtered_frame,
+                );
+                self.compositor
+                    .compose(&self.filtered_frame, PixelFormat::Rgba8888)
+            }
+            None => self.compositor.compose(ppu.frame(), ppu.pixel_format()),
+        }
+    }
+
+    pub fn debug_view(&self) -> Option<DebugView> {
+        self.debug_view
+    }
+
+    // Show a debug view on the debug layer, redrawn after every frame, or clear it with `None`
+    pub fn set_debug_view(&mut self, view: Option<DebugView>) {
+        self.debug_view = view;
+        self.draw_debug_view();
+    }
+
+    fn draw_debug_view(&mut self) {
+        let memory = self.memory.borrow();
+        match self.debug_view {
+            Some(DebugView::Cpu) => {
+                debug::overlay_cpu(self.cpu.registers(), &memory, &mut self.compositor)
+            }
+            Some(DebugView::Apu) => {
+                debug::overlay_apu(&self.scope, &mut self.compositor);
+                self.scope.clear();
+            }
+            Some(view) => debug::overlay(view, memory.ppu(), &mut self.compositor),
+            None => self.compositor.clear(Layer::Debug),
+        }
+    }
+
+    // Record the APU channels' levels after every instruction, as the APU view does, for front
+    // ends that draw their own scopes
+    pub fn set_scope_recording(&mut self, enabled: bool) {
+        self.scope_recording = enabled;
+        if !enabled {
+            self.scope.clear();
+        }
+    }
+
+    // The levels recorded since the last call, or since the APU view last drew them
+    pub fn take_scope(&mut self, scope: &mut Vec<[f32; CHANNELS]>) {
+        scope.append(&mut self.scope);
+    }
+
+    // Show a line of text in the bottom-left corner of the picture, on the OSD layer, for a
+    // couple of seconds. It replaces any message still showing.
+    pub fn show_message(&mut self, text: &str) {
+        let overscan = self.overscan;
+        let x = overscan.left + 4;
+        let y = SCREEN_HEIGHT.saturating_sub(overscan.bottom + 4 + osd::GLYPH_HEIGHT + 4);
+        self.compositor.clear(Layer::Osd);
+        self.compositor.fill_rect(
+            Layer::Osd,
+            x,
+            y,
+            osd::text_width(text) + 4,
+            osd::GLYPH_HEIGHT + 4,
+            [0x00, 0x00, 0x00, 0xA0],
+        );
+        osd::draw_text(
+            &mut self.compositor,
+            Layer::Osd,
+            x + 2,
+            y + 2,
+            text,
+            [0xFF, 0xFF, 0xFF, 0xFF],
+        );
+        self.message_frames = MESSAGE_FRAMES;
+    }
+
+    // Have `hook` told about every CPU bus read and write, from the CPU, DMA or cheats. It runs
+    // in the middle of an access, so it must not call back into the `Nes`.
+    pub fn set_access_hook(&mut self, hook: impl FnMut(BusAccess) + 'static) {
+        self.memory.borrow_mut().set_access_hook(hook);
+    }
+
+    pub fn clear_access_hook(&mut self) {
+        self.memory.borrow_mut().clear_access_hook();
+    }
+
+    pub fn unmapped_reads(&self) -> UnmappedReads {
+        self.memory.borrow().unmapped_reads()
+    }
+
+    // Whether reads nothing answers return open bus (the default) or 0
+    pub fn set_unmapped_reads(&mut self, unmapped_reads: UnmappedReads) {
+        self.memory.borrow_mut().set_unmapped_reads(unmapped_reads);
+    }
+
+    pub fn overscan(&self) -> Overscan {
+        self.overscan
+    }
+
+    pub fn set_overscan(&mut self, overscan: Overscan) {
+        self.overscan = overscan.clamped();
+    }
+
+    // The composited frame with the overscan trimmed off, as `overscan().width()` by
+    // `overscan().height()` RGBA pixels. This is what front ends should display or save.
+    pub fn output_frame(&mut self) -> &[u8] {
+        let overscan = self.overscan;
+        let mut output = core::mem::take(&mut self.output);
+        overscan.crop(self.composited_frame(), 4, &mut output);
+        self.output = output;
+        &self.output
+    }
+
+    pub fn audio_sample_rate(&self) -> Option<u32> {
+        self.memory.borrow().apu().sample_rate()
+    }
+
+    // Start generating mono audio at `rate` Hz for `take_audio_samples`, or stop with `None`.
+    // Samples are only kept while a rate is set. While a recording is running the rate stays
+    // at the recording's.
+    pub fn set_audio_sample_rate(&mut self, rate: Option<u32>) {
+        #[cfg(feature = "std")]
+        if let Some(recording) = self.audio_recording.as_mut() {
+            recording.keep_samples = rate.is_some();
+            return;
+        }
+        self.memory.borrow_mut().apu_mut().set_sample_rate(rate);
+    }
+
+    // Replace the analog output filters applied to the samples (`NES_FILTERS` by default)
+    pub fn set_audio_filters(&mut self, filters: &[AudioFilter]) {
+        self.memory.borrow_mut().apu_mut().set_filters(filters);
+    }
+
+    // Scale the Famicom Disk System's expansion sound, when the game uses it
+    pub fn set_fds_volume(&mut self, volume: f32) {
+        self.memory.borrow_mut().apu_mut().set_fds_volume(volume);
+    }
+
+    // Append the samples generated since the last call to `samples`. Call it regularly (e.g.
+    // once per frame), as samples pile up until they are taken.
+    pub fn take_audio_samples(&mut self, samples: &mut Vec<f32>) {
+        samples.append(&mut self.audio_samples);
+        self.memory.borrow_mut().apu_mut().take_samples(samples);
+    }
+
+    #[cfg(feature = "std")]
+    pub fn audio_recording(&self) -> bool {
+        self.audio_recording.is_some()
+    }
+
+    // Stream the mixed audio to a WAV file at `path`, plus one file per channel next to it
+    // (`out.pulse1.wav` and so on) with `stems`. Uses the current sample rate, or
+    // `DEFAULT_RECORDING_RATE` if none is set. Any recording already running is stopped first.
+    #[cfg(feature = "std")]
+    pub fn start_audio_recording(&mut self, path: impl AsRef<Path>, stems: bool) -> io::Result<()> {
+        self.stop_audio_recording()?;
+
+        let path = path.as_ref();
+        let keep_samples = self.audio_sample_rate().is_some();
+        let rate = self.audio_sample_rate().unwrap_or(DEFAULT_RECORDING_RATE);
+        let mix = WavWriter::create(path, rate)?;
+        let stems = if stems {
+            CHANNEL_NAMES
+                .iter()
+                .map(|name| WavWriter::create(&path.with_extension(format!("{}.wav", name)), rate))
+                .collect::<io::Result<Vec<_>>>()?
+        } else {
+            Vec::new()
+        };
+
+        let mut memory = self.memory.borrow_mut();
+        let apu = memory.apu_mut();
+        if !keep_samples {
+            apu.set_sample_rate(Some(rate));
+        }
+        apu.set_stems(!stems.is_empty());
+        self.audio_recording = Some(AudioRecording {
+            mix,
+            stems,
+            keep_samples,
+            error: None,
+        });
+        Ok(())
+    }
+
+    // Finish the current recording, if any, reporting the first error hit while writing it
+    #[cfg(feature = "std")]
+    pub fn stop_audio_recording(&mut self) -> io::Result<()> {
+        self.record_audio();
+        let Some(recording) = self.audio_recording.take() else {
+            return Ok(());
+        };
+
+        let mut memory = self.memory.borrow_mut();
+        let apu = memory.apu_mut();
+        apu.set_stems(false);
+        if !recording.keep_samples {
+            apu.set_sample_rate(None);
+        }
+
+        let mut result = recording.mix.finish();
+        for stem in recording.stems {
+            result = result.and(stem.finish());
+        }
+        match recording.error {
+            Some(e) => Err(e),
+            None => result,
+        }
+    }
+
+    // Write the samples generated since the last call to the recording
+    #[cfg(feature = "std")]
+    fn record_audio(&mut self) {
+        let Some(recording) = self.audio_recording.as_mut() else {
+            return;
+        };
+        let mut memory = self.memory.borrow_mut();
+        let apu = memory.apu_mut();
+
+        let start = self.audio_samples.len();
+        apu.take_samples(&mut self.audio_samples);
+        let mut result = recording.mix.write(&self.audio_samples[start..]);
+        if !recording.keep_samples {
+            self.audio_samples.truncate(start);
+        }
+
+        let mut stem_samples = Vec::new();
+        for (channel, stem) in (0..CHANNELS).zip(&mut recording.stems) {
+            apu.take_stem_samples(channel, &mut stem_samples);
+            result = result.and(stem.write(&stem_samples));
+            stem_samples.clear();
+        }
+
+        if let Err(e) = result {
+            recording.error.get_or_insert(e);
+        }
+    }
+
+    pub fn controller(&self, port: usize) -> Option<Ref<'_, Controller>> {
+        Ref::filter_map(self.memory.borrow(), |bus| bus.controller(port)).ok()
+    }
+
+    pub fn four_score(&self) -> bool {
+        self.memory.borrow().four_score()
+    }
+
+    // Connect a Four Score, so games that support it read controllers 3 and 4 (ports 2 and 3)
+    pub fn set_four_score(&mut self, connected: bool) {
+        self.memory.borrow_mut().set_four_score(connected);
+    }
+
+    pub fn family_keyboard(&self) -> bool {
+        self.memory.borrow().family_keyboard().is_some()
+    }
+
+    // Plug in the Family BASIC keyboard, for Family BASIC itself and homebrew that supports it
+    pub fn set_family_keyboard(&mut self, connected: bool) {
+        self.memory.borrow_mut().set_family_keyboard(connected);
+    }
+
+    pub fn family_keyboard_mut(&mut self) -> Option<RefMut<'_, FamilyKeyboard>> {
+        RefMut::filter_map(self.memory.borrow_mut(), |bus| bus.family_keyboard_mut()).ok()
+    }
+
+    pub fn microphone(&self) -> bool {
+        self.memory.borrow().microphone()
+    }
+
+    // Hold the Famicom microphone's line high or low, e.g. while a key is held
+    pub fn set_microphone(&mut self, active: bool) {
+        self.memory.borrow_mut().set_microphone(active);
+    }
+
+    pub fn turbo_rate(&self) -> u64 {
+        self.turbo_rate
+    }
+
+    // Set how many frames autofire holds a button down and then releases it for. The phase
+    // follows the frame number, so recordings replay with the same presses.
+    pub fn set_turbo_rate(&mut self, frames: u64) {
+        self.turbo_rate = frames.max(1);
+    }
+
+    // For front ends to press and release buttons as the player does
+    pub fn controller_mut(&mut self, port: usize) -> Option<RefMut<'_, Controller>> {
+        RefMut::filter_map(self.memory.borrow_mut(), |bus| bus.controller_mut(port)).ok()
+    }
+
+    pub fn memory(&self) -> &Rc<RefCell<CpuBus>> {
+        &self.memory
+    }
+
+    // The CPU registers between instructions, PC pointing at the next one
+    pub fn cpu_registers(&self) -> Registers {
+        self.cpu.registers()
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    // Read the CPU address space without side effects: no cycles pass, $2002 keeps its VBlank
+    // flag, $2007 and the controllers don't advance
+    pub fn peek(&self, address: u16) -> u8 {
+        self.memory.borrow().peek(address)
+    }
+
+    pub fn peek_range(&self, range: RangeInclusive<u16>) -> Vec<u8> {
+        let memory = self.memory.borrow();
+        range.map(|address| memory.peek(address)).collect()
+    }
+
+    // Keep `address` at `value` like an "infinite lives" cheat, even when the game keeps
+    // writing it. Only internal RAM ($0000-$1FFF) and $6000-$7FFF can be frozen.
+    pub fn freeze(&mut self, address: u16, value: u8) {
+        if let 0x0000..=0x1FFF | 0x6000..=0x7FFF = address {
+            self.frozen.insert(address, value);
+            self.memory.borrow_mut().write_ram(address, value);
+        }
+    }
+
+    pub fn unfreeze(&mut self, address: u16) {
+        self.frozen.remove(&address);
+    }
+
+    pub fn unfreeze_all(&mut self) {
+        self.frozen.clear();
+    }
+
+    // The frozen addresses and their values, in address order
+    pub fn frozen(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.frozen
+            .iter()
+            .map(|(&address, &value)| (address, value))
+    }
+
+    pub fn freeze_timing(&self) -> FreezeTiming {
+        self.freeze_timing
+    }
+
+    pub fn set_freeze_timing(&mut self, timing: FreezeTiming) {
+        self.freeze_timing = timing;
+    }
+
+    fn apply_frozen(&mut self) {
+        if self.frozen.is_empty() {
+            return;
+        }
+        let mut memory = self.memory.borrow_mut();
+        for (&address, &value) in &self.frozen {
+            memory.write_ram(address, value);
+        }
+    }
+
+    // Add a Game Genie or raw cheat code, which takes effect on the next read of its address
+    pub fn add_cheat(&mut self, code: &str) -> Result<(), CheatError> {
+        self.memory.borrow_mut().cheats_mut().add(code)
+    }
+
+    pub fn remove_cheat(&mut self, code: &str) -> bool {
+        self.memory.borrow_mut().cheats_mut().remove(code)
+    }
+
+    pub fn set_cheat_enabled(&mut self, code: &str, enabled: bool) -> bool {
+        self.memory
+            .borrow_mut()
+            .cheats_mut()
+            .set_enabled(code, enabled)
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.memory.borrow_mut().cheats_mut().clear();
+    }
+
+    // Replace the cheats with another list, e.g. one parsed from a game's cheat file
+    pub fn set_cheats(&mut self, cheats: Cheats) {
+        *self.memory.borrow_mut().cheats_mut() = cheats;
+    }
+
+    pub fn cheats(&self) -> Ref<'_, Cheats> {
+        Ref::map(self.memory.borrow(), |bus| bus.cheats())
+    }
+
+    // Whether the cartridge currently has its PRG-RAM disabled or write-protected, for boards
+    // that can do either
+    pub fn prg_ram_protection(&self) -> Option<PrgRamProtection> {
+        let memory = self.memory.borrow();
+        let protection = memory.cartridge()?.borrow().prg_ram_protection();
+        protection
+    }
+
+    // A copy of the cartridge memory that survives power-off (battery-backed RAM or flash), for
+    // boards that have some
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        let memory = self.memory.borrow();
+        let data = memory.cartridge()?.borrow().save_data().map(<[u8]>::to_vec);
+        data
+    }
+
+    // Restore the memory returned by `save_data`, e.g. from a save file
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        if let Some(cartridge) = self.memory.borrow().cartridge() {
+            cartridge.borrow_mut().load_save_data(data);
+        }
+    }
+
+    // Whether the save data changed since the last call and should be written out again
+    pub fn take_save_changed(&mut self) -> bool {
+        self.memory
+            .borrow()
+            .cartridge()
+            .is_some_and(|cartridge| cartridge.borrow_mut().take_save_changed())
+    }
+
+    // Schedule a button state for `port` that is latched at the start of `frame`.
+    // Several inputs for the same frame and port are applied in the order they were queued.
+    pub fn queue_input(&mut self, frame: u64, port: usize, buttons: u8) -> Result<(), InputError> {
+        if port >= CONTROLLER_PORTS {
+            return Err(InputError::InvalidPort(port));
+        }
+
+        let current = self.frame();
+        let started = self
+            .inputs_applied
+            .is_some_and(|applied| applied >= current);
+        if frame < current || (frame == current && started) {
+            return Err(InputError::FrameAlreadyPassed { frame, current });
+        }
+
+        self.input_queue
+            .entry(frame)
+            .or_default()
+            .push((port, buttons));
+        Ok(())
+    }
+
+    // Let `source` set the controllers from now on. Inputs queued for a frame are applied after
+    // it is polled, so they win.
+    pub fn set_input_source(&mut self, source: impl InputSource + 'static) {
+        self.input_source = Some(Box::new(source));
+    }
+
+    pub fn clear_input_source(&mut self) {
+        self.input_source = None;
+    }
+
+    fn apply_queued_inputs(&mut self) {
+        let frame = self.frame();
+        if self.inputs_applied == Some(frame) {
+            return;
+        }
+        if self.freeze_timing == FreezeTiming::Frame {
+            self.apply_frozen();
+        }
+
+        let mut memory = self.memory.borrow_mut();
+        memory.set_turbo_on((frame / self.turbo_rate).is_multiple_of(2));
+        if let Some(source) = self.input_source.as_mut() {
+            for port in 0..CONTROLLER_PORTS {
+                if let (Some(buttons), Some(controller)) =
+                    (source.poll(frame, port), memory.controller_mut(port))
+                {
+                    controller.set_buttons(buttons);
+                }
+            }
+        }
+        if let Some(inputs) = self.input_queue.remove(&frame) {
+            for (port, buttons) in inputs {
+                if let Some(controller) = memory.controller_mut(port) {
+                    controller.set_buttons(buttons);
+                }
+            }
+        }
+        self.inputs_applied = Some(frame);
+    }
+
+    // Snapshot the whole console (CPU, RAM, PPU, APU, the cartridge's banks and RAM, and the
+    // controller ports) mid-frame if need be. Settings, cheats, hooks and the buttons held now
+    // aren't part of it.
+    #[cfg(feature = "serde")]
+    pub fn save_state(&self) -> Vec<u8> {
+        self.memory.borrow_mut().sync();
+        let memory = self.memory.borrow();
+        let mut state = StateWriter::new(self.rom_crc32);
+        state.section(Section::Cpu, &self.cpu.registers());
+        state.section(Section::Bus, &(&*memory, self.inputs_applied));
+        state.section(Section::Ppu, memory.ppu());
+        state.section(Section::Apu, memory.apu());
+        if let Some(cartridge) = memory.cartridge() {
+            state.raw_section(Section::Cartridge, &cartridge.borrow().save_state());
+        }
+        state.finish()
+    }
+
+    // Return to a state from `save_state` on the same game. Every section is checked before
+    // anything is restored, so nothing changes if it fails.
+    #[cfg(feature = "serde")]
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let state = StateReader::new(data, self.rom_crc32)?;
+        let registers: Registers = state.section(Section::Cpu)?;
+        let (bus, inputs_applied): (CpuBus, Option<u64>) = state.section(Section::Bus)?;
+        let ppu: PPU = state.section(Section::Ppu)?;
+        let apu: APU = state.section(Section::Apu)?;
+        let mut memory = self.memory.borrow_mut();
+        if let Some(cartridge) = memory.cartridge() {
+            let mapper = state.raw_section(Section::Cartridge)?;
+            cartridge.borrow_mut().load_state(mapper)?;
+        }
+        memory.restore(bus);
+        memory.ppu_mut().restore(ppu);
+        memory.apu_mut().restore(apu);
+        drop(memory);
+        self.cpu.set_registers(registers);
+        self.inputs_applied = inputs_applied;
+        Ok(())
+    }
+
+    // Start recording every controller state change, from the next frame on. Start right after
+    // `new` for a movie that replays from power-on.
+    pub fn start_input_recording(&mut self) {
+        self.input_recording = Some(InputRecording {
+            movie: Movie::new(self.rom_crc32),
+            last: [0; CONTROLLER_PORTS],
+            frame: None,
+        });
+    }
+
+    pub fn input_recording(&self) -> bool {
+        self.input_recording.is_some()
+    }
+
+    pub fn stop_input_recording(&mut self) -> Option<Movie> {
+        self.input_recording.take().map(|recording| recording.movie)
+    }
+
+    // Queue a recorded movie's inputs for playback. Like recording, this belongs right after
+    // `new`, and the front end should stop feeding in its own input while it plays.
+    pub fn play_movie(&mut self, movie: &Movie) -> Result<(), MovieError> {
+        if movie.rom_crc32 != self.rom_crc32 {
+            return Err(MovieError::WrongRom(movie.rom_crc32, self.rom_crc32));
+        }
+        for input in &movie.inputs {
+            self.queue_input(input.frame, input.port, input.buttons)?;
+        }
+        Ok(())
+    }
+
+    // Note the controller states the first time the CPU runs in a frame, which is after any
+    // input the front end gave between frames. Playback latches them at the same point.
+    fn record_inputs(&mut self) {
+        let frame = self.frame();
+        let Some(recording) = self.input_recording.as_mut() else {
+            return;
+        };
+        if recording.frame == Some(frame) {
+            return;
+        }
+        recording.frame = Some(frame);
+
+        let memory = self.memory.borrow();
+        for (port, last) in recording.last.iter_mut().enumerate() {
+            let buttons = memory.controller(port).map_or(0, Controller::buttons);
+            if *last != buttons {
+                *last = buttons;
+                recording.movie.inputs.push(MovieInput {
+                    frame,
+                    port,
+                    buttons,
+                });
+            }
+        }
+    }
+
+    // Press the reset button, which starts the game over but keeps what is in RAM and on the
+    // cartridge. Some test ROMs ask for it between tests.
+    pub fn reset(&mut self) {
+        self.memory.borrow_mut().reset();
+        self.run_for(|cpu| cpu.soft_reset());
+    }
+
+    // Execute one CPU instruction (plus any DMA and interrupt it triggered) and advance the PPU
+    // and APU by the same amount of time
+    pub fn step(&mut self) -> usize {
+        #[cfg(feature = "std")]
+        if self.memory.borrow().profile().is_some() {
+            let start = Instant::now();
+            let cycles = self.run_step();
+            self.memory.borrow_mut().add_step_time(start.elapsed());
+            return cycles;
+        }
+        self.run_step()
+    }
+
+    fn run_step(&mut self) -> usize {
+        self.apply_queued_inputs();
+        self.record_inputs();
+
+        if self.history_len > 0 {
+            self.record_instruction();
+        }
+        let mut cycles = self.run_for(|cpu| cpu.execute());
+        self.instructions += 1;
+        cycles += self.memory.borrow_mut().run_dma();
+        if self.memory.borrow_mut().take_nmi() {
+            cycles += self.run_for(|cpu| cpu.nmi());
+        } else if self.memory.borrow().irq() {
+            cycles += self.run_for(|cpu| cpu.irq());
+        }
+        if self.freeze_timing == FreezeTiming::Instruction {
+            self.apply_frozen();
+        }
+        if self.scope_recording || self.debug_view == Some(DebugView::Apu) {
+            let mut memory = self.memory.borrow_mut();
+            memory.sync();
+            let levels = memory.apu().channel_outputs();
+            drop(memory);
+            self.scope.push(levels);
+        }
+
+        let frame_complete = self.memory.borrow_mut().take_frame_complete();
+        if frame_complete {
+            #[cfg(feature = "std")]
+            self.record_audio();
+            if self.debug_view.is_some() {
+                self.draw_debug_view();
+            }
+            if self.message_frames > 0 {
+                self.message_frames -= 1;
+                if self.message_frames == 0 {
+                    self.compositor.clear(Layer::Osd);
+                }
+            }
+            if let Some(callback) = self.frame_callback.as_mut() {
+                callback(self.memory.borrow().ppu().frame());
+            }
+        }
+        cycles
+    }
+
+    fn record_instruction(&mut self) {
+        let memory = self.memory.borrow();
+        let registers = self.cpu.registers();
+        let traced = TracedInstruction {
+            cycle: memory.cycles(),
+            registers,
+            instruction: disasm::decode(registers.pc, |address| memory.peek(address)),
+        };
+        drop(memory);
+        if self.history.len() == self.history_len {
+            self.history.pop_front();
+        }
+        self.history.push_back(traced);
+    }
+
+    // Run a CPU operation, then tick off whatever part of its cycle count was not spent on bus accesses
+    fn run_for(&mut self, operation: impl FnOnce(&mut CPU) -> usize) -> usize {
+        let start = self.memory.borrow().cycles();
+        let cycles = operation(&mut self.cpu);
+
+        let mut memory = self.memory.borrow_mut();
+        let elapsed = (memory.cycles() - start) as usize;
+        for _ in elapsed..cycles {
+            memory.tick();
+        }
+        cycles.max(elapsed)
+    }
+
+    pub fn step_frame(&mut self) {
+        let frame = self.frame();
+        while self.frame() == frame {
+            self.step();
+        }
+        self.apply_queued_inputs();
+    }
+
+    // Run like `step_frame`, but stop before executing an instruction at a breakpoint. The first
+    // instruction always runs, so calling it again carries on from a breakpoint. Returns whether
+    // it stopped at one.
+    pub fn step_frame_until_breakpoint(&mut self) -> bool {
+        let frame = self.frame();
+        self.step();
+        while self.frame() == frame {
+            if self.breakpoints.contains(&self.cpu.registers().pc) {
+                return true;
+            }
+            self.step();
+        }
+        self.apply_queued_inputs();
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom;
+
+    // Reads controller 1 into $00 every pass of its main loop and makes that the backdrop
+    // colour, so what is held shows in the frame
+    const SHOW_BUTTONS: [u8; 41] = [
+        0xA9, 0x01, 0x8D, 0x16, 0x40, // LDA #1; STA $4016
+        0xA9, 0x00, 0x8D, 0x16, 0x40, // LDA #0; STA $4016
+        0xA2, 0x08, // LDX #8
+        0xAD, 0x16, 0x40, 0x4A, 0x26, 0x00, 0xCA, 0xD0,
+        0xF7, // LDA $4016; LSR; ROL $00; DEX; BNE
+        0xA9, 0x3F, 0x8D, 0x06, 0x20, // LDA #$3F; STA $2006
+        0xA9, 0x00, 0x8D, 0x06, 0x20, // LDA #0; STA $2006
+        0xA5, 0x00, 0x29, 0x3F, // LDA $00; AND #$3F
+        0x8D, 0x07, 0x20, // STA $2007
+        0x4C, 0x00, 0x80, // JMP $8000
+    ];
+
+    fn console() -> Nes {
+        Nes::new(&test_rom::nrom(&SHOW_BUTTONS, &[0x40])).unwrap()
+    }
+
+    fn run_until(nes: &mut Nes, frame: u64) {
+        while nes.frame() < frame {
+            nes.step_frame();
+        }
+    }
+
+    #[test]
+    fn inputs_for_a_frame_apply_in_the_order_queued() {
+        let mut nes = console();
+        nes.queue_input(2, 0, 0x01).unwrap();
+        nes.queue_input(2, 1, 0x10).unwrap();
+        nes.queue_input(2, 0, 0x80).unwrap();
+        nes.queue_input(3, 0, 0x02).unwrap();
+        run_until(&mut nes, 2);
+        assert_eq!(nes.controller(0).unwrap().buttons(), 0x80);
+        assert_eq!(nes.controller(1).unwrap().buttons(), 0x10);
+        run_until(&mut nes, 3);
+        assert_eq!(nes.controller(0).unwrap().buttons(), 0x02);
+    }
+
+    // NINA-001's bank registers sit at $7FFD-$7FFF, inside its PRG-RAM
+    #[test]
+    fn freezing_prg_ram_skips_the_board_registers() {
+        let mut nes = Nes::new(&test_rom::banked(34, 0x10000, 0x10000)).unwrap();
+        nes.freeze(0x7FFD, 1);
+        assert_eq!(nes.peek(0x7FFD), 1);
+        assert_eq!(nes.peek(0x8000), 0);
+    }
+
+    // The Namco 163 write-protects its PRG-RAM at power on
+    #[test]
+    fn freezing_prg_ram_ignores_write_protection() {
+        let mut nes = Nes::new(&test_rom::banked(19, 0x10000, 0x10000)).unwrap();
+        nes.freeze(0x6000, 0x42);
+        assert_eq!(nes.peek(0x6000), 0x42);
+    }
+
+    #[test]
+    fn frames_already_started_are_refused() {
+        let mut nes = console();
+        run_until(&mut nes, 2);
+        assert_eq!(
+            nes.queue_input(1, 0, 0x01),
+            Err(InputError::FrameAlreadyPassed {
+                frame: 1,
+                current: 2
+            })
+        );
+        assert_eq!(
+            nes.queue_input(2, 0, 0x01),
+            Err(InputError::FrameAlreadyPassed {
+                frame: 2,
+                current: 2
+            })
+        );
+        assert_eq!(nes.queue_input(3, 0, 0x01), Ok(()));
+    }
+
+    #[test]
+    fn invalid_port() {
+        let mut nes = console();
+        assert_eq!(
+            nes.queue_input(1, CONTROLLER_PORTS, 0x01),
+            Err(InputError::InvalidPort(CONTROLLER_PORTS))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn loading_a_state_redraws_its_picture() {
+        let mut nes = console();
+        nes.queue_input(2, 0, 0x21).unwrap();
+        run_until(&mut nes, 3);
+        let picture = nes.memory.borrow().ppu().frame().to_vec();
+        let state = nes.save_state();
+        nes.queue_input(4, 0, 0x05).unwrap();
+        run_until(&mut nes, 5);
+        assert_ne!(nes.memory.borrow().ppu().frame(), &picture[..]);
+        nes.load_state(&state).unwrap();
+        assert_eq!(nes.memory.borrow().ppu().frame(), &picture[..]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn states_leave_out_the_rom_and_take_it_back_on_load() {
+        let mut nes = console();
+        nes.queue_input(2, 0, 0x21).unwrap();
+        run_until(&mut nes, 3);
+        let state = nes.save_state();
+        // The 32KB PRG-ROM stays out; 8KB each of CHR-RAM and PRG-RAM go in
+        let memory = nes.memory.borrow();
+        let cartridge = memory.cartridge().unwrap().borrow().save_state();
+        assert!(cartridge.len() < 0x4100, "{} bytes", cartridge.len());
+        drop(memory);
+        run_until(&mut nes, 6);
+        let hash = nes.frame_hash();
+        nes.load_state(&state).unwrap();
+        run_until(&mut nes, 6);
+        assert_eq!(nes.frame_hash(), hash);
+    }
+
+    #[test]
+    fn queued_inputs_are_deterministic() {
+        let hashes = |inputs: &[(u64, usize, u8)]| {
+            let mut nes = console();
+            for &(frame, port, buttons) in inputs {
+                nes.queue_input(frame, port, buttons).unwrap();
+            }
+            (1..=12)
+                .map(|frame| {
+                    run_until(&mut nes, frame);
+                    nes.frame_hash()
+                })
+                .collect::<Vec<_>>()
+        };
+        let inputs = [(3, 0, 0x01), (5, 0, 0x81), (5, 1, 0x08), (8, 0, 0x00)];
+        let first = hashes(&inputs);
+        assert_eq!(first, hashes(&inputs));
+        // The buttons really do change the picture
+        assert_ne!(first, hashes(&[]));
+    }
+
+    #[test]
+    fn the_scope_is_only_kept_while_recording() {
+        let mut nes = console();
+        let mut scope = Vec::new();
+        nes.set_scope_recording(true);
+        run_until(&mut nes, 1);
+        nes.take_scope(&mut scope);
+        assert!(!scope.is_empty());
+
+        scope.clear();
+        nes.set_scope_recording(false);
+        run_until(&mut nes, 2);
+        nes.take_scope(&mut scope);
+        assert!(scope.is_empty());
+    }
+}
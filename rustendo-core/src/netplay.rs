@@ -0,0 +1,338 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::input::InputSource;
+
+// Every packet starts with the magic bytes and a type, followed by little-endian fields:
+//   Hello    client -> host  ROM CRC32 (u32)
+//   Welcome  host -> client  ROM CRC32 (u32), input delay (u8)
+//   Input    both ways       next frame wanted from the peer (u64), first frame (u64), count
+//                            (u8), then one button mask per frame
+//   Bye      both ways       nothing; the sender is leaving
+// Inputs are sent again in every Input packet until the peer says it has them, so a lost packet
+// only costs time.
+const MAGIC: &[u8; 4] = b"RNNP";
+const HELLO: u8 = 0;
+const WELCOME: u8 = 1;
+const INPUT: u8 = 2;
+const BYE: u8 = 3;
+
+// Most frames of input in one packet; the delay is far smaller, so it's never reached in practice
+const MAX_INPUTS_PER_PACKET: usize = 64;
+
+// How long a wait for the peer can go before the inputs are sent again
+const RESEND_INTERVAL: Duration = Duration::from_millis(15);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub const DEFAULT_DELAY: u8 = 2;
+
+#[derive(Debug)]
+pub enum NetplayError {
+    Io(io::Error),
+    WrongRom(u32, u32), // The host's, ours (ROM CRC32s)
+    TimedOut,           // Nothing heard from the peer for a while
+    Disconnected,       // The peer left
+}
+
+impl fmt::Display for NetplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetplayError::Io(e) => write!(f, "{}", e),
+            NetplayError::WrongRom(host, ours) => write!(
+                f,
+                "the host is playing a ROM with CRC32 {:08X}, but this one is {:08X}",
+                host, ours
+            ),
+            NetplayError::TimedOut => write!(f, "the other player stopped responding"),
+            NetplayError::Disconnected => write!(f, "the other player left"),
+        }
+    }
+}
+
+impl core::error::Error for NetplayError {}
+
+impl From<io::Error> for NetplayError {
+    fn from(e: io::Error) -> Self {
+        NetplayError::Io(e)
+    }
+}
+
+// Delay-based lockstep for two players over UDP. Both consoles start from power-on with the same
+// ROM and run the same inputs on the same frames, so they stay in step without sending anything
+// else. An input pressed on frame N takes effect on frame N + delay on both sides, which gives it
+// that many frames to reach the peer; if it hasn't by then, the frame waits for it.
+//
+// The host plays controller 1 and the client controller 2. Pass the local buttons to
+// `push_local_input` once per frame, and set the session as the console's input source.
+pub struct Netplay {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    local_port: usize,
+    rom_crc32: u32,
+    delay: u64,
+    local: BTreeMap<u64, u8>, // By frame, until the peer has them and they have been applied
+    remote: BTreeMap<u64, u8>, // By frame, until they have been applied
+    next_local: u64,          // The frame the next local input is for
+    remote_next: u64,         // The first frame not yet received from the peer
+    peer_next: u64,           // The first frame the peer hasn't received from us
+    error: Option<NetplayError>,
+}
+
+impl Netplay {
+    // Wait for a player to connect to `port`, and start a session with them if their ROM is the
+    // same. `delay` is in frames and applies to both sides.
+    pub fn host(port: u16, rom_crc32: u32, delay: u8) -> Result<Self, NetplayError> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        let mut packet = [0; 64];
+        loop {
+            let (size, peer) = socket.recv_from(&mut packet)?;
+            let Some((HELLO, body)) = parse(&packet[..size]) else {
+                continue;
+            };
+            let Some(crc) = read_u32(body) else {
+                continue;
+            };
+            // A client with another ROM gets our CRC32 back and gives up
+            socket.send_to(&welcome(rom_crc32, delay), peer)?;
+            if crc == rom_crc32 {
+                return Self::new(socket, peer, 0, rom_crc32, delay);
+            }
+        }
+    }
+
+    // Join the session hosted at `address` (e.g. "192.168.1.2:7777")
+    pub fn connect(address: impl ToSocketAddrs, rom_crc32: u32) -> Result<Self, NetplayError> {
+        let peer = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address to connect to"))?;
+        let local = if peer.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.set_read_timeout(Some(Duration::from_millis(250)))?;
+
+        let mut hello = header(HELLO);
+        hello.extend_from_slice(&rom_crc32.to_le_bytes());
+        let start = Instant::now();
+        let mut packet = [0; 64];
+        while start.elapsed() < CONNECT_TIMEOUT {
+            socket.send_to(&hello, peer)?;
+            let size = match socket.recv_from(&mut packet) {
+                Ok((size, from)) if from == peer => size,
+                Ok(_) => continue,
+                Err(e) if is_timeout(&e) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let Some((WELCOME, body)) = parse(&packet[..size]) else {
+                continue;
+            };
+            let (Some(crc), Some(&delay)) = (read_u32(body), body.get(4)) else {
+                continue;
+            };
+            if crc != rom_crc32 {
+                return Err(NetplayError::WrongRom(crc, rom_crc32));
+            }
+            return Self::new(socket, peer, 1, rom_crc32, delay);
+        }
+        Err(NetplayError::TimedOut)
+    }
+
+    fn new(
+        socket: UdpSocket,
+        peer: SocketAddr,
+        local_port: usize,
+        rom_crc32: u32,
+        delay: u8,
+    ) -> Result<Self, NetplayError> {
+        socket.set_read_timeout(Some(RESEND_INTERVAL))?;
+        let delay = delay as u64;
+        Ok(Self {
+            socket,
+            peer,
+            local_port,
+            rom_crc32,
+            delay,
+            local: BTreeMap::new(),
+            remote: BTreeMap::new(),
+            next_local: delay,
+            remote_next: delay,
+            peer_next: delay,
+            error: None,
+        })
+    }
+
+    // The controller port the local player drives
+    pub fn local_port(&self) -> usize {
+        self.local_port
+    }
+
+    pub fn delay(&self) -> u64 {
+        self.delay
+    }
+
+    // Why the session ended, once it has. Polling after that leaves the controllers alone.
+    pub fn error(&self) -> Option<&NetplayError> {
+        self.error.as_ref()
+    }
+
+    // The buttons the local player holds now, for the frame `delay` frames ahead of the last
+    // one given
+    pub fn push_local_input(&mut self, buttons: u8) {
+        self.local.insert(self.next_local, buttons);
+        self.next_local += 1;
+        if let Err(e) = self.send_inputs() {
+            self.error.get_or_insert(e);
+        }
+    }
+
+    // The buttons on `port` for `frame`, waiting for the peer's if they haven't arrived
+    pub fn input(&mut self, frame: u64, port: usize) -> Option<u8> {
+        if self.error.is_some() || port > 1 {
+            return None;
+        }
+        if frame < self.delay {
+            return Some(0);
+        }
+        if port == self.local_port {
+            let buttons = self.local.get(&frame).copied();
+            self.local
+                .retain(|&local_frame, _| local_frame >= frame.min(self.peer_next));
+            return buttons;
+        }
+
+        let mut last_heard = Instant::now();
+        while self.remote_next <= frame {
+            match self.receive() {
+                Ok(true) => last_heard = Instant::now(),
+                Ok(false) if last_heard.elapsed() > TIMEOUT => {
+                    self.error = Some(NetplayError::TimedOut);
+                }
+                Ok(false) => {}
+                Err(e) => self.error = Some(e),
+            }
+            if self.error.is_some() {
+                return None;
+            }
+            // The peer may be waiting on inputs that got lost
+            if let Err(e) = self.send_inputs() {
+                self.error = Some(e);
+                return None;
+            }
+        }
+        self.remote.retain(|&remote_frame, _| remote_frame >= frame);
+        self.remote.get(&frame).copied()
+    }
+
+    // Send every input the peer hasn't confirmed, and which frame we want next
+    fn send_inputs(&mut self) -> Result<(), NetplayError> {
+        let first = self.peer_next;
+        let inputs: Vec<u8> = self
+            .local
+            .range(first..)
+            .map(|(_, &buttons)| buttons)
+            .take(MAX_INPUTS_PER_PACKET)
+            .collect();
+        let mut packet = header(INPUT);
+        packet.extend_from_slice(&self.remote_next.to_le_bytes());
+        packet.extend_from_slice(&first.to_le_bytes());
+        packet.push(inputs.len() as u8);
+        packet.extend_from_slice(&inputs);
+        self.socket.send_to(&packet, self.peer)?;
+        Ok(())
+    }
+
+    // Wait up to the resend interval for a packet from the peer. Returns whether one came.
+    fn receive(&mut self) -> Result<bool, NetplayError> {
+        let mut packet = [0; 32 + MAX_INPUTS_PER_PACKET];
+        let size = match self.socket.recv_from(&mut packet) {
+            Ok((size, from)) if from == self.peer => size,
+            Ok(_) => return Ok(false),
+            Err(e) if is_timeout(&e) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        match parse(&packet[..size]) {
+            Some((INPUT, body)) => {
+                let (Some(wanted), Some(first), Some((&count, inputs))) = (
+                    read_u64(body),
+                    body.get(8..).and_then(read_u64),
+                    body.get(16..).and_then(<[u8]>::split_first),
+                ) else {
+                    return Ok(true);
+                };
+                self.peer_next = self.peer_next.max(wanted);
+                for (frame, &buttons) in (first..).zip(inputs.iter().take(count as usize)) {
+                    if frame >= self.remote_next {
+                        self.remote.insert(frame, buttons);
+                    }
+                }
+                while self.remote.contains_key(&self.remote_next) {
+                    self.remote_next += 1;
+                }
+            }
+            // Our welcome got lost, so the client is still saying hello
+            Some((HELLO, _)) if self.local_port == 0 => {
+                let welcome = welcome(self.rom_crc32, self.delay as u8);
+                self.socket.send_to(&welcome, self.peer)?;
+            }
+            Some((BYE, _)) => return Err(NetplayError::Disconnected),
+            _ => {}
+        }
+        Ok(true)
+    }
+}
+
+impl InputSource for Netplay {
+    fn poll(&mut self, frame: u64, port: usize) -> Option<u8> {
+        self.input(frame, port)
+    }
+}
+
+impl Drop for Netplay {
+    fn drop(&mut self) {
+        // Saves the peer waiting out the timeout; if it's lost they will anyway
+        let _ = self.socket.send_to(&header(BYE), self.peer);
+    }
+}
+
+fn header(kind: u8) -> Vec<u8> {
+    let mut packet = MAGIC.to_vec();
+    packet.push(kind);
+    packet
+}
+
+fn welcome(rom_crc32: u32, delay: u8) -> Vec<u8> {
+    let mut packet = header(WELCOME);
+    packet.extend_from_slice(&rom_crc32.to_le_bytes());
+    packet.push(delay);
+    packet
+}
+
+// The packet's type and the rest of it
+fn parse(packet: &[u8]) -> Option<(u8, &[u8])> {
+    let rest = packet.strip_prefix(MAGIC)?;
+    let (&kind, body) = rest.split_first()?;
+    Some((kind, body))
+}
+
+fn read_u32(data: &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(..4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(..8)?.try_into().ok()?))
+}
+
+// Read timeouts are WouldBlock on Unix and TimedOut on Windows
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
@@ -0,0 +1,126 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Composite signal simulation in the style of blargg's nes_ntsc: every PPU pixel is turned back
+// into the 2C02's square-wave video signal (8 samples per pixel, 12 samples per colour
+// subcarrier cycle), then decoded to YIQ through a one-cycle window. Neighbouring pixels bleed
+// into each other, which gives the colour fringing, and the subcarrier phase drifts from frame
+// to frame, which gives the dot crawl.
+
+const SAMPLES_PER_PIXEL: usize = 8;
+const PHASES: usize = 12;
+// Decoder phase offset that puts hue $x6 at red, matching the reference palette
+const DEFAULT_HUE: usize = 4;
+
+// Signal voltages for the four luma levels, low and high halves of the square wave
+const LOW_LEVELS: [f32; 4] = [0.228, 0.312, 0.552, 0.880];
+const HIGH_LEVELS: [f32; 4] = [0.616, 0.840, 1.100, 1.100];
+const BLACK: f32 = 0.312;
+const WHITE: f32 = 1.100;
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+
+// cos/sin of 30 degree steps, one per subcarrier phase
+const COS: [f32; PHASES] = [
+    1.0, 0.866_025, 0.5, 0.0, -0.5, -0.866_025, -1.0, -0.866_025, -0.5, 0.0, 0.5, 0.866_025,
+];
+const SIN: [f32; PHASES] = [
+    0.0, 0.5, 0.866_025, 1.0, 0.866_025, 0.5, 0.0, -0.5, -0.866_025, -1.0, -0.866_025, -0.5,
+];
+
+pub struct NtscFilter {
+    signal: Vec<[f32; PHASES]>, // Signal level for each of the 512 colours at each phase
+    hue: usize,                 // Extra phase rotation, in 30 degree steps
+}
+
+impl Default for NtscFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NtscFilter {
+    pub fn new() -> Self {
+        let mut signal = vec![[0.0; PHASES]; 512];
+        for (pixel, levels) in signal.iter_mut().enumerate() {
+            for (phase, level) in levels.iter_mut().enumerate() {
+                *level = Self::signal_level(pixel as u16, phase);
+            }
+        }
+        Self {
+            signal,
+            hue: DEFAULT_HUE,
+        }
+    }
+
+    pub fn set_hue(&mut self, steps: usize) {
+        self.hue = steps % PHASES;
+    }
+
+    fn signal_level(pixel: u16, phase: usize) -> f32 {
+        let color = (pixel & 0x0F) as usize;
+        let level = if color > 13 {
+            1
+        } else {
+            (pixel >> 4) as usize & 0x03
+        };
+        let emphasis = pixel >> 6;
+
+        let mut low = LOW_LEVELS[level];
+        let mut high = HIGH_LEVELS[level];
+        if color == 0 {
+            low = high;
+        } else if color > 12 {
+            high = low;
+        }
+
+        let in_phase = |c: usize| (c + phase) % PHASES < 6;
+        let mut signal = if in_phase(color) { high } else { low };
+        if (emphasis & 0x01 != 0 && in_phase(0))
+            || (emphasis & 0x02 != 0 && in_phase(4))
+            || (emphasis & 0x04 != 0 && in_phase(8))
+        {
+            signal *= EMPHASIS_ATTENUATION;
+        }
+        signal
+    }
+
+    // Filter a frame of palette indices (colour | emphasis << 6) into 256x240 RGBA pixels
+    pub fn apply(&self, indices: &[u16], frame: u32, output: &mut [u8]) {
+        let frame_phase = (frame as usize % 3) * 4;
+        let line_samples = SCREEN_WIDTH * SAMPLES_PER_PIXEL;
+
+        for y in 0..SCREEN_HEIGHT {
+            let row = &indices[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH];
+            // 341 dots of 8 samples per scanline advance the subcarrier by 4 phases
+            let line_phase = frame_phase + y * 4;
+
+            for x in 0..SCREEN_WIDTH {
+                let (mut luma, mut i, mut q) = (0.0, 0.0, 0.0);
+                let center = x * SAMPLES_PER_PIXEL + SAMPLES_PER_PIXEL / 2;
+                for offset in 0..PHASES {
+                    let sample = (center + offset).saturating_sub(PHASES / 2);
+                    let pixel = row[sample.min(line_samples - 1) / SAMPLES_PER_PIXEL];
+                    let phase = (line_phase + sample) % PHASES;
+                    let level = self.signal[pixel as usize & 0x1FF][phase];
+                    let angle = (phase + self.hue) % PHASES;
+                    luma += level;
+                    i += level * COS[angle];
+                    q += level * SIN[angle];
+                }
+
+                let scale = 1.0 / (PHASES as f32 * (WHITE - BLACK));
+                let luma = (luma / PHASES as f32 - BLACK) / (WHITE - BLACK);
+                let (i, q) = (i * scale * 2.0, q * scale * 2.0);
+
+                let to_byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0) as u8;
+                let offset = (y * SCREEN_WIDTH + x) * 4;
+                output[offset] = to_byte(luma + 0.946_882 * i + 0.623_557 * q);
+                output[offset + 1] = to_byte(luma - 0.274_788 * i - 0.635_691 * q);
+                output[offset + 2] = to_byte(luma - 1.108_545 * i + 1.709_007 * q);
+                output[offset + 3] = 0xFF;
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use alloc::vec::Vec;
+
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Pixels trimmed from each edge of the frame. NTSC TVs hid roughly the top and bottom 8 lines,
+// and games often leave scrolling garbage or mapper glitches in them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overscan {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Default for Overscan {
+    fn default() -> Self {
+        Self {
+            top: 8,
+            bottom: 8,
+            left: 0,
+            right: 0,
+        }
+    }
+}
+
+impl Overscan {
+    pub const NONE: Overscan = Overscan {
+        top: 0,
+        bottom: 0,
+        left: 0,
+        right: 0,
+    };
+
+    // Clamp the margins so at least one pixel of the frame is left in each direction
+    pub fn clamped(self) -> Self {
+        let left = self.left.min(SCREEN_WIDTH - 1);
+        let top = self.top.min(SCREEN_HEIGHT - 1);
+        Self {
+            top,
+            bottom: self.bottom.min(SCREEN_HEIGHT - 1 - top),
+            left,
+            right: self.right.min(SCREEN_WIDTH - 1 - left),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        SCREEN_WIDTH - self.left - self.right
+    }
+
+    pub fn height(&self) -> usize {
+        SCREEN_HEIGHT - self.top - self.bottom
+    }
+
+    // Copy the visible part of a 256x240 frame with `bytes_per_pixel` bytes per pixel into
+    // `output`, which ends up `width() * height() * bytes_per_pixel` bytes long
+    pub fn crop(&self, frame: &[u8], bytes_per_pixel: usize, output: &mut Vec<u8>) {
+        output.clear();
+        let row_bytes = SCREEN_WIDTH * bytes_per_pixel;
+        for row in frame
+            .chunks_exact(row_bytes)
+            .skip(self.top)
+            .take(self.height())
+        {
+            output.extend_from_slice(
+                &row[self.left * bytes_per_pixel..(SCREEN_WIDTH - self.right) * bytes_per_pixel],
+            );
+        }
+    }
+}
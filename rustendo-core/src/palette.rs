@@ -0,0 +1,183 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+// RGB values for the 64 colours the 2C02 can output
+pub const DEFAULT_PALETTE: [[u8; 3]; 64] = [
+    [0x62, 0x62, 0x62],
+    [0x00, 0x1F, 0xB2],
+    [0x24, 0x04, 0xC8],
+    [0x52, 0x00, 0xB2],
+    [0x73, 0x00, 0x76],
+    [0x80, 0x00, 0x24],
+    [0x73, 0x0B, 0x00],
+    [0x52, 0x28, 0x00],
+    [0x24, 0x44, 0x00],
+    [0x00, 0x57, 0x00],
+    [0x00, 0x5C, 0x00],
+    [0x00, 0x53, 0x24],
+    [0x00, 0x3C, 0x76],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0xAB, 0xAB, 0xAB],
+    [0x0D, 0x57, 0xFF],
+    [0x4B, 0x30, 0xFF],
+    [0x8A, 0x13, 0xFF],
+    [0xBC, 0x08, 0xD6],
+    [0xD2, 0x12, 0x69],
+    [0xC7, 0x2E, 0x00],
+    [0x9D, 0x54, 0x00],
+    [0x60, 0x7B, 0x00],
+    [0x20, 0x98, 0x00],
+    [0x00, 0xA3, 0x00],
+    [0x00, 0x99, 0x42],
+    [0x00, 0x7D, 0xB4],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF],
+    [0x53, 0xAE, 0xFF],
+    [0x90, 0x85, 0xFF],
+    [0xD3, 0x65, 0xFF],
+    [0xFF, 0x57, 0xFF],
+    [0xFF, 0x5D, 0xCF],
+    [0xFF, 0x77, 0x57],
+    [0xFA, 0x9E, 0x00],
+    [0xBD, 0xC7, 0x00],
+    [0x7A, 0xE7, 0x00],
+    [0x43, 0xF6, 0x11],
+    [0x26, 0xEF, 0x7E],
+    [0x2C, 0xD5, 0xF6],
+    [0x4E, 0x4E, 0x4E],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF],
+    [0xB6, 0xE1, 0xFF],
+    [0xCE, 0xD1, 0xFF],
+    [0xE9, 0xC3, 0xFF],
+    [0xFF, 0xBC, 0xFF],
+    [0xFF, 0xBD, 0xF4],
+    [0xFF, 0xC6, 0xC3],
+    [0xFF, 0xD5, 0x9A],
+    [0xE9, 0xE6, 0x81],
+    [0xCE, 0xF4, 0x81],
+    [0xB6, 0xFB, 0x9A],
+    [0xA9, 0xFA, 0xC3],
+    [0xA9, 0xF0, 0xF4],
+    [0xB8, 0xB8, 0xB8],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PaletteError {
+    InvalidSize(usize),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::InvalidSize(size) => write!(
+                f,
+                "palette files must be 192 or 1536 bytes (64 or 512 RGB entries), got {}",
+                size
+            ),
+        }
+    }
+}
+
+impl core::error::Error for PaletteError {}
+
+// A full colour table indexed by the 6-bit colour plus the three PPUMASK emphasis bits
+// (`emphasis << 6 | colour`), 512 entries in total
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::from_base(&DEFAULT_PALETTE)
+    }
+}
+
+impl Palette {
+    // Parse an FCEUX/Mesen style .pal file: raw RGB triplets, either 64 entries (emphasis is
+    // then approximated) or 512 entries with every emphasis combination spelled out
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaletteError> {
+        let colors: Vec<[u8; 3]> = bytes
+            .chunks_exact(3)
+            .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+            .collect();
+
+        match (bytes.len() % 3, colors.len()) {
+            (0, 64) => {
+                let mut base = [[0; 3]; 64];
+                base.copy_from_slice(&colors);
+                Ok(Self::from_base(&base))
+            }
+            (0, 512) => Ok(Self { colors }),
+            _ => Err(PaletteError::InvalidSize(bytes.len())),
+        }
+    }
+
+    // Derive the emphasis variants by dimming the channels that are not emphasized
+    fn from_base(base: &[[u8; 3]; 64]) -> Self {
+        let mut colors = Vec::with_capacity(512);
+        for emphasis in 0..8u8 {
+            for &[r, g, b] in base {
+                let dim = |value: u8, emphasized: bool| {
+                    if emphasis == 0 || emphasized {
+                        value
+                    } else {
+                        (value as u16 * 0xD0 / 0xFF) as u8
+                    }
+                };
+                colors.push([
+                    dim(r, emphasis & 0x01 != 0),
+                    dim(g, emphasis & 0x02 != 0),
+                    dim(b, emphasis & 0x04 != 0),
+                ]);
+            }
+        }
+        Self { colors }
+    }
+
+    pub fn color(&self, index: u16) -> [u8; 3] {
+        self.colors[index as usize & 0x1FF]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PixelFormat {
+    #[default]
+    Rgba8888, // R, G, B, A bytes
+    Bgra8888, // B, G, R, A bytes
+    Rgb565,   // Little-endian 16-bit 5:6:5
+    Indexed,  // One byte per pixel holding the 6-bit NES colour index
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgba8888 | PixelFormat::Bgra8888 => 4,
+            PixelFormat::Rgb565 => 2,
+            PixelFormat::Indexed => 1,
+        }
+    }
+
+    pub fn encode(self, index: u8, [r, g, b]: [u8; 3]) -> [u8; 4] {
+        match self {
+            PixelFormat::Rgba8888 => [r, g, b, 0xFF],
+            PixelFormat::Bgra8888 => [b, g, r, 0xFF],
+            PixelFormat::Rgb565 => {
+                let value = ((r as u16 >> 3) << 11) | ((g as u16 >> 2) << 5) | (b as u16 >> 3);
+                let [lo, hi] = value.to_le_bytes();
+                [lo, hi, 0, 0]
+            }
+            PixelFormat::Indexed => [index & 0x3F, 0, 0, 0],
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::checksum;
+
+// Patches for fan translations and ROM hacks, applied to the whole .nes file (header included)
+// before it is parsed. IPS is the classic format; BPS adds checksums of the files it applies
+// to and produces, so a patch for a different dump is caught instead of making garbage.
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PatchError {
+    UnknownFormat,  // Neither an IPS nor a BPS patch
+    Truncated,      // The patch ends in the middle of a record
+    PatchChecksum,  // BPS: the patch itself is corrupt
+    SourceMismatch, // BPS: the patch was made for a different ROM
+    TargetMismatch, // BPS: the patched ROM isn't what the patch was supposed to produce
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PatchError::UnknownFormat => "not an IPS or BPS patch",
+            PatchError::Truncated => "the patch is truncated",
+            PatchError::PatchChecksum => "the patch is corrupt (checksum mismatch)",
+            PatchError::SourceMismatch => "the patch was made for a different ROM",
+            PatchError::TargetMismatch => "patching produced a different ROM than expected",
+        })
+    }
+}
+
+impl core::error::Error for PatchError {}
+
+// Apply an IPS or BPS patch to `data`, telling them apart by their magic
+pub fn apply(data: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if let Some(records) = patch.strip_prefix(b"PATCH") {
+        apply_ips(data, records)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(data, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+// Reads the patch front to back, failing with `Truncated` when it runs out
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], PatchError> {
+        let bytes = slice(self.data, self.position, count)?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn big_endian(&mut self, count: usize) -> Result<usize, PatchError> {
+        let bytes = self.bytes(count)?;
+        Ok(bytes
+            .iter()
+            .fold(0, |value, &byte| value << 8 | byte as usize))
+    }
+
+    // BPS numbers: 7 bits per byte, least significant first, with the top bit marking the
+    // last byte. Each continuation also adds one, so no number has two encodings.
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.bytes(1)?[0];
+            value = value.wrapping_add(((byte & 0x7F) as usize).wrapping_mul(shift));
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift <<= 7;
+            value = value.wrapping_add(shift);
+        }
+    }
+}
+
+// Records of a 24-bit offset and 16-bit size followed by that many bytes, or by a 16-bit count
+// and a fill byte when the size is 0. After "EOF" an optional 24-bit size truncates the file.
+fn apply_ips(data: &[u8], records: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut output = data.to_vec();
+    let mut reader = Reader {
+        data: records,
+        position: 0,
+    };
+    loop {
+        let offset = reader.big_endian(3)?;
+        if offset == 0x454F46 {
+            // "EOF"
+            if let Ok(size) = reader.big_endian(3) {
+                output.truncate(size);
+            }
+            return Ok(output);
+        }
+
+        match reader.big_endian(2)? {
+            0 => {
+                let count = reader.big_endian(2)?;
+                let value = reader.bytes(1)?[0];
+                region(&mut output, offset, count).fill(value);
+            }
+            size => {
+                let bytes = reader.bytes(size)?;
+                region(&mut output, offset, size).copy_from_slice(bytes);
+            }
+        }
+    }
+}
+
+// `output[offset..offset + size]`, growing the file if the patch writes past its end
+fn region(output: &mut Vec<u8>, offset: usize, size: usize) -> &mut [u8] {
+    if output.len() < offset + size {
+        output.resize(offset + size, 0);
+    }
+    &mut output[offset..offset + size]
+}
+
+fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.len() < 4 + 12 {
+        return Err(PatchError::Truncated);
+    }
+    let (body, footer) = patch.split_at(patch.len() - 12);
+    let checksum = |index: usize| {
+        let bytes = &footer[index * 4..index * 4 + 4];
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    };
+    if checksum::crc32(&patch[..patch.len() - 4]) != checksum(2) {
+        return Err(PatchError::PatchChecksum);
+    }
+    if checksum::crc32(source) != checksum(0) {
+        return Err(PatchError::SourceMismatch);
+    }
+
+    let mut reader = Reader {
+        data: body,
+        position: 4,
+    };
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    reader.bytes(metadata_size)?;
+    if source_size != source.len() {
+        return Err(PatchError::SourceMismatch);
+    }
+
+    let mut target = Vec::new();
+    let mut source_offset = 0usize;
+    let mut target_offset = 0usize;
+    while reader.position < body.len() {
+        let command = reader.number()?;
+        let length = (command >> 2) + 1;
+        match command & 0x03 {
+            // SourceRead: the bytes at the same offset in the source
+            0 => {
+                target.extend_from_slice(slice(source, target.len(), length)?);
+            }
+            // TargetRead: bytes stored in the patch
+            1 => target.extend_from_slice(reader.bytes(length)?),
+            // SourceCopy: bytes from anywhere in the source
+            2 => {
+                source_offset = relative(source_offset, reader.number()?);
+                target.extend_from_slice(slice(source, source_offset, length)?);
+                source_offset += length;
+            }
+            // TargetCopy: bytes already written, one at a time as the ranges may overlap
+            _ => {
+                target_offset = relative(target_offset, reader.number()?);
+                for _ in 0..length {
+                    let byte = *target.get(target_offset).ok_or(PatchError::Truncated)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size || checksum::crc32(&target) != checksum(1) {
+        return Err(PatchError::TargetMismatch);
+    }
+    Ok(target)
+}
+
+// `data[start..start + length]`, for offsets and lengths that come from the patch
+fn slice(data: &[u8], start: usize, length: usize) -> Result<&[u8], PatchError> {
+    start
+        .checked_add(length)
+        .and_then(|end| data.get(start..end))
+        .ok_or(PatchError::Truncated)
+}
+
+// Copy offsets move by a signed amount, stored as magnitude << 1 | sign
+fn relative(offset: usize, delta: usize) -> usize {
+    if delta & 1 == 0 {
+        offset.wrapping_add(delta >> 1)
+    } else {
+        offset.wrapping_sub(delta >> 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &[u8] = b"ABCDEFGH";
+
+    // A BPS number, as `Reader::number` reads it
+    fn number(patch: &mut Vec<u8>, mut value: usize) {
+        loop {
+            let low = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                patch.push(0x80 | low);
+                return;
+            }
+            patch.push(low);
+            value -= 1;
+        }
+    }
+
+    // A patch from SOURCE to "ABCDxyGHGHGH", one command of each kind
+    fn bps() -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        for value in [SOURCE.len(), 12, 0] {
+            number(&mut patch, value);
+        }
+        number(&mut patch, 3 << 2); // SourceRead "ABCD"
+        number(&mut patch, 1 << 2 | 1); // TargetRead "xy"
+        patch.extend_from_slice(b"xy");
+        number(&mut patch, 1 << 2 | 2); // SourceCopy "GH" from 6
+        number(&mut patch, 6 << 1);
+        number(&mut patch, 3 << 2 | 3); // TargetCopy "GHGH" from 6, overlapping itself
+        number(&mut patch, 6 << 1);
+        patch.extend_from_slice(&checksum::crc32(SOURCE).to_le_bytes());
+        patch.extend_from_slice(&checksum::crc32(b"ABCDxyGHGHGH").to_le_bytes());
+        patch.extend_from_slice(&checksum::crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn ips_records_and_fills() {
+        let patch = b"PATCH\x00\x00\x01\x00\x02xy\x00\x00\x05\x00\x00\x00\x02zEOF";
+        assert_eq!(apply(SOURCE, patch).unwrap(), b"AxyDEzzH");
+    }
+
+    #[test]
+    fn ips_grows_and_truncates_the_file() {
+        let patch = b"PATCH\x00\x00\x0A\x00\x01!EOF";
+        assert_eq!(apply(SOURCE, patch).unwrap(), b"ABCDEFGH\0\0!");
+        assert_eq!(apply(SOURCE, b"PATCHEOF\x00\x00\x03").unwrap(), b"ABC");
+    }
+
+    #[test]
+    fn ips_truncated() {
+        assert_eq!(
+            apply(SOURCE, b"PATCH\x00\x00\x01\x00\x04xy"),
+            Err(PatchError::Truncated)
+        );
+        assert_eq!(apply(SOURCE, b"PATCH\x00\x00"), Err(PatchError::Truncated));
+    }
+
+    #[test]
+    fn unknown_format() {
+        assert_eq!(apply(SOURCE, b"UPS1"), Err(PatchError::UnknownFormat));
+    }
+
+    #[test]
+    fn bps_commands() {
+        assert_eq!(apply(SOURCE, &bps()).unwrap(), b"ABCDxyGHGHGH");
+    }
+
+    #[test]
+    fn bps_numbers_take_several_bytes() {
+        for value in [0, 127, 128, 300, 16511, 16512, 1 << 24] {
+            let mut data = Vec::new();
+            number(&mut data, value);
+            let mut reader = Reader {
+                data: &data,
+                position: 0,
+            };
+            assert_eq!(reader.number(), Ok(value));
+            assert_eq!(reader.position, data.len());
+        }
+    }
+
+    #[test]
+    fn bps_checksums() {
+        assert_eq!(apply(b"ABCDEFGX", &bps()), Err(PatchError::SourceMismatch));
+
+        let mut corrupt = bps();
+        corrupt[8] ^= 0x01;
+        assert_eq!(apply(SOURCE, &corrupt), Err(PatchError::PatchChecksum));
+
+        // A patch that is intact but claims the wrong target
+        let mut wrong = bps();
+        let end = wrong.len() - 4;
+        wrong[end - 4..end].copy_from_slice(&0u32.to_le_bytes());
+        let crc = checksum::crc32(&wrong[..end]);
+        wrong[end..].copy_from_slice(&crc.to_le_bytes());
+        assert_eq!(apply(SOURCE, &wrong), Err(PatchError::TargetMismatch));
+    }
+}
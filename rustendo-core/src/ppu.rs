@@ -0,0 +1,1033 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use tracing::{debug, trace};
+
+use crate::mapper::SharedMapper;
+use crate::palette::{Palette, PixelFormat};
+use crate::ppu_bus::PpuBus;
+use crate::region::Region;
+
+const VBLANK_FLAG: u8 = 0b1000_0000;
+const SPRITE_ZERO_HIT_FLAG: u8 = 0b0100_0000;
+const SPRITE_OVERFLOW_FLAG: u8 = 0b0010_0000;
+const NMI_ENABLE: u8 = 0b1000_0000;
+const SHOW_BACKGROUND_LEFT: u8 = 0b0000_0010;
+const SHOW_SPRITES_LEFT: u8 = 0b0000_0100;
+const SHOW_BACKGROUND: u8 = 0b0000_1000;
+const SHOW_SPRITES: u8 = 0b0001_0000;
+
+// How long A12 has to stay low before a rise counts. Longer than the gap between the last
+// prefetch of a line and the first fetch of the next, shorter than the sprite fetch window.
+const A12_FILTER_DOTS: u16 = 16;
+
+const PRE_RENDER_SCANLINE: i32 = -1;
+pub(crate) const DOTS_PER_SCANLINE: u32 = 341;
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+// Background fetch latches and shift registers
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Background {
+    nametable: u8, // Tile fetched for the next 8 dots
+    attribute: u8, // Its 2-bit palette number
+    pattern_low: u8,
+    pattern_high: u8,
+    pattern_low_shift: u16, // Two tiles of pixels, the one being drawn in the high byte
+    pattern_high_shift: u16,
+    attribute_low_shift: u16, // Palette bits, expanded to one bit per pixel like the patterns
+    attribute_high_shift: u16,
+}
+
+impl Background {
+    fn shift(&mut self) {
+        self.pattern_low_shift <<= 1;
+        self.pattern_high_shift <<= 1;
+        self.attribute_low_shift <<= 1;
+        self.attribute_high_shift <<= 1;
+    }
+
+    // Move the fetched tile into the low byte of the shift registers
+    fn load(&mut self) {
+        let fill = |bit: bool| if bit { 0xFF } else { 0x00 };
+        self.pattern_low_shift = (self.pattern_low_shift & 0xFF00) | self.pattern_low as u16;
+        self.pattern_high_shift = (self.pattern_high_shift & 0xFF00) | self.pattern_high as u16;
+        self.attribute_low_shift =
+            (self.attribute_low_shift & 0xFF00) | fill(self.attribute & 0x01 != 0);
+        self.attribute_high_shift =
+            (self.attribute_high_shift & 0xFF00) | fill(self.attribute & 0x02 != 0);
+    }
+
+    // The 4-bit palette RAM offset of the pixel at `bit`, 0 when it is transparent
+    fn pixel(&self, bit: u16) -> u8 {
+        let low = (self.pattern_low_shift & bit != 0) as u8;
+        let high = (self.pattern_high_shift & bit != 0) as u8;
+        if low | high == 0 {
+            return 0;
+        }
+        let palette = (self.attribute_low_shift & bit != 0) as u8
+            | ((self.attribute_high_shift & bit != 0) as u8) << 1;
+        palette << 2 | high << 1 | low
+    }
+}
+
+// A sprite picked for the current scanline, with its pattern row already fetched
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct LineSprite {
+    x: u8,
+    attributes: u8,
+    tile: u8,
+    row: u8,         // Row of the sprite on this line, vertical flip applied
+    pattern_low: u8, // Horizontal flip applied, leftmost pixel in bit 7
+    pattern_high: u8,
+    sprite_zero: bool,
+}
+
+// Debug switches that hide parts of the picture without affecting emulation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LayerVisibility {
+    pub hide_background: bool,
+    pub hide_sprites: bool,
+    pub sprite_palette: Option<u8>, // Only draw sprites using this palette (4-7)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RenderMode {
+    #[default]
+    Accurate, // Fetch and shift the background every dot
+    Fast, // Draw whole scanlines at once unless a register is written mid-line
+}
+
+// With the `serde` feature the whole PPU, down to the current dot and the fetch pipeline, can be
+// saved and restored mid-frame. The picture is saved as palette indices only; `restore` draws
+// the pixels again from them.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PPU {
+    control: u8,
+    mask: u8,
+    status: u8,
+    oam_addr: u8,
+    data: u8,     // $2007 read buffer
+    open_bus: u8, // Last value written to any PPU register
+    bus: PpuBus,  // Pattern tables, nametables and palette RAM
+    v: u16,
+    t: u16,
+    x: u8,
+    w: bool,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_arrays"))]
+    oam: [u8; 256],
+    background: Background,
+    sprites: [LineSprite; 8], // Up to eight sprites on the line being drawn
+    sprite_count: usize,
+    line_deferred: bool, // Fast mode: this line's pixels are drawn at dot 256
+    render_mode: RenderMode,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    visibility: LayerVisibility,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    framebuffer: Vec<u8>, // Row-major, in `pixel_format`
+    indices: Vec<u16>, // The same frame as palette indices (colour | emphasis << 6)
+    pixel_format: PixelFormat,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    palette: Palette,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    colors: Vec<[u8; 4]>, // Each palette entry already encoded in `pixel_format`
+    cycle: u32,
+    scanline: i32, // -1 is the pre-render scanline, 241 (291 on Dendy) starts vertical blank
+    frame_count: u32,
+    region: Region,        // Sets the lines per frame and when VBlank starts
+    suppress_vblank: bool, // $2002 was read one dot before VBlank would be set
+    nmi_pending: bool,
+    skip_dot: bool,       // This frame's pre-render line skips its last dot
+    frame_complete: bool, // The visible scanlines of a new frame have been drawn
+    #[cfg_attr(feature = "serde", serde(skip))]
+    vblank_started: bool, // For the bus, which pauses the PPU here when overclocking
+    a12_high: bool,       // Last address the PPU put on its bus had A12 set
+    a12_low_dots: u16,    // Dots since A12 was last high, saturating
+    a12_rises: u8,        // Filtered A12 rising edges not yet taken
+}
+
+impl Default for PPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PPU {
+    pub fn new() -> Self {
+        Self {
+            control: 0,
+            mask: 0,
+            status: 0,
+            oam_addr: 0,
+            data: 0,
+            open_bus: 0,
+            bus: PpuBus::new(),
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            oam: [0; 256],
+            background: Background::default(),
+            sprites: [LineSprite::default(); 8],
+            sprite_count: 0,
+            line_deferred: false,
+            render_mode: RenderMode::Accurate,
+            visibility: LayerVisibility::default(),
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+            indices: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            pixel_format: PixelFormat::Rgba8888,
+            palette: Palette::default(),
+            colors: Self::encode_colors(PixelFormat::Rgba8888, &Palette::default()),
+            cycle: 0,
+            scanline: PRE_RENDER_SCANLINE,
+            frame_count: 0,
+            region: Region::Ntsc,
+            suppress_vblank: false,
+            nmi_pending: false,
+            skip_dot: false,
+            frame_complete: false,
+            vblank_started: false,
+            a12_high: false,
+            a12_low_dots: 0,
+            a12_rises: 0,
+        }
+    }
+
+    pub fn step(&mut self) {
+        self.cycle += 1;
+        if !self.a12_high {
+            self.a12_low_dots = self.a12_low_dots.saturating_add(1);
+        }
+
+        if self.cycle >= DOTS_PER_SCANLINE {
+            self.cycle = 0;
+            self.scanline += 1;
+            if self.scanline > self.region.last_scanline() {
+                self.scanline = PRE_RENDER_SCANLINE;
+                self.frame_count += 1;
+            }
+        }
+
+        let visible_line = (0..SCREEN_HEIGHT as i32).contains(&self.scanline);
+        if self.render_mode == RenderMode::Fast
+            && visible_line
+            && self.cycle == 1
+            && self.rendering_enabled()
+        {
+            self.line_deferred = true;
+        }
+
+        if self.line_deferred {
+            if self.cycle == SCREEN_WIDTH as u32 {
+                self.render_line_fast();
+            }
+        } else {
+            if self.rendering_enabled() && (visible_line || self.scanline == PRE_RENDER_SCANLINE) {
+                self.fetch_background();
+                self.fetch_sprites();
+            }
+            if visible_line && (1..=SCREEN_WIDTH as u32).contains(&self.cycle) {
+                self.draw_dot();
+            }
+        }
+
+        if self.cycle == 1 {
+            if self.scanline == self.region.vblank_scanline() {
+                debug!(target: "ppu", "Frame {} finished, entering vblank", self.frame_count);
+                self.frame_complete = true;
+                self.vblank_started = true;
+                if !self.suppress_vblank {
+                    self.status |= VBLANK_FLAG;
+                    if self.control & NMI_ENABLE != 0 {
+                        self.nmi_pending = true;
+                    }
+                }
+                self.suppress_vblank = false;
+            } else if self.scanline == PRE_RENDER_SCANLINE {
+                self.status &= !(VBLANK_FLAG | SPRITE_ZERO_HIT_FLAG | SPRITE_OVERFLOW_FLAG);
+            }
+        }
+
+        // On odd frames with rendering enabled the pre-render line skips its last dot, so NTSC
+        // frames alternate between 89342 and 89341 dots. PAL PPUs never skip. Rendering has to
+        // be on by dot 338 for the skip; CPU writes land at the end of their three dots here, so
+        // one that shows on 338 was made in time for the real PPU's check on 339.
+        if self.scanline == PRE_RENDER_SCANLINE {
+            if self.cycle == DOTS_PER_SCANLINE - 3 {
+                self.skip_dot = self.region == Region::Ntsc
+                    && self.frame_count % 2 == 1
+                    && self.rendering_enabled();
+            } else if self.cycle == DOTS_PER_SCANLINE - 2 && self.skip_dot {
+                self.skip_dot = false;
+                self.cycle += 1;
+            }
+        }
+    }
+
+    fn draw_dot(&mut self) {
+        let x = self.cycle as usize - 1;
+        let background = if self.show_background_at(x) {
+            self.background.pixel(0x8000 >> self.x)
+        } else {
+            0
+        };
+        let color = self.compose_pixel(x, background);
+        self.put_pixel(x, self.scanline as usize, color);
+    }
+
+    // PPUMASK bits 1 and 2 blank the leftmost 8 pixels of each layer
+    fn show_background_at(&self, x: usize) -> bool {
+        self.mask & SHOW_BACKGROUND != 0 && (x >= 8 || self.mask & SHOW_BACKGROUND_LEFT != 0)
+    }
+
+    fn show_sprites_at(&self, x: usize) -> bool {
+        self.mask & SHOW_SPRITES != 0 && (x >= 8 || self.mask & SHOW_SPRITES_LEFT != 0)
+    }
+
+    // Pick between the background pixel and the sprites at `x`, checking for a sprite zero hit
+    // on the way, and return the resulting colour
+    fn compose_pixel(&mut self, x: usize, background: u8) -> u8 {
+        let sprite = self.sprite_pixel(x, None);
+        if let Some((_, _, true)) = sprite {
+            if background != 0 && x != 255 {
+                self.status |= SPRITE_ZERO_HIT_FLAG;
+            }
+        }
+
+        // The debug visibility switches only change what is drawn, never the hit above
+        let visibility = self.visibility;
+        let background = if visibility.hide_background {
+            0
+        } else {
+            background
+        };
+        let sprite = if visibility.hide_sprites {
+            None
+        } else if visibility.sprite_palette.is_some() {
+            self.sprite_pixel(x, visibility.sprite_palette)
+        } else {
+            sprite
+        };
+
+        let mut pixel = background;
+        if let Some((sprite, behind_background, _)) = sprite {
+            if background == 0 || !behind_background {
+                pixel = sprite;
+            }
+        }
+        // Transparent pixels show the backdrop colour
+        self.read_vram(0x3F00 + pixel as u16) & 0x3F
+    }
+
+    // The first opaque sprite pixel at `x` as (palette RAM offset, behind background, sprite 0),
+    // optionally only looking at sprites that use one palette (4-7)
+    fn sprite_pixel(&self, x: usize, palette: Option<u8>) -> Option<(u8, bool, bool)> {
+        if !self.show_sprites_at(x) {
+            return None;
+        }
+        let sprites = self.sprites[..self.sprite_count].iter();
+        sprites
+            .filter(|sprite| {
+                palette.is_none_or(|palette| sprite.attributes & 0x03 == palette & 0x03)
+            })
+            .find_map(|sprite| {
+                let column = x
+                    .checked_sub(sprite.x as usize)
+                    .filter(|&column| column < 8)?;
+                let bit = 7 - column;
+                let value = ((sprite.pattern_low >> bit) & 0x01)
+                    | (((sprite.pattern_high >> bit) & 0x01) << 1);
+                if value == 0 {
+                    return None;
+                }
+                let pixel = 0x10 | (sprite.attributes & 0x03) << 2 | value;
+                Some((pixel, sprite.attributes & 0x20 != 0, sprite.sprite_zero))
+            })
+    }
+
+    // Sprite evaluation for the next line at dot 257, then one pattern fetch per slot over
+    // dots 257-320. Empty slots still fetch tile $FF, as the hardware does.
+    fn fetch_sprites(&mut self) {
+        let cycle = self.cycle;
+        if cycle == 257 {
+            self.evaluate_sprites();
+        }
+        if !(257..=320).contains(&cycle) {
+            return;
+        }
+
+        self.oam_addr = 0;
+        let slot = (cycle as usize - 257) / 8;
+        let sprite = self.sprites[slot];
+        let address = self.sprite_row_address(&sprite);
+        match (cycle - 257) % 8 {
+            5 => {
+                let value = self.fetch_vram(address);
+                self.sprites[slot].pattern_low = self.sprite_pattern(slot, value);
+            }
+            7 => {
+                let value = self.fetch_vram(address + 8);
+                self.sprites[slot].pattern_high = self.sprite_pattern(slot, value);
+            }
+            _ => {}
+        }
+    }
+
+    fn sprite_pattern(&self, slot: usize, value: u8) -> u8 {
+        if slot >= self.sprite_count {
+            0
+        } else if self.sprites[slot].attributes & 0x40 != 0 {
+            value.reverse_bits()
+        } else {
+            value
+        }
+    }
+
+    fn sprite_row_address(&self, sprite: &LineSprite) -> u16 {
+        let row = sprite.row as u16;
+        if self.sprite_height() == 16 {
+            // 8x16 sprites take their pattern table from bit 0 of the tile number
+            let table = (sprite.tile as u16 & 0x01) * 0x1000;
+            let tile = (sprite.tile as u16 & 0xFE) + row / 8;
+            table + tile * 16 + (row & 0x07)
+        } else {
+            self.sprite_pattern_table() + sprite.tile as u16 * 16 + row
+        }
+    }
+
+    // Find the first eight sprites that cover the next line. OAM holds each sprite's top minus
+    // one, so comparing against the current line gives the row on the next one.
+    fn evaluate_sprites(&mut self) {
+        let height = self.sprite_height() as i32;
+        let mut count = 0;
+        for index in 0..64 {
+            let entry: [u8; 4] = self.oam[index * 4..index * 4 + 4].try_into().unwrap();
+            let row = self.scanline - entry[0] as i32;
+            if !(0..height).contains(&row) {
+                continue;
+            }
+            if count == self.sprites.len() {
+                self.status |= SPRITE_OVERFLOW_FLAG;
+                break;
+            }
+            let row = if entry[2] & 0x80 != 0 {
+                height - 1 - row
+            } else {
+                row
+            };
+            self.sprites[count] = LineSprite {
+                x: entry[3],
+                attributes: entry[2],
+                tile: entry[1],
+                row: row as u8,
+                pattern_low: 0,
+                pattern_high: 0,
+                sprite_zero: index == 0,
+            };
+            count += 1;
+        }
+
+        for sprite in &mut self.sprites[count..] {
+            *sprite = LineSprite {
+                tile: 0xFF,
+                x: 0xFF,
+                ..LineSprite::default()
+            };
+        }
+        self.sprite_count = count;
+    }
+
+    // Fast mode: draw dots 1-256 of a line in one go, tile by tile. This only matches the
+    // accurate pipeline because nothing that affects rendering changed since dot 1.
+    fn render_line_fast(&mut self) {
+        self.line_deferred = false;
+
+        // The first two tiles were prefetched into the shift registers on the previous line
+        let mut pixels = [0u8; 34 * 8];
+        for (i, pixel) in pixels[..16].iter_mut().enumerate() {
+            *pixel = self.background.pixel(0x8000 >> i);
+        }
+        for tile in pixels[16..].chunks_exact_mut(8) {
+            let nametable = self.read_vram(0x2000 | (self.v & 0x0FFF));
+            let palette = self.attribute_bits();
+            let address = self.background_tile_row(nametable);
+            let (low, high) = (self.read_vram(address), self.read_vram(address + 8));
+            for (column, pixel) in tile.iter_mut().enumerate() {
+                let bit = 7 - column;
+                let value = ((low >> bit) & 0x01) | (((high >> bit) & 0x01) << 1);
+                *pixel = if value == 0 { 0 } else { palette << 2 | value };
+            }
+            self.increment_coarse_x();
+        }
+
+        for x in 0..SCREEN_WIDTH {
+            let background = if self.show_background_at(x) {
+                pixels[x + self.x as usize]
+            } else {
+                0
+            };
+            let color = self.compose_pixel(x, background);
+            self.put_pixel(x, self.scanline as usize, color);
+        }
+        self.increment_y();
+    }
+
+    // Fast mode: a register that affects rendering is about to change mid-line, so draw the dots
+    // up to now with the old values and continue the line with the accurate pipeline
+    fn catch_up(&mut self) {
+        if !self.line_deferred {
+            return;
+        }
+        self.line_deferred = false;
+
+        let end = self.cycle;
+        for dot in 1..=end {
+            self.cycle = dot;
+            self.fetch_background();
+            self.draw_dot();
+        }
+    }
+
+    pub fn layer_visibility(&self) -> LayerVisibility {
+        self.visibility
+    }
+
+    pub fn set_layer_visibility(&mut self, visibility: LayerVisibility) {
+        self.catch_up();
+        self.visibility = visibility;
+    }
+
+    pub fn render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.catch_up();
+        self.render_mode = mode;
+    }
+
+    // One dot of the background fetch schedule: every 8 dots the PPU reads a nametable byte, an
+    // attribute byte and the two pattern bytes of the next tile, while the shift registers feed
+    // the tile being drawn out one pixel per dot. Dots 321-336 prefetch the first two tiles of
+    // the next line.
+    fn fetch_background(&mut self) {
+        let cycle = self.cycle;
+        let fetching = (2..=257).contains(&cycle) || (322..=337).contains(&cycle);
+        if fetching {
+            self.background.shift();
+        }
+
+        if cycle == 1 || cycle == 321 || fetching {
+            match (cycle - 1) % 8 {
+                0 => {
+                    self.background.load();
+                    self.background.nametable = self.fetch_vram(0x2000 | (self.v & 0x0FFF));
+                }
+                2 => self.background.attribute = self.attribute_bits(),
+                4 => {
+                    let address = self.background_tile_row(self.background.nametable);
+                    self.background.pattern_low = self.fetch_vram(address);
+                }
+                6 => {
+                    let address = self.background_tile_row(self.background.nametable) + 8;
+                    self.background.pattern_high = self.fetch_vram(address);
+                }
+                7 => self.increment_coarse_x(),
+                _ => {}
+            }
+        }
+
+        match cycle {
+            256 => self.increment_y(),
+            257 => {
+                // Horizontal scroll bits are copied from t for the next line
+                self.v = (self.v & !0x041F) | (self.t & 0x041F);
+            }
+            280..=304 if self.scanline == PRE_RENDER_SCANLINE => {
+                // Vertical scroll bits are copied from t for the new frame
+                self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+            }
+            _ => {}
+        }
+    }
+
+    // The 2-bit palette number of the tile at v
+    fn attribute_bits(&self) -> u8 {
+        let address = 0x23C0 | (self.v & 0x0C00) | ((self.v >> 4) & 0x38) | ((self.v >> 2) & 0x07);
+        // Each attribute byte covers 4x4 tiles, two bits per 2x2 quadrant
+        let shift = ((self.v >> 4) & 0x04) | (self.v & 0x02);
+        (self.read_vram(address) >> shift) & 0x03
+    }
+
+    // Address of the low pattern plane of `tile` for the fine Y scroll in v
+    fn background_tile_row(&self, tile: u8) -> u16 {
+        self.background_pattern_table() + tile as u16 * 16 + (self.v >> 12)
+    }
+
+    fn increment_coarse_x(&mut self) {
+        if self.v & 0x001F == 31 {
+            // Wrap into the horizontally adjacent nametable
+            self.v &= !0x001F;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03E0) >> 5;
+        if coarse_y == 29 {
+            // Wrap into the vertically adjacent nametable
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            // Rows 30 and 31 are attribute data; scrolling into them wraps without switching
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+
+    // A rendering fetch: the address goes out on the PPU bus, where cartridges can watch it
+    fn fetch_vram(&mut self, address: u16) -> u8 {
+        self.observe_address(address);
+        self.read_vram(address)
+    }
+
+    // Track PPU address line A12 for mappers like MMC3 that count its rising edges. Like MMC3,
+    // a rise is ignored unless A12 was low for a while, so the quick toggles between nametable
+    // and pattern fetches within the sprite fetch window do not count.
+    fn observe_address(&mut self, address: u16) {
+        let high = address & 0x1000 != 0;
+        if high && !self.a12_high && self.a12_low_dots >= A12_FILTER_DOTS {
+            self.a12_rises = self.a12_rises.saturating_add(1);
+        }
+        if high {
+            self.a12_low_dots = 0;
+        }
+        self.a12_high = high;
+    }
+
+    // Returns the number of filtered A12 rising edges since the last call
+    pub fn take_a12_rises(&mut self) -> u8 {
+        core::mem::take(&mut self.a12_rises)
+    }
+
+    fn read_vram(&self, address: u16) -> u8 {
+        self.bus.read(address)
+    }
+
+    fn write_vram(&mut self, address: u16, value: u8) {
+        self.bus.write(address, value);
+    }
+
+    pub fn rendering_enabled(&self) -> bool {
+        self.mask & (SHOW_BACKGROUND | SHOW_SPRITES) != 0
+    }
+
+    // Address of the pattern table background tiles are drawn from
+    pub fn background_pattern_table(&self) -> u16 {
+        if self.control & 0x10 != 0 {
+            0x1000
+        } else {
+            0x0000
+        }
+    }
+
+    // Address of the pattern table 8x8 sprites are drawn from (8x16 sprites pick their own)
+    pub fn sprite_pattern_table(&self) -> u16 {
+        if self.control & 0x08 != 0 {
+            0x1000
+        } else {
+            0x0000
+        }
+    }
+
+    pub fn sprite_height(&self) -> usize {
+        if self.control & 0x20 != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    // Scroll position the next frame starts at, in pixels across the 512x480 nametable space
+    pub fn scroll(&self) -> (usize, usize) {
+        let table = (self.t >> 10) as usize & 0x03;
+        let x = (table & 0x01) * 256 + (self.t as usize & 0x1F) * 8 + self.x as usize;
+        let y = (table >> 1) * 240 + ((self.t >> 5) as usize & 0x1F) * 8 + (self.t >> 12) as usize;
+        (x, y)
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    pub fn scanline(&self) -> i32 {
+        self.scanline
+    }
+
+    pub fn cycle(&self) -> u32 {
+        self.cycle
+    }
+
+    // The last rendered frame as 256x240 pixels in the current pixel format
+    pub fn frame(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    // The last rendered frame as palette indices, for filters that work on the NES signal
+    pub fn frame_indices(&self) -> &[u16] {
+        &self.indices
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    // Switch the format the PPU renders in. The frame buffer is cleared and refilled from the
+    // next dot on.
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.pixel_format = format;
+        self.colors = Self::encode_colors(format, &self.palette);
+        self.framebuffer = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * format.bytes_per_pixel()];
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.colors = Self::encode_colors(self.pixel_format, &palette);
+        self.palette = palette;
+    }
+
+    fn encode_colors(format: PixelFormat, palette: &Palette) -> Vec<[u8; 4]> {
+        (0..512u16)
+            .map(|index| format.encode(index as u8, palette.color(index)))
+            .collect()
+    }
+
+    // Draw a 6-bit palette colour, applying the emphasis bits currently set in PPUMASK
+    fn put_pixel(&mut self, x: usize, y: usize, color: u8) {
+        let index = ((self.mask as usize & 0xE0) << 1) | (color as usize & 0x3F);
+        self.indices[y * SCREEN_WIDTH + x] = index as u16;
+        let size = self.pixel_format.bytes_per_pixel();
+        let offset = (y * SCREEN_WIDTH + x) * size;
+        self.framebuffer[offset..offset + size].copy_from_slice(&self.colors[index][..size]);
+    }
+
+    // Dots until the next `step` that starts vblank (the flag, the NMI and the finished frame),
+    // clears the vblank flag or starts a new frame. Nothing else the PPU does on its own shows
+    // outside it until a register is accessed, so the bus can run it in bursts between these.
+    pub(crate) fn dots_until_event(&self) -> u64 {
+        let line_dots = DOTS_PER_SCANLINE as u64;
+        let position = (self.scanline - PRE_RENDER_SCANLINE) as u64 * line_dots + self.cycle as u64;
+        let vblank = (self.region.vblank_scanline() - PRE_RENDER_SCANLINE) as u64 * line_dots + 1;
+        let frame_end = (self.region.last_scanline() - PRE_RENDER_SCANLINE + 1) as u64 * line_dots;
+        let event = [1, vblank, frame_end]
+            .into_iter()
+            .find(|&event| event > position)
+            .unwrap_or(frame_end);
+        let dots = event - position;
+        // The pre-render line may be a dot short
+        if self.scanline == PRE_RENDER_SCANLINE && dots > 1 {
+            dots - 1
+        } else {
+            dots
+        }
+    }
+
+    // Returns true once for every frame that finished rendering since the last call
+    pub fn take_frame_complete(&mut self) -> bool {
+        core::mem::take(&mut self.frame_complete)
+    }
+
+    // The same, separately, for the bus
+    pub(crate) fn take_vblank_started(&mut self) -> bool {
+        core::mem::take(&mut self.vblank_started)
+    }
+
+    pub fn nmi_pending(&self) -> bool {
+        self.nmi_pending
+    }
+
+    // Returns true once for every NMI the PPU has raised since the last call
+    pub fn take_nmi(&mut self) -> bool {
+        core::mem::take(&mut self.nmi_pending)
+    }
+
+    pub fn read_register(&mut self, address: u16) -> u8 {
+        match address & 0x07 {
+            0x02 => self.read_status(),
+            0x04 => self.oam[self.oam_addr as usize],
+            0x07 => {
+                // Reading $2007 moves v
+                self.catch_up();
+                self.read_data()
+            }
+            _ => self.open_bus,
+        }
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        trace!(target: "ppu", "${:04X} = {:02X}", 0x2000 | address & 0x07, value);
+        if !matches!(address & 0x07, 0x03 | 0x04) {
+            self.catch_up();
+        }
+        self.open_bus = value;
+        match address & 0x07 {
+            0x00 => {
+                let was_enabled = self.control & NMI_ENABLE != 0;
+                self.control = value;
+                self.t = (self.t & 0xF3FF) | ((value as u16 & 0x03) << 10);
+                let enabled = value & NMI_ENABLE != 0;
+                let clearing = self.scanline == PRE_RENDER_SCANLINE && self.cycle == 0;
+                if !was_enabled && enabled && self.status & VBLANK_FLAG != 0 && !clearing {
+                    // Enabling NMI during VBlank fires one immediately, unless the flag clears
+                    // on the next dot, too soon for the CPU to see it
+                    self.nmi_pending = true;
+                } else if !enabled
+                    && self.scanline == self.region.vblank_scanline()
+                    && self.cycle <= 2
+                {
+                    // Disabling NMI right as VBlank starts cancels it
+                    self.nmi_pending = false;
+                }
+            }
+            0x01 => self.mask = value,
+            0x03 => self.oam_addr = value,
+            0x04 => {
+                self.oam[self.oam_addr as usize] = value;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            0x05 => {
+                if !self.w {
+                    self.t = (self.t & 0xFFE0) | (value as u16 >> 3);
+                    self.x = value & 0x07;
+                } else {
+                    self.t = (self.t & 0x8C1F)
+                        | ((value as u16 & 0x07) << 12)
+                        | ((value as u16 & 0xF8) << 2);
+                }
+                self.w = !self.w;
+            }
+            0x06 => {
+                if !self.w {
+                    self.t = (self.t & 0x00FF) | ((value as u16 & 0x3F) << 8);
+                } else {
+                    self.t = (self.t & 0xFF00) | value as u16;
+                    self.v = self.t;
+                    // Outside rendering the PPU bus holds v, so this can clock an A12 counter
+                    self.observe_address(self.v);
+                }
+                self.w = !self.w;
+            }
+            0x07 => {
+                self.write_vram(self.v, value);
+                self.increment_vram_address();
+            }
+            _ => {}
+        }
+    }
+
+    fn read_status(&mut self) -> u8 {
+        // Sprite zero hits are only known once the line has been drawn up to this dot
+        self.catch_up();
+        if self.scanline == self.region.vblank_scanline() {
+            match self.cycle {
+                // One dot before the flag is set: it reads clear and is never set this frame
+                0 => self.suppress_vblank = true,
+                // Same dot or one after: it reads set but the NMI is swallowed
+                1 | 2 => self.nmi_pending = false,
+                _ => {}
+            }
+        }
+
+        let value = (self.status & 0xE0) | (self.open_bus & 0x1F);
+        self.status &= !VBLANK_FLAG;
+        self.w = false;
+        value
+    }
+
+    fn read_data(&mut self) -> u8 {
+        let address = self.v & 0x3FFF;
+        let value = self.read_vram(address);
+        self.increment_vram_address();
+
+        if address >= 0x3F00 {
+            // Palette reads are not buffered, but still refill the buffer from the nametable below
+            self.data = self.read_vram(address - 0x1000);
+            value
+        } else {
+            core::mem::replace(&mut self.data, value)
+        }
+    }
+
+    fn increment_vram_address(&mut self) {
+        let step = if self.control & 0x04 != 0 { 32 } else { 1 };
+        self.v = self.v.wrapping_add(step) & 0x7FFF;
+        self.observe_address(self.v);
+    }
+
+    // Take over the state of a PPU from a save state. The cartridge connection and the display
+    // settings (palette, pixel format, render mode and layers) stay as they are, and the saved
+    // picture is redrawn in the current palette.
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore(&mut self, mut saved: PPU) {
+        saved.bus.take_cartridge(&mut self.bus);
+        saved.render_mode = self.render_mode;
+        saved.visibility = self.visibility;
+        saved.pixel_format = self.pixel_format;
+        saved.palette = core::mem::take(&mut self.palette);
+        saved.colors = core::mem::take(&mut self.colors);
+
+        let size = saved.pixel_format.bytes_per_pixel();
+        saved.framebuffer.resize(saved.indices.len() * size, 0);
+        for (pixel, &index) in saved.framebuffer.chunks_exact_mut(size).zip(&saved.indices) {
+            pixel.copy_from_slice(&saved.colors[index as usize][..size]);
+        }
+        *self = saved;
+    }
+
+    // Route pattern table accesses and nametable mirroring through the cartridge. With
+    // `four_screen` the board's own nametable RAM is used instead and its mirroring is ignored.
+    pub fn connect_cartridge(&mut self, cartridge: SharedMapper, four_screen: bool) {
+        self.bus.connect_cartridge(cartridge, four_screen);
+    }
+
+    // Raw access to PPU memory for debug views, scripts and tests. None of these touch v, the
+    // read buffer or any other register state.
+
+    // Read PPU address space ($0000-$3FFF, mirrored) without the side effects of $2007
+    pub fn peek(&self, address: u16) -> u8 {
+        self.read_vram(address)
+    }
+
+    // Read a register ($2000-$3FFF) without clearing VBlank, moving v or refilling the $2007
+    // buffer. The status read does not catch up on rendering first, so a sprite zero hit on the
+    // current line may not show yet.
+    pub fn peek_register(&self, address: u16) -> u8 {
+        match address & 0x07 {
+            0x02 => (self.status & 0xE0) | (self.open_bus & 0x1F),
+            0x04 => self.oam[self.oam_addr as usize],
+            0x07 if self.v & 0x3FFF >= 0x3F00 => self.read_vram(self.v),
+            0x07 => self.data,
+            _ => self.open_bus,
+        }
+    }
+
+    // The 8KB of pattern tables currently mapped at $0000-$1FFF
+    pub fn pattern_tables(&self) -> Vec<u8> {
+        (0..0x2000).map(|address| self.read_vram(address)).collect()
+    }
+
+    // The console's 2KB of nametable RAM, before mirroring maps it into $2000-$2FFF, followed by
+    // the cartridge's 2KB on four-screen boards
+    pub fn nametable_ram(&self) -> &[u8] {
+        self.bus.nametable_ram()
+    }
+
+    // The 32 palette entries at $3F00-$3F1F, with $3F10/$3F14/$3F18/$3F1C reading their mirrors
+    pub fn palette_ram(&self) -> [u8; 32] {
+        core::array::from_fn(|entry| self.read_vram(0x3F00 + entry as u16))
+    }
+
+    pub fn oam(&self) -> &[u8; 256] {
+        &self.oam
+    }
+
+    // Write PPU address space directly, mirrored the same way as $2007 writes
+    #[cfg(feature = "poke")]
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.write_vram(address, value);
+    }
+
+    #[cfg(feature = "poke")]
+    pub fn oam_mut(&mut self) -> &mut [u8; 256] {
+        &mut self.oam
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Run a PPU with NMIs enabled to `dot` of the first line of vertical blank, read $2002
+    // there, then run on to the end of the line. Returns whether the read saw the VBlank flag,
+    // whether the flag is still set afterwards and whether an NMI was raised.
+    fn read_status_at(dot: u32) -> (bool, bool, bool) {
+        let mut ppu = PPU::new();
+        ppu.write_register(0x2000, NMI_ENABLE);
+        while !(ppu.scanline() == 241 && ppu.cycle() == dot) {
+            ppu.step();
+        }
+        let read = ppu.read_register(0x2002) & VBLANK_FLAG != 0;
+        let mut nmi = ppu.take_nmi();
+        while ppu.scanline() == 241 {
+            ppu.step();
+            nmi |= ppu.take_nmi();
+        }
+        (read, ppu.status & VBLANK_FLAG != 0, nmi)
+    }
+
+    #[test]
+    fn vblank_starts_at_dot_1_with_an_nmi() {
+        let mut ppu = PPU::new();
+        ppu.write_register(0x2000, NMI_ENABLE);
+        while !(ppu.scanline() == 241 && ppu.cycle() == 0) {
+            ppu.step();
+        }
+        assert!(ppu.status & VBLANK_FLAG == 0 && !ppu.take_nmi());
+        ppu.step();
+        assert!(ppu.status & VBLANK_FLAG != 0 && ppu.take_nmi());
+    }
+
+    #[test]
+    fn read_one_dot_before_vblank_suppresses_the_flag_and_nmi() {
+        assert_eq!(read_status_at(0), (false, false, false));
+    }
+
+    #[test]
+    fn read_as_vblank_starts_sees_the_flag_but_swallows_the_nmi() {
+        assert_eq!(read_status_at(1), (true, false, false));
+        assert_eq!(read_status_at(2), (true, false, false));
+    }
+
+    #[test]
+    fn read_after_the_race_leaves_the_nmi() {
+        assert_eq!(read_status_at(3), (true, false, true));
+    }
+
+    #[test]
+    fn vblank_clears_on_the_pre_render_line() {
+        let mut ppu = PPU::new();
+        while ppu.scanline() != 241 || ppu.cycle() != 1 {
+            ppu.step();
+        }
+        while ppu.scanline() != PRE_RENDER_SCANLINE || ppu.cycle() != 0 {
+            ppu.step();
+        }
+        assert!(ppu.status & VBLANK_FLAG != 0);
+        ppu.step();
+        assert!(ppu.status & VBLANK_FLAG == 0);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::mapper::{Mirroring, SharedMapper};
+
+// The PPU's address space. The cartridge supplies the pattern tables at $0000-$1FFF and decides
+// how the console's 2KB of nametable RAM fills $2000-$3EFF; palette RAM sits at $3F00-$3FFF.
+// Without a cartridge the pattern tables read as 0 and the nametables are mirrored
+// horizontally.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PpuBus {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_arrays"))]
+    nametables: [u8; 0x1000], // The console's 2KB, then 2KB more on four-screen boards
+    palette: [u8; 0x20],
+    #[cfg_attr(feature = "serde", serde(skip))]
+    cartridge: Option<SharedMapper>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    four_screen: bool, // The cartridge's own nametable RAM overrides its mirroring
+}
+
+impl Default for PpuBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PpuBus {
+    pub fn new() -> Self {
+        Self {
+            nametables: [0; 0x1000],
+            palette: [0; 0x20],
+            cartridge: None,
+            four_screen: false,
+        }
+    }
+
+    // Route pattern table accesses and nametable mirroring through the cartridge. With
+    // `four_screen` the board's own nametable RAM is used instead and its mirroring is ignored.
+    pub fn connect_cartridge(&mut self, cartridge: SharedMapper, four_screen: bool) {
+        self.cartridge = Some(cartridge);
+        self.four_screen = four_screen;
+    }
+
+    // Move the cartridge connection over from `other`, for a bus restored from a save state
+    #[cfg(feature = "serde")]
+    pub(crate) fn take_cartridge(&mut self, other: &mut PpuBus) {
+        self.cartridge = other.cartridge.take();
+        self.four_screen = other.four_screen;
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address & 0x3FFF {
+            address @ 0x0000..=0x1FFF => self
+                .cartridge
+                .as_ref()
+                .map_or(0, |cartridge| cartridge.borrow_mut().ppu_read(address)),
+            address @ 0x2000..=0x3EFF => self.nametables[self.nametable_index(address)],
+            address => self.palette[palette_index(address)],
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address & 0x3FFF {
+            address @ 0x0000..=0x1FFF => {
+                if let Some(cartridge) = self.cartridge.as_ref() {
+                    cartridge.borrow_mut().ppu_write(address, value);
+                }
+            }
+            address @ 0x2000..=0x3EFF => {
+                let index = self.nametable_index(address);
+                self.nametables[index] = value;
+            }
+            address => self.palette[palette_index(address)] = value,
+        }
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        if self.four_screen {
+            return Mirroring::FourScreen;
+        }
+        self.cartridge
+            .as_ref()
+            .map_or(Mirroring::Horizontal, |cartridge| {
+                cartridge.borrow().mirroring()
+            })
+    }
+
+    // The console's 2KB of nametable RAM, before mirroring maps it into $2000-$2FFF, followed by
+    // the cartridge's 2KB on four-screen boards
+    pub fn nametable_ram(&self) -> &[u8] {
+        if self.four_screen {
+            &self.nametables
+        } else {
+            &self.nametables[..0x800]
+        }
+    }
+
+    // Only two nametables exist; the cartridge wiring decides which pairs share one.
+    // Four-screen boards supply the other two.
+    fn nametable_index(&self, address: u16) -> usize {
+        let offset = (address - 0x2000) & 0x0FFF;
+        let table = offset >> 10;
+        let physical = match self.mirroring() {
+            Mirroring::Horizontal => table >> 1,
+            Mirroring::Vertical => table & 0x01,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+            Mirroring::FourScreen => table,
+        };
+        (physical * 0x400 + (offset & 0x3FF)) as usize
+    }
+}
+
+// $3F10/$3F14/$3F18/$3F1C mirror the background entries below them
+fn palette_index(address: u16) -> usize {
+    let index = address & 0x1F;
+    if index & 0x13 == 0x10 {
+        (index & 0x0F) as usize
+    } else {
+        index as usize
+    }
+}
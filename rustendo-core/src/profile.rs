@@ -0,0 +1,29 @@
+use std::time::{Duration, Instant};
+
+// Wall-clock time spent in each part of the console while profiling is on. The bus times the
+// PPU, APU and cartridge where it catches them up with the CPU, and the CPU is charged with the
+// rest of `Nes::step`. Reading the clock costs time too, so a profiled run is slower than a
+// plain one; compare profiles with each other, not with plain timings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub total: Duration,     // In `Nes::step`
+    pub ppu: Duration,       // Dots, including mapper A12 listeners
+    pub apu: Duration,       // Including DMC DMA requests
+    pub cartridge: Duration, // Mapper cycle counters and IRQs
+}
+
+impl Profile {
+    // What the other parts don't account for: the CPU, DMA, interrupts and end-of-frame work
+    pub fn cpu(&self) -> Duration {
+        self.total
+            .saturating_sub(self.ppu + self.apu + self.cartridge)
+    }
+}
+
+// The time since `start`, which is moved on to now
+pub(crate) fn lap(start: &mut Instant) -> Duration {
+    let now = Instant::now();
+    let elapsed = now - *start;
+    *start = now;
+    elapsed
+}
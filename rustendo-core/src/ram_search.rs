@@ -0,0 +1,83 @@
+use alloc::vec::Vec;
+
+use crate::nes::Nes;
+
+// How a candidate's current value has to compare to its value at the previous snapshot (or to
+// a constant) to stay in the search
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchFilter {
+    Equal(u8),
+    NotEqual(u8),
+    Increased,
+    Decreased,
+    Changed,
+    Unchanged,
+}
+
+impl SearchFilter {
+    fn matches(self, previous: u8, value: u8) -> bool {
+        match self {
+            SearchFilter::Equal(expected) => value == expected,
+            SearchFilter::NotEqual(expected) => value != expected,
+            SearchFilter::Increased => value > previous,
+            SearchFilter::Decreased => value < previous,
+            SearchFilter::Changed => value != previous,
+            SearchFilter::Unchanged => value == previous,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchCandidate {
+    pub address: u16,
+    pub value: u8, // As of the last snapshot
+}
+
+// Finds where a game keeps a value such as the number of lives: start a search, let the game
+// change the value, filter by how it changed, and repeat until a few addresses are left. Covers
+// the 2KB of internal RAM and, if the cartridge has any, the PRG-RAM at $6000-$7FFF.
+pub struct RamSearch {
+    candidates: Vec<SearchCandidate>,
+}
+
+impl RamSearch {
+    // Start with every searchable address as a candidate, snapshotting its current value
+    pub fn new(nes: &Nes) -> Self {
+        let memory = nes.memory().borrow();
+        let prg_ram = memory
+            .cartridge()
+            .is_some_and(|cartridge| cartridge.borrow_mut().cpu_peek(0x6000).is_some());
+        let prg_ram_addresses = if prg_ram { 0x6000..0x8000 } else { 0..0 };
+
+        let candidates = (0x0000..0x0800)
+            .chain(prg_ram_addresses)
+            .map(|address| SearchCandidate {
+                address,
+                value: memory.peek(address),
+            })
+            .collect();
+        Self { candidates }
+    }
+
+    // Drop the candidates that don't match `filter`, and snapshot the values of the rest
+    pub fn filter(&mut self, nes: &Nes, filter: SearchFilter) {
+        self.candidates.retain_mut(|candidate| {
+            let value = nes.peek(candidate.address);
+            let matches = filter.matches(candidate.value, value);
+            candidate.value = value;
+            matches
+        });
+    }
+
+    // Snapshot the current values without dropping anything, e.g. to skip a change that isn't
+    // part of the search
+    pub fn snapshot(&mut self, nes: &Nes) {
+        for candidate in &mut self.candidates {
+            candidate.value = nes.peek(candidate.address);
+        }
+    }
+
+    pub fn candidates(&self) -> &[SearchCandidate] {
+        &self.candidates
+    }
+}
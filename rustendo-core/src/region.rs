@@ -0,0 +1,73 @@
+// The console model a game was made for, which sets the clock speed, the number of scanlines
+// and the APU's timer tables. Dendy is the Russian famiclone: PAL frame rate and scanline
+// count, but the NTSC CPU:PPU ratio and APU tables and a later VBlank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Region {
+    pub fn cpu_frequency(self) -> u32 {
+        match self {
+            Region::Ntsc => 1_789_773,
+            Region::Pal => 1_662_607,
+            Region::Dendy => 1_773_448,
+        }
+    }
+
+    // Frames per second: 60.0988 on NTSC, 50.007 on PAL and Dendy. An NTSC frame is 29780.5 CPU
+    // cycles on average (every other one skips a dot), PAL 33247.5 and Dendy 35464.
+    pub fn frame_rate(self) -> f64 {
+        let (numerator, denominator) = self.frame_rate_fraction();
+        numerator as f64 / denominator as f64
+    }
+
+    // The frame rate as an exact fraction, for container formats that want one: CPU
+    // half-cycles per second over half-cycles per frame
+    pub fn frame_rate_fraction(self) -> (u32, u32) {
+        let half_cycles_per_frame = match self {
+            Region::Ntsc => 59_561,
+            Region::Pal => 66_495,
+            Region::Dendy => 70_928,
+        };
+        (self.cpu_frequency() * 2, half_cycles_per_frame)
+    }
+
+    // The last scanline before the pre-render line wraps the frame around
+    pub(crate) fn last_scanline(self) -> i32 {
+        match self {
+            Region::Ntsc => 260,
+            Region::Pal | Region::Dendy => 310,
+        }
+    }
+
+    pub(crate) fn vblank_scanline(self) -> i32 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    // PPU dots in the CPU cycle numbered `cycle`: 3 on NTSC and Dendy, and 3.2 on average on
+    // PAL, where every fifth cycle gets a fourth dot
+    pub(crate) fn dots_per_cycle(self, cycle: u64) -> u8 {
+        match self {
+            Region::Pal if cycle.is_multiple_of(5) => 4,
+            _ => 3,
+        }
+    }
+
+    // The CPU cycles it takes to run `dots` PPU dots, rounded down to be safe on PAL
+    pub(crate) fn cycles_for_dots(self, dots: u64) -> u64 {
+        match self {
+            // At most one cycle in five has a fourth dot, so k cycles run at most
+            // (16k + 5) / 5 dots
+            Region::Pal => (dots.saturating_sub(1) * 5 / 16).max(1),
+            _ => dots.div_ceil(3),
+        }
+    }
+}
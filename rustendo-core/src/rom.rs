@@ -0,0 +1,483 @@
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use crate::checksum;
+use crate::mapper::MapperError;
+use crate::region::Region;
+use crate::zip::{self, ZipError};
+
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+
+// The parts of an iNES file, in the order they appear
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomSection {
+    Header,
+    Trainer,
+    PrgRom,
+    ChrRom,
+}
+
+impl fmt::Display for RomSection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            RomSection::Header => "header",
+            RomSection::Trainer => "trainer",
+            RomSection::PrgRom => "PRG-ROM",
+            RomSection::ChrRom => "CHR-ROM",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    InvalidMagic, // Doesn't start with "NES\x1A"
+    // The file ends before `section`, which starts at byte `offset`, has all of its `size`
+    // bytes
+    TruncatedFile {
+        section: RomSection,
+        offset: usize,
+        size: usize,
+        file_size: usize,
+    },
+    NoPrgRom, // The header gives a PRG-ROM size of 0
+    // The file is longer than the header, trainer, PRG-ROM and CHR-ROM together. Only NES 2.0
+    // files may have more, as miscellaneous ROM.
+    SizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    UnsupportedMapper(u16),
+    ArchiveError(ZipError), // A zip file without a ROM that can be taken out of it
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            RomError::Io(e) => write!(f, "{}", e),
+            RomError::InvalidMagic => write!(f, "not an iNES file (missing \"NES\\x1A\")"),
+            RomError::TruncatedFile {
+                section,
+                offset,
+                size,
+                file_size,
+            } => write!(
+                f,
+                "file is truncated: the {} needs {} bytes from offset {}, but the file ends \
+                 at {}",
+                section, size, offset, file_size
+            ),
+            RomError::NoPrgRom => write!(f, "the header gives no PRG-ROM"),
+            RomError::SizeMismatch { expected, actual } => write!(
+                f,
+                "the header adds up to {} bytes, but the file has {}",
+                expected, actual
+            ),
+            RomError::UnsupportedMapper(number) => write!(f, "mapper {} is not supported", number),
+            RomError::ArchiveError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl core::error::Error for RomError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            RomError::Io(e) => Some(e),
+            RomError::ArchiveError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for RomError {
+    fn from(e: io::Error) -> Self {
+        RomError::Io(e)
+    }
+}
+
+impl From<MapperError> for RomError {
+    fn from(e: MapperError) -> Self {
+        match e {
+            MapperError::Unsupported(number) => RomError::UnsupportedMapper(number),
+        }
+    }
+}
+
+pub struct Rom {
+    pub prg_rom: Vec<u8>,    // PRG-ROM (Program ROM) data
+    pub chr_rom: Vec<u8>,    // CHR-ROM (Character ROM) data
+    pub trainer: Vec<u8>,    // 512 bytes for $7000-$71FF, empty for almost every dump
+    pub mapper: u16,         // Mapper number, up to 4095 with NES 2.0
+    pub submapper: u8,       // Board variant, from NES 2.0 headers (0 if unknown)
+    pub mirroring: u8,       // Mirroring type
+    pub battery: bool,       // The board keeps its RAM (or flash) across power cycles
+    pub prg_ram_size: usize, // PRG-RAM in bytes, work and battery-backed together
+    pub four_screen: bool,   // Header bit 3: four-screen VRAM, or one-screen mirroring on mapper 30
+    pub region: Region,      // The console the game expects, NTSC unless the header says otherwise
+}
+
+impl Rom {
+    // CRC-32 of the PRG-ROM followed by the CHR-ROM, which is what ROM databases key on
+    pub fn crc32(&self) -> u32 {
+        checksum::crc32(&self.contents())
+    }
+
+    pub fn sha1(&self) -> [u8; 20] {
+        checksum::sha1(&self.contents())
+    }
+
+    fn contents(&self) -> Vec<u8> {
+        [&self.prg_rom[..], &self.chr_rom[..]].concat()
+    }
+
+    // Write the ROM back out as a file with a NES 2.0 header, e.g. after the game database has
+    // corrected it. Readers that only know iNES still find the mapper, mirroring and sizes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // NES 2.0 RAM sizes are 64 << n bytes, rounded up here for sizes in between
+        let shift = |size: usize| match size {
+            0 => 0,
+            _ => size.div_ceil(64).next_power_of_two().trailing_zeros() as u8,
+        };
+
+        let mut header = [0; HEADER_SIZE];
+        header[..4].copy_from_slice(b"NES\x1A");
+        let (prg_low, prg_high, prg_size) = encode_rom_size(self.prg_rom.len(), 0x4000);
+        let (chr_low, chr_high, chr_size) = encode_rom_size(self.chr_rom.len(), 0x2000);
+        header[4] = prg_low;
+        header[5] = chr_low;
+        header[6] = (self.mapper as u8) << 4
+            | (self.four_screen as u8) << 3
+            | (!self.trainer.is_empty() as u8) << 2
+            | (self.battery as u8) << 1
+            | self.mirroring & 0x01;
+        header[7] = self.mapper as u8 & 0xF0 | 0x08;
+        header[8] = self.submapper << 4 | (self.mapper >> 8) as u8 & 0x0F;
+        header[9] = chr_high << 4 | prg_high;
+        // Battery boards keep all of their PRG-RAM, so it goes in the battery-backed nibble
+        header[10] = if self.battery {
+            shift(self.prg_ram_size) << 4
+        } else {
+            shift(self.prg_ram_size)
+        };
+        // Boards without CHR-ROM get the usual 8KB of CHR-RAM
+        header[11] = if self.chr_rom.is_empty() { 7 } else { 0 };
+        header[12] = match self.region {
+            Region::Ntsc => 0,
+            Region::Pal => 1,
+            Region::Dendy => 3,
+        };
+
+        let mut file = [&header[..], &self.trainer, &self.prg_rom].concat();
+        file.resize(file.len() + prg_size - self.prg_rom.len(), 0);
+        file.extend_from_slice(&self.chr_rom);
+        file.resize(file.len() + chr_size - self.chr_rom.len(), 0);
+        file
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load_from_file<P: AsRef<Path>>(file_path: P) -> Result<Self, RomError> {
+        Self::from_reader(File::open(file_path)?)
+    }
+
+    // Read a whole iNES file from e.g. a network stream or an archive entry
+    #[cfg(feature = "std")]
+    pub fn from_reader(mut reader: impl Read) -> Result<Self, RomError> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+        Self::from_bytes(&buffer)
+    }
+
+    // Parse an iNES file that is already in memory, or take the first ROM out of a zip file,
+    // which also works without `std`
+    pub fn from_bytes(buffer: &[u8]) -> Result<Self, RomError> {
+        if zip::is_zip(buffer) {
+            let rom = zip::extract_rom(buffer).map_err(RomError::ArchiveError)?;
+            return Self::parse(&rom);
+        }
+        Self::parse(buffer)
+    }
+
+    fn parse(buffer: &[u8]) -> Result<Self, RomError> {
+        let section = |section, offset: usize, size: usize| {
+            offset
+                .checked_add(size)
+                .and_then(|end| buffer.get(offset..end))
+                .ok_or(RomError::TruncatedFile {
+                    section,
+                    offset,
+                    size,
+                    file_size: buffer.len(),
+                })
+        };
+
+        // Parse the iNES header
+        if buffer.len() >= 4 && !buffer.starts_with(b"NES\x1A") {
+            return Err(RomError::InvalidMagic);
+        }
+        let header = section(RomSection::Header, 0, HEADER_SIZE)?;
+
+        let mirroring = header[6] & 0x01;
+        let battery = header[6] & 0x02 != 0;
+        let four_screen = header[6] & 0x08 != 0;
+        let has_trainer = header[6] & 0x04 != 0;
+        let nes2 = header[7] & 0x0C == 0x08;
+        let submapper = if nes2 { header[8] >> 4 } else { 0 };
+        // NES 2.0 puts bits 8-11 of the mapper number in the low nibble of byte 8
+        let mapper_high = if nes2 { (header[8] & 0x0F) as u16 } else { 0 };
+
+        // NES 2.0 adds high bits to the sizes in byte 9. With those all set the low byte is an
+        // exponent and multiplier instead, for sizes that aren't a multiple of the unit.
+        let rom_size = |low: u8, high: u8, unit: usize| {
+            if !nes2 || high == 0 {
+                low as usize * unit
+            } else if high < 0x0F {
+                ((high as usize) << 8 | low as usize) * unit
+            } else {
+                1usize
+                    .checked_shl((low >> 2) as u32)
+                    .unwrap_or(usize::MAX)
+                    .saturating_mul((low & 0x03) as usize * 2 + 1)
+            }
+        };
+        let prg_rom_size = rom_size(header[4], header[9] & 0x0F, 0x4000);
+        if prg_rom_size == 0 {
+            return Err(RomError::NoPrgRom);
+        }
+        let chr_rom_size = rom_size(header[5], header[9] >> 4, 0x2000);
+
+        // Old dumps with junk in bytes 12-15 (e.g. "DiskDude!") can't be trusted on anything
+        // after byte 7
+        let junk = !nes2 && header[12..16].iter().any(|&byte| byte != 0);
+        // That includes the mapper's high nibble: "DiskDude!" starts in byte 7
+        let mapper = if junk {
+            (header[6] >> 4) as u16
+        } else {
+            mapper_high << 8 | (header[6] >> 4 | header[7] & 0xF0) as u16
+        };
+
+        // NES 2.0 has a timing field (multi-region games run as NTSC); iNES only a PAL bit
+        let region = if nes2 {
+            match header[12] & 0x03 {
+                1 => Region::Pal,
+                3 => Region::Dendy,
+                _ => Region::Ntsc,
+            }
+        } else if !junk && header[9] & 0x01 != 0 {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        };
+
+        // NES 2.0 gives volatile (low nibble) and battery-backed (high nibble) PRG-RAM as
+        // 64 << n bytes. iNES counts 8KB units in byte 8, where 0 also means 8KB.
+        let prg_ram_size = if nes2 {
+            let size = |shift: u8| if shift == 0 { 0 } else { 64 << shift };
+            size(header[10] & 0x0F) + size(header[10] >> 4)
+        } else if junk {
+            0x2000
+        } else {
+            header[8].max(1) as usize * 0x2000
+        };
+
+        // A trainer sits between the header and the PRG-ROM
+        let trainer_size = if has_trainer { TRAINER_SIZE } else { 0 };
+        let prg_rom_start = HEADER_SIZE + trainer_size;
+
+        let trainer = section(RomSection::Trainer, HEADER_SIZE, trainer_size)?.to_vec();
+        let prg_rom = section(RomSection::PrgRom, prg_rom_start, prg_rom_size)?.to_vec();
+        // The sections so far are all in the file, so these can't overflow
+        let chr_rom_start = prg_rom_start + prg_rom_size;
+        let chr_rom = section(RomSection::ChrRom, chr_rom_start, chr_rom_size)?.to_vec();
+        let expected = chr_rom_start + chr_rom_size;
+        if buffer.len() > expected && !nes2 {
+            return Err(RomError::SizeMismatch {
+                expected,
+                actual: buffer.len(),
+            });
+        }
+
+        Ok(Self {
+            prg_rom,
+            chr_rom,
+            trainer,
+            mapper,
+            submapper,
+            mirroring,
+            battery,
+            prg_ram_size,
+            four_screen,
+            region,
+        })
+    }
+}
+
+// A NES 2.0 ROM size as the size byte and byte 9's nibble: a count of `unit`s, or where that
+// doesn't work, $F in the nibble and 2^E * (2M + 1) bytes with E in the byte's top six bits and
+// M in the bottom two. Also returns the size that gives, which is rounded up for the rare size
+// neither form can express, so the file is padded to match.
+fn encode_rom_size(size: usize, unit: usize) -> (u8, u8, usize) {
+    let units = size / unit;
+    if size.is_multiple_of(unit) && units < 0xF00 {
+        return (units as u8, (units >> 8) as u8, size);
+    }
+    (0..64u8)
+        .flat_map(|exponent| (0..4u8).map(move |multiplier| (exponent, multiplier)))
+        .filter_map(|(exponent, multiplier)| {
+            let encoded = 1usize
+                .checked_shl(exponent as u32)?
+                .checked_mul(multiplier as usize * 2 + 1)?;
+            Some((exponent << 2 | multiplier, 0x0F, encoded))
+        })
+        .filter(|&(_, _, encoded)| encoded >= size)
+        .min_by_key(|&(_, _, encoded)| encoded)
+        .expect("a Vec is never larger than the exponent form can express")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    // An iNES file with 32KB of PRG-ROM, 8KB of CHR-ROM and the given header bytes 6-15
+    fn file(flags: &[u8; 10]) -> Vec<u8> {
+        let mut data = vec![0; HEADER_SIZE + 0x8000 + 0x2000];
+        data[..6].copy_from_slice(b"NES\x1A\x02\x01");
+        data[6..HEADER_SIZE].copy_from_slice(flags);
+        data
+    }
+
+    #[test]
+    fn trailing_data_is_a_size_mismatch() {
+        let mut data = file(&[0; 10]);
+        data.extend_from_slice(&[0xFF; 128]);
+        assert!(matches!(
+            Rom::from_bytes(&data),
+            Err(RomError::SizeMismatch {
+                expected: 0xA010,
+                actual: 0xA090
+            })
+        ));
+    }
+
+    #[test]
+    fn nes2_files_may_have_miscellaneous_rom() {
+        let mut data = file(&[0, 0x08, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&[0xFF; 128]);
+        let rom = Rom::from_bytes(&data).unwrap();
+        assert_eq!((rom.prg_rom.len(), rom.chr_rom.len()), (0x8000, 0x2000));
+    }
+
+    #[test]
+    fn truncated_chr_rom() {
+        let data = file(&[0; 10]);
+        assert!(matches!(
+            Rom::from_bytes(&data[..0x9000]),
+            Err(RomError::TruncatedFile {
+                section: RomSection::ChrRom,
+                offset: 0x8010,
+                size: 0x2000,
+                file_size: 0x9000
+            })
+        ));
+    }
+
+    #[test]
+    fn nes2_size_high_bits() {
+        // 0x102 * 16KB of PRG-ROM, no CHR-ROM
+        let mut data = vec![0; HEADER_SIZE + 0x102 * 0x4000];
+        data[..6].copy_from_slice(b"NES\x1A\x02\x00");
+        data[7] = 0x08;
+        data[9] = 0x01;
+        let rom = Rom::from_bytes(&data).unwrap();
+        assert_eq!(rom.prg_rom.len(), 0x102 * 0x4000);
+        assert_eq!(
+            Rom::from_bytes(&rom.to_bytes()).unwrap().prg_rom.len(),
+            rom.prg_rom.len()
+        );
+    }
+
+    #[test]
+    fn nes2_exponent_sizes() {
+        // PRG-ROM of 2^3 * 3 = 24 bytes, and an absurd 2^63 * 7 bytes of CHR-ROM
+        let mut data = vec![0; HEADER_SIZE + 24];
+        data[..6].copy_from_slice(b"NES\x1A\x0D\xFF");
+        data[7] = 0x08;
+        data[9] = 0xFF;
+        assert!(matches!(
+            Rom::from_bytes(&data),
+            Err(RomError::TruncatedFile {
+                section: RomSection::ChrRom,
+                offset: 40,
+                size: usize::MAX,
+                ..
+            })
+        ));
+        data[5] = 0;
+        data[9] = 0x0F;
+        assert_eq!(Rom::from_bytes(&data).unwrap().prg_rom.len(), 24);
+    }
+
+    #[test]
+    fn exponent_sizes_round_trip() {
+        // 24KB of PRG-ROM is 1.5 banks, 2^13 * 3 bytes
+        let mut rom = Rom::from_bytes(&file(&[0; 10])).unwrap();
+        rom.prg_rom = (0..0x6000).map(|index| index as u8).collect();
+        rom.chr_rom = vec![0x42; 0x2000];
+        let data = rom.to_bytes();
+        assert_eq!((data[4], data[5], data[9]), (13 << 2 | 1, 1, 0x0F));
+        let read = Rom::from_bytes(&data).unwrap();
+        assert_eq!((&read.prg_rom, &read.chr_rom), (&rom.prg_rom, &rom.chr_rom));
+
+        // 9 bytes can't be expressed, so it is padded to 2^1 * 5
+        rom.chr_rom = vec![0x42; 9];
+        let read = Rom::from_bytes(&rom.to_bytes()).unwrap();
+        assert_eq!(read.chr_rom, [&[0x42; 9][..], &[0]].concat());
+    }
+
+    #[test]
+    fn nes2_mapper_high_bits() {
+        let rom = Rom::from_bytes(&file(&[0x41, 0x08, 0x01, 0, 0, 0, 0, 0, 0, 0])).unwrap();
+        assert_eq!(rom.mapper, 0x104);
+        assert_eq!(Rom::from_bytes(&rom.to_bytes()).unwrap().mapper, 0x104);
+        assert_eq!(
+            crate::Nes::new(&rom)
+                .err()
+                .map(RomError::from)
+                .map(|e| e.to_string()),
+            Some("mapper 260 is not supported".into())
+        );
+        // iNES byte 8 is the PRG-RAM size
+        let rom = Rom::from_bytes(&file(&[0x41, 0x00, 0x01, 0, 0, 0, 0, 0, 0, 0])).unwrap();
+        assert_eq!(rom.mapper, 4);
+    }
+
+    #[test]
+    fn mapper_high_nibble_comes_from_byte_7() {
+        let rom = Rom::from_bytes(&file(&[0x41, 0x10, 0, 0, 0, 0, 0, 0, 0, 0])).unwrap();
+        assert_eq!(rom.mapper, 0x14);
+    }
+
+    #[test]
+    fn diskdude_header_ignores_bytes_after_6() {
+        let mut flags = [0; 10];
+        flags[0] = 0x41;
+        flags[1..].copy_from_slice(b"DiskDude!");
+        let rom = Rom::from_bytes(&file(&flags)).unwrap();
+        assert_eq!(rom.mapper, 4);
+        assert_eq!(rom.submapper, 0);
+        assert_eq!(rom.region, Region::Ntsc);
+        assert_eq!(rom.prg_ram_size, 0x2000);
+        assert!(rom.mirroring == 1 && !rom.battery);
+    }
+}
@@ -0,0 +1,74 @@
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+// The parts of the console that run behind the CPU and are caught up with it in bursts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Device {
+    Ppu,
+    Apu,
+    Cartridge,
+}
+
+const DEVICES: [Device; 3] = [Device::Ppu, Device::Apu, Device::Cartridge];
+
+// No event to wait for
+pub(crate) const NEVER: u64 = u64::MAX;
+
+// Rescheduling leaves the old entries in the queue until they come to the top. Past this many
+// the queue is rebuilt from the current deadlines instead.
+const MAX_QUEUED: usize = 32;
+
+// A priority queue of the CPU cycles at which devices next do something the CPU or another
+// device can see: the PPU setting the vblank flag, the APU raising its frame IRQ, a mapper's
+// counter running out. Until the earliest of them the bus needn't run the devices at all.
+#[derive(Debug)]
+pub(crate) struct Scheduler {
+    queue: BinaryHeap<Reverse<(u64, Device)>>,
+    deadlines: [u64; DEVICES.len()], // The current event for each device, NEVER for none
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            queue: BinaryHeap::new(),
+            deadlines: [NEVER; DEVICES.len()],
+        }
+    }
+
+    // Replace the device's next event with one at `cycle`
+    pub fn schedule(&mut self, device: Device, cycle: u64) {
+        let deadline = &mut self.deadlines[device as usize];
+        if *deadline == cycle {
+            return;
+        }
+        *deadline = cycle;
+        if self.queue.len() >= MAX_QUEUED {
+            let entries: Vec<_> = DEVICES
+                .iter()
+                .map(|&device| Reverse((self.deadlines[device as usize], device)))
+                .filter(|&Reverse((cycle, _))| cycle != NEVER)
+                .collect();
+            self.queue = BinaryHeap::from(entries);
+        } else if cycle != NEVER {
+            self.queue.push(Reverse((cycle, device)));
+        }
+    }
+
+    // The cycle of the earliest event, or NEVER
+    pub fn next(&mut self) -> u64 {
+        while let Some(&Reverse((cycle, device))) = self.queue.peek() {
+            if self.deadlines[device as usize] == cycle {
+                return cycle;
+            }
+            self.queue.pop();
+        }
+        NEVER
+    }
+}
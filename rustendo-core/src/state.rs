@@ -0,0 +1,255 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// Save states start with a header: the magic bytes, the format version (u16) and the CRC32 of
+// the ROM they were saved from (u32). A section per component follows, each a four-byte tag and
+// a u32 length before its data, all little-endian. The data is the component's serde state
+// written with bincode's standard configuration. Sections are found by tag, so their order
+// doesn't matter and ones this version doesn't know are skipped.
+const MAGIC: &[u8; 4] = b"RNSS";
+
+// Bumped whenever a component's state changes shape, since bincode can't tell
+pub const FORMAT_VERSION: u16 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Section {
+    Cpu,       // Registers
+    Bus,       // RAM, DMA, the controller ports and the frame inputs were last applied on
+    Ppu,       // Registers, memories and the rendering pipeline
+    Apu,       // Channels and the frame counter
+    Cartridge, // The mapper's banks, IRQ counters and RAM
+}
+
+impl Section {
+    fn tag(self) -> &'static [u8; 4] {
+        match self {
+            Section::Cpu => b"CPU ",
+            Section::Bus => b"BUS ",
+            Section::Ppu => b"PPU ",
+            Section::Apu => b"APU ",
+            Section::Cartridge => b"CART",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Section::Cpu => "CPU",
+            Section::Bus => "bus",
+            Section::Ppu => "PPU",
+            Section::Apu => "APU",
+            Section::Cartridge => "cartridge",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StateError {
+    NotAState,                    // Doesn't start with the save state header
+    UnsupportedVersion(u16),      // Saved in another format version
+    WrongRom(u32, u32),           // Saved from, being loaded into (ROM CRC32s)
+    Truncated,                    // Ends partway through the header or a section
+    MissingSection(&'static str), // Component name
+    InvalidSection(&'static str), // Doesn't decode, or doesn't fit this console
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::NotAState => write!(f, "not a rustendo save state"),
+            StateError::UnsupportedVersion(version) => write!(
+                f,
+                "saved by another version of the emulator (format {}, this one reads {})",
+                version, FORMAT_VERSION
+            ),
+            StateError::WrongRom(saved, loaded) => write!(
+                f,
+                "saved from a ROM with CRC32 {:08X}, but this one is {:08X}",
+                saved, loaded
+            ),
+            StateError::Truncated => write!(f, "the save state is cut short"),
+            StateError::MissingSection(name) => write!(f, "the {} state is missing", name),
+            StateError::InvalidSection(name) => write!(f, "the {} state is damaged", name),
+        }
+    }
+}
+
+impl core::error::Error for StateError {}
+
+pub(crate) struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn new(rom_crc32: u32) -> Self {
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        data.extend_from_slice(&rom_crc32.to_le_bytes());
+        Self { data }
+    }
+
+    pub(crate) fn section<T: Serialize + ?Sized>(&mut self, section: Section, value: &T) {
+        self.raw_section(section, &encode(value));
+    }
+
+    // For components that encode themselves, like mappers
+    pub(crate) fn raw_section(&mut self, section: Section, data: &[u8]) {
+        self.data.extend_from_slice(section.tag());
+        self.data
+            .extend_from_slice(&(data.len() as u32).to_le_bytes());
+        self.data.extend_from_slice(data);
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub(crate) struct StateReader<'a> {
+    sections: Vec<(&'a [u8], &'a [u8])>, // Tag, data
+}
+
+impl<'a> StateReader<'a> {
+    // Check the header against this format and ROM, and split the rest into sections
+    pub(crate) fn new(data: &'a [u8], rom_crc32: u32) -> Result<Self, StateError> {
+        let mut rest = data;
+        if take(&mut rest, MAGIC.len()) != Some(&MAGIC[..]) {
+            return Err(StateError::NotAState);
+        }
+        let version = take(&mut rest, 2).ok_or(StateError::Truncated)?;
+        let version = u16::from_le_bytes([version[0], version[1]]);
+        if version != FORMAT_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let saved_crc32 = read_u32(&mut rest)?;
+        if saved_crc32 != rom_crc32 {
+            return Err(StateError::WrongRom(saved_crc32, rom_crc32));
+        }
+
+        let mut sections = Vec::new();
+        while !rest.is_empty() {
+            let tag = take(&mut rest, 4).ok_or(StateError::Truncated)?;
+            let length = read_u32(&mut rest)? as usize;
+            let data = take(&mut rest, length).ok_or(StateError::Truncated)?;
+            sections.push((tag, data));
+        }
+        Ok(Self { sections })
+    }
+
+    pub(crate) fn section<T: DeserializeOwned>(&self, section: Section) -> Result<T, StateError> {
+        decode(self.raw_section(section)?).ok_or(StateError::InvalidSection(section.name()))
+    }
+
+    pub(crate) fn raw_section(&self, section: Section) -> Result<&'a [u8], StateError> {
+        self.sections
+            .iter()
+            .find(|(tag, _)| *tag == section.tag())
+            .map(|&(_, data)| data)
+            .ok_or(StateError::MissingSection(section.name()))
+    }
+}
+
+// The next `length` bytes, if there are that many
+fn take<'a>(data: &mut &'a [u8], length: usize) -> Option<&'a [u8]> {
+    if data.len() < length {
+        return None;
+    }
+    let (taken, rest) = data.split_at(length);
+    *data = rest;
+    Some(taken)
+}
+
+fn read_u32(data: &mut &[u8]) -> Result<u32, StateError> {
+    let bytes = take(data, 4).ok_or(StateError::Truncated)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+pub(crate) fn encode<T: Serialize + ?Sized>(value: &T) -> Vec<u8> {
+    // Only a failing Serialize impl can make this fail, and the emulator has none
+    bincode::serde::encode_to_vec(value, bincode::config::standard()).unwrap_or_default()
+}
+
+// None unless `data` holds exactly one `T`
+pub(crate) fn decode<T: DeserializeOwned>(data: &[u8]) -> Option<T> {
+    match bincode::serde::decode_from_slice(data, bincode::config::standard()) {
+        Ok((value, read)) if read == data.len() => Some(value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CRC32: u32 = 0x1234_5678;
+
+    fn state() -> Vec<u8> {
+        let mut state = StateWriter::new(CRC32);
+        state.section(Section::Cpu, &(1u8, 0x8000u16));
+        state.raw_section(Section::Cartridge, &[1, 2, 3]);
+        state.finish()
+    }
+
+    #[test]
+    fn sections_read_back() {
+        let state = state();
+        let reader = StateReader::new(&state, CRC32).unwrap();
+        assert_eq!(reader.section::<(u8, u16)>(Section::Cpu), Ok((1, 0x8000)));
+        assert_eq!(reader.raw_section(Section::Cartridge), Ok(&[1, 2, 3][..]));
+        assert_eq!(
+            reader.raw_section(Section::Ppu),
+            Err(StateError::MissingSection("PPU"))
+        );
+        // A section holding something else, or more than it should, is refused
+        assert_eq!(
+            reader.section::<u8>(Section::Cpu),
+            Err(StateError::InvalidSection("CPU"))
+        );
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let mut state = state();
+        state[4..6].copy_from_slice(&(FORMAT_VERSION - 1).to_le_bytes());
+        assert_eq!(
+            StateReader::new(&state, CRC32).err(),
+            Some(StateError::UnsupportedVersion(FORMAT_VERSION - 1))
+        );
+    }
+
+    #[test]
+    fn other_roms_are_refused() {
+        assert_eq!(
+            StateReader::new(&state(), 0xDEAD_BEEF).err(),
+            Some(StateError::WrongRom(CRC32, 0xDEAD_BEEF))
+        );
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        assert_eq!(
+            StateReader::new(b"NES\x1A\x02\x01", CRC32).err(),
+            Some(StateError::NotAState)
+        );
+        assert_eq!(
+            StateReader::new(b"RNS", CRC32).err(),
+            Some(StateError::NotAState)
+        );
+    }
+
+    #[test]
+    fn truncated_states_are_refused() {
+        let state = state();
+        for length in [5, 9, state.len() - 1] {
+            assert_eq!(
+                StateReader::new(&state[..length], CRC32).err(),
+                Some(StateError::Truncated),
+                "{} bytes",
+                length
+            );
+        }
+    }
+}
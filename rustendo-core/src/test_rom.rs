@@ -0,0 +1,39 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::region::Region;
+use crate::rom::Rom;
+
+// An NROM image for unit tests: 32KB of PRG-ROM with `reset` at $8000 and `nmi` at $C000, the
+// vectors pointing at them, and 8KB of CHR-RAM. The rest of the PRG-ROM is NOPs.
+pub fn nrom_file(reset: &[u8], nmi: &[u8]) -> Vec<u8> {
+    let mut prg = vec![0xEA; 0x8000];
+    prg[..reset.len()].copy_from_slice(reset);
+    prg[0x4000..0x4000 + nmi.len()].copy_from_slice(nmi);
+    prg[0x7FFA..].copy_from_slice(&[0x00, 0xC0, 0x00, 0x80, 0x00, 0xC0]);
+    let mut file = b"NES\x1A\x02\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    file.extend_from_slice(&prg);
+    file
+}
+
+pub fn nrom(reset: &[u8], nmi: &[u8]) -> Rom {
+    Rom::from_bytes(&nrom_file(reset, nmi)).unwrap()
+}
+
+// A cartridge for board tests, with `prg` bytes of PRG-ROM and `chr` of CHR-ROM. Every byte
+// holds the number of the bank it is in, 8KB banks for PRG and 1KB for CHR, so a read shows
+// which bank is mapped there.
+pub fn banked(mapper: u16, prg: usize, chr: usize) -> Rom {
+    Rom {
+        prg_rom: (0..prg).map(|index| (index / 0x2000) as u8).collect(),
+        chr_rom: (0..chr).map(|index| (index / 0x400) as u8).collect(),
+        trainer: Vec::new(),
+        mapper,
+        submapper: 0,
+        mirroring: 0,
+        battery: false,
+        prg_ram_size: 0x2000,
+        four_screen: false,
+        region: Region::Ntsc,
+    }
+}
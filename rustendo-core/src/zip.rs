@@ -0,0 +1,259 @@
+use alloc::vec::Vec;
+use core::fmt;
+use miniz_oxide::inflate;
+
+use crate::checksum::crc32;
+
+const LOCAL_HEADER: u32 = 0x0403_4B50;
+const CENTRAL_HEADER: u32 = 0x0201_4B50;
+const END_OF_DIRECTORY: u32 = 0x0605_4B50;
+const END_OF_DIRECTORY_SIZE: usize = 22;
+
+// Compression methods
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZipError {
+    Corrupt,               // The directory or an entry runs off the end, or a header is wrong
+    NoRom,                 // No .nes file in the archive
+    Unsupported(u16),      // Compressed some other way than deflate
+    Encrypted,             // Needs a password
+    ChecksumMismatch(u32), // The CRC-32 the ROM actually has
+}
+
+impl fmt::Display for ZipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ZipError::Corrupt => write!(f, "the zip file is corrupt"),
+            ZipError::NoRom => write!(f, "the zip file has no .nes file in it"),
+            ZipError::Unsupported(method) => {
+                write!(
+                    f,
+                    "the ROM is compressed with an unsupported method ({})",
+                    method
+                )
+            }
+            ZipError::Encrypted => write!(f, "the ROM in the zip file is encrypted"),
+            ZipError::ChecksumMismatch(crc) => {
+                write!(f, "the ROM in the zip file is corrupt (CRC32 {:08X})", crc)
+            }
+        }
+    }
+}
+
+impl core::error::Error for ZipError {}
+
+// Whether `data` looks like a zip file rather than a ROM
+pub fn is_zip(data: &[u8]) -> bool {
+    data.starts_with(&LOCAL_HEADER.to_le_bytes())
+}
+
+// The first .nes file in the archive, by the order of its directory
+pub fn extract_rom(data: &[u8]) -> Result<Vec<u8>, ZipError> {
+    // The end of directory record is last, followed by a comment of up to 64KB
+    let end = (0..=data.len().saturating_sub(END_OF_DIRECTORY_SIZE))
+        .rev()
+        .take(u16::MAX as usize + 1)
+        .find(|&offset| u32_at(data, offset) == Some(END_OF_DIRECTORY))
+        .ok_or(ZipError::Corrupt)?;
+    let entries = u16_at(data, end + 10).ok_or(ZipError::Corrupt)?;
+    let mut offset = u32_at(data, end + 16).ok_or(ZipError::Corrupt)? as usize;
+
+    for _ in 0..entries {
+        let field = |at: usize| u16_at(data, offset + at).ok_or(ZipError::Corrupt);
+        if u32_at(data, offset) != Some(CENTRAL_HEADER) {
+            return Err(ZipError::Corrupt);
+        }
+        let (flags, method) = (field(8)?, field(10)?);
+        let crc = u32_at(data, offset + 16).ok_or(ZipError::Corrupt)?;
+        let compressed_size = u32_at(data, offset + 20).ok_or(ZipError::Corrupt)? as usize;
+        let size = u32_at(data, offset + 24).ok_or(ZipError::Corrupt)? as usize;
+        let name_length = field(28)? as usize;
+        let header_length = 46 + name_length + field(30)? as usize + field(32)? as usize;
+        let local_header = u32_at(data, offset + 42).ok_or(ZipError::Corrupt)? as usize;
+        let name = data
+            .get(offset + 46..offset + 46 + name_length)
+            .ok_or(ZipError::Corrupt)?;
+        offset += header_length;
+        if !name.to_ascii_lowercase().ends_with(b".nes") {
+            continue;
+        }
+
+        if flags & 0x01 != 0 {
+            return Err(ZipError::Encrypted);
+        }
+        if u32_at(data, local_header) != Some(LOCAL_HEADER) {
+            return Err(ZipError::Corrupt);
+        }
+        let local_field = |at: usize| u16_at(data, local_header + at).ok_or(ZipError::Corrupt);
+        let start = local_header + 30 + local_field(26)? as usize + local_field(28)? as usize;
+        let contents = data
+            .get(start..start + compressed_size)
+            .ok_or(ZipError::Corrupt)?;
+        let rom = match method {
+            STORED => contents.to_vec(),
+            DEFLATED => inflate::decompress_to_vec_with_limit(contents, size)
+                .map_err(|_| ZipError::Corrupt)?,
+            _ => return Err(ZipError::Unsupported(method)),
+        };
+        let actual = crc32(&rom);
+        if actual != crc || rom.len() != size {
+            return Err(ZipError::ChecksumMismatch(actual));
+        }
+        return Ok(rom);
+    }
+    Err(ZipError::NoRom)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use miniz_oxide::deflate::compress_to_vec;
+
+    use super::*;
+
+    struct Entry<'a> {
+        name: &'a str,
+        method: u16,
+        flags: u16,
+        contents: &'a [u8],
+    }
+
+    fn entry<'a>(name: &'a str, contents: &'a [u8]) -> Entry<'a> {
+        Entry {
+            name,
+            method: DEFLATED,
+            flags: 0,
+            contents,
+        }
+    }
+
+    // The local headers and data, then the central directory and its end record
+    fn zip(entries: &[Entry]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut directory = Vec::new();
+        for entry in entries {
+            let stored = match entry.method {
+                DEFLATED => compress_to_vec(entry.contents, 6),
+                _ => entry.contents.to_vec(),
+            };
+            let sizes = [
+                crc32(entry.contents),
+                stored.len() as u32,
+                entry.contents.len() as u32,
+            ];
+            let offset = data.len() as u32;
+            data.extend(LOCAL_HEADER.to_le_bytes());
+            data.extend([20, 0]);
+            data.extend(entry.flags.to_le_bytes());
+            data.extend(entry.method.to_le_bytes());
+            data.extend([0; 4]);
+            sizes
+                .iter()
+                .for_each(|value| data.extend(value.to_le_bytes()));
+            data.extend((entry.name.len() as u16).to_le_bytes());
+            data.extend([0, 0]);
+            data.extend(entry.name.as_bytes());
+            data.extend(&stored);
+
+            directory.extend(CENTRAL_HEADER.to_le_bytes());
+            directory.extend([20, 0, 20, 0]);
+            directory.extend(entry.flags.to_le_bytes());
+            directory.extend(entry.method.to_le_bytes());
+            directory.extend([0; 4]);
+            sizes
+                .iter()
+                .for_each(|value| directory.extend(value.to_le_bytes()));
+            directory.extend((entry.name.len() as u16).to_le_bytes());
+            directory.extend([0; 12]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(entry.name.as_bytes());
+        }
+        let directory_offset = data.len() as u32;
+        data.extend(&directory);
+        data.extend(END_OF_DIRECTORY.to_le_bytes());
+        data.extend([0; 4]);
+        data.extend((entries.len() as u16).to_le_bytes());
+        data.extend((entries.len() as u16).to_le_bytes());
+        data.extend((directory.len() as u32).to_le_bytes());
+        data.extend(directory_offset.to_le_bytes());
+        data.extend(5u16.to_le_bytes());
+        data.extend(b"Hello");
+        data
+    }
+
+    fn rom() -> Vec<u8> {
+        (0..0x6010).map(|index| (index % 251) as u8).collect()
+    }
+
+    #[test]
+    fn first_rom_in_the_archive() {
+        let rom = rom();
+        let archive = zip(&[
+            entry("readme.txt", b"Not a ROM"),
+            entry("Game.NES", &rom),
+            entry("other.nes", b"NES\x1A"),
+        ]);
+        assert!(is_zip(&archive));
+        assert!(!is_zip(b"NES\x1A"));
+        assert_eq!(extract_rom(&archive), Ok(rom));
+    }
+
+    #[test]
+    fn stored_roms() {
+        let rom = rom();
+        let mut stored = entry("game.nes", &rom);
+        stored.method = STORED;
+        assert_eq!(extract_rom(&zip(&[stored])), Ok(rom));
+    }
+
+    #[test]
+    fn archives_it_cant_read() {
+        assert_eq!(
+            extract_rom(&zip(&[entry("readme.txt", b"")])),
+            Err(ZipError::NoRom)
+        );
+        assert_eq!(extract_rom(b"PK\x03\x04"), Err(ZipError::Corrupt));
+
+        let rom = rom();
+        let mut encrypted = entry("game.nes", &rom);
+        encrypted.flags = 0x01;
+        assert_eq!(extract_rom(&zip(&[encrypted])), Err(ZipError::Encrypted));
+        let mut bzip2 = entry("game.nes", &rom);
+        bzip2.method = 12;
+        assert_eq!(extract_rom(&zip(&[bzip2])), Err(ZipError::Unsupported(12)));
+
+        // The directory points past the end of the file
+        let mut archive = zip(&[entry("game.nes", &rom)]);
+        let length = archive.len();
+        archive[length - 11..length - 7].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(extract_rom(&archive), Err(ZipError::Corrupt));
+    }
+
+    #[test]
+    fn corrupt_roms() {
+        let mut rom = vec![0x55; 0x100];
+        let mut stored = entry("game.nes", &rom);
+        stored.method = STORED;
+        let mut archive = zip(&[stored]);
+        // Flip a byte of the stored contents, after the 30-byte header and the name
+        archive[30 + 8] ^= 0xFF;
+        rom[0] ^= 0xFF;
+        assert_eq!(
+            extract_rom(&archive),
+            Err(ZipError::ChecksumMismatch(crc32(&rom)))
+        );
+    }
+}
@@ -0,0 +1,45 @@
+// Boots the homebrew ROMs in `roms/` from their reset vectors and checks the frame they draw
+// against hashes recorded from a known-good build. Unlike the programs in golden_frames.rs,
+// these set their boards up, switch banks and take NMIs and IRQs, so they cover the CPU's
+// interrupts and the mappers along with the PPU. `roms/build.py` assembles them; a change to a
+// ROM or one that is meant to alter a picture should update its hash in the same commit.
+use rustendo_core::{Nes, Rom};
+
+const FRAMES: u64 = 30;
+
+fn check(image: &[u8], expected: u64) {
+    let rom = Rom::from_bytes(image).expect("the test ROM should load");
+    let mut nes = Nes::new(&rom).expect("the test ROM's board is supported");
+    while nes.frame() < FRAMES {
+        nes.step_frame();
+    }
+    let hash = nes.frame_hash();
+    assert_eq!(
+        hash, expected,
+        "frame {} hashed to {:016X}, expected {:016X}",
+        FRAMES, hash, expected
+    );
+}
+
+#[test]
+fn nrom() {
+    check(include_bytes!("roms/nrom.nes"), 0x0560_5550_4BD7_9249);
+}
+
+// CHR-RAM filled from one switched bank and the data read from another
+#[test]
+fn unrom512() {
+    check(include_bytes!("roms/unrom512.nes"), 0x9FEF_24C1_940B_43F7);
+}
+
+// Registers written through the serial port, with 4KB CHR banks
+#[test]
+fn mmc1() {
+    check(include_bytes!("roms/mmc1.nes"), 0xFDD6_646D_4F82_E420);
+}
+
+// A scanline IRQ partway down that switches the background's CHR banks
+#[test]
+fn mmc3() {
+    check(include_bytes!("roms/mmc3.nes"), 0x1AE1_8AB6_7683_3EA5);
+}
@@ -0,0 +1,288 @@
+#!/usr/bin/env python3
+# Builds the homebrew ROMs the golden frame tests boot. Each one starts from its reset vector,
+# waits for the PPU to warm up, sets its board up, loads the palette, nametable and sprites from
+# a switched PRG bank and scrolls the picture from its NMI handler. Run it from this directory
+# and update the hashes in ../golden_roms.rs if a ROM changes.
+
+OPCODES = {
+    'adc#': 0x69, 'bit': 0x2C, 'bne': 0xD0, 'bpl': 0x10, 'clc': 0x18, 'cld': 0xD8, 'cli': 0x58,
+    'cpx#': 0xE0, 'inx': 0xE8, 'jmp': 0x4C, 'lda#': 0xA9, 'lda': 0xAD, 'lda,x': 0xBD,
+    'ldx#': 0xA2, 'rti': 0x40, 'sei': 0x78, 'sta': 0x8D, 'sta,x': 0x9D, 'stx': 0x8E, 'txs': 0x9A,
+}
+
+
+# Just enough of an assembler for these programs: one 8KB or 16KB bank at a time, with labels
+class Bank:
+    def __init__(self, origin, size):
+        self.origin, self.size = origin, size
+        self.code = bytearray()
+        self.labels, self.fixups = {}, []
+
+    def here(self):
+        return self.origin + len(self.code)
+
+    def label(self, name):
+        self.labels[name] = self.here()
+
+    def op(self, name, operand=None):
+        self.code.append(OPCODES[name])
+        if name in ('bne', 'bpl'):
+            self.fixups.append((len(self.code), operand, True))
+            self.code.append(0)
+        elif isinstance(operand, str):
+            self.fixups.append((len(self.code), operand, False))
+            self.code.extend([0, 0])
+        elif operand is not None and name.endswith('#'):
+            self.code.append(operand)
+        elif operand is not None:
+            self.code.extend([operand & 0xFF, operand >> 8])
+
+    def write(self, address, value):
+        self.op('lda#', value)
+        self.op('sta', address)
+
+    def data(self, name, values):
+        self.label(name)
+        self.code.extend(values)
+
+    def build(self, vectors=None):
+        for offset, name, relative in self.fixups:
+            target = self.labels[name]
+            if relative:
+                delta = target - (self.origin + offset + 1)
+                assert -128 <= delta < 128, name
+                self.code[offset] = delta & 0xFF
+            else:
+                self.code[offset:offset + 2] = bytes([target & 0xFF, target >> 8])
+        image = self.code + bytes([0xEA] * (self.size - len(self.code)))
+        if vectors:
+            words = [self.labels[name] for name in ('nmi', 'reset', 'irq')]
+            image[-6:] = b''.join(word.to_bytes(2, 'little') for word in words)
+        return bytes(image)
+
+
+PALETTE = [
+    0x0F, 0x16, 0x1A, 0x30, 0x0F, 0x12, 0x2A, 0x27, 0x0F, 0x06, 0x19, 0x3C, 0x0F, 0x01, 0x11, 0x21,
+    0x0F, 0x28, 0x38, 0x30, 0x0F, 0x14, 0x24, 0x34, 0x0F, 0x0A, 0x1A, 0x2A, 0x0F, 0x00, 0x10, 0x20,
+]
+
+# Y, tile, attributes, X for eight sprites, the rest hidden below the picture
+SPRITES = [
+    40, 1, 0x00, 40, 40, 2, 0x01, 60, 40, 3, 0x02, 80, 40, 4, 0x03, 100,
+    140, 5, 0x40, 40, 140, 6, 0x81, 60, 140, 7, 0xC2, 80, 140, 8, 0x23, 100,
+] + [0xF0] * 224
+
+
+# The palette, sprites and attributes, at the start of a bank. The other banks are filled with
+# their own number, so reading the wrong one shows in the picture.
+def data_bank(origin, size):
+    bank = Bank(origin, size)
+    bank.data('palette', PALETTE)
+    bank.data('sprites', SPRITES)
+    bank.data('attributes', [(i * 0x1B) & 0xFF for i in range(64)])
+    return bank
+
+
+# Tile patterns that differ from one CHR bank to the next
+def chr_rom(banks, bank_size):
+    chr = bytearray()
+    for bank in range(banks):
+        for tile in range(bank_size // 16):
+            low = [0xFF if row in (0, 7) else 0x81 | 0x80 >> (row + bank) % 8 for row in range(8)]
+            high = [(tile ^ bank * 37) & 0xFF if row % 2 == 0 else 0 for row in range(8)]
+            chr += bytes(low + high)
+    return bytes(chr)
+
+
+def wait_vblank(bank, name):
+    bank.label(name)
+    bank.op('bit', 0x2002)
+    bank.op('bpl', name)
+
+
+# Copy `count` bytes from a table to $2007 or to RAM
+def copy(bank, name, table, count, to=0x2007):
+    bank.op('ldx#', 0)
+    bank.label(name)
+    bank.op('lda,x', table)
+    bank.op('sta,x' if to != 0x2007 else 'sta', to)
+    bank.op('inx')
+    if count < 256:
+        bank.op('cpx#', count)
+    bank.op('bne', name)
+
+
+# Everything after the board is set up: the palette, a nametable of tiles 0-255 repeated, its
+# attributes and the sprites, then rendering on and NMIs with sprite patterns at `sprite_table`.
+# Enabling NMIs in vblank raises one after the next instruction, and the handler doesn't save A,
+# so rendering goes on first.
+def draw(bank, data, ctrl=0x80):
+    bank.write(0x2006, 0x3F)
+    bank.write(0x2006, 0x00)
+    copy(bank, 'copy_palette', data.labels['palette'], 32)
+    bank.write(0x2006, 0x20)
+    bank.write(0x2006, 0x00)
+    for page in range(3):
+        bank.op('ldx#', 0)
+        bank.label('tiles%d' % page)
+        bank.op('stx', 0x2007)
+        bank.op('inx')
+        bank.op('bne', 'tiles%d' % page)
+    bank.op('ldx#', 0)
+    bank.label('tiles3')
+    bank.op('stx', 0x2007)
+    bank.op('inx')
+    bank.op('cpx#', 0xC0)
+    bank.op('bne', 'tiles3')
+    copy(bank, 'copy_attributes', data.labels['attributes'], 64)
+    copy(bank, 'copy_sprites', data.labels['sprites'], 256, to=0x0200)
+    bank.write(0x2005, 0)
+    bank.write(0x2005, 0)
+    bank.write(0x2001, 0x1E)
+    bank.write(0x2000, ctrl)
+
+
+# Sprites by DMA and the picture scrolled one more pixel each frame
+def nmi(bank, ctrl=0x80):
+    bank.label('nmi')
+    bank.write(0x4014, 0x02)
+    bank.op('lda', 0x0300)
+    bank.op('clc')
+    bank.op('adc#', 1)
+    bank.op('sta', 0x0300)
+    bank.op('sta', 0x2005)
+    bank.write(0x2005, 0)
+    bank.write(0x2000, ctrl)
+
+
+def header(mapper, prg, chr, flags6=0x01):
+    return bytes([0x4E, 0x45, 0x53, 0x1A, prg // 0x4000, chr // 0x2000,
+                  flags6 | (mapper & 0x0F) << 4, mapper & 0xF0]) + bytes(8)
+
+
+# The usual start: interrupts off, the stack set up and the APU's frame IRQ turned off, as
+# only the MMC3 ROM handles IRQs
+def start(bank):
+    bank.label('reset')
+    bank.op('sei')
+    bank.op('cld')
+    bank.op('ldx#', 0xFF)
+    bank.op('txs')
+    bank.write(0x4017, 0x40)
+    wait_vblank(bank, 'warm_up1')
+    wait_vblank(bank, 'warm_up2')
+
+
+def finish(bank):
+    bank.label('main')
+    bank.op('jmp', 'main')
+
+
+# NROM-256: 32KB of PRG and 8KB of CHR, with nothing to switch
+def nrom():
+    bank = data_bank(0x8000, 0x8000)
+    start(bank)
+    draw(bank, bank, ctrl=0x88)
+    finish(bank)
+    nmi(bank, ctrl=0x88)
+    bank.op('rti')
+    bank.label('irq')
+    bank.op('rti')
+    return header(0, 0x8000, 0x2000) + bank.build(vectors=True) + chr_rom(2, 0x1000)
+
+
+# UNROM-512: the tiles are copied to CHR-RAM from bank 2 and the data read from bank 1. Banks
+# are switched through a table that holds its own values, to avoid bus conflicts.
+def unrom512():
+    fixed = Bank(0xC000, 0x4000)
+    start(fixed)
+    fixed.write(0xFFE0 + 2, 2)
+    fixed.write(0x2006, 0x00)
+    fixed.write(0x2006, 0x00)
+    for page in range(32):
+        copy(fixed, 'copy_chr%d' % page, 0x8000 + page * 0x100, 256)
+    data = data_bank(0x8000, 0x4000)
+    fixed.write(0xFFE0 + 1, 1)
+    draw(fixed, data)
+    finish(fixed)
+    nmi(fixed)
+    fixed.op('rti')
+    fixed.label('irq')
+    fixed.op('rti')
+    fixed.code += bytes(0x3FE0 - len(fixed.code))
+    fixed.code += bytes(range(16))
+    banks = [bytes([0] * 0x4000), data.build(), chr_rom(4, 0x1000)]
+    banks += [bytes([bank] * 0x4000) for bank in range(3, 7)]
+    return header(30, 0x20000, 0) + b''.join(banks) + fixed.build(vectors=True)
+
+
+# MMC1 (SNROM without the RAM): the data in 16KB bank 3 at $8000, with the last bank fixed at
+# $C000, and 4KB CHR banks 2 and 5 for the background and sprites
+def mmc1():
+    def register(bank, address, value):
+        for bit in range(5):
+            bank.write(address, value >> bit & 0x01)
+
+    fixed = Bank(0xC000, 0x4000)
+    start(fixed)
+    fixed.write(0x8000, 0x80)
+    register(fixed, 0x8000, 0x1E)
+    register(fixed, 0xA000, 2)
+    register(fixed, 0xC000, 5)
+    register(fixed, 0xE000, 3)
+    data = data_bank(0x8000, 0x4000)
+    draw(fixed, data, ctrl=0x88)
+    finish(fixed)
+    nmi(fixed, ctrl=0x88)
+    fixed.op('rti')
+    fixed.label('irq')
+    fixed.op('rti')
+    banks = [bytes([bank] * 0x4000) for bank in range(7)]
+    banks[3] = data.build()
+    return header(1, 0x20000, 0x8000) + b''.join(banks) + fixed.build(vectors=True) \
+        + chr_rom(8, 0x1000)
+
+
+# MMC3: the data in 8KB bank 5 at $8000, and an IRQ 120 scanlines down that switches the
+# background to other CHR banks, so the lower part of the picture has other tiles
+def mmc3():
+    fixed = Bank(0xE000, 0x2000)
+    start(fixed)
+    for register, value in [(0, 0), (1, 2), (2, 16), (3, 17), (4, 18), (5, 19), (6, 5), (7, 6)]:
+        fixed.write(0x8000, register)
+        fixed.write(0x8001, value)
+    fixed.write(0xA000, 0x00)
+    data = data_bank(0x8000, 0x2000)
+    draw(fixed, data, ctrl=0x88)
+    fixed.op('cli')
+    finish(fixed)
+    nmi(fixed, ctrl=0x88)
+    fixed.write(0x8000, 0)
+    fixed.write(0x8001, 0)
+    fixed.write(0x8000, 1)
+    fixed.write(0x8001, 2)
+    fixed.write(0xC000, 119)
+    fixed.write(0xC001, 0)
+    fixed.write(0xE001, 0)
+    fixed.op('rti')
+    fixed.label('irq')
+    fixed.write(0xE000, 0)
+    fixed.write(0x8000, 0)
+    fixed.write(0x8001, 8)
+    fixed.write(0x8000, 1)
+    fixed.write(0x8001, 10)
+    fixed.op('rti')
+    banks = [bytes([bank] * 0x2000) for bank in range(15)]
+    banks[5] = data.build()
+    # The banks the IRQ switches in have solid tiles, so the split is plain to see
+    chr = bytearray(chr_rom(128, 0x400))
+    chr[8 * 0x400:12 * 0x400] = bytes([0xFF] * 8 + [0x00] * 8) * 256
+    return header(4, 0x20000, 0x20000) + b''.join(banks) + fixed.build(vectors=True) + chr
+
+
+ROMS = {'nrom.nes': nrom, 'unrom512.nes': unrom512, 'mmc1.nes': mmc1, 'mmc3.nes': mmc3}
+
+if __name__ == '__main__':
+    for name, build in ROMS.items():
+        with open(name, 'wb') as file:
+            file.write(build())
@@ -0,0 +1,102 @@
+use std::error::Error;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{BufferSize, Device, FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use rtrb::{Consumer, Producer, RingBuffer};
+
+use crate::config::AudioConfig;
+
+// How many device buffers worth of samples the queue holds. Samples that don't fit are dropped
+// rather than waited on; the frame pacer keeps emulation at real time, not the device.
+const QUEUED_BUFFERS: usize = 3;
+
+// Plays mono samples from the APU on an output device. The device pulls from a lock-free ring
+// buffer on its own thread and plays silence whenever the emulator falls behind, so neither
+// side ever waits for the other.
+pub struct AudioOutput {
+    _stream: Stream,
+    queue: Producer<f32>,
+    sample_rate: u32,
+}
+
+impl AudioOutput {
+    pub fn open(config: &AudioConfig) -> Result<Self, Box<dyn Error>> {
+        let host = cpal::default_host();
+        let device = match &config.device {
+            Some(name) => host
+                .output_devices()?
+                .find(|device| device.name().is_ok_and(|device_name| &device_name == name))
+                .ok_or_else(|| format!("audio device \"{}\" not found", name))?,
+            None => host
+                .default_output_device()
+                .ok_or("no default audio device")?,
+        };
+
+        let supported = device.default_output_config()?;
+        let format = supported.sample_format();
+        let mut stream_config = supported.config();
+        stream_config.buffer_size = BufferSize::Fixed(config.buffer_size);
+
+        let (queue, samples) = RingBuffer::new(config.buffer_size as usize * QUEUED_BUFFERS);
+        let stream = match format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &stream_config, samples)?,
+            SampleFormat::I16 => build_stream::<i16>(&device, &stream_config, samples)?,
+            SampleFormat::U16 => build_stream::<u16>(&device, &stream_config, samples)?,
+            format => return Err(format!("unsupported sample format {}", format).into()),
+        };
+        stream.play()?;
+
+        Ok(Self {
+            _stream: stream,
+            queue,
+            sample_rate: stream_config.sample_rate.0,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // Queue samples for playback, dropping any the queue has no room for (when the device
+    // stalls)
+    pub fn push(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            if self.queue.push(sample).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+// The names of the output devices, as `AudioConfig::device` takes them. Empty if the host can't
+// list them.
+pub fn device_names() -> Vec<String> {
+    let host = cpal::default_host();
+    match host.output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn build_stream<T>(
+    device: &Device,
+    config: &StreamConfig,
+    mut samples: Consumer<f32>,
+) -> Result<Stream, Box<dyn Error>>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            for frame in data.chunks_mut(channels) {
+                let sample = samples.pop().unwrap_or(0.0);
+                frame.fill(T::from_sample(sample));
+            }
+        },
+        |e| eprintln!("Audio stream error: {}", e),
+        None,
+    )?;
+    Ok(stream)
+}
@@ -0,0 +1,430 @@
+use std::path::PathBuf;
+
+use clap::{ArgGroup, Args, Parser, Subcommand};
+
+use crate::config::RegionConfig;
+use crate::Options;
+
+// Without a subcommand the arguments are those of `run`, so `rustendo-sdl game.nes` plays a
+// game as it always has
+#[derive(Parser)]
+#[command(
+    name = "rustendo-sdl",
+    version,
+    about = "NES emulator",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Play a game (the default), or run it headless or as a benchmark")]
+    Run(RunArgs),
+    #[command(about = "Run a game headless and print a hash of its last frame")]
+    Test(TestArgs),
+    #[command(about = "Play a game in a window while recording its sound, picture or input")]
+    Record(RecordArgs),
+}
+
+// Settings for any run of a game
+#[derive(Args)]
+struct GameArgs {
+    #[arg(
+        long,
+        value_name = "FILE.PAL",
+        help = "Colours to use instead of the built-in palette"
+    )]
+    palette: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "GAMES.DB",
+        help = "Game database to correct headers with"
+    )]
+    db: Option<PathBuf>,
+    #[arg(long, value_name = "FILE.IPS|BPS", help = "Patch to apply to the ROM")]
+    patch: Option<PathBuf>,
+    #[arg(long, help = "Region to run as, instead of the header's")]
+    region: Option<RegionConfig>,
+    #[arg(
+        long = "cheat",
+        value_name = "CODE",
+        help = "Game Genie or raw cheat code"
+    )]
+    cheats: Vec<String>,
+    #[arg(
+        long = "map",
+        value_name = "BINDING",
+        help = "Key binding, e.g. player1.a=Space"
+    )]
+    mappings: Vec<String>,
+    #[arg(long, help = "Blur and fringe the picture like a composite signal")]
+    ntsc: bool,
+    #[arg(long, help = "Show the rows and columns TVs hid")]
+    no_overscan: bool,
+    #[arg(
+        long,
+        value_name = "FILTER",
+        help = "Log filter in RUST_LOG syntax, e.g. cpu=trace"
+    )]
+    log: Option<String>,
+}
+
+// Settings for the window
+#[derive(Args)]
+struct WindowArgs {
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Window size as a multiple of the picture"
+    )]
+    scale: Option<u32>,
+    #[arg(long, help = "Cover the screen")]
+    fullscreen: bool,
+    #[arg(long, help = "Run without opening the sound device")]
+    no_audio: bool,
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("limit").args(["frames", "cycles"]).multiple(true)))]
+#[command(group(ArgGroup::new("mode").args(["headless", "bench"])))]
+struct RunArgs {
+    #[arg(
+        value_name = "ROM",
+        required_unless_present = "setup",
+        help = "The .nes file, or a zip holding one, relative to here or to the ROM directory"
+    )]
+    rom: Option<PathBuf>,
+    #[arg(long, help = "Write a config file, asking for the key bindings")]
+    setup: bool,
+    #[command(flatten)]
+    game: GameArgs,
+    #[command(flatten)]
+    window: WindowArgs,
+    #[arg(long, value_name = "OUT.WAV", help = "Record the sound")]
+    record_wav: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "record_wav",
+        help = "Also record each channel on its own"
+    )]
+    record_stems: bool,
+    #[arg(long, value_name = "OUT.MP4|PNG", help = "Record the picture")]
+    record_video: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "OUT.TXT",
+        conflicts_with = "play_input",
+        help = "Record the controllers"
+    )]
+    record_input: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "IN.TXT",
+        help = "Play recorded controller input back"
+    )]
+    play_input: Option<PathBuf>,
+    // A headless run needs somewhere to stop, and the limits mean nothing in a window
+    #[arg(
+        long,
+        requires = "limit",
+        help = "Run without a window or sound, as fast as possible"
+    )]
+    headless: bool,
+    // A benchmark runs a number of frames, and only runs them
+    #[arg(
+        long,
+        requires = "frames",
+        conflicts_with_all = ["headless", "cycles"],
+        help = "Time the core over a number of frames"
+    )]
+    bench: bool,
+    #[arg(long, value_name = "N", requires = "mode", help = "Frames to run")]
+    frames: Option<u64>,
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "headless",
+        help = "CPU cycles to run"
+    )]
+    cycles: Option<u64>,
+    #[arg(
+        long,
+        value_name = "OUT.PNG",
+        requires = "headless",
+        help = "Write the last frame"
+    )]
+    screenshot: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "OUT.Y4M",
+        requires = "headless",
+        help = "Write every frame, uncompressed"
+    )]
+    dump_video: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "OUT.PCM",
+        requires = "headless",
+        help = "Write the sound as raw PCM"
+    )]
+    dump_audio: Option<PathBuf>,
+    // Netplay is played in a window from power-on, and the host picks the delay
+    #[arg(
+        long,
+        value_name = "PORT",
+        conflicts_with_all = ["netplay_connect", "headless", "play_input"],
+        help = "Wait for a second player on this UDP port"
+    )]
+    netplay_host: Option<u16>,
+    #[arg(
+        long,
+        value_name = "HOST:PORT",
+        conflicts_with_all = ["headless", "play_input"],
+        help = "Join a netplay host"
+    )]
+    netplay_connect: Option<String>,
+    #[arg(
+        long,
+        value_name = "FRAMES",
+        requires = "netplay_host",
+        help = "Input delay for netplay"
+    )]
+    netplay_delay: Option<u8>,
+}
+
+#[derive(Args)]
+#[command(group(
+    ArgGroup::new("limit").args(["frames", "cycles"]).multiple(true).required(true)
+))]
+struct TestArgs {
+    #[arg(value_name = "ROM", help = "The .nes file, or a zip holding one")]
+    rom: PathBuf,
+    #[command(flatten)]
+    game: GameArgs,
+    #[arg(long, value_name = "N", help = "Frames to run")]
+    frames: Option<u64>,
+    #[arg(long, value_name = "N", help = "CPU cycles to run")]
+    cycles: Option<u64>,
+    #[arg(
+        long,
+        value_name = "IN.TXT",
+        help = "Play recorded controller input back"
+    )]
+    play_input: Option<PathBuf>,
+    #[arg(long, value_name = "OUT.PNG", help = "Write the last frame")]
+    screenshot: Option<PathBuf>,
+    #[arg(long, value_name = "OUT.Y4M", help = "Write every frame, uncompressed")]
+    dump_video: Option<PathBuf>,
+    #[arg(long, value_name = "OUT.PCM", help = "Write the sound as raw PCM")]
+    dump_audio: Option<PathBuf>,
+}
+
+#[derive(Args)]
+#[command(group(
+    ArgGroup::new("output").args(["wav", "video", "input"]).multiple(true).required(true)
+))]
+struct RecordArgs {
+    #[arg(value_name = "ROM", help = "The .nes file, or a zip holding one")]
+    rom: PathBuf,
+    #[command(flatten)]
+    game: GameArgs,
+    #[command(flatten)]
+    window: WindowArgs,
+    #[arg(long, value_name = "OUT.WAV", help = "Record the sound")]
+    wav: Option<PathBuf>,
+    #[arg(long, requires = "wav", help = "Also record each channel on its own")]
+    stems: bool,
+    #[arg(long, value_name = "OUT.MP4|PNG", help = "Record the picture")]
+    video: Option<PathBuf>,
+    #[arg(long, value_name = "OUT.TXT", help = "Record the controllers")]
+    input: Option<PathBuf>,
+}
+
+impl GameArgs {
+    fn options(self, rom: Option<PathBuf>) -> Options {
+        Options {
+            rom_path: rom,
+            palette: self.palette,
+            game_db: self.db,
+            patch: self.patch,
+            region: self.region.map(Into::into),
+            cheats: self.cheats,
+            mappings: self.mappings,
+            ntsc: self.ntsc,
+            no_overscan: self.no_overscan,
+            log: self.log,
+            ..Options::default()
+        }
+    }
+}
+
+impl WindowArgs {
+    fn apply(self, options: &mut Options) {
+        options.scale = self.scale;
+        options.fullscreen = self.fullscreen;
+        // Builds without the feature have no sound to turn off
+        #[cfg(feature = "audio")]
+        {
+            options.no_audio = self.no_audio;
+        }
+    }
+}
+
+impl RunArgs {
+    fn options(self) -> Options {
+        let mut options = self.game.options(self.rom);
+        self.window.apply(&mut options);
+        // Headless builds have no netplay to delay
+        #[cfg(any(feature = "video", feature = "winit"))]
+        {
+            options.netplay_delay = self.netplay_delay;
+        }
+        Options {
+            setup: self.setup,
+            record_wav: self.record_wav,
+            record_stems: self.record_stems,
+            record_video: self.record_video,
+            record_input: self.record_input,
+            play_input: self.play_input,
+            headless: self.headless,
+            bench: self.bench,
+            frames: self.frames,
+            cycles: self.cycles,
+            screenshot: self.screenshot,
+            dump_video: self.dump_video,
+            dump_audio: self.dump_audio,
+            netplay_host: self.netplay_host,
+            netplay_connect: self.netplay_connect,
+            ..options
+        }
+    }
+}
+
+impl TestArgs {
+    fn options(self) -> Options {
+        Options {
+            headless: true,
+            frames: self.frames,
+            cycles: self.cycles,
+            play_input: self.play_input,
+            screenshot: self.screenshot,
+            dump_video: self.dump_video,
+            dump_audio: self.dump_audio,
+            ..self.game.options(Some(self.rom))
+        }
+    }
+}
+
+impl RecordArgs {
+    fn options(self) -> Options {
+        let mut options = self.game.options(Some(self.rom));
+        self.window.apply(&mut options);
+        Options {
+            record_wav: self.wav,
+            record_stems: self.stems,
+            record_video: self.video,
+            record_input: self.input,
+            ..options
+        }
+    }
+}
+
+impl Cli {
+    fn options(self) -> Options {
+        match self.command {
+            Some(Command::Run(args)) => args.options(),
+            Some(Command::Test(args)) => args.options(),
+            Some(Command::Record(args)) => args.options(),
+            None => self.run.options(),
+        }
+    }
+}
+
+// Parse the command line, printing the usage and exiting if it doesn't make sense
+pub fn parse() -> Options {
+    Cli::parse().options()
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, clap::Error> {
+        Cli::try_parse_from(["rustendo-sdl"].iter().chain(args)).map(Cli::options)
+    }
+
+    #[test]
+    fn definition_is_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn a_bare_rom_runs_it() {
+        let options = parse(&["--scale", "3", "game.nes", "--cheat", "SXIOPO"]).unwrap();
+        assert_eq!(options.rom_path, Some(PathBuf::from("game.nes")));
+        assert_eq!(options.scale, Some(3));
+        assert_eq!(options.cheats, ["SXIOPO"]);
+        let options = parse(&["run", "--region", "pal", "game.nes"]).unwrap();
+        assert_eq!(options.region, Some(rustendo_core::Region::Pal));
+        assert!(parse(&["--setup"]).unwrap().setup);
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["--scale", "0", "game.nes"]).is_err());
+    }
+
+    #[test]
+    fn test_runs_headless() {
+        let options = parse(&["test", "--frames", "600", "game.nes"]).unwrap();
+        assert!(options.headless);
+        assert_eq!(options.frames, Some(600));
+        // It needs somewhere to stop
+        assert!(parse(&["test", "game.nes"]).is_err());
+    }
+
+    #[test]
+    fn record_needs_an_output() {
+        let options = parse(&["record", "--wav", "out.wav", "--stems", "game.nes"]).unwrap();
+        assert_eq!(options.record_wav, Some(PathBuf::from("out.wav")));
+        assert!(options.record_stems && !options.headless);
+        assert!(parse(&["record", "game.nes"]).is_err());
+    }
+
+    #[test]
+    fn run_keeps_the_old_rules() {
+        for args in [
+            &["--headless", "game.nes"][..],
+            &["--frames", "60", "game.nes"],
+            &["--screenshot", "out.png", "game.nes"],
+            &["--bench", "--headless", "--frames", "60", "game.nes"],
+            &["--bench", "--cycles", "60", "game.nes"],
+            &[
+                "--record-input",
+                "a.txt",
+                "--play-input",
+                "b.txt",
+                "game.nes",
+            ],
+            &[
+                "--netplay-host",
+                "7000",
+                "--netplay-connect",
+                "x:7000",
+                "game.nes",
+            ],
+            &["--netplay-delay", "2", "game.nes"],
+            &["--region", "secam", "game.nes"],
+        ] {
+            assert!(parse(args).is_err(), "{:?}", args);
+        }
+        assert!(parse(&["--headless", "--cycles", "1000", "game.nes"]).is_ok());
+        assert!(parse(&["--bench", "--frames", "60", "game.nes"]).is_ok());
+    }
+}
@@ -0,0 +1,493 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rustendo_core::apu::{AudioFilter, NES_FILTERS};
+use rustendo_core::nes::DEFAULT_TURBO_RATE;
+use rustendo_core::{Overscan, Region, RenderMode};
+use serde::{Deserialize, Serialize};
+
+use crate::pacer::PacingStrategy;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub rom_directory: Option<PathBuf>,
+    pub resume: bool, // Keep the session on exit and offer to pick it up on the next start
+    pub video: VideoConfig,
+    pub audio: AudioConfig,
+    pub input: InputConfig,
+    pub hotkeys: HotkeyConfig,
+    pub games: BTreeMap<String, GameConfig>, // Overrides keyed by ROM file name
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoConfig {
+    pub scale: u32,               // Integer window scale factor
+    pub fullscreen: bool,         // Fill the screen instead of opening a window
+    pub palette: Option<PathBuf>, // .pal file to use instead of the built-in palette
+    pub ntsc_filter: bool,        // Simulate composite video artifacts
+    pub overscan: OverscanConfig, // Pixels hidden at each edge of the picture
+    pub render_mode: RenderModeConfig,
+    pub pacing: PacingStrategy, // How the main loop waits for the next frame
+    pub fast_forward_speed: f64, // Times normal speed while fast-forwarding, 0 for uncapped
+    pub slow_motion_speed: f64, // Times normal speed in slow motion
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        Self {
+            scale: 3,
+            fullscreen: false,
+            palette: None,
+            ntsc_filter: false,
+            overscan: OverscanConfig::default(),
+            render_mode: RenderModeConfig::Accurate,
+            pacing: PacingStrategy::default(),
+            fast_forward_speed: 0.0,
+            slow_motion_speed: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverscanConfig {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Default for OverscanConfig {
+    fn default() -> Self {
+        let overscan = Overscan::default();
+        Self {
+            top: overscan.top,
+            bottom: overscan.bottom,
+            left: overscan.left,
+            right: overscan.right,
+        }
+    }
+}
+
+impl From<&OverscanConfig> for Overscan {
+    fn from(config: &OverscanConfig) -> Self {
+        Overscan {
+            top: config.top,
+            bottom: config.bottom,
+            left: config.left,
+            right: config.right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderModeConfig {
+    #[default]
+    Accurate,
+    Fast, // Whole scanlines at once, for slow machines
+}
+
+impl From<RenderModeConfig> for RenderMode {
+    fn from(mode: RenderModeConfig) -> Self {
+        match mode {
+            RenderModeConfig::Accurate => RenderMode::Accurate,
+            RenderModeConfig::Fast => RenderMode::Fast,
+        }
+    }
+}
+
+// Settings for a single game, taking precedence over the global ones. They come from the
+// game's `[games."<file name>"]` section and from its profile, a file of its own named after
+// the ROM's CRC-32.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    pub render_mode: Option<RenderModeConfig>,
+    pub palette: Option<PathBuf>,
+    pub region: Option<RegionConfig>, // Instead of the header's and the game database's
+    pub four_score: Option<bool>,
+    pub family_keyboard: Option<bool>,
+    pub overclock: Option<u32>, // Extra scanlines of CPU time per frame
+    pub cheats: Vec<String>,    // Game Genie or raw (AAAA:VV) codes to apply at startup
+}
+
+impl GameConfig {
+    // `other`'s settings where it has them and these elsewhere, and the cheats of both
+    fn merge(&mut self, other: GameConfig) {
+        self.render_mode = other.render_mode.or(self.render_mode);
+        self.palette = other.palette.or(self.palette.take());
+        self.region = other.region.or(self.region);
+        self.four_score = other.four_score.or(self.four_score);
+        self.family_keyboard = other.family_keyboard.or(self.family_keyboard);
+        self.overclock = other.overclock.or(self.overclock);
+        for code in other.cheats {
+            if !self.cheats.contains(&code) {
+                self.cheats.push(code);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RegionConfig {
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl From<RegionConfig> for Region {
+    fn from(region: RegionConfig) -> Self {
+        match region {
+            RegionConfig::Ntsc => Region::Ntsc,
+            RegionConfig::Pal => Region::Pal,
+            RegionConfig::Dendy => Region::Dendy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    pub device: Option<String>, // Output device name, None for the system default
+    pub buffer_size: u32,       // Frames per device buffer; smaller means less latency
+    pub fds_volume: f32,        // FDS sound relative to the console's, 0.0-2.0
+    // Output filters in the order they are applied, the console's analog stage by default
+    pub filters: Vec<FilterConfig>,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            device: None,
+            buffer_size: 1024,
+            fds_volume: 1.0,
+            filters: NES_FILTERS.iter().map(FilterConfig::from).collect(),
+        }
+    }
+}
+
+// A first-order filter and its cutoff in Hz, written as `{ high_pass = 90.0 }`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterConfig {
+    HighPass(f32),
+    LowPass(f32),
+}
+
+impl From<&AudioFilter> for FilterConfig {
+    fn from(filter: &AudioFilter) -> Self {
+        match *filter {
+            AudioFilter::HighPass(cutoff) => FilterConfig::HighPass(cutoff),
+            AudioFilter::LowPass(cutoff) => FilterConfig::LowPass(cutoff),
+        }
+    }
+}
+
+impl From<&FilterConfig> for AudioFilter {
+    fn from(filter: &FilterConfig) -> Self {
+        match *filter {
+            FilterConfig::HighPass(cutoff) => AudioFilter::HighPass(cutoff),
+            FilterConfig::LowPass(cutoff) => AudioFilter::LowPass(cutoff),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputConfig {
+    pub four_score: bool,      // Connect the four-player adapter
+    pub family_keyboard: bool, // Connect the Family BASIC keyboard
+    pub turbo_rate: u64,       // Frames autofire holds a button down, then up
+    pub player1: KeyBindings,
+    pub player2: KeyBindings,
+    pub player3: KeyBindings, // Players 3 and 4 need `four_score`
+    pub player4: KeyBindings,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            four_score: false,
+            family_keyboard: false,
+            turbo_rate: DEFAULT_TURBO_RATE,
+            player1: KeyBindings::player1(),
+            player2: KeyBindings::default(),
+            player3: KeyBindings::default(),
+            player4: KeyBindings::default(),
+        }
+    }
+}
+
+impl InputConfig {
+    // Apply a binding given on the command line as its path in the config file, like
+    // `player2.start=Return` or `player1.gamepad.a=y`. Returns None if it doesn't name a button.
+    pub fn map(&mut self, mapping: &str) -> Option<()> {
+        let (path, name) = mapping.split_once('=')?;
+        let mut parts = path.split('.');
+        let bindings = match parts.next()? {
+            "player1" => &mut self.player1,
+            "player2" => &mut self.player2,
+            "player3" => &mut self.player3,
+            "player4" => &mut self.player4,
+            _ => return None,
+        };
+        let (names, button) = match (parts.next()?, parts.next(), parts.next()) {
+            ("gamepad", Some(button), None) => (bindings.gamepad.buttons_mut(), button),
+            (button, None, None) => (bindings.keys_mut(), button),
+            _ => return None,
+        };
+        let index = BUTTON_NAMES.iter().position(|&known| known == button)?;
+        *names[index] = name.to_string();
+        Some(())
+    }
+}
+
+// Keys for the front end's own functions, by the same names as the controller bindings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HotkeyConfig {
+    pub save_state: String, // To the selected slot
+    pub load_state: String, // From the selected slot
+    pub next_slot: String,  // Select the next of the 10 slots, wrapping around
+    pub previous_slot: String,
+    pub fast_forward: String,  // Held
+    pub slow_motion: String,   // Toggles
+    pub debug_overlay: String, // Shows and hides the debug overlay
+    pub overlay_page: String,  // Its next page
+    pub next_cheat: String,    // Select the next of the game's cheats, wrapping around
+    pub toggle_cheat: String,  // Switch the selected cheat on or off
+}
+
+impl Default for HotkeyConfig {
+    fn default() -> Self {
+        Self {
+            save_state: "F5".to_string(),
+            load_state: "F8".to_string(),
+            next_slot: "F7".to_string(),
+            previous_slot: "F6".to_string(),
+            fast_forward: "Tab".to_string(),
+            slow_motion: "Backspace".to_string(),
+            debug_overlay: "F1".to_string(),
+            overlay_page: "F2".to_string(),
+            next_cheat: "F3".to_string(),
+            toggle_cheat: "F4".to_string(),
+        }
+    }
+}
+
+// Names of the buttons and autofire buttons, in the controller's shift order
+pub const BUTTON_NAMES: [&str; 10] = [
+    "a", "b", "select", "start", "up", "down", "left", "right", "turbo_a", "turbo_b",
+];
+
+// The keys (by scancode name, e.g. "X" or "Right Shift") and gamepad buttons for one player's
+// controller. An empty name leaves the button unbound.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub a: String,
+    pub b: String,
+    pub select: String,
+    pub start: String,
+    pub up: String,
+    pub down: String,
+    pub left: String,
+    pub right: String,
+    pub turbo_a: String, // A and B on autofire while held
+    pub turbo_b: String,
+    // Buttons on the player's gamepad: player 1 uses the first one connected, and so on
+    pub gamepad: GamepadBindings,
+}
+
+impl KeyBindings {
+    // Labels for the setup prompts, in `BUTTON_NAMES` order
+    #[cfg(any(feature = "video", feature = "winit"))]
+    pub const BUTTONS: [&'static str; 10] = [
+        "A", "B", "Select", "Start", "Up", "Down", "Left", "Right", "Turbo A", "Turbo B",
+    ];
+
+    // Arrow keys, Z/X for B/A, Enter and right Shift
+    pub fn player1() -> Self {
+        Self {
+            a: "X".to_string(),
+            b: "Z".to_string(),
+            select: "RShift".to_string(),
+            start: "Return".to_string(),
+            up: "Up".to_string(),
+            down: "Down".to_string(),
+            left: "Left".to_string(),
+            right: "Right".to_string(),
+            turbo_a: "S".to_string(),
+            turbo_b: "A".to_string(),
+            gamepad: GamepadBindings::default(),
+        }
+    }
+
+    // In `BUTTON_NAMES` order
+    #[cfg(any(feature = "video", feature = "winit"))]
+    pub fn keys(&self) -> [&str; 10] {
+        [
+            &self.a,
+            &self.b,
+            &self.select,
+            &self.start,
+            &self.up,
+            &self.down,
+            &self.left,
+            &self.right,
+            &self.turbo_a,
+            &self.turbo_b,
+        ]
+    }
+
+    pub fn keys_mut(&mut self) -> [&mut String; 10] {
+        [
+            &mut self.a,
+            &mut self.b,
+            &mut self.select,
+            &mut self.start,
+            &mut self.up,
+            &mut self.down,
+            &mut self.left,
+            &mut self.right,
+            &mut self.turbo_a,
+            &mut self.turbo_b,
+        ]
+    }
+}
+
+// SDL game controller button names ("a", "b", "x", "y", "back", "start", "dpup", ...), laid out
+// like an Xbox pad. The face buttons keep the NES's B-left, A-right arrangement.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadBindings {
+    pub a: String,
+    pub b: String,
+    pub select: String,
+    pub start: String,
+    pub up: String,
+    pub down: String,
+    pub left: String,
+    pub right: String,
+    pub turbo_a: String,
+    pub turbo_b: String,
+}
+
+impl Default for GamepadBindings {
+    fn default() -> Self {
+        Self {
+            a: "b".to_string(),
+            b: "a".to_string(),
+            select: "back".to_string(),
+            start: "start".to_string(),
+            up: "dpup".to_string(),
+            down: "dpdown".to_string(),
+            left: "dpleft".to_string(),
+            right: "dpright".to_string(),
+            turbo_a: "y".to_string(),
+            turbo_b: "x".to_string(),
+        }
+    }
+}
+
+impl GamepadBindings {
+    // In `BUTTON_NAMES` order
+    #[cfg(feature = "video")]
+    pub fn buttons(&self) -> [&str; 10] {
+        [
+            &self.a,
+            &self.b,
+            &self.select,
+            &self.start,
+            &self.up,
+            &self.down,
+            &self.left,
+            &self.right,
+            &self.turbo_a,
+            &self.turbo_b,
+        ]
+    }
+
+    pub fn buttons_mut(&mut self) -> [&mut String; 10] {
+        [
+            &mut self.a,
+            &mut self.b,
+            &mut self.select,
+            &mut self.start,
+            &mut self.up,
+            &mut self.down,
+            &mut self.left,
+            &mut self.right,
+            &mut self.turbo_a,
+            &mut self.turbo_b,
+        ]
+    }
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("rustendo").join("config.toml"))
+    }
+
+    // The settings specific to a ROM, if there are any
+    pub fn game(&self, rom_path: &Path) -> Option<&GameConfig> {
+        let name = rom_path.file_name()?.to_str()?;
+        self.games.get(name)
+    }
+
+    // Where the profile for the ROM with this CRC-32 (of its PRG and CHR data, as `rustendo
+    // rom-info` prints it) is kept: `games/1A2B3C4D.toml` next to the config file
+    pub fn profile_path(crc32: u32) -> Option<PathBuf> {
+        let path = Self::default_path()?;
+        Some(
+            path.with_file_name("games")
+                .join(format!("{:08X}.toml", crc32)),
+        )
+    }
+
+    // Fold a game's profile into its `[games]` section, then put that section's palette, render
+    // mode and controllers in place of the global ones. Its region, overclock and cheats are
+    // left for the caller to read with `game`.
+    pub fn apply_game(&mut self, rom_path: &Path, profile: Option<GameConfig>) {
+        let Some(name) = rom_path.file_name().and_then(|name| name.to_str()) else {
+            return;
+        };
+        let game = self.games.entry(name.to_string()).or_default();
+        if let Some(profile) = profile {
+            game.merge(profile);
+        }
+        let game = game.clone();
+        self.video.palette = game.palette.or(self.video.palette.take());
+        self.video.render_mode = game.render_mode.unwrap_or(self.video.render_mode);
+        self.input.four_score = game.four_score.unwrap_or(self.input.four_score);
+        self.input.family_keyboard = game.family_keyboard.unwrap_or(self.input.family_keyboard);
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    // A game's profile, or None if it hasn't got one
+    pub fn load_profile(path: &Path) -> Result<Option<GameConfig>, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(path)?;
+        Ok(Some(toml::from_str(&contents)?))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use std::backtrace::Backtrace;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rustendo_core::mapper;
+use rustendo_core::{Nes, Rom};
+
+use crate::config::Config;
+
+// Instructions the console keeps for a report
+pub const HISTORY: usize = 256;
+
+// The last panic's message, where it happened and the backtrace, noted by the hook
+static PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+// Note each panic for `guard`'s report, then print it as usual
+pub fn install_hook() {
+    let print = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let panic = format!("{}\n\n{}", info, Backtrace::force_capture());
+        *PANIC.lock().unwrap_or_else(PoisonError::into_inner) = Some(panic);
+        print(info);
+    }));
+}
+
+// What a report says about the game and the settings, gathered before the game starts
+pub struct CrashContext {
+    rom: String,    // The path, hashes and header
+    config: String, // TOML, as it would be saved
+}
+
+impl CrashContext {
+    pub fn new(rom_path: &Path, rom: &Rom, config: &Config) -> Self {
+        let sha1: String = rom
+            .sha1()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let board = mapper::info(rom.mapper).map_or("unsupported", |info| info.name);
+        let rom = format!(
+            "ROM: {}\nCRC32 {:08X}, SHA-1 {}\nMapper {} ({}), {:?}, {}KB PRG-ROM, {}KB CHR-ROM\n",
+            rom_path.display(),
+            rom.crc32(),
+            sha1,
+            rom.mapper,
+            board,
+            rom.region,
+            rom.prg_rom.len() / 1024,
+            rom.chr_rom.len() / 1024
+        );
+        let config = toml::to_string_pretty(config)
+            .unwrap_or_else(|e| format!("(could not be written: {})\n", e));
+        Self { rom, config }
+    }
+}
+
+// Run `f` on the console. If it panics, write a crash report and then carry on panicking.
+pub fn guard<R>(nes: &mut Nes, context: &CrashContext, f: impl FnOnce(&mut Nes) -> R) -> R {
+    match panic::catch_unwind(AssertUnwindSafe(|| f(nes))) {
+        Ok(result) => result,
+        Err(payload) => {
+            match write_report(nes, context) {
+                Ok(path) => eprintln!(
+                    "Wrote a crash report to {}; please attach it to a bug report",
+                    path.display()
+                ),
+                Err(e) => eprintln!("Could not write a crash report: {}", e),
+            }
+            panic::resume_unwind(payload)
+        }
+    }
+}
+
+// In `crashes` next to the config file, or the current directory if there's no config
+// directory
+fn write_report(nes: &mut Nes, context: &CrashContext) -> io::Result<PathBuf> {
+    let directory = match Config::default_path() {
+        Some(path) => path.with_file_name("crashes"),
+        None => PathBuf::new(),
+    };
+    fs::create_dir_all(&directory)?;
+    let time = timestamp();
+    let path = directory.join(format!("crash-{}.txt", time));
+    fs::write(&path, report(nes, context, &time))?;
+    Ok(path)
+}
+
+fn report(nes: &mut Nes, context: &CrashContext, time: &str) -> String {
+    let mut report = format!(
+        "rustendo {} crash report, {} UTC\n\n",
+        env!("CARGO_PKG_VERSION"),
+        time
+    );
+    match PANIC.lock().unwrap_or_else(PoisonError::into_inner).take() {
+        Some(panic) => report += &panic,
+        None => report += "The panic message was not recorded\n",
+    }
+    report += "\n";
+    report += &context.rom;
+    let args: Vec<String> = env::args().collect();
+    let _ = writeln!(report, "Command line: {}", args.join(" "));
+    let _ = writeln!(report, "Frame {}, CPU cycle {}", nes.frame(), nes.cycles());
+
+    let _ = writeln!(report, "\nLast instructions, oldest first:");
+    for traced in nes.instruction_history() {
+        let _ = writeln!(report, "{}", traced);
+    }
+
+    let _ = write!(report, "\nConfig:\n{}", context.config);
+
+    // The console may be too broken to save, and that panic has been printed already
+    match panic::catch_unwind(AssertUnwindSafe(|| nes.save_state())) {
+        Ok(state) => {
+            let _ = writeln!(
+                report,
+                "\nMachine state, {} bytes in hex (`xxd -r -p` turns it back into a save state):",
+                state.len()
+            );
+            for line in state.chunks(32) {
+                for byte in line {
+                    let _ = write!(report, "{:02x}", byte);
+                }
+                report += "\n";
+            }
+        }
+        Err(_) => report += "\nThe machine state could not be saved\n",
+    }
+    report
+}
+
+// The current UTC time as `20261016-105901`
+fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let (days, time) = (seconds / 86_400, seconds % 86_400);
+    // Days since 1970 to a civil date, shifted so years start in March and leap days come last
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
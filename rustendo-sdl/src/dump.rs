@@ -0,0 +1,77 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Writes frames uncompressed to a YUV4MPEG2 (.y4m) stream, which encoders like ffmpeg and x264
+// read directly. Frames are converted from RGBA to full-resolution (4:4:4) BT.601 YCbCr, so no
+// colour detail is lost to subsampling.
+pub struct VideoDump {
+    file: BufWriter<File>,
+    planes: Vec<u8>, // Y, Cb and Cr planes of the frame being written
+}
+
+impl VideoDump {
+    pub fn create(
+        path: &Path,
+        width: usize,
+        height: usize,
+        (numerator, denominator): (u32, u32),
+    ) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(
+            file,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+            width, height, numerator, denominator
+        )?;
+        Ok(Self {
+            file,
+            planes: vec![0; width * height * 3],
+        })
+    }
+
+    // Append a frame of RGBA pixels, the size given to `create`
+    pub fn write_frame(&mut self, rgba: &[u8]) -> io::Result<()> {
+        let size = self.planes.len() / 3;
+        let (y, chroma) = self.planes.split_at_mut(size);
+        let (cb, cr) = chroma.split_at_mut(size);
+        for (i, pixel) in rgba.chunks_exact(4).take(size).enumerate() {
+            let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(i32::from);
+            y[i] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+            cb[i] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+            cr[i] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+        }
+        self.file.write_all(b"FRAME\n")?;
+        self.file.write_all(&self.planes)
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// Writes samples as headerless mono 16-bit signed little-endian PCM, the same samples a WAV
+// recording gets
+pub struct AudioDump {
+    file: BufWriter<File>,
+}
+
+impl AudioDump {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    // Append samples in the -1.0 to 1.0 range, clipping anything outside it
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.file.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
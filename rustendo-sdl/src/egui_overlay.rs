@@ -0,0 +1,564 @@
+use std::mem;
+
+use egui::{
+    ClippedPrimitive, Color32, ColorImage, ComboBox, Context, Grid, Key, Pos2, Sense, Shape,
+    Slider, Stroke, TextEdit, TextureHandle, TextureOptions, TexturesDelta, Ui, Vec2, ViewportId,
+};
+use egui_wgpu::{Renderer, ScreenDescriptor};
+use pixels::{wgpu, Pixels, PixelsContext};
+use rustendo_core::apu::{CHANNELS, CHANNEL_NAMES};
+use rustendo_core::cheat::CheatEntry;
+use rustendo_core::debug::{self, GRAYSCALE};
+use rustendo_core::Nes;
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+use crate::config::Config;
+use crate::overlay::{Edit, Session, FAST_FORWARD_SPEEDS, SLOW_MOTION_SPEEDS};
+use crate::resume;
+
+// Points across each channel's scope, which a frame's worth of levels is thinned out to
+const SCOPE_POINTS: usize = 256;
+const SCOPE_HEIGHT: f32 = 40.0;
+
+const SCOPE_COLORS: [Color32; CHANNELS] = [
+    Color32::from_rgb(0xFF, 0x60, 0x60),
+    Color32::from_rgb(0xFF, 0xB0, 0x40),
+    Color32::from_rgb(0x60, 0xD0, 0xFF),
+    Color32::from_rgb(0xC0, 0xC0, 0xC0),
+    Color32::from_rgb(0x80, 0xFF, 0x80),
+];
+
+// What the windows show, copied from the console after each frame while they're open. The
+// console stays on the emulation thread, and the windows are drawn on the window's.
+pub struct Snapshot {
+    cpu: Vec<String>, // Registers, stack and disassembly
+    pattern_tables: [Vec<u8>; 2],
+    nametables: Vec<u8>,
+    sprites: Vec<u8>,
+    palettes: Vec<u8>,
+    scope: Vec<[f32; CHANNELS]>,
+    cheats: Vec<CheatEntry>,
+    settings: resume::Settings,
+    details: Vec<String>, // What config.toml sets
+    netplay: bool,
+}
+
+impl Snapshot {
+    // Takes the APU levels recorded since the last one, so the console should be recording them
+    pub fn take(nes: &mut Nes, config: &Config, session: &Session, netplay: bool) -> Self {
+        let mut levels = Vec::new();
+        nes.take_scope(&mut levels);
+        let scope = if levels.is_empty() {
+            Vec::new()
+        } else {
+            (0..SCOPE_POINTS)
+                .map(|point| levels[point * levels.len() / SCOPE_POINTS])
+                .collect()
+        };
+
+        let memory = nes.memory().borrow();
+        let ppu = memory.ppu();
+        let video = &config.video;
+        Self {
+            cpu: debug::cpu_lines(nes.cpu_registers(), &memory),
+            pattern_tables: [0, 1].map(|table| debug::pattern_table(ppu, table, GRAYSCALE)),
+            nametables: debug::nametables(ppu),
+            sprites: debug::sprite_preview(ppu),
+            palettes: debug::palette_view(ppu),
+            scope,
+            cheats: nes.cheats().iter().cloned().collect(),
+            settings: session.settings(nes),
+            details: vec![
+                format!("Region: {:?}  Speed now: {}x", nes.region(), session.speed),
+                format!("Scale: {}  Fullscreen: {}", video.scale, video.fullscreen),
+                format!("Pacing: {:?}", video.pacing),
+                format!("Audio buffer: {}", config.audio.buffer_size),
+            ],
+            netplay,
+        }
+    }
+}
+
+// A change made in the windows, for the emulation thread to make
+pub enum Command {
+    AddCheat(String),
+    SetCheatEnabled(String, bool),
+    RemoveCheat(String),
+    NextSlot,
+    PreviousSlot,
+    Settings(resume::Settings), // The settings window's values; its slot is ignored
+}
+
+// Make a change from the windows. Cheats and the controllers can't be changed in netplay, where
+// the other console would carry on without the change.
+pub fn apply(
+    nes: &mut Nes,
+    command: Command,
+    session: &mut Session,
+    netplay: bool,
+) -> Option<Edit> {
+    match command {
+        Command::AddCheat(_) | Command::SetCheatEnabled(..) | Command::RemoveCheat(_)
+            if netplay =>
+        {
+            nes.show_message("Can't change cheats in netplay");
+            None
+        }
+        Command::AddCheat(code) => match nes.add_cheat(&code) {
+            Ok(()) => {
+                session.cheat = nes.cheats().len() - 1;
+                Some(Edit::CheatsChanged)
+            }
+            Err(e) => {
+                nes.show_message(&format!("{}: {}", code, e));
+                None
+            }
+        },
+        Command::SetCheatEnabled(code, enabled) => nes
+            .set_cheat_enabled(&code, enabled)
+            .then_some(Edit::CheatsChanged),
+        Command::RemoveCheat(code) => {
+            let removed = nes.remove_cheat(&code);
+            session.cheat = session.cheat.min(nes.cheats().len().saturating_sub(1));
+            removed.then_some(Edit::CheatsChanged)
+        }
+        Command::NextSlot => Some(Edit::NextSlot),
+        Command::PreviousSlot => Some(Edit::PreviousSlot),
+        Command::Settings(mut settings) => {
+            let current = session.settings(nes);
+            settings.slot = current.slot;
+            let controllers = |settings: &resume::Settings| {
+                (
+                    settings.four_score,
+                    settings.turbo_rate,
+                    settings.family_keyboard,
+                )
+            };
+            if netplay && controllers(&settings) != controllers(&current) {
+                nes.show_message("Can't change the controllers in netplay");
+                settings.four_score = current.four_score;
+                settings.turbo_rate = current.turbo_rate;
+                settings.family_keyboard = current.family_keyboard;
+            }
+            session.restore(nes, &settings);
+            None
+        }
+    }
+}
+
+// Which windows are open. The bar across the top opens and closes them.
+struct Windows {
+    cpu: bool,
+    ppu: bool,
+    apu: bool,
+    cheats: bool,
+    settings: bool,
+}
+
+// What the PPU window shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PpuView {
+    PatternTables,
+    Nametables,
+    Sprites,
+    Palettes,
+}
+
+// The snapshot's pictures, uploaded for egui to draw
+struct Textures {
+    pattern_tables: [TextureHandle; 2],
+    nametables: TextureHandle,
+    sprites: TextureHandle,
+    palettes: TextureHandle,
+}
+
+impl Textures {
+    fn new(context: &Context, snapshot: &Snapshot) -> Self {
+        let load = |name: &str, image| context.load_texture(name, image, TextureOptions::NEAREST);
+        let [pattern_table0, pattern_table1, nametables, sprites, palettes] = images(snapshot);
+        Self {
+            pattern_tables: [
+                load("pattern table 0", pattern_table0),
+                load("pattern table 1", pattern_table1),
+            ],
+            nametables: load("nametables", nametables),
+            sprites: load("sprites", sprites),
+            palettes: load("palettes", palettes),
+        }
+    }
+
+    fn update(&mut self, snapshot: &Snapshot) {
+        let [pattern_table0, pattern_table1] = &mut self.pattern_tables;
+        let textures = [
+            pattern_table0,
+            pattern_table1,
+            &mut self.nametables,
+            &mut self.sprites,
+            &mut self.palettes,
+        ];
+        for (texture, image) in textures.into_iter().zip(images(snapshot)) {
+            texture.set(image, TextureOptions::NEAREST);
+        }
+    }
+}
+
+// In the order `Textures` keeps them
+fn images(snapshot: &Snapshot) -> [ColorImage; 5] {
+    let image =
+        |width, height, rgba: &[u8]| ColorImage::from_rgba_unmultiplied([width, height], rgba);
+    let table = debug::PATTERN_TABLE_SIZE;
+    [
+        image(table, table, &snapshot.pattern_tables[0]),
+        image(table, table, &snapshot.pattern_tables[1]),
+        image(
+            debug::NAMETABLES_WIDTH,
+            debug::NAMETABLES_HEIGHT,
+            &snapshot.nametables,
+        ),
+        image(
+            debug::SPRITE_PREVIEW_WIDTH,
+            debug::SPRITE_PREVIEW_HEIGHT,
+            &snapshot.sprites,
+        ),
+        image(
+            debug::PALETTE_VIEW_WIDTH,
+            debug::PALETTE_VIEW_HEIGHT,
+            &snapshot.palettes,
+        ),
+    ]
+}
+
+// A frame of the windows, ready for the GPU
+struct Paint {
+    jobs: Vec<ClippedPrimitive>,
+    textures: TexturesDelta,
+    screen: ScreenDescriptor,
+}
+
+// The debug overlay's windows, drawn with egui over the picture on the window's thread. They
+// show while the emulation thread sends snapshots, and the changes made in them go back to it
+// as commands.
+pub struct Overlay {
+    context: Context,
+    state: egui_winit::State,
+    renderer: Renderer,
+    snapshot: Option<Snapshot>,
+    textures: Option<Textures>,
+    windows: Windows,
+    ppu_view: PpuView,
+    code: String, // A cheat code being typed in
+    commands: Vec<Command>,
+    paint: Option<Paint>,
+}
+
+impl Overlay {
+    pub fn new(window: &Window, pixels: &Pixels) -> Self {
+        let context = Context::default();
+        let max_texture_side = pixels.device().limits().max_texture_dimension_2d as usize;
+        let state = egui_winit::State::new(
+            context.clone(),
+            ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            Some(max_texture_side),
+        );
+        let renderer = Renderer::new(pixels.device(), pixels.render_texture_format(), None, 1);
+        Self {
+            context,
+            state,
+            renderer,
+            snapshot: None,
+            textures: None,
+            windows: Windows {
+                cpu: true,
+                ppu: true,
+                apu: true,
+                cheats: true,
+                settings: true,
+            },
+            ppu_view: PpuView::PatternTables,
+            code: String::new(),
+            commands: Vec::new(),
+            paint: None,
+        }
+    }
+
+    // Pass a window event on to egui. Returns whether the windows took it (a key typed into a
+    // text field, say), in which case the game shouldn't get it too.
+    pub fn handle_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        let response = self.state.on_window_event(window, event);
+        self.snapshot.is_some() && response.consumed
+    }
+
+    // Show the windows with what `snapshot` holds, or hide them with None
+    pub fn show(&mut self, snapshot: Option<Snapshot>) {
+        if let Some(snapshot) = &snapshot {
+            match &mut self.textures {
+                Some(textures) => textures.update(snapshot),
+                None => self.textures = Some(Textures::new(&self.context, snapshot)),
+            }
+        }
+        self.snapshot = snapshot;
+    }
+
+    // The changes made in the windows since the last call
+    pub fn take_commands(&mut self) -> Vec<Command> {
+        mem::take(&mut self.commands)
+    }
+
+    // Lay the windows out for the next frame, if they're showing
+    pub fn prepare(&mut self, window: &Window) {
+        if self.snapshot.is_none() {
+            return;
+        }
+        let input = self.state.take_egui_input(window);
+        let context = self.context.clone();
+        let output = context.run(input, |context| self.ui(context));
+        self.state
+            .handle_platform_output(window, output.platform_output);
+        let size = window.inner_size();
+        self.paint = Some(Paint {
+            jobs: context.tessellate(output.shapes, output.pixels_per_point),
+            textures: output.textures_delta,
+            screen: ScreenDescriptor {
+                size_in_pixels: [size.width, size.height],
+                pixels_per_point: output.pixels_per_point,
+            },
+        });
+    }
+
+    // Draw what `prepare` laid out over the picture pixels has drawn
+    pub fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        context: &PixelsContext,
+    ) {
+        let Some(paint) = self.paint.take() else {
+            return;
+        };
+        for (id, image) in &paint.textures.set {
+            self.renderer
+                .update_texture(&context.device, &context.queue, *id, image);
+        }
+        self.renderer.update_buffers(
+            &context.device,
+            &context.queue,
+            encoder,
+            &paint.jobs,
+            &paint.screen,
+        );
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("egui"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            self.renderer.render(&mut pass, &paint.jobs, &paint.screen);
+        }
+        for id in &paint.textures.free {
+            self.renderer.free_texture(id);
+        }
+    }
+
+    fn ui(&mut self, context: &Context) {
+        let Self {
+            snapshot: Some(snapshot),
+            textures: Some(textures),
+            windows,
+            ppu_view,
+            code,
+            commands,
+            ..
+        } = self
+        else {
+            return;
+        };
+
+        egui::TopBottomPanel::top("windows").show(context, |ui| {
+            ui.horizontal(|ui| {
+                ui.toggle_value(&mut windows.cpu, "CPU");
+                ui.toggle_value(&mut windows.ppu, "PPU");
+                ui.toggle_value(&mut windows.apu, "APU");
+                ui.toggle_value(&mut windows.cheats, "Cheats");
+                ui.toggle_value(&mut windows.settings, "Settings");
+            });
+        });
+        egui::Window::new("CPU")
+            .open(&mut windows.cpu)
+            .default_pos([10.0, 40.0])
+            .show(context, |ui| {
+                for line in &snapshot.cpu {
+                    ui.monospace(line);
+                }
+            });
+        egui::Window::new("PPU")
+            .open(&mut windows.ppu)
+            .default_pos([300.0, 40.0])
+            .show(context, |ui| ppu_window(ui, textures, ppu_view));
+        egui::Window::new("APU")
+            .open(&mut windows.apu)
+            .default_pos([10.0, 420.0])
+            .show(context, |ui| apu_window(ui, &snapshot.scope));
+        egui::Window::new("Cheats")
+            .open(&mut windows.cheats)
+            .default_pos([300.0, 420.0])
+            .show(context, |ui| cheats_window(ui, snapshot, code, commands));
+        egui::Window::new("Settings")
+            .open(&mut windows.settings)
+            .default_pos([620.0, 40.0])
+            .show(context, |ui| settings_window(ui, snapshot, commands));
+    }
+}
+
+fn ppu_window(ui: &mut Ui, textures: &Textures, view: &mut PpuView) {
+    ui.horizontal(|ui| {
+        ui.selectable_value(view, PpuView::PatternTables, "Pattern tables");
+        ui.selectable_value(view, PpuView::Nametables, "Nametables");
+        ui.selectable_value(view, PpuView::Sprites, "Sprites");
+        ui.selectable_value(view, PpuView::Palettes, "Palettes");
+    });
+    // Twice the size, but for the nametables, which are four screens already
+    let image = |ui: &mut Ui, texture: &TextureHandle, scale: f32| {
+        ui.image((texture.id(), texture.size_vec2() * scale));
+    };
+    match view {
+        PpuView::PatternTables => {
+            ui.horizontal(|ui| {
+                for texture in &textures.pattern_tables {
+                    image(ui, texture, 2.0);
+                }
+            });
+        }
+        PpuView::Nametables => image(ui, &textures.nametables, 1.0),
+        PpuView::Sprites => image(ui, &textures.sprites, 2.0),
+        PpuView::Palettes => image(ui, &textures.palettes, 2.0),
+    }
+}
+
+// A scope per channel, each scaled to its own peak
+fn apu_window(ui: &mut Ui, scope: &[[f32; CHANNELS]]) {
+    for (channel, name) in CHANNEL_NAMES.iter().enumerate() {
+        ui.label(*name);
+        let size = Vec2::new(SCOPE_POINTS as f32, SCOPE_HEIGHT);
+        let (response, painter) = ui.allocate_painter(size, Sense::hover());
+        let rect = response.rect;
+        let peak = scope
+            .iter()
+            .map(|levels| levels[channel])
+            .fold(f32::EPSILON, f32::max);
+        let points = scope
+            .iter()
+            .enumerate()
+            .map(|(x, levels)| {
+                let y = rect.bottom() - levels[channel] / peak * (SCOPE_HEIGHT - 1.0);
+                Pos2::new(rect.left() + x as f32, y)
+            })
+            .collect();
+        painter.add(Shape::line(points, Stroke::new(1.0, SCOPE_COLORS[channel])));
+    }
+}
+
+fn cheats_window(ui: &mut Ui, snapshot: &Snapshot, code: &mut String, commands: &mut Vec<Command>) {
+    if snapshot.netplay {
+        ui.label("Cheats can't be changed in netplay.");
+    }
+    ui.add_enabled_ui(!snapshot.netplay, |ui| {
+        if snapshot.cheats.is_empty() {
+            ui.label("None yet.");
+        }
+        Grid::new("cheats").show(ui, |ui| {
+            for cheat in &snapshot.cheats {
+                let mut enabled = cheat.enabled;
+                if ui.checkbox(&mut enabled, "").changed() {
+                    commands.push(Command::SetCheatEnabled(cheat.code.clone(), enabled));
+                }
+                ui.monospace(&cheat.code);
+                ui.label(&cheat.name);
+                if ui.button("Remove").clicked() {
+                    commands.push(Command::RemoveCheat(cheat.code.clone()));
+                }
+                ui.end_row();
+            }
+        });
+        ui.horizontal(|ui| {
+            let field = ui.add(TextEdit::singleline(code).hint_text("Game Genie or AAAA:VV"));
+            let entered = field.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter));
+            if (ui.button("Add").clicked() || entered) && !code.trim().is_empty() {
+                commands.push(Command::AddCheat(mem::take(code).trim().to_uppercase()));
+            }
+        });
+    });
+}
+
+fn settings_window(ui: &mut Ui, snapshot: &Snapshot, commands: &mut Vec<Command>) {
+    let mut settings = snapshot.settings.clone();
+    ui.horizontal(|ui| {
+        if ui.button("<").clicked() {
+            commands.push(Command::PreviousSlot);
+        }
+        ui.label(format!("State slot: {}", settings.slot));
+        if ui.button(">").clicked() {
+            commands.push(Command::NextSlot);
+        }
+    });
+    ui.checkbox(&mut settings.ntsc_filter, "NTSC filter");
+    speed(
+        ui,
+        "Fast-forward",
+        &mut settings.fast_forward_speed,
+        &FAST_FORWARD_SPEEDS,
+    );
+    speed(
+        ui,
+        "Slow motion",
+        &mut settings.slow_motion_speed,
+        &SLOW_MOTION_SPEEDS,
+    );
+    ui.add(
+        Slider::new(&mut settings.fds_volume, 0.0..=2.0)
+            .step_by(0.1)
+            .text("FDS volume"),
+    );
+    ui.add_enabled_ui(!snapshot.netplay, |ui| {
+        ui.checkbox(&mut settings.four_score, "Four Score");
+        ui.add(Slider::new(&mut settings.turbo_rate, 1..=30).text("Turbo rate"));
+        ui.checkbox(&mut settings.family_keyboard, "Family keyboard");
+    });
+    if settings != snapshot.settings {
+        commands.push(Command::Settings(settings));
+    }
+    ui.separator();
+    ui.label("Until the emulator is closed. Set in config.toml:");
+    for line in &snapshot.details {
+        ui.label(line);
+    }
+}
+
+// A choice of `steps`, where 0 is uncapped
+fn speed(ui: &mut Ui, label: &str, speed: &mut f64, steps: &[f64]) {
+    let times = |speed: f64| {
+        if speed == 0.0 {
+            "uncapped".to_string()
+        } else {
+            format!("{}x", speed)
+        }
+    };
+    ComboBox::from_label(label)
+        .selected_text(times(*speed))
+        .show_ui(ui, |ui| {
+            for &step in steps {
+                ui.selectable_value(speed, step, times(step));
+            }
+        });
+}
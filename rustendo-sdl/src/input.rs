@@ -0,0 +1,346 @@
+use rustendo_core::keyboard::FamilyKeyboard;
+
+use crate::config::{HotkeyConfig, InputConfig};
+use crate::overlay::PageKey;
+
+// Other spellings of the key names used in the config (SDL's Keycode names, e.g. "RShift"),
+// written the way `normalize` leaves them. SDL reports "Right Shift" and winit "ShiftRight".
+const ALIASES: [(&str, &str); 13] = [
+    ("rightshift", "rshift"),
+    ("shiftright", "rshift"),
+    ("leftshift", "lshift"),
+    ("shiftleft", "lshift"),
+    ("rightctrl", "rctrl"),
+    ("controlright", "rctrl"),
+    ("leftctrl", "lctrl"),
+    ("controlleft", "lctrl"),
+    ("rightalt", "ralt"),
+    ("altright", "ralt"),
+    ("leftalt", "lalt"),
+    ("altleft", "lalt"),
+    ("enter", "return"),
+];
+
+// Host keys for the Family BASIC keyboard keys that aren't named the same, normalized: SDL's
+// names first, then winit's for the punctuation SDL names by its symbol
+const FAMILY_KEYBOARD_KEYS: [(&str, &str); 26] = [
+    ("escape", "ESC"),
+    ("lctrl", "CTR"),
+    ("lalt", "GRPH"),
+    ("ralt", "KANA"),
+    ("end", "STOP"),
+    ("home", "CLR"),
+    ("insert", "INS"),
+    ("backspace", "DEL"),
+    ("delete", "DEL"),
+    ("'", ":"),
+    ("`", "@"),
+    ("=", "^"),
+    ("\\", "YEN"),
+    ("quote", ":"),
+    ("backquote", "@"),
+    ("equal", "^"),
+    ("backslash", "YEN"),
+    ("intlyen", "YEN"),
+    ("intlro", "_"),
+    ("bracketleft", "["),
+    ("bracketright", "]"),
+    ("semicolon", ";"),
+    ("minus", "-"),
+    ("slash", "/"),
+    ("comma", ","),
+    ("period", "."),
+];
+
+// Keys that aren't a letter, a digit or F1-F24, normalized: SDL's scancode names, then winit's
+// key codes without their "Key", "Digit" and "Arrow" prefixes
+const KEY_NAMES: [&str; 57] = [
+    "return",
+    "escape",
+    "backspace",
+    "tab",
+    "space",
+    "capslock",
+    "scrolllock",
+    "numlock",
+    "pause",
+    "printscreen",
+    "insert",
+    "delete",
+    "home",
+    "end",
+    "pageup",
+    "pagedown",
+    "up",
+    "down",
+    "left",
+    "right",
+    "lshift",
+    "rshift",
+    "lctrl",
+    "rctrl",
+    "lalt",
+    "ralt",
+    "leftgui",
+    "rightgui",
+    "application",
+    "-",
+    "=",
+    "[",
+    "]",
+    "\\",
+    ";",
+    "'",
+    "`",
+    ",",
+    ".",
+    "/",
+    "superleft",
+    "superright",
+    "contextmenu",
+    "minus",
+    "equal",
+    "bracketleft",
+    "bracketright",
+    "backslash",
+    "semicolon",
+    "quote",
+    "backquote",
+    "comma",
+    "period",
+    "slash",
+    "intlbackslash",
+    "intlro",
+    "intlyen",
+];
+
+// The keypad's keys other than its digits, after "keypad" (SDL) or "numpad" (winit)
+const KEYPAD_KEYS: [&str; 14] = [
+    "+", "-", "*", "/", ".", "enter", "add", "subtract", "multiply", "divide", "decimal", "period",
+    "comma", "equal",
+];
+
+// SDL's game controller button names
+#[cfg(feature = "video")]
+const GAMEPAD_BUTTONS: [&str; 21] = [
+    "a",
+    "b",
+    "x",
+    "y",
+    "back",
+    "guide",
+    "start",
+    "leftstick",
+    "rightstick",
+    "leftshoulder",
+    "rightshoulder",
+    "dpup",
+    "dpdown",
+    "dpleft",
+    "dpright",
+    "misc1",
+    "paddle1",
+    "paddle2",
+    "paddle3",
+    "paddle4",
+    "touchpad",
+];
+
+// Switches the host keyboard between the Family BASIC keyboard and the controller bindings
+const FAMILY_KEYBOARD_TOGGLE: &str = "scrolllock";
+
+// A key or gamepad button going down or up, by the name the window front end gives it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Key(String),
+    #[cfg(feature = "video")]
+    Gamepad(usize, String), // Gamepads are numbered in the order they were connected
+}
+
+// The front end's own functions, bound in the `[hotkeys]` config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    SaveState,
+    LoadState,
+    NextSlot,
+    PreviousSlot,
+    FastForward,
+    SlowMotion,
+    DebugOverlay,
+    OverlayPage,
+    NextCheat,
+    ToggleCheat,
+}
+
+// Which player's button each key and gamepad button drives, from the `[input]` config.
+// Buttons are numbered as in `BUTTON_NAMES`: 0-7 the controller's own, 8 and 9 autofire A and B.
+pub struct Keymap {
+    keys: Vec<(String, usize, usize)>, // Normalized key name, player, button
+    hotkeys: Vec<(String, Hotkey)>,    // Normalized key name
+    #[cfg(feature = "video")]
+    gamepads: Vec<[String; 10]>, // Normalized button names for each player's gamepad
+}
+
+impl Keymap {
+    pub fn new(config: &InputConfig, hotkeys: &HotkeyConfig) -> Self {
+        let players = [
+            &config.player1,
+            &config.player2,
+            &config.player3,
+            &config.player4,
+        ];
+        let keys = players
+            .iter()
+            .enumerate()
+            .flat_map(|(player, bindings)| {
+                bindings
+                    .keys()
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_, key)| !key.is_empty())
+                    .map(move |(button, key)| (normalize(key), player, button))
+            })
+            .collect();
+        let hotkeys = [
+            (&hotkeys.save_state, Hotkey::SaveState),
+            (&hotkeys.load_state, Hotkey::LoadState),
+            (&hotkeys.next_slot, Hotkey::NextSlot),
+            (&hotkeys.previous_slot, Hotkey::PreviousSlot),
+            (&hotkeys.fast_forward, Hotkey::FastForward),
+            (&hotkeys.slow_motion, Hotkey::SlowMotion),
+            (&hotkeys.debug_overlay, Hotkey::DebugOverlay),
+            (&hotkeys.overlay_page, Hotkey::OverlayPage),
+            (&hotkeys.next_cheat, Hotkey::NextCheat),
+            (&hotkeys.toggle_cheat, Hotkey::ToggleCheat),
+        ]
+        .into_iter()
+        .filter(|(key, _)| !key.is_empty())
+        .map(|(key, hotkey)| (normalize(key), hotkey))
+        .collect();
+        Self {
+            keys,
+            hotkeys,
+            #[cfg(feature = "video")]
+            gamepads: players
+                .iter()
+                .map(|bindings| bindings.gamepad.buttons().map(normalize))
+                .collect(),
+        }
+    }
+
+    pub fn hotkey(&self, input: &Input) -> Option<Hotkey> {
+        let key = match input {
+            Input::Key(key) => normalize(key),
+            #[cfg(feature = "video")]
+            Input::Gamepad(..) => return None,
+        };
+        self.hotkeys
+            .iter()
+            .find(|(bound, _)| *bound == key)
+            .map(|&(_, hotkey)| hotkey)
+    }
+
+    // The player (0-3) and button an input is bound to, if any
+    pub fn lookup(&self, input: &Input) -> Option<(usize, usize)> {
+        match input {
+            Input::Key(key) => {
+                let key = normalize(key);
+                self.keys
+                    .iter()
+                    .find(|(bound, _, _)| *bound == key)
+                    .map(|&(_, player, button)| (player, button))
+            }
+            #[cfg(feature = "video")]
+            Input::Gamepad(pad, name) => {
+                let name = normalize(name);
+                let buttons = self.gamepads.get(*pad)?;
+                let button = buttons.iter().position(|bound| *bound == name)?;
+                Some((*pad, button))
+            }
+        }
+    }
+}
+
+// The Family BASIC keyboard key a host key types in pass-through mode: letters, digits and
+// F1-F8 by the same name, the rest by their place on the keyboard
+pub fn family_keyboard_key(input: &Input) -> Option<usize> {
+    let key = match input {
+        Input::Key(key) => normalize(key),
+        #[cfg(feature = "video")]
+        Input::Gamepad(..) => return None,
+    };
+    let name = FAMILY_KEYBOARD_KEYS
+        .iter()
+        .find(|(host, _)| *host == key)
+        .map_or(key.as_str(), |(_, name)| name);
+    FamilyKeyboard::key_index(name)
+}
+
+// What a key does on the overlay's cheats and settings pages. Letters and digits type
+// themselves, and the keys for ';' and '/' type the ':' and '?' of raw cheat codes.
+pub fn page_key(input: &Input) -> Option<PageKey> {
+    let key = match input {
+        Input::Key(key) => normalize(key),
+        #[cfg(feature = "video")]
+        Input::Gamepad(..) => return None,
+    };
+    let page_key = match key.as_str() {
+        "up" => PageKey::Up,
+        "down" => PageKey::Down,
+        "left" => PageKey::Left,
+        "right" => PageKey::Right,
+        "return" => PageKey::Enter,
+        "escape" => PageKey::Escape,
+        "backspace" => PageKey::Backspace,
+        "delete" => PageKey::Delete,
+        ";" | "semicolon" => PageKey::Char(':'),
+        "/" | "slash" => PageKey::Char('?'),
+        _ => {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii_alphanumeric() => {
+                    PageKey::Char(c.to_ascii_uppercase())
+                }
+                _ => return None,
+            }
+        }
+    };
+    Some(page_key)
+}
+
+// Whether a key name from the config is one the window front ends report
+pub fn is_key_name(name: &str) -> bool {
+    let key = normalize(name);
+    if let Some(keypad) = key.strip_prefix("keypad").or(key.strip_prefix("numpad")) {
+        return matches!(keypad.as_bytes(), [b'0'..=b'9']) || KEYPAD_KEYS.contains(&keypad);
+    }
+    if let Some(number) = key
+        .strip_prefix('f')
+        .and_then(|number| number.parse::<u32>().ok())
+    {
+        return (1..=24).contains(&number);
+    }
+    matches!(key.as_bytes(), [c] if c.is_ascii_alphanumeric()) || KEY_NAMES.contains(&key.as_str())
+}
+
+#[cfg(feature = "video")]
+pub fn is_gamepad_button(name: &str) -> bool {
+    GAMEPAD_BUTTONS.contains(&normalize(name).as_str())
+}
+
+pub fn is_family_keyboard_toggle(input: &Input) -> bool {
+    matches!(input, Input::Key(key) if normalize(key) == FAMILY_KEYBOARD_TOGGLE)
+}
+
+// Names compare case-insensitively and without spaces, with aliases resolved
+fn normalize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    match ALIASES.iter().find(|(alias, _)| *alias == name) {
+        Some((_, key)) => key.to_string(),
+        None => name,
+    }
+}
@@ -0,0 +1,1395 @@
+#[cfg(feature = "audio")]
+mod audio;
+mod cli;
+mod config;
+mod crash;
+mod dump;
+#[cfg(all(feature = "winit", not(feature = "video")))]
+mod egui_overlay;
+#[cfg(any(feature = "video", feature = "winit"))]
+mod input;
+#[cfg(any(feature = "video", feature = "winit"))]
+mod overlay;
+mod pacer;
+#[cfg(any(feature = "video", feature = "winit"))]
+mod presenter;
+mod recorder;
+#[cfg(any(feature = "video", feature = "winit"))]
+mod resume;
+#[cfg(feature = "video")]
+mod video;
+#[cfg(all(feature = "winit", not(feature = "video")))]
+mod winit_video;
+mod wizard;
+
+#[cfg(any(feature = "video", feature = "winit"))]
+use std::cell::RefCell;
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process;
+#[cfg(any(feature = "video", feature = "winit"))]
+use std::rc::Rc;
+#[cfg(any(feature = "video", feature = "winit"))]
+use std::thread;
+use std::time::Instant;
+
+use config::{Config, GameConfig};
+use crash::CrashContext;
+use dump::{AudioDump, VideoDump};
+use pacer::FramePacer;
+#[cfg(any(feature = "video", feature = "winit"))]
+use presenter::Screen;
+use recorder::VideoRecorder;
+use rustendo_core::apu::AudioFilter;
+use rustendo_core::cheat::Cheats;
+use rustendo_core::checksum::crc32;
+#[cfg(any(feature = "video", feature = "winit"))]
+use rustendo_core::controller::Controller;
+use rustendo_core::game_db::GameDb;
+use rustendo_core::movie::Movie;
+use rustendo_core::nes::DEFAULT_RECORDING_RATE;
+#[cfg(any(feature = "video", feature = "winit"))]
+use rustendo_core::netplay::{self, Netplay};
+use rustendo_core::patch;
+use rustendo_core::zip;
+use rustendo_core::{Nes, Overscan, Palette, Region, Rom};
+use tracing_subscriber::EnvFilter;
+#[cfg(feature = "video")]
+use video::VideoOutput;
+#[cfg(all(feature = "winit", not(feature = "video")))]
+use winit_video::VideoOutput;
+
+// Frames between checks for changed save data, about a second
+const AUTOSAVE_INTERVAL: u64 = 60;
+
+// Save state slots per game, numbered from 0
+#[cfg(any(feature = "video", feature = "winit"))]
+const STATE_SLOTS: usize = 10;
+
+// How long the offer to resume the last session stands, in frames from power-on
+#[cfg(any(feature = "video", feature = "winit"))]
+const RESUME_OFFER_FRAMES: u64 = 600;
+
+#[cfg(any(feature = "video", feature = "winit"))]
+const RESUME_PROMPT: &str = "Resume? Enter: yes, Esc: no";
+
+#[derive(Default)]
+struct Options {
+    rom_path: Option<PathBuf>,
+    setup: bool,
+    palette: Option<PathBuf>,
+    game_db: Option<PathBuf>,
+    patch: Option<PathBuf>,
+    region: Option<Region>,
+    scale: Option<u32>,
+    fullscreen: bool,
+    #[cfg(feature = "audio")]
+    no_audio: bool,
+    cheats: Vec<String>,
+    mappings: Vec<String>,
+    ntsc: bool,
+    no_overscan: bool,
+    record_wav: Option<PathBuf>,
+    record_stems: bool,
+    record_video: Option<PathBuf>,
+    record_input: Option<PathBuf>,
+    play_input: Option<PathBuf>,
+    headless: bool,
+    bench: bool,
+    frames: Option<u64>,
+    cycles: Option<u64>,
+    screenshot: Option<PathBuf>,
+    dump_video: Option<PathBuf>,
+    dump_audio: Option<PathBuf>,
+    netplay_host: Option<u16>,       // UDP port to wait on
+    netplay_connect: Option<String>, // host:port to join
+    #[cfg(any(feature = "video", feature = "winit"))]
+    netplay_delay: Option<u8>,
+    log: Option<String>, // Log filter, RUST_LOG syntax
+}
+
+impl Options {
+    fn netplay(&self) -> bool {
+        self.netplay_host.is_some() || self.netplay_connect.is_some()
+    }
+}
+
+// Send the core's log events (targets cpu, ppu, apu and mapper) to stderr. `filter` is in
+// RUST_LOG syntax, e.g. `cpu=trace,mapper=debug`, and takes precedence over RUST_LOG; with
+// neither only warnings and errors are shown.
+fn init_logging(filter: Option<&str>) {
+    let filter = filter
+        .map(str::to_string)
+        .or_else(|| env::var("RUST_LOG").ok())
+        .unwrap_or_else(|| "warn".to_string());
+    let filter = match EnvFilter::try_new(&filter) {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("Invalid log filter {}: {}", filter, e);
+            process::exit(1);
+        }
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
+}
+
+fn load_palette(path: &Path) -> Palette {
+    let result = fs::read(path)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| Palette::from_bytes(&bytes).map_err(|e| e.to_string()));
+    match result {
+        Ok(palette) => palette,
+        Err(e) => {
+            eprintln!("Error loading palette {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    }
+}
+
+// Load the ROM, or the first one in a zip file, with a patch applied if one was given or sits
+// next to it as game.ips/game.bps
+fn load_rom(rom_path: &Path, patch: Option<&Path>) -> Result<Rom, String> {
+    let sibling = ["ips", "bps"]
+        .into_iter()
+        .map(|extension| rom_path.with_extension(extension))
+        .find(|path| path.is_file());
+    let patch = patch.map(Path::to_path_buf).or(sibling);
+
+    fs::read(rom_path)
+        .map_err(|e| e.to_string())
+        .and_then(|data| {
+            if zip::is_zip(&data) {
+                zip::extract_rom(&data).map_err(|e| e.to_string())
+            } else {
+                Ok(data)
+            }
+        })
+        .and_then(|data| {
+            let data = match &patch {
+                Some(path) => {
+                    let patch = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                    patch::apply(&data, &patch).map_err(|e| format!("{}: {}", path.display(), e))?
+                }
+                None => data,
+            };
+            Rom::from_bytes(&data).map_err(|e| e.to_string())
+        })
+}
+
+// Fix up the header of dumps the game database knows
+fn apply_game_db(rom: &mut Rom, path: &Path) {
+    let result = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| GameDb::parse(&text).map_err(|e| e.to_string()));
+    match result {
+        Ok(db) => {
+            if let Some(entry) = db.lookup(rom) {
+                entry.apply(rom);
+            }
+        }
+        Err(e) => eprintln!("Error loading game database {}: {}", path.display(), e),
+    }
+}
+
+// A relative ROM path that doesn't lead anywhere from the current directory is looked up in the
+// configured ROM directory
+fn find_rom(config: &Config, rom_path: PathBuf) -> PathBuf {
+    match &config.rom_directory {
+        Some(directory) if rom_path.is_relative() && !rom_path.exists() => directory.join(rom_path),
+        _ => rom_path,
+    }
+}
+
+// Load the user's configuration, walking them through setup the first time (or when asked to)
+fn load_config(force_setup: bool) -> Config {
+    let Some(path) = Config::default_path() else {
+        return Config::default();
+    };
+
+    if path.exists() && !force_setup {
+        return match Config::load(&path) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Error reading {}: {}", path.display(), e);
+                process::exit(1);
+            }
+        };
+    }
+
+    if !io::stdin().is_terminal() {
+        return Config::default();
+    }
+
+    #[cfg(feature = "audio")]
+    let audio_devices = audio::device_names();
+    #[cfg(not(feature = "audio"))]
+    let audio_devices = Vec::new();
+    let config = match wizard::run(io::stdin().lock(), io::stdout(), &audio_devices) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Setup aborted: {}", e);
+            process::exit(1);
+        }
+    };
+    match config.save(&path) {
+        Ok(()) => println!("Saved configuration to {}", path.display()),
+        Err(e) => eprintln!("Could not save configuration to {}: {}", path.display(), e),
+    }
+    config
+}
+
+// Restore the cartridge's save data (battery RAM or flash) from an earlier session
+fn load_save(nes: &mut Nes, path: &Path) {
+    if nes.save_data().is_none() {
+        return;
+    }
+    match fs::read(path) {
+        Ok(data) => nes.load_save_data(&data),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => eprintln!("Could not read save file {}: {}", path.display(), e),
+    }
+}
+
+// Returns whether the file is safe to write over: it was read, or there isn't one yet
+fn load_cheats(nes: &mut Nes, path: &Path) -> bool {
+    let result = match fs::read_to_string(path) {
+        Ok(text) => Cheats::parse(&text).map_err(|e| e.to_string()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return true,
+        Err(e) => Err(e.to_string()),
+    };
+    match result {
+        Ok(cheats) => {
+            nes.set_cheats(cheats);
+            true
+        }
+        Err(e) => {
+            eprintln!("Could not read cheat file {}: {}", path.display(), e);
+            false
+        }
+    }
+}
+
+fn save_cheats(nes: &Nes, path: Option<&Path>) {
+    let Some(path) = path else {
+        return;
+    };
+    if let Err(e) = fs::write(path, nes.cheats().to_text()) {
+        eprintln!("Could not write cheat file {}: {}", path.display(), e);
+    }
+}
+
+// Drive the controllers from a recording made with --record-input, exiting if it can't be read
+fn play_input(nes: &mut Nes, path: &Path) {
+    let result = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| Movie::parse(&text).map_err(|e| e.to_string()))
+        .and_then(|movie| nes.play_movie(&movie).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        eprintln!("Error playing {}: {}", path.display(), e);
+        process::exit(1);
+    }
+}
+
+fn save_input_recording(nes: &mut Nes, path: &Path) {
+    let Some(movie) = nes.stop_input_recording() else {
+        return;
+    };
+    match fs::write(path, movie.to_text()) {
+        Ok(()) => println!("Saved input recording to {}", path.display()),
+        Err(e) => eprintln!("Could not write input recording {}: {}", path.display(), e),
+    }
+}
+
+fn start_video_recording(nes: &mut Nes, options: &Options) -> Option<VideoRecorder> {
+    let path = options.record_video.as_ref()?;
+    match VideoRecorder::start(path, nes, options.record_wav.as_deref()) {
+        Ok(recorder) => Some(recorder),
+        Err(e) => {
+            eprintln!("Error recording to {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    }
+}
+
+// Add the frame just finished to the video, giving up on the recording if that fails
+fn record_frame(recorder: &mut Option<VideoRecorder>, nes: &mut Nes) {
+    let Some(video) = recorder.as_mut() else {
+        return;
+    };
+    if let Err(e) = video.push_frame(nes.output_frame()) {
+        eprintln!("Stopped recording the video: {}", e);
+        *recorder = None;
+    }
+}
+
+fn finish_video_recording(recorder: Option<VideoRecorder>, nes: &mut Nes, options: &Options) {
+    let (Some(recorder), Some(path)) = (recorder, &options.record_video) else {
+        return;
+    };
+    match recorder.finish(nes) {
+        Ok(()) => println!("Saved video to {}", path.display()),
+        Err(e) => eprintln!("Error finishing the video {}: {}", path.display(), e),
+    }
+}
+
+fn dump_failed(path: &Path, e: io::Error) -> ! {
+    eprintln!("Error writing {}: {}", path.display(), e);
+    process::exit(1);
+}
+
+// Run as fast as possible, without a window or sound, until the frame or cycle limit (whichever
+// comes first), then print a CRC32 of the last frame and how long it took. The save file is
+// read but never written, so runs can be repeated. The video and audio dumps get every whole
+// frame and the sound made during it, so a run cut short mid-frame leaves that frame out of both.
+fn run_headless(
+    nes: &mut Nes,
+    options: &Options,
+    mut video: Option<VideoRecorder>,
+    crash: &CrashContext,
+) {
+    let mut video_dump = options.dump_video.as_ref().map(|path| {
+        let overscan = nes.overscan();
+        let rate = nes.region().frame_rate_fraction();
+        VideoDump::create(path, overscan.width(), overscan.height(), rate)
+            .unwrap_or_else(|e| dump_failed(path, e))
+    });
+    let mut audio_dump = options.dump_audio.as_ref().map(|path| {
+        let rate = nes.audio_sample_rate().unwrap_or(DEFAULT_RECORDING_RATE);
+        nes.set_audio_sample_rate(Some(rate));
+        println!(
+            "Dumping audio as 16-bit signed little-endian mono PCM at {} Hz",
+            rate
+        );
+        AudioDump::create(path).unwrap_or_else(|e| dump_failed(path, e))
+    });
+    let mut samples = Vec::new();
+
+    let frames = options.frames.unwrap_or(u64::MAX);
+    let cycles = options.cycles.unwrap_or(u64::MAX);
+    let start = Instant::now();
+    crash::guard(nes, crash, |nes| {
+        while nes.frame() < frames && nes.cycles() < cycles {
+            let frame = nes.frame();
+            nes.step();
+            if nes.frame() == frame {
+                continue;
+            }
+            record_frame(&mut video, nes);
+            if let (Some(dump), Some(path)) = (video_dump.as_mut(), &options.dump_video) {
+                dump.write_frame(nes.output_frame())
+                    .unwrap_or_else(|e| dump_failed(path, e));
+            }
+            if let (Some(dump), Some(path)) = (audio_dump.as_mut(), &options.dump_audio) {
+                nes.take_audio_samples(&mut samples);
+                dump.write(&samples)
+                    .unwrap_or_else(|e| dump_failed(path, e));
+                samples.clear();
+            }
+        }
+    });
+    let elapsed = start.elapsed().as_secs_f64();
+    let (frame_count, cycle_count) = (nes.frame(), nes.cycles());
+
+    let hash = nes.frame_hash();
+    let overscan = nes.overscan();
+    let (width, height) = (overscan.width(), overscan.height());
+    let frame = nes.output_frame();
+    println!(
+        "Frame {} (cycle {}): CRC32 {:08X}, hash {:016X}",
+        frame_count,
+        cycle_count,
+        crc32(frame),
+        hash
+    );
+    println!(
+        "Ran in {:.3}s ({:.1} frames per second)",
+        elapsed,
+        frame_count as f64 / elapsed
+    );
+    if let Some(path) = &options.screenshot {
+        if let Err(e) = write_png(path, width as u32, height as u32, frame) {
+            eprintln!("Error writing {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    }
+    if let Some(path) = &options.record_input {
+        save_input_recording(nes, path);
+    }
+    finish_video_recording(video, nes, options);
+    if let (Some(dump), Some(path)) = (video_dump, &options.dump_video) {
+        dump.finish().unwrap_or_else(|e| dump_failed(path, e));
+    }
+    if let (Some(dump), Some(path)) = (audio_dump, &options.dump_audio) {
+        dump.finish().unwrap_or_else(|e| dump_failed(path, e));
+    }
+}
+
+// The game's profile, if it has one, found by its CRC-32
+fn load_profile(crc32: u32) -> Result<Option<GameConfig>, String> {
+    let Some(path) = Config::profile_path(crc32) else {
+        return Ok(None);
+    };
+    Config::load_profile(&path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// The ROM, fixed up by the game database, and `config` with the game's profile applied
+fn load_game(
+    options: &Options,
+    config: &Config,
+    rom_path: &Path,
+    patch: Option<&Path>,
+) -> Result<(Config, Rom), String> {
+    let mut rom = load_rom(rom_path, patch)?;
+    if let Some(path) = &options.game_db {
+        apply_game_db(&mut rom, path);
+    }
+    let mut config = config.clone();
+    config.apply_game(rom_path, load_profile(rom.crc32())?);
+    // The header and database pick the console timing unless the game's settings or the
+    // command line override it
+    if let Some(region) = config.game(rom_path).and_then(|game| game.region) {
+        rom.region = region.into();
+    }
+    if let Some(region) = options.region {
+        rom.region = region;
+    }
+    Ok((config, rom))
+}
+
+fn new_console(rom: &Rom) -> Nes {
+    match Nes::new(rom) {
+        Ok(nes) => nes,
+        Err(e) => {
+            eprintln!("Error loading ROM: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// Run `frames` frames from power-on as fast as possible, twice: once to time them, then again
+// with the subsystems timed separately, which slows them down. The console has no cheats,
+// filters or settings from the config, so runs on different builds compare.
+fn run_bench(rom: &Rom, frames: u64) {
+    let mut nes = new_console(rom);
+    let start = Instant::now();
+    while nes.frame() < frames {
+        nes.step();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    let emulated = frames as f64 / nes.region().frame_rate();
+    println!("{} frames in {:.3}s", frames, elapsed);
+    println!(
+        "{:.1} frames per second ({:.2}x real time)",
+        frames as f64 / elapsed,
+        emulated / elapsed
+    );
+    println!(
+        "{:.2}M instructions per second, {:.2}M CPU cycles per second",
+        nes.instructions() as f64 / elapsed / 1e6,
+        nes.cycles() as f64 / elapsed / 1e6
+    );
+
+    let mut nes = new_console(rom);
+    nes.set_profiling(true);
+    while nes.frame() < frames {
+        nes.step();
+    }
+    let Some(profile) = nes.profile() else {
+        return;
+    };
+    let total = profile.total.as_secs_f64();
+    println!("Time per subsystem ({:.3}s with the timers):", total);
+    for (name, time) in [
+        ("CPU", profile.cpu()),
+        ("PPU", profile.ppu),
+        ("APU", profile.apu),
+        ("Cartridge", profile.cartridge),
+    ] {
+        let time = time.as_secs_f64();
+        println!(
+            "  {:<10} {:>8.3}s {:>5.1}%",
+            name,
+            time,
+            time / total * 100.0
+        );
+    }
+}
+
+// Connect to the other player, or wait for them to connect, and let the session drive the
+// controllers. None when the command line asks for no netplay; exits if the session can't be set
+// up.
+#[cfg(any(feature = "video", feature = "winit"))]
+fn start_netplay(nes: &mut Nes, options: &Options, rom_crc32: u32) -> Option<Rc<RefCell<Netplay>>> {
+    let session = match (options.netplay_host, &options.netplay_connect) {
+        (Some(port), _) => {
+            println!("Waiting for the other player on UDP port {}", port);
+            let delay = options.netplay_delay.unwrap_or(netplay::DEFAULT_DELAY);
+            Netplay::host(port, rom_crc32, delay)
+        }
+        (None, Some(address)) => {
+            println!("Connecting to {}", address);
+            Netplay::connect(address.as_str(), rom_crc32)
+        }
+        (None, None) => return None,
+    };
+    let session = match session {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("Netplay: {}", e);
+            process::exit(1);
+        }
+    };
+    println!(
+        "Connected as player {}, with {} frames of input delay",
+        session.local_port() + 1,
+        session.delay()
+    );
+    let session = Rc::new(RefCell::new(session));
+    let source = Rc::clone(&session);
+    nes.set_input_source(move |frame, port| source.borrow_mut().input(frame, port));
+    Some(session)
+}
+
+#[cfg(any(feature = "video", feature = "winit"))]
+fn press(controller: &mut Controller, button: usize, pressed: bool) {
+    match button {
+        0..=7 if pressed => controller.press_button(button),
+        0..=7 => controller.release_button(button),
+        _ => controller.set_turbo(button - 8, pressed),
+    }
+}
+
+// Each game's save states get a directory of their own, named after the ROM file, in the
+// user's data directory (or next to the ROM without one), holding slot0.state to slot9.state
+// and the session it was left in
+#[cfg(any(feature = "video", feature = "winit"))]
+fn state_directory(rom_path: &Path) -> PathBuf {
+    let name = rom_path.file_stem().unwrap_or_default();
+    match dirs::data_dir() {
+        Some(dir) => dir.join("rustendo").join("states").join(name),
+        None => rom_path.with_extension("states"),
+    }
+}
+
+#[cfg(any(feature = "video", feature = "winit"))]
+fn state_path(rom_path: &Path, slot: usize) -> PathBuf {
+    state_directory(rom_path).join(format!("slot{}.state", slot))
+}
+
+#[cfg(any(feature = "video", feature = "winit"))]
+fn save_state(nes: &mut Nes, rom_path: &Path, slot: usize) {
+    let path = state_path(rom_path, slot);
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&path, nes.save_state()));
+    match result {
+        Ok(()) => {
+            println!("Saved state to {}", path.display());
+            nes.show_message(&format!("Saved state {}", slot));
+        }
+        Err(e) => {
+            eprintln!("Could not write state {}: {}", path.display(), e);
+            nes.show_message(&format!("Could not save state {}", slot));
+        }
+    }
+}
+
+#[cfg(any(feature = "video", feature = "winit"))]
+fn load_state(nes: &mut Nes, rom_path: &Path, slot: usize) {
+    let path = state_path(rom_path, slot);
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            nes.show_message(&format!("State {} is empty", slot));
+            return;
+        }
+        Err(e) => {
+            eprintln!("Could not read state {}: {}", path.display(), e);
+            nes.show_message(&format!("Could not load state {}", slot));
+            return;
+        }
+    };
+    match nes.load_state(&data) {
+        Ok(()) => {
+            println!("Loaded state from {}", path.display());
+            nes.show_message(&format!("Loaded state {}", slot));
+        }
+        Err(e) => {
+            eprintln!("Could not load state {}: {}", path.display(), e);
+            nes.show_message(&format!("Could not load state {}", slot));
+        }
+    }
+}
+
+// The session the game was left in last time, if it was kept, offered on screen
+#[cfg(any(feature = "video", feature = "winit"))]
+fn find_resume(nes: &mut Nes, rom_path: &Path) -> Option<resume::Resume> {
+    match resume::find(nes, &state_directory(rom_path)) {
+        Ok(Some(resume)) => {
+            nes.show_message(RESUME_PROMPT);
+            Some(resume)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            eprintln!("Not resuming the last session: {}", e);
+            nes.show_message("Could not resume the last session");
+            None
+        }
+    }
+}
+
+#[cfg(any(feature = "video", feature = "winit"))]
+fn resume_session(nes: &mut Nes, resume: &resume::Resume, session: &mut overlay::Session) {
+    match resume.load(nes) {
+        Ok(()) => {
+            session.restore(nes, &resume.settings);
+            nes.show_message("Resumed the last session");
+        }
+        Err(e) => {
+            eprintln!("Could not resume the last session: {}", e);
+            nes.show_message("Could not resume the last session");
+        }
+    }
+}
+
+// Keep the console's state and settings to offer them on the next start. The cartridge save
+// has to be written first, as the session is checked against it.
+#[cfg(any(feature = "video", feature = "winit"))]
+fn keep_session(nes: &mut Nes, rom_path: &Path, session: &overlay::Session) {
+    let directory = state_directory(rom_path);
+    let settings = session.settings(nes);
+    if let Err(e) = resume::save(nes, &directory, settings) {
+        eprintln!(
+            "Could not save the session to {}: {}",
+            directory.display(),
+            e
+        );
+    }
+}
+
+// Select another slot and say whether it holds a state
+#[cfg(any(feature = "video", feature = "winit"))]
+fn select_slot(nes: &mut Nes, rom_path: &Path, slot: usize) {
+    let used = state_path(rom_path, slot).is_file();
+    nes.show_message(&format!(
+        "State slot {}{}",
+        slot,
+        if used { "" } else { " (empty)" }
+    ));
+}
+
+// What's left to do after the debug overlay made a change
+#[cfg(any(feature = "video", feature = "winit"))]
+fn finish_edit(
+    nes: &mut Nes,
+    edit: Option<overlay::Edit>,
+    session: &mut overlay::Session,
+    rom_path: &Path,
+    cheat_path: Option<&Path>,
+) {
+    match edit {
+        Some(overlay::Edit::NextSlot) => {
+            session.slot = (session.slot + 1) % STATE_SLOTS;
+            select_slot(nes, rom_path, session.slot);
+        }
+        Some(overlay::Edit::PreviousSlot) => {
+            session.slot = (session.slot + STATE_SLOTS - 1) % STATE_SLOTS;
+            select_slot(nes, rom_path, session.slot);
+        }
+        Some(overlay::Edit::CheatsChanged) => save_cheats(nes, cheat_path),
+        None => {}
+    }
+}
+
+#[cfg(any(feature = "video", feature = "winit"))]
+fn show_cheat(nes: &mut Nes, index: usize) {
+    let message = match nes.cheats().iter().nth(index) {
+        Some(entry) => format!(
+            "Cheat {}/{}: {} {}",
+            index + 1,
+            nes.cheats().len(),
+            entry.code,
+            if entry.enabled { "on" } else { "off" }
+        ),
+        None => "No cheats for this game".to_string(),
+    };
+    nes.show_message(&message);
+}
+
+#[cfg(any(feature = "video", feature = "winit"))]
+fn toggle_cheat(nes: &mut Nes, index: usize) {
+    let Some((code, enabled)) = nes
+        .cheats()
+        .iter()
+        .nth(index)
+        .map(|entry| (entry.code.clone(), entry.enabled))
+    else {
+        return;
+    };
+    nes.set_cheat_enabled(&code, !enabled);
+}
+
+fn write_png(path: &Path, width: u32, height: u32, rgba: &[u8]) -> Result<(), Box<dyn Error>> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(rgba)?;
+    Ok(())
+}
+
+// Write the save data out whenever the game has changed it
+fn autosave(nes: &mut Nes, path: &Path) {
+    if nes.frame().is_multiple_of(AUTOSAVE_INTERVAL) {
+        save(nes, path);
+    }
+}
+
+fn save(nes: &mut Nes, path: &Path) {
+    if !nes.take_save_changed() {
+        return;
+    }
+    if let Some(data) = nes.save_data() {
+        if let Err(e) = fs::write(path, data) {
+            eprintln!("Could not write save file {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(any(feature = "video", feature = "winit"))]
+fn open_video(
+    config: &config::VideoConfig,
+    rom_path: &Path,
+    overscan: Overscan,
+    vsync: bool,
+) -> VideoOutput {
+    let title = window_title(rom_path);
+    let (width, height) = (overscan.width() as u32, overscan.height() as u32);
+    match VideoOutput::open(
+        &title,
+        width,
+        height,
+        config.scale,
+        config.fullscreen,
+        vsync,
+    ) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("Error opening a window: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(any(feature = "video", feature = "winit"))]
+fn window_title(rom_path: &Path) -> String {
+    match rom_path.file_stem() {
+        Some(name) => format!("rustendo - {}", name.to_string_lossy()),
+        None => "rustendo".to_string(),
+    }
+}
+
+// The sample rate that makes the device play the sound at `speed` times normal, or None when
+// there's no keeping up (uncapped)
+#[cfg(feature = "audio")]
+fn sample_rate_at_speed(device_rate: u32, speed: f64) -> Option<u32> {
+    (speed > 0.0).then(|| (device_rate as f64 / speed).round() as u32)
+}
+
+#[cfg(feature = "audio")]
+fn open_audio(config: &config::AudioConfig, nes: &mut Nes) -> Option<audio::AudioOutput> {
+    match audio::AudioOutput::open(config) {
+        Ok(output) => {
+            nes.set_audio_sample_rate(Some(output.sample_rate()));
+            Some(output)
+        }
+        Err(e) => {
+            eprintln!("Audio disabled: {}", e);
+            None
+        }
+    }
+}
+
+fn main() {
+    let mut options = cli::parse();
+    init_logging(options.log.as_deref());
+    crash::install_hook();
+    if options.setup {
+        load_config(true);
+        return;
+    }
+
+    let mut config = load_config(false);
+    let rom_path = find_rom(
+        &config,
+        options
+            .rom_path
+            .take()
+            .expect("the ROM is required without --setup"),
+    );
+    options.rom_path = Some(rom_path.clone());
+    for mapping in &options.mappings {
+        if config.input.map(mapping).is_none() {
+            eprintln!(
+                "Invalid --map {}: expected e.g. player1.a=Space or player2.gamepad.start=start",
+                mapping
+            );
+            process::exit(1);
+        }
+    }
+    if let Some(scale) = options.scale {
+        config.video.scale = scale;
+    }
+    config.video.fullscreen |= options.fullscreen;
+
+    let (game_config, rom) = match load_game(&options, &config, &rom_path, options.patch.as_deref())
+    {
+        Ok(game) => game,
+        Err(e) => {
+            eprintln!("Error loading ROM: {}", e);
+            process::exit(1);
+        }
+    };
+    if let (true, Some(frames)) = (options.bench, options.frames) {
+        run_bench(&rom, frames);
+        return;
+    }
+    if options.headless {
+        let mut game = start_game(&options, &game_config, &rom);
+        run_headless(&mut game.nes, &options, game.video_recording, &game.crash);
+        return;
+    }
+    #[cfg(any(feature = "video", feature = "winit"))]
+    run_windowed(options, config, game_config, rom);
+    #[cfg(not(any(feature = "video", feature = "winit")))]
+    run(
+        start_game(&options, &game_config, &rom),
+        &options,
+        &game_config,
+    );
+}
+
+// The console and what the front end keeps alongside it for the game
+struct Game {
+    nes: Nes,
+    #[cfg(any(feature = "video", feature = "winit"))]
+    rom_path: PathBuf,
+    save_path: PathBuf,
+    #[cfg(any(feature = "video", feature = "winit"))]
+    cheat_path: Option<PathBuf>, // None if the game's cheat file couldn't be read
+    video_recording: Option<VideoRecorder>,
+    crash: CrashContext,
+}
+
+// Power on the console with the game, set up the way the command line and config say
+fn start_game(options: &Options, config: &Config, rom: &Rom) -> Game {
+    let rom_path = options.rom_path.clone().unwrap_or_default();
+    let mut nes = new_console(rom);
+
+    // The game's cheat file first, then its config section and the command line. A cheat from
+    // the config that the file has switched off stays off; one given on the command line is
+    // switched on. Any new ones are added to the file, unless it couldn't be read.
+    let cheat_path = rom_path.with_extension("cht");
+    let cheat_path = load_cheats(&mut nes, &cheat_path).then_some(cheat_path);
+    let saved_cheats = nes.cheats().clone();
+    let game = config.game(&rom_path);
+    let config_cheats = game.map_or(&[][..], |game| &game.cheats[..]);
+    let config_cheats = config_cheats
+        .iter()
+        .filter(|code| !saved_cheats.contains(code));
+    for code in config_cheats.chain(&options.cheats) {
+        if let Err(e) = nes.add_cheat(code) {
+            eprintln!("Ignoring cheat {}: {}", code, e);
+        }
+    }
+    if *nes.cheats() != saved_cheats {
+        save_cheats(&nes, cheat_path.as_deref());
+    }
+
+    // Both consoles in a netplay session have to start out the same, so it leaves battery saves
+    // alone
+    let save_path = rom_path.with_extension("sav");
+    if !options.netplay() {
+        load_save(&mut nes, &save_path);
+    }
+
+    // The command line takes precedence over the config file
+    if let Some(path) = options.palette.as_ref().or(config.video.palette.as_ref()) {
+        nes.set_palette(load_palette(path));
+    }
+    nes.set_four_score(config.input.four_score);
+    nes.set_family_keyboard(config.input.family_keyboard);
+    nes.set_turbo_rate(config.input.turbo_rate);
+    nes.set_ntsc_filter(options.ntsc || config.video.ntsc_filter);
+    nes.set_render_mode(config.video.render_mode.into());
+    nes.set_overclock(game.and_then(|game| game.overclock).unwrap_or(0));
+    nes.set_fds_volume(config.audio.fds_volume.clamp(0.0, 2.0));
+    let filters: Vec<AudioFilter> = config.audio.filters.iter().map(AudioFilter::from).collect();
+    nes.set_audio_filters(&filters);
+    nes.set_overscan(overscan(options, config));
+
+    // The WAV header is kept up to date as it is written, so the file is complete whenever the
+    // emulator is closed
+    if let Some(path) = &options.record_wav {
+        if let Err(e) = nes.start_audio_recording(path, options.record_stems) {
+            eprintln!("Error recording to {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    }
+
+    // Both start from power-on, before the first frame. The keyboard and gamepads are ignored
+    // while a recording plays so they can't make it go out of sync.
+    if let Some(path) = &options.play_input {
+        play_input(&mut nes, path);
+    }
+    if options.record_input.is_some() {
+        nes.start_input_recording();
+    }
+
+    let video_recording = start_video_recording(&mut nes, options);
+    // Kept for a crash report, as is the rest of the context
+    nes.set_instruction_history(crash::HISTORY);
+    let crash = CrashContext::new(&rom_path, rom, config);
+    Game {
+        nes,
+        #[cfg(any(feature = "video", feature = "winit"))]
+        rom_path,
+        save_path,
+        #[cfg(any(feature = "video", feature = "winit"))]
+        cheat_path,
+        video_recording,
+        crash,
+    }
+}
+
+fn overscan(options: &Options, config: &Config) -> Overscan {
+    if options.no_overscan {
+        Overscan::NONE
+    } else {
+        Overscan::from(&config.video.overscan)
+    }
+}
+
+// Open the window on this thread and run the emulator on another, so that waiting for the
+// display or the GPU never holds up emulation. Frames go one way and input the other. A ROM
+// dropped on the window takes the place of the game, with a console of its own; `config` is
+// the settings before `game_config` added the first game's profile, for the next game's.
+#[cfg(any(feature = "video", feature = "winit"))]
+fn run_windowed(mut options: Options, config: Config, game_config: Config, rom: Rom) {
+    let vsync = game_config.video.pacing == pacer::PacingStrategy::Vsync;
+    let rom_path = options.rom_path.clone().unwrap_or_default();
+    let overscan = overscan(&options, &game_config);
+    let mut video = open_video(&game_config.video, &rom_path, overscan, vsync);
+    let (mut screen, presenter) = presenter::connect();
+    let emulation = thread::spawn(move || {
+        let (mut game_config, mut rom) = (game_config, rom);
+        while let Some((rom_path, next_config, next_rom)) = run(
+            start_game(&options, &game_config, &rom),
+            &options,
+            &game_config,
+            &config,
+            &rom,
+            &mut screen,
+        ) {
+            // The patch and cheats on the command line were for the game it named
+            screen.set_title(window_title(&rom_path));
+            options.rom_path = Some(rom_path);
+            options.patch = None;
+            options.cheats.clear();
+            (game_config, rom) = (next_config, next_rom);
+        }
+    });
+    // A panic on the emulation thread has printed its message already
+    if presenter.run(&mut video, emulation).is_err() {
+        process::exit(101);
+    }
+}
+
+// One frame per iteration: emulate it, handle input and show it, queue its sound, then wait
+// until the next one is due. Returns when the window is closed, or with the path, settings and
+// ROM of the game dropped on it, which `base_config` is the settings for before its profile.
+fn run(
+    game: Game,
+    options: &Options,
+    config: &Config,
+    #[cfg(any(feature = "video", feature = "winit"))] base_config: &Config,
+    #[cfg(any(feature = "video", feature = "winit"))] rom: &Rom,
+    #[cfg(any(feature = "video", feature = "winit"))] screen: &mut Screen,
+) -> Option<(PathBuf, Config, Rom)> {
+    let Game {
+        mut nes,
+        #[cfg(any(feature = "video", feature = "winit"))]
+        rom_path,
+        save_path,
+        #[cfg(any(feature = "video", feature = "winit"))]
+        cheat_path,
+        mut video_recording,
+        crash,
+    } = game;
+    #[cfg(any(feature = "video", feature = "winit"))]
+    let rom_path = rom_path.as_path();
+
+    #[cfg(any(feature = "video", feature = "winit"))]
+    let mut netplay = start_netplay(&mut nes, options, rom.crc32());
+    #[cfg(not(any(feature = "video", feature = "winit")))]
+    if options.netplay() {
+        eprintln!("Netplay needs a window (the video or winit feature)");
+        process::exit(1);
+    }
+    // The local player's buttons in netplay, which the session hands to the console
+    #[cfg(any(feature = "video", feature = "winit"))]
+    let mut local_pad = Controller::new();
+
+    #[cfg(feature = "audio")]
+    let mut audio = if options.no_audio {
+        None
+    } else {
+        open_audio(&config.audio, &mut nes)
+    };
+    #[cfg(feature = "audio")]
+    let mut samples = Vec::new();
+
+    #[cfg(any(feature = "video", feature = "winit"))]
+    let vsync = config.video.pacing == pacer::PacingStrategy::Vsync;
+    #[cfg(any(feature = "video", feature = "winit"))]
+    let keymap = input::Keymap::new(&config.input, &config.hotkeys);
+    // Fast-forward while its key is held, slow motion toggled by another
+    #[cfg(any(feature = "video", feature = "winit"))]
+    let (mut fast_forward, mut slow_motion) = (false, false);
+    // With the Family BASIC keyboard connected, typing goes to it until Scroll Lock hands the
+    // keys back to the controllers
+    #[cfg(any(feature = "video", feature = "winit"))]
+    let mut keyboard_mode = config.input.family_keyboard;
+    // The debug overlay's page while it's showing, and the one it comes back on
+    #[cfg(feature = "video")]
+    let (mut overlay_page, mut last_overlay_page) = (None, overlay::OverlayPage::Cpu);
+    // Whether the debug overlay's egui windows are showing
+    #[cfg(all(feature = "winit", not(feature = "video")))]
+    let mut overlay_open = false;
+    // The state slot, the cheat the cheat hotkeys act on and what the settings page changes
+    #[cfg(any(feature = "video", feature = "winit"))]
+    let mut session = overlay::Session::new(config);
+    // Recordings, playback and netplay start from power-on, so they neither offer to resume
+    // nor keep their session. A session is only kept once the offer of the last one has been
+    // answered or has run out, so closing straight away doesn't lose it.
+    #[cfg(any(feature = "video", feature = "winit"))]
+    let resumable = config.resume
+        && !options.netplay()
+        && options.play_input.is_none()
+        && options.record_input.is_none();
+    #[cfg(any(feature = "video", feature = "winit"))]
+    let mut resume = resumable.then(|| find_resume(&mut nes, rom_path)).flatten();
+
+    let mut pacer = FramePacer::new(nes.region().frame_rate(), config.video.pacing);
+    loop {
+        crash::guard(&mut nes, &crash, Nes::step_frame);
+        if !options.netplay() {
+            autosave(&mut nes, &save_path);
+        }
+        record_frame(&mut video_recording, &mut nes);
+
+        #[cfg(any(feature = "video", feature = "winit"))]
+        {
+            let playing = options.play_input.is_some();
+            let mut hotkeys = Vec::new();
+            #[cfg(feature = "video")]
+            let mut page_keys = Vec::new();
+            let mut resume_answer = None;
+            for (input, pressed) in screen.inputs() {
+                if resume.is_some() && pressed {
+                    match input::page_key(&input) {
+                        Some(overlay::PageKey::Enter) => resume_answer = Some(true),
+                        Some(overlay::PageKey::Escape) => resume_answer = Some(false),
+                        _ => {}
+                    }
+                    if resume_answer.is_some() {
+                        continue;
+                    }
+                }
+                // The cheats and settings pages take the keys they use while they show. Releases
+                // still go through, so nothing the game was holding gets stuck down.
+                #[cfg(feature = "video")]
+                if let Some(page) =
+                    overlay_page.filter(|&page| overlay::OverlayPage::is_editable(page))
+                {
+                    if let Some(key) = input::page_key(&input).filter(|_| pressed) {
+                        page_keys.push((page, key));
+                        continue;
+                    }
+                }
+                if !keyboard_mode {
+                    if let Some(hotkey) = keymap.hotkey(&input) {
+                        hotkeys.push((hotkey, pressed));
+                        continue;
+                    }
+                }
+                if playing {
+                    continue;
+                }
+                if nes.family_keyboard() && input::is_family_keyboard_toggle(&input) {
+                    if pressed {
+                        keyboard_mode = !keyboard_mode;
+                        if let Some(mut keyboard) = nes.family_keyboard_mut() {
+                            keyboard.release_all();
+                        }
+                        println!(
+                            "Keyboard drives the {}",
+                            if keyboard_mode {
+                                "Family BASIC keyboard"
+                            } else {
+                                "controllers"
+                            }
+                        );
+                    }
+                    continue;
+                }
+                if keyboard_mode {
+                    if let (Some(key), Some(mut keyboard)) = (
+                        input::family_keyboard_key(&input),
+                        nes.family_keyboard_mut(),
+                    ) {
+                        if pressed {
+                            keyboard.press_key(key);
+                        } else {
+                            keyboard.release_key(key);
+                        }
+                        continue;
+                    }
+                }
+                let Some((player, button)) = keymap.lookup(&input) else {
+                    continue;
+                };
+                // In netplay player 1's keys are the local player's, whichever port they have
+                if netplay.is_some() {
+                    if player == 0 {
+                        press(&mut local_pad, button, pressed);
+                    }
+                    continue;
+                }
+                if let Some(mut controller) = nes.controller_mut(player) {
+                    press(&mut controller, button, pressed);
+                }
+            }
+            if let Some(session) = &netplay {
+                let mut session = session.borrow_mut();
+                session.push_local_input(local_pad.buttons());
+                if let Some(e) = session.error() {
+                    eprintln!("Netplay ended: {}", e);
+                    nes.show_message("Netplay ended");
+                    drop(session);
+                    nes.clear_input_source();
+                    netplay = None;
+                }
+            }
+            if let Some(offer) = resume.take() {
+                match resume_answer {
+                    Some(true) => resume_session(&mut nes, &offer, &mut session),
+                    Some(false) => nes.show_message("Starting over"),
+                    None if nes.frame() < RESUME_OFFER_FRAMES => {
+                        // Kept on screen until it runs out
+                        if nes.frame().is_multiple_of(60) {
+                            nes.show_message(RESUME_PROMPT);
+                        }
+                        resume = Some(offer);
+                    }
+                    None => {}
+                }
+            }
+            if screen.closed() {
+                if !options.netplay() {
+                    save(&mut nes, &save_path);
+                }
+                if resumable && resume.is_none() {
+                    keep_session(&mut nes, rom_path, &session);
+                }
+                if let Some(path) = &options.record_input {
+                    save_input_recording(&mut nes, path);
+                }
+                finish_video_recording(video_recording, &mut nes, options);
+                return None;
+            }
+            // Recordings and netplay sessions are of the one game. The dropped ROM is loaded
+            // first so that a bad one leaves this game running.
+            if let Some(path) = screen.dropped() {
+                let recording = options.record_input.is_some()
+                    || options.record_video.is_some()
+                    || options.record_wav.is_some();
+                if options.netplay() {
+                    nes.show_message("Can't switch games in netplay");
+                } else if recording || playing {
+                    nes.show_message("Can't switch games while recording or playing input");
+                } else {
+                    match load_game(options, base_config, &path, None) {
+                        Ok((config, rom)) => {
+                            save(&mut nes, &save_path);
+                            if resumable && resume.is_none() {
+                                keep_session(&mut nes, rom_path, &session);
+                            }
+                            println!("Switching to {}", path.display());
+                            return Some((path, config, rom));
+                        }
+                        Err(e) => {
+                            eprintln!("Error loading ROM {}: {}", path.display(), e);
+                            nes.show_message("Could not load the ROM");
+                        }
+                    }
+                }
+            }
+            for (hotkey, pressed) in hotkeys {
+                match hotkey {
+                    // Both consoles run at the pace of the slower one
+                    input::Hotkey::FastForward | input::Hotkey::SlowMotion if netplay.is_some() => {
+                    }
+                    input::Hotkey::FastForward => fast_forward = pressed,
+                    _ if !pressed => {}
+                    input::Hotkey::SaveState => save_state(&mut nes, rom_path, session.slot),
+                    // Jumping to another point would leave a gap or overlap in the input
+                    // recording, or take the game somewhere the one playing wasn't made for
+                    input::Hotkey::LoadState if options.record_input.is_some() || playing => {
+                        nes.show_message("Can't load states while recording or playing input")
+                    }
+                    input::Hotkey::LoadState if netplay.is_some() => {
+                        nes.show_message("Can't load states in netplay")
+                    }
+                    input::Hotkey::LoadState => load_state(&mut nes, rom_path, session.slot),
+                    input::Hotkey::NextSlot => {
+                        session.slot = (session.slot + 1) % STATE_SLOTS;
+                        select_slot(&mut nes, rom_path, session.slot);
+                    }
+                    input::Hotkey::PreviousSlot => {
+                        session.slot = (session.slot + STATE_SLOTS - 1) % STATE_SLOTS;
+                        select_slot(&mut nes, rom_path, session.slot);
+                    }
+                    input::Hotkey::SlowMotion => {
+                        slow_motion = !slow_motion;
+                        nes.show_message(if slow_motion {
+                            "Slow motion"
+                        } else {
+                            "Normal speed"
+                        });
+                    }
+                    #[cfg(feature = "video")]
+                    input::Hotkey::DebugOverlay => {
+                        overlay_page = match overlay_page {
+                            Some(_) => None,
+                            None => Some(last_overlay_page),
+                        };
+                        overlay::show(&mut nes, overlay_page);
+                    }
+                    #[cfg(feature = "video")]
+                    input::Hotkey::OverlayPage => {
+                        if let Some(page) = overlay_page {
+                            last_overlay_page = page.next();
+                            overlay_page = Some(last_overlay_page);
+                            overlay::show(&mut nes, overlay_page);
+                        }
+                    }
+                    #[cfg(all(feature = "winit", not(feature = "video")))]
+                    input::Hotkey::DebugOverlay => {
+                        overlay_open = !overlay_open;
+                        nes.set_scope_recording(overlay_open);
+                        if !overlay_open {
+                            screen.show_overlay(None);
+                        }
+                    }
+                    // The egui windows all show at once, and their bar opens and closes them
+                    #[cfg(all(feature = "winit", not(feature = "video")))]
+                    input::Hotkey::OverlayPage => {}
+                    input::Hotkey::NextCheat => {
+                        session.cheat = (session.cheat + 1) % nes.cheats().len().max(1);
+                        show_cheat(&mut nes, session.cheat);
+                    }
+                    // The other console would carry on without it
+                    input::Hotkey::ToggleCheat if netplay.is_some() => {
+                        nes.show_message("Can't change cheats in netplay")
+                    }
+                    input::Hotkey::ToggleCheat => {
+                        toggle_cheat(&mut nes, session.cheat);
+                        save_cheats(&nes, cheat_path.as_deref());
+                        show_cheat(&mut nes, session.cheat);
+                    }
+                }
+            }
+            #[cfg(feature = "video")]
+            for (page, key) in page_keys {
+                let edit = overlay::edit(&mut nes, page, key, &mut session, netplay.is_some());
+                finish_edit(
+                    &mut nes,
+                    edit,
+                    &mut session,
+                    rom_path,
+                    cheat_path.as_deref(),
+                );
+            }
+            #[cfg(all(feature = "winit", not(feature = "video")))]
+            for command in screen.overlay_commands() {
+                let edit = egui_overlay::apply(&mut nes, command, &mut session, netplay.is_some());
+                finish_edit(
+                    &mut nes,
+                    edit,
+                    &mut session,
+                    rom_path,
+                    cheat_path.as_deref(),
+                );
+            }
+            let speed = if fast_forward {
+                session.fast_forward_speed
+            } else if slow_motion {
+                session.slow_motion_speed
+            } else {
+                1.0
+            };
+            if speed != pacer.speed() {
+                pacer.set_speed(speed);
+                #[cfg(feature = "audio")]
+                if let Some(rate) = audio
+                    .as_ref()
+                    .and_then(|audio| sample_rate_at_speed(audio.sample_rate(), speed))
+                {
+                    nes.set_audio_sample_rate(Some(rate));
+                }
+            }
+            #[cfg(feature = "video")]
+            if let Some(page) = overlay_page {
+                session.speed = pacer.speed();
+                overlay::draw(&mut nes, page, config, &session);
+            }
+            #[cfg(all(feature = "winit", not(feature = "video")))]
+            if overlay_open {
+                session.speed = pacer.speed();
+                let snapshot =
+                    egui_overlay::Snapshot::take(&mut nes, config, &session, netplay.is_some());
+                screen.show_overlay(Some(snapshot));
+            }
+            let overscan = nes.overscan();
+            let (width, height) = (overscan.width() as u32, overscan.height() as u32);
+            screen.show(nes.output_frame(), width, height, vsync);
+        }
+
+        // The device plays samples at the rate they are made, so it stays in step with the
+        // pacer; pushing never blocks, and drops samples if the queue somehow fills up. Off normal speed the samples
+        // are made at a rate scaled to match, which pitches the sound up or down, and dropped if
+        // that isn't possible (uncapped, or a WAV recording holding the rate).
+        #[cfg(feature = "audio")]
+        if let Some(audio) = &mut audio {
+            nes.take_audio_samples(&mut samples);
+            let speed = pacer.speed();
+            if speed == 1.0
+                || sample_rate_at_speed(audio.sample_rate(), speed)
+                    .is_some_and(|rate| nes.audio_sample_rate() == Some(rate))
+            {
+                audio.push(&samples);
+            }
+            samples.clear();
+        }
+
+        pacer.wait();
+    }
+}
@@ -0,0 +1,411 @@
+#[cfg(feature = "video")]
+use rustendo_core::debug::{self, DebugView, GRAYSCALE};
+use rustendo_core::Nes;
+
+use crate::config::Config;
+use crate::resume;
+
+// The debug overlay's pages, in the order the page hotkey steps through them. The core draws the
+// CPU, PPU and APU ones itself each frame; the rest are drawn here. The winit window has egui
+// windows instead (see egui_overlay.rs).
+#[cfg(feature = "video")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayPage {
+    Cpu,
+    PatternTables,
+    Nametables,
+    Sprites,
+    Palettes,
+    Apu,
+    Cheats,
+    Settings,
+}
+
+#[cfg(feature = "video")]
+const PAGES: [OverlayPage; 8] = [
+    OverlayPage::Cpu,
+    OverlayPage::PatternTables,
+    OverlayPage::Nametables,
+    OverlayPage::Sprites,
+    OverlayPage::Palettes,
+    OverlayPage::Apu,
+    OverlayPage::Cheats,
+    OverlayPage::Settings,
+];
+
+// Steps the settings page (or the egui settings window) offers; 0 is uncapped
+pub const FAST_FORWARD_SPEEDS: [f64; 5] = [1.5, 2.0, 3.0, 4.0, 0.0];
+pub const SLOW_MOTION_SPEEDS: [f64; 4] = [0.25, 0.5, 0.75, 0.9];
+
+// The settings page's rows, in order
+#[cfg(feature = "video")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Setting {
+    Slot,
+    NtscFilter,
+    FastForward,
+    SlowMotion,
+    FdsVolume,
+    FourScore,
+    TurboRate,
+    FamilyKeyboard,
+}
+
+#[cfg(feature = "video")]
+const SETTINGS: [Setting; 8] = [
+    Setting::Slot,
+    Setting::NtscFilter,
+    Setting::FastForward,
+    Setting::SlowMotion,
+    Setting::FdsVolume,
+    Setting::FourScore,
+    Setting::TurboRate,
+    Setting::FamilyKeyboard,
+];
+
+// What the front end is running with. The settings page changes it for as long as the
+// emulator runs; config.toml keeps the values it starts with.
+pub struct Session {
+    pub slot: usize,
+    pub speed: f64,   // The current one, from the pacer
+    pub cheat: usize, // The one the cheat hotkeys act on
+    pub fast_forward_speed: f64,
+    pub slow_motion_speed: f64,
+    fds_volume: f32,
+    #[cfg(feature = "video")]
+    setting: usize, // The settings page's selected row
+    #[cfg(feature = "video")]
+    code: Option<String>, // A cheat code being typed in on the cheats page
+}
+
+// The keys that work the cheats and settings pages while one of them is showing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageKey {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Escape,
+    Backspace,
+    Delete,
+    Char(char), // For typing cheat codes
+}
+
+// What's left for the front end to do after a page handled a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    NextSlot,
+    PreviousSlot,
+    CheatsChanged, // Write the game's cheat file
+}
+
+impl Session {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            slot: 0,
+            speed: 1.0,
+            cheat: 0,
+            fast_forward_speed: config.video.fast_forward_speed,
+            slow_motion_speed: config.video.slow_motion_speed,
+            fds_volume: config.audio.fds_volume.clamp(0.0, 2.0),
+            #[cfg(feature = "video")]
+            setting: 0,
+            #[cfg(feature = "video")]
+            code: None,
+        }
+    }
+
+    // The settings page's values, kept with the session when the game is closed
+    pub fn settings(&self, nes: &Nes) -> resume::Settings {
+        resume::Settings {
+            slot: self.slot,
+            ntsc_filter: nes.ntsc_filter_enabled(),
+            fast_forward_speed: self.fast_forward_speed,
+            slow_motion_speed: self.slow_motion_speed,
+            fds_volume: self.fds_volume,
+            four_score: nes.four_score(),
+            turbo_rate: nes.turbo_rate(),
+            family_keyboard: nes.family_keyboard(),
+        }
+    }
+
+    pub fn restore(&mut self, nes: &mut Nes, settings: &resume::Settings) {
+        self.slot = settings.slot;
+        self.fast_forward_speed = settings.fast_forward_speed;
+        self.slow_motion_speed = settings.slow_motion_speed;
+        self.fds_volume = settings.fds_volume.clamp(0.0, 2.0);
+        nes.set_ntsc_filter(settings.ntsc_filter);
+        nes.set_fds_volume(self.fds_volume);
+        nes.set_four_score(settings.four_score);
+        nes.set_turbo_rate(settings.turbo_rate.clamp(1, 30));
+        nes.set_family_keyboard(settings.family_keyboard);
+    }
+}
+
+#[cfg(feature = "video")]
+impl OverlayPage {
+    pub fn next(self) -> Self {
+        let index = PAGES.iter().position(|&page| page == self).unwrap_or(0);
+        PAGES[(index + 1) % PAGES.len()]
+    }
+
+    // Whether the page takes keys of its own while it's showing
+    pub fn is_editable(self) -> bool {
+        matches!(self, OverlayPage::Cheats | OverlayPage::Settings)
+    }
+
+    fn debug_view(self) -> Option<DebugView> {
+        match self {
+            OverlayPage::Cpu => Some(DebugView::Cpu),
+            OverlayPage::PatternTables => Some(DebugView::PatternTables(GRAYSCALE)),
+            OverlayPage::Nametables => Some(DebugView::Nametables),
+            OverlayPage::Sprites => Some(DebugView::Sprites),
+            OverlayPage::Palettes => Some(DebugView::Palettes),
+            OverlayPage::Apu => Some(DebugView::Apu),
+            OverlayPage::Cheats | OverlayPage::Settings => None,
+        }
+    }
+}
+
+// Switch to `page`, or hide the overlay with None
+#[cfg(feature = "video")]
+pub fn show(nes: &mut Nes, page: Option<OverlayPage>) {
+    nes.set_debug_view(page.and_then(OverlayPage::debug_view));
+}
+
+// Act on a key pressed while `page` shows. Cheats and the controllers can't be changed in
+// netplay, where the other console would carry on without the change.
+#[cfg(feature = "video")]
+pub fn edit(
+    nes: &mut Nes,
+    page: OverlayPage,
+    key: PageKey,
+    session: &mut Session,
+    netplay: bool,
+) -> Option<Edit> {
+    match page {
+        OverlayPage::Cheats if netplay => {
+            nes.show_message("Can't change cheats in netplay");
+            None
+        }
+        OverlayPage::Cheats => edit_cheats(nes, key, session),
+        OverlayPage::Settings => edit_settings(nes, key, session, netplay),
+        _ => None,
+    }
+}
+
+// Up and Down select, Enter switches the selected cheat on or off and Delete removes it.
+// Typing starts a new code, which Enter adds and Escape abandons.
+#[cfg(feature = "video")]
+fn edit_cheats(nes: &mut Nes, key: PageKey, session: &mut Session) -> Option<Edit> {
+    let count = nes.cheats().len();
+    if let Some(code) = session.code.as_mut() {
+        match key {
+            PageKey::Char(c) => code.push(c),
+            PageKey::Backspace => {
+                code.pop();
+            }
+            PageKey::Escape => session.code = None,
+            PageKey::Enter => {
+                let code = session.code.take().unwrap_or_default();
+                return match nes.add_cheat(&code) {
+                    Ok(()) => {
+                        session.cheat = nes.cheats().len() - 1;
+                        Some(Edit::CheatsChanged)
+                    }
+                    Err(e) => {
+                        nes.show_message(&format!("{}: {}", code, e));
+                        None
+                    }
+                };
+            }
+            _ => {}
+        }
+        return None;
+    }
+    let selected = nes.cheats().iter().nth(session.cheat).cloned();
+    match key {
+        PageKey::Char(c) => session.code = Some(c.to_string()),
+        PageKey::Up if count > 0 => session.cheat = (session.cheat + count - 1) % count,
+        PageKey::Down if count > 0 => session.cheat = (session.cheat + 1) % count,
+        PageKey::Enter => {
+            let cheat = selected?;
+            nes.set_cheat_enabled(&cheat.code, !cheat.enabled);
+            return Some(Edit::CheatsChanged);
+        }
+        PageKey::Delete => {
+            nes.remove_cheat(&selected?.code);
+            session.cheat = session.cheat.min(count.saturating_sub(2));
+            return Some(Edit::CheatsChanged);
+        }
+        _ => {}
+    }
+    None
+}
+
+// Up and Down select, Left and Right change the value; Enter does the same as Right
+#[cfg(feature = "video")]
+fn edit_settings(
+    nes: &mut Nes,
+    key: PageKey,
+    session: &mut Session,
+    netplay: bool,
+) -> Option<Edit> {
+    let forward = match key {
+        PageKey::Up => {
+            session.setting = (session.setting + SETTINGS.len() - 1) % SETTINGS.len();
+            return None;
+        }
+        PageKey::Down => {
+            session.setting = (session.setting + 1) % SETTINGS.len();
+            return None;
+        }
+        PageKey::Right | PageKey::Enter => true,
+        PageKey::Left => false,
+        _ => return None,
+    };
+    let setting = SETTINGS[session.setting];
+    if netplay
+        && matches!(
+            setting,
+            Setting::FourScore | Setting::TurboRate | Setting::FamilyKeyboard
+        )
+    {
+        nes.show_message("Can't change the controllers in netplay");
+        return None;
+    }
+    match setting {
+        Setting::Slot if forward => return Some(Edit::NextSlot),
+        Setting::Slot => return Some(Edit::PreviousSlot),
+        Setting::NtscFilter => nes.set_ntsc_filter(!nes.ntsc_filter_enabled()),
+        Setting::FastForward => {
+            session.fast_forward_speed =
+                step(&FAST_FORWARD_SPEEDS, session.fast_forward_speed, forward);
+        }
+        Setting::SlowMotion => {
+            session.slow_motion_speed =
+                step(&SLOW_MOTION_SPEEDS, session.slow_motion_speed, forward);
+        }
+        Setting::FdsVolume => {
+            let change = if forward { 1.0 } else { -1.0 };
+            // In tenths, so repeated steps land on round numbers
+            session.fds_volume =
+                ((session.fds_volume * 10.0).round() + change).clamp(0.0, 20.0) / 10.0;
+            nes.set_fds_volume(session.fds_volume);
+        }
+        Setting::FourScore => nes.set_four_score(!nes.four_score()),
+        Setting::TurboRate => {
+            let rate = if forward {
+                nes.turbo_rate() + 1
+            } else {
+                nes.turbo_rate().saturating_sub(1)
+            };
+            nes.set_turbo_rate(rate.clamp(1, 30));
+        }
+        Setting::FamilyKeyboard => nes.set_family_keyboard(!nes.family_keyboard()),
+    }
+    None
+}
+
+// The next or previous of `steps` after `value`, wrapping around. A value that isn't one of
+// them goes to the first.
+#[cfg(feature = "video")]
+fn step(steps: &[f64], value: f64, forward: bool) -> f64 {
+    let next = match steps.iter().position(|&step| step == value) {
+        Some(index) if forward => (index + 1) % steps.len(),
+        Some(index) => (index + steps.len() - 1) % steps.len(),
+        None => 0,
+    };
+    steps[next]
+}
+
+// Redraw the page if it's one of the front end's, after each frame
+#[cfg(feature = "video")]
+pub fn draw(nes: &mut Nes, page: OverlayPage, config: &Config, session: &Session) {
+    let lines = match page {
+        OverlayPage::Cheats => cheat_lines(nes, config, session),
+        OverlayPage::Settings => settings_lines(nes, config, session),
+        _ => return,
+    };
+    debug::overlay_text(nes.compositor_mut(), &lines);
+}
+
+#[cfg(feature = "video")]
+fn cheat_lines(nes: &Nes, config: &Config, session: &Session) -> Vec<String> {
+    let cheats = nes.cheats();
+    let hotkeys = &config.hotkeys;
+    let mut lines = vec![
+        "Cheats: Up/Down select, Enter switches".to_string(),
+        "on/off, Delete removes. Type a code to".to_string(),
+        format!(
+            "add it. In game: {} selects, {} on/off.",
+            hotkeys.next_cheat, hotkeys.toggle_cheat
+        ),
+        String::new(),
+    ];
+    if cheats.is_empty() && session.code.is_none() {
+        lines.push("None yet.".to_string());
+    }
+    for (index, cheat) in cheats.iter().enumerate() {
+        let selected = if index == session.cheat { "*" } else { " " };
+        let state = if cheat.enabled { "on " } else { "off" };
+        lines.push(format!(
+            "{}{:<11} {} {}",
+            selected, cheat.code, state, cheat.name
+        ));
+    }
+    if let Some(code) = &session.code {
+        lines.push(String::new());
+        lines.push(format!("New: {}_", code));
+        lines.push("Enter adds it, Escape cancels".to_string());
+    }
+    lines
+}
+
+#[cfg(feature = "video")]
+fn settings_lines(nes: &Nes, config: &Config, session: &Session) -> Vec<String> {
+    let yes_no = |value: bool| if value { "yes" } else { "no" };
+    let times = |speed: f64| {
+        if speed == 0.0 {
+            "uncapped".to_string()
+        } else {
+            format!("{}x", speed)
+        }
+    };
+    let mut lines = vec![
+        "Settings: Up/Down select, Left/Right".to_string(),
+        "change, until the emulator is closed".to_string(),
+        String::new(),
+    ];
+    for (index, setting) in SETTINGS.iter().enumerate() {
+        let value = match setting {
+            Setting::Slot => format!("State slot: {}", session.slot),
+            Setting::NtscFilter => format!("NTSC filter: {}", yes_no(nes.ntsc_filter_enabled())),
+            Setting::FastForward => format!("Fast-forward: {}", times(session.fast_forward_speed)),
+            Setting::SlowMotion => format!("Slow motion: {}", times(session.slow_motion_speed)),
+            Setting::FdsVolume => format!("FDS volume: {:.1}", session.fds_volume),
+            Setting::FourScore => format!("Four Score: {}", yes_no(nes.four_score())),
+            Setting::TurboRate => format!("Turbo rate: {}", nes.turbo_rate()),
+            Setting::FamilyKeyboard => {
+                format!("Family keyboard: {}", yes_no(nes.family_keyboard()))
+            }
+        };
+        let selected = if index == session.setting { "*" } else { " " };
+        lines.push(format!("{}{}", selected, value));
+    }
+    let video = &config.video;
+    lines.extend([
+        String::new(),
+        "Set in config.toml:".to_string(),
+        format!("Region: {:?}  Speed now: {}x", nes.region(), session.speed),
+        format!(
+            "Scale: {}  Fullscreen: {}",
+            video.scale,
+            yes_no(video.fullscreen)
+        ),
+        format!("Pacing: {:?}", video.pacing),
+        format!("Audio buffer: {}", config.audio.buffer_size),
+    ]);
+    lines
+}
@@ -0,0 +1,94 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+// How the frame pacer waits out the rest of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PacingStrategy {
+    // Sleep until shortly before the frame is due, then spin the rest: accurate without
+    // burning a core
+    #[default]
+    Hybrid,
+    Sleep, // Sleep only; lightest on the CPU, but the OS may wake it late
+    Spin,  // Busy-wait; the steadiest timing at the cost of a full core
+    Vsync, // Let presenting the frame wait for the display, which runs at its own rate
+}
+
+// The OS oversleeps by up to about this much, so hybrid pacing spins for the last stretch
+const SPIN_MARGIN: Duration = Duration::from_millis(2);
+
+// Frames the pacer may fall behind before it gives up catching up (e.g. after the window was
+// dragged) and starts counting from now
+const MAX_LAG_FRAMES: u32 = 4;
+
+// Holds the main loop to the console's frame rate, or a multiple of it for fast-forward and
+// slow motion. Only the waiting changes, so every frame is still emulated in full.
+pub struct FramePacer {
+    #[cfg(any(feature = "video", feature = "winit"))]
+    frame_rate: f64,
+    speed: f64,           // Relative to the console, 0 for uncapped
+    frame_time: Duration, // At that speed
+    strategy: PacingStrategy,
+    next: Instant, // When the next frame is due
+}
+
+impl FramePacer {
+    pub fn new(frame_rate: f64, strategy: PacingStrategy) -> Self {
+        Self {
+            #[cfg(any(feature = "video", feature = "winit"))]
+            frame_rate,
+            speed: 1.0,
+            frame_time: Duration::from_secs_f64(1.0 / frame_rate),
+            strategy,
+            next: Instant::now(),
+        }
+    }
+
+    // Run at `speed` times the console's frame rate, or as fast as possible for 0. With vsync
+    // pacing the display still caps it.
+    #[cfg(any(feature = "video", feature = "winit"))]
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed.max(0.0);
+        if self.speed > 0.0 {
+            self.frame_time = Duration::from_secs_f64(1.0 / (self.frame_rate * self.speed));
+        }
+        // Time owed or banked at the old speed doesn't carry over
+        self.next = Instant::now();
+    }
+
+    #[cfg(any(feature = "video", feature = "winit", feature = "audio"))]
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    // Wait until the next frame is due. Frame times are added up rather than measured from
+    // when this returns, so the average rate is exact even if single waits end a little late.
+    pub fn wait(&mut self) {
+        self.next += self.frame_time;
+        let now = Instant::now();
+        if self.speed == 0.0 || now > self.next + self.frame_time * MAX_LAG_FRAMES {
+            self.next = now;
+            return;
+        }
+
+        match self.strategy {
+            PacingStrategy::Vsync => {}
+            PacingStrategy::Sleep => thread::sleep(self.next.saturating_duration_since(now)),
+            PacingStrategy::Spin => spin_until(self.next),
+            PacingStrategy::Hybrid => {
+                if let Some(sleep) = self.next.checked_duration_since(now + SPIN_MARGIN) {
+                    thread::sleep(sleep);
+                }
+                spin_until(self.next);
+            }
+        }
+    }
+}
+
+fn spin_until(deadline: Instant) {
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}
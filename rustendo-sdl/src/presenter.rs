@@ -0,0 +1,207 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryIter};
+#[cfg(all(feature = "winit", not(feature = "video")))]
+use std::sync::mpsc::{SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use rtrb::{Consumer, Producer, RingBuffer};
+
+#[cfg(all(feature = "winit", not(feature = "video")))]
+use crate::egui_overlay::{Command, Snapshot};
+use crate::input::Input;
+use crate::VideoOutput;
+
+// Finished frames waiting to be shown. When the window falls further behind than this (a slow
+// present, the window being dragged) the emulator drops frames instead of waiting for it.
+const QUEUED_FRAMES: usize = 2;
+
+// How long either side sleeps when there's nothing for it to do
+const IDLE: Duration = Duration::from_millis(1);
+
+struct Frame {
+    pixels: Vec<u8>, // RGBA
+    width: u32,
+    height: u32,
+}
+
+// The emulation thread's end of the window: it hands over finished frames and gets the keys and
+// buttons pressed in the window back
+pub struct Screen {
+    frames: Producer<Frame>,
+    spare: Consumer<Vec<u8>>, // Frame buffers the window is done with, for reuse
+    inputs: Receiver<(Input, bool)>,
+    dropped: Receiver<PathBuf>, // Files dropped on the window
+    titles: Sender<String>,
+    #[cfg(all(feature = "winit", not(feature = "video")))]
+    overlays: SyncSender<Option<Snapshot>>, // What the overlay's windows show, None to hide them
+    #[cfg(all(feature = "winit", not(feature = "video")))]
+    commands: Receiver<Command>, // Changes made in them
+    closed: Arc<AtomicBool>,
+}
+
+// The window thread's end. The window has to stay on the main thread (some platforms insist),
+// so that is where this runs, and the emulator gets a thread of its own.
+pub struct Presenter {
+    frames: Consumer<Frame>,
+    spare: Producer<Vec<u8>>,
+    inputs: Sender<(Input, bool)>,
+    dropped: Sender<PathBuf>,
+    titles: Receiver<String>,
+    #[cfg(all(feature = "winit", not(feature = "video")))]
+    overlays: Receiver<Option<Snapshot>>,
+    #[cfg(all(feature = "winit", not(feature = "video")))]
+    commands: Sender<Command>,
+    closed: Arc<AtomicBool>,
+}
+
+pub fn connect() -> (Screen, Presenter) {
+    let (frames_in, frames_out) = RingBuffer::new(QUEUED_FRAMES);
+    let (spare_in, spare_out) = RingBuffer::new(QUEUED_FRAMES + 1);
+    let (inputs_in, inputs_out) = mpsc::channel();
+    let (dropped_in, dropped_out) = mpsc::channel();
+    let (titles_in, titles_out) = mpsc::channel();
+    #[cfg(all(feature = "winit", not(feature = "video")))]
+    let (overlays_in, overlays_out) = mpsc::sync_channel(QUEUED_FRAMES);
+    #[cfg(all(feature = "winit", not(feature = "video")))]
+    let (commands_in, commands_out) = mpsc::channel();
+    let closed = Arc::new(AtomicBool::new(false));
+    let screen = Screen {
+        frames: frames_in,
+        spare: spare_out,
+        inputs: inputs_out,
+        dropped: dropped_out,
+        titles: titles_in,
+        #[cfg(all(feature = "winit", not(feature = "video")))]
+        overlays: overlays_in,
+        #[cfg(all(feature = "winit", not(feature = "video")))]
+        commands: commands_out,
+        closed: Arc::clone(&closed),
+    };
+    let presenter = Presenter {
+        frames: frames_out,
+        spare: spare_in,
+        inputs: inputs_in,
+        dropped: dropped_in,
+        titles: titles_out,
+        #[cfg(all(feature = "winit", not(feature = "video")))]
+        overlays: overlays_out,
+        #[cfg(all(feature = "winit", not(feature = "video")))]
+        commands: commands_in,
+        closed,
+    };
+    (screen, presenter)
+}
+
+impl Screen {
+    // The key and button changes since the last call, in order
+    pub fn inputs(&self) -> TryIter<'_, (Input, bool)> {
+        self.inputs.try_iter()
+    }
+
+    // The file last dropped on the window since the last call, if any
+    pub fn dropped(&self) -> Option<PathBuf> {
+        self.dropped.try_iter().last()
+    }
+
+    pub fn set_title(&self, title: String) {
+        let _ = self.titles.send(title);
+    }
+
+    // Show the debug overlay's windows with what `snapshot` holds, or hide them with None.
+    // Snapshots the window hasn't caught up with are dropped, like frames, but hiding waits for
+    // room.
+    #[cfg(all(feature = "winit", not(feature = "video")))]
+    pub fn show_overlay(&self, snapshot: Option<Snapshot>) {
+        let hide = snapshot.is_none();
+        let mut message = snapshot;
+        while let Err(TrySendError::Full(rejected)) = self.overlays.try_send(message) {
+            if !hide || self.closed() {
+                return;
+            }
+            message = rejected;
+            thread::sleep(IDLE);
+        }
+    }
+
+    // The changes made in the overlay's windows since the last call, in order
+    #[cfg(all(feature = "winit", not(feature = "video")))]
+    pub fn overlay_commands(&self) -> TryIter<'_, Command> {
+        self.commands.try_iter()
+    }
+
+    // Whether the window has been closed, after which the emulator should save and stop
+    pub fn closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    // Pass a frame of `width` by `height` RGBA pixels to the window. If the window is still
+    // busy with earlier ones the frame is dropped, unless `wait` is set: then the emulator waits
+    // for the window, which holds it to the display's rate when presenting waits for vsync.
+    pub fn show(&mut self, pixels: &[u8], width: u32, height: u32, wait: bool) {
+        while wait && self.frames.is_full() && !self.closed() {
+            thread::sleep(IDLE);
+        }
+        if self.frames.is_full() {
+            return;
+        }
+        let mut buffer = self.spare.pop().unwrap_or_default();
+        buffer.clear();
+        buffer.extend_from_slice(pixels);
+        let frame = Frame {
+            pixels: buffer,
+            width,
+            height,
+        };
+        // Only this thread adds frames, so there is still room
+        let _ = self.frames.push(frame);
+    }
+}
+
+impl Presenter {
+    // Show the emulator's frames and send it the window's input until the window is closed or
+    // the emulation thread ends, then wait for that thread to finish
+    pub fn run(mut self, video: &mut VideoOutput, emulation: JoinHandle<()>) -> thread::Result<()> {
+        loop {
+            for title in self.titles.try_iter() {
+                video.set_title(&title);
+            }
+            let (inputs, dropped) = (&self.inputs, &self.dropped);
+            // Sending only fails once the emulator has stopped, when input no longer matters
+            let open = video.handle_events(
+                |input, pressed| {
+                    let _ = inputs.send((input, pressed));
+                },
+                |path| {
+                    let _ = dropped.send(path);
+                },
+            );
+            if !open {
+                self.closed.store(true, Ordering::Release);
+                break;
+            }
+            #[cfg(all(feature = "winit", not(feature = "video")))]
+            {
+                if let Some(snapshot) = self.overlays.try_iter().last() {
+                    video.show_overlay(snapshot);
+                }
+                for command in video.take_overlay_commands() {
+                    let _ = self.commands.send(command);
+                }
+            }
+            match self.frames.pop() {
+                Ok(frame) => {
+                    if let Err(e) = video.present(&frame.pixels, frame.width, frame.height) {
+                        eprintln!("Error drawing the frame: {}", e);
+                    }
+                    let _ = self.spare.push(frame.pixels);
+                }
+                Err(_) if emulation.is_finished() => break,
+                Err(_) => thread::sleep(IDLE),
+            }
+        }
+        emulation.join()
+    }
+}
@@ -0,0 +1,232 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use rustendo_core::checksum::crc32;
+use rustendo_core::Nes;
+
+// Frames an APNG frame can be held for before it is written again, so its delay fits in a u16
+// numerator
+const MAX_HELD_FRAMES: u16 = 600;
+
+// Captures every frame the console outputs, at the console's frame rate. `.png` and `.apng`
+// files are written as animated PNGs, without sound, which suits short clips; anything else is
+// piped to ffmpeg, which picks the format from the extension and gets the sound too.
+pub enum VideoRecorder {
+    Apng(ApngRecorder),
+    Ffmpeg(FfmpegRecorder),
+}
+
+impl VideoRecorder {
+    // `wav` is the file the sound is already being recorded to, if it is
+    pub fn start(path: &Path, nes: &mut Nes, wav: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("png" | "apng") => Ok(VideoRecorder::Apng(ApngRecorder::start(path, nes)?)),
+            _ => Ok(VideoRecorder::Ffmpeg(FfmpegRecorder::start(
+                path, nes, wav,
+            )?)),
+        }
+    }
+
+    // Add the frame the console just finished, as `Nes::output_frame` returns it
+    pub fn push_frame(&mut self, frame: &[u8]) -> Result<(), Box<dyn Error>> {
+        match self {
+            VideoRecorder::Apng(recorder) => recorder.push_frame(frame),
+            VideoRecorder::Ffmpeg(recorder) => recorder.push_frame(frame),
+        }
+    }
+
+    pub fn finish(self, nes: &mut Nes) -> Result<(), Box<dyn Error>> {
+        match self {
+            VideoRecorder::Apng(recorder) => recorder.finish(),
+            VideoRecorder::Ffmpeg(recorder) => recorder.finish(nes),
+        }
+    }
+}
+
+// The frame count in the acTL chunk isn't known until the end, so the file is written claiming
+// as many frames as there can be and the count is patched once the recording stops. Runs of
+// identical frames become one frame shown for longer.
+pub struct ApngRecorder {
+    writer: png::Writer<BufWriter<File>>,
+    file: File,                   // Another handle on the file, for the patch
+    held: Option<(Vec<u8>, u16)>, // Last frame and how many frames it has been shown for
+    frames_written: u32,
+    frame_rate: u16, // Frames per 100 seconds, the delay denominator
+}
+
+impl ApngRecorder {
+    fn start(path: &Path, nes: &Nes) -> Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let overscan = nes.overscan();
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(file.try_clone()?),
+            overscan.width() as u32,
+            overscan.height() as u32,
+        );
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(u32::MAX, 0)?;
+        Ok(Self {
+            writer: encoder.write_header()?,
+            file,
+            held: None,
+            frames_written: 0,
+            frame_rate: (nes.region().frame_rate() * 100.0).round() as u16,
+        })
+    }
+
+    fn push_frame(&mut self, frame: &[u8]) -> Result<(), Box<dyn Error>> {
+        match self.held.as_mut() {
+            Some((held, count)) if held == frame && *count < MAX_HELD_FRAMES => *count += 1,
+            _ => {
+                self.write_held()?;
+                self.held = Some((frame.to_vec(), 1));
+            }
+        }
+        Ok(())
+    }
+
+    fn write_held(&mut self) -> Result<(), Box<dyn Error>> {
+        let Some((frame, count)) = self.held.take() else {
+            return Ok(());
+        };
+        self.writer.set_frame_delay(count * 100, self.frame_rate)?;
+        self.writer.write_image_data(&frame)?;
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        self.write_held()?;
+        if self.frames_written == 0 {
+            return Err("no frames were recorded".into());
+        }
+        self.writer.finish()?;
+
+        // acTL follows IHDR near the start: its length, type, frame count, play count and CRC
+        let mut header = [0; 64];
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_exact(&mut header)?;
+        let actl = header
+            .windows(4)
+            .position(|window| window == b"acTL")
+            .ok_or("acTL chunk not found")?;
+        let mut chunk = header[actl..actl + 12].to_vec();
+        chunk[4..8].copy_from_slice(&self.frames_written.to_be_bytes());
+        let crc = crc32(&chunk);
+        self.file.seek(SeekFrom::Start(actl as u64 + 4))?;
+        self.file.write_all(&chunk[4..])?;
+        self.file.write_all(&crc.to_be_bytes())?;
+        Ok(())
+    }
+}
+
+// ffmpeg encodes the frames as they are piped to it into a silent file next to the output,
+// while the core records the sound to a WAV file (or the one `--record-wav` is writing). When
+// the recording stops a second run of ffmpeg muxes the two into the output.
+pub struct FfmpegRecorder {
+    ffmpeg: Child,
+    stdin: ChildStdin,
+    path: PathBuf,
+    video_path: PathBuf,
+    audio_path: Option<PathBuf>, // None for formats without sound
+    own_audio: bool,             // The WAV file is ours to delete
+}
+
+impl FfmpegRecorder {
+    fn start(path: &Path, nes: &mut Nes, wav: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        let extension = path.extension().map_or(String::new(), |extension| {
+            extension.to_string_lossy().to_string()
+        });
+        let video_path = path.with_extension(format!("video.{}", extension));
+        let overscan = nes.overscan();
+        let mut command = Command::new("ffmpeg");
+        command
+            .args(["-y", "-loglevel", "error", "-f", "rawvideo"])
+            .args(["-pixel_format", "rgba", "-video_size"])
+            .arg(format!("{}x{}", overscan.width(), overscan.height()))
+            .arg("-framerate")
+            .arg(nes.region().frame_rate().to_string())
+            .args(["-i", "-"]);
+        let has_audio = !extension.eq_ignore_ascii_case("gif");
+        if has_audio {
+            // Players expect 4:2:0 video, which needs even dimensions
+            command.args([
+                "-pix_fmt",
+                "yuv420p",
+                "-vf",
+                "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+            ]);
+        }
+        let mut ffmpeg = command
+            .arg(&video_path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("could not run ffmpeg: {}", e))?;
+        let stdin = ffmpeg.stdin.take().ok_or("could not write to ffmpeg")?;
+
+        let (audio_path, own_audio) = match wav {
+            _ if !has_audio => (None, false),
+            Some(wav) => (Some(wav.to_path_buf()), false),
+            None => {
+                let audio_path = path.with_extension(format!("{}.wav", extension));
+                nes.start_audio_recording(&audio_path, false)?;
+                (Some(audio_path), true)
+            }
+        };
+
+        Ok(Self {
+            ffmpeg,
+            stdin,
+            path: path.to_path_buf(),
+            video_path,
+            audio_path,
+            own_audio,
+        })
+    }
+
+    fn push_frame(&mut self, frame: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.stdin.write_all(frame)?;
+        Ok(())
+    }
+
+    fn finish(mut self, nes: &mut Nes) -> Result<(), Box<dyn Error>> {
+        drop(self.stdin);
+        if !self.ffmpeg.wait()?.success() {
+            return Err("ffmpeg failed to encode the video".into());
+        }
+
+        let Some(audio_path) = &self.audio_path else {
+            fs::rename(&self.video_path, &self.path)?;
+            return Ok(());
+        };
+        nes.stop_audio_recording()?;
+        let status = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error", "-i"])
+            .arg(&self.video_path)
+            .arg("-i")
+            .arg(audio_path)
+            .args(["-c:v", "copy", "-shortest"])
+            .arg(&self.path)
+            .status()?;
+        fs::remove_file(&self.video_path)?;
+        if self.own_audio {
+            fs::remove_file(audio_path)?;
+        }
+        if !status.success() {
+            return Err("ffmpeg failed to add the sound".into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use rustendo_core::checksum::crc32;
+use rustendo_core::Nes;
+use serde::{Deserialize, Serialize};
+
+// Kept in the game's state directory, beside the slots
+const STATE_FILE: &str = "resume.state";
+const SESSION_FILE: &str = "resume.toml";
+
+// What the settings page can change, which a resumed session picks up again
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    pub slot: usize,
+    pub ntsc_filter: bool,
+    pub fast_forward_speed: f64,
+    pub slow_motion_speed: f64,
+    pub fds_volume: f32,
+    pub four_score: bool,
+    pub turbo_rate: u64,
+    pub family_keyboard: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Session {
+    save_crc32: Option<u32>, // The cartridge save the state was taken with
+    settings: Settings,
+}
+
+// The session a game was left in, offered when it is started again
+pub struct Resume {
+    state: Vec<u8>,
+    pub settings: Settings,
+}
+
+// Keep the console's state and the settings for the next time the game is started, with a
+// CRC-32 of the cartridge save, which the front end writes before this
+pub fn save(nes: &mut Nes, directory: &Path, settings: Settings) -> Result<(), Box<dyn Error>> {
+    let session = Session {
+        save_crc32: nes.save_data().as_deref().map(crc32),
+        settings,
+    };
+    fs::create_dir_all(directory)?;
+    fs::write(directory.join(STATE_FILE), nes.save_state())?;
+    fs::write(directory.join(SESSION_FILE), toml::to_string(&session)?)?;
+    Ok(())
+}
+
+// The session the game was left in, if there is one. `nes` has just been powered on with the
+// save file loaded: if that save isn't the one the state was taken with, the game was played
+// without it since (or the file was replaced), and resuming would undo that.
+pub fn find(nes: &Nes, directory: &Path) -> Result<Option<Resume>, Box<dyn Error>> {
+    let text = match fs::read_to_string(directory.join(SESSION_FILE)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let session: Session = toml::from_str(&text)?;
+    if session.save_crc32 != nes.save_data().as_deref().map(crc32) {
+        return Err("the save file has changed since".into());
+    }
+    Ok(Some(Resume {
+        state: fs::read(directory.join(STATE_FILE))?,
+        settings: session.settings,
+    }))
+}
+
+impl Resume {
+    pub fn load(&self, nes: &mut Nes) -> Result<(), Box<dyn Error>> {
+        Ok(nes.load_state(&self.state)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use rustendo_core::Rom;
+
+    use super::*;
+
+    fn settings() -> Settings {
+        Settings {
+            slot: 3,
+            ntsc_filter: true,
+            fast_forward_speed: 4.0,
+            slow_motion_speed: 0.5,
+            fds_volume: 1.5,
+            four_score: false,
+            turbo_rate: 6,
+            family_keyboard: false,
+        }
+    }
+
+    // NROM with 8KB of battery RAM, spinning in a loop
+    fn console() -> Nes {
+        let mut data = vec![0; 16 + 0x4000 + 0x2000];
+        data[..8].copy_from_slice(b"NES\x1a\x01\x01\x02\x00");
+        data[16..19].copy_from_slice(&[0x4C, 0x00, 0xC0]); // JMP $C000
+        data[16 + 0x3FFC..16 + 0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+        Nes::new(&Rom::from_bytes(&data).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn sessions_resume_where_they_were_left() {
+        let directory = env::temp_dir().join(format!("rustendo-resume-{}", std::process::id()));
+        let mut nes = console();
+        assert!(find(&nes, &directory).unwrap().is_none());
+        for _ in 0..5 {
+            nes.step_frame();
+        }
+        save(&mut nes, &directory, settings()).unwrap();
+
+        let mut resumed = console();
+        let resume = find(&resumed, &directory).unwrap().unwrap();
+        assert_eq!(resume.settings, settings());
+        resume.load(&mut resumed).unwrap();
+        assert_eq!(resumed.save_state(), nes.save_state());
+
+        // A save that has changed since isn't resumed over
+        let mut changed = console();
+        changed.load_save_data(&[1; 0x2000]);
+        assert!(find(&changed, &directory).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
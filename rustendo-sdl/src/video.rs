@@ -0,0 +1,146 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use sdl2::controller::GameController;
+use sdl2::event::Event;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+use sdl2::{EventPump, GameControllerSubsystem};
+
+use crate::input::Input;
+
+// A resizable window showing the emulator's output. The picture is scaled to fill the window
+// with its aspect ratio kept, letterboxed as needed.
+pub struct VideoOutput {
+    canvas: Canvas<Window>,
+    events: EventPump,
+    gamepad_subsystem: GameControllerSubsystem,
+    gamepads: Vec<GameController>, // In the order they were connected
+    // Streaming RGBA texture the size of the last frame. Textures are freed with the renderer,
+    // so one replaced after a size change just waits for that.
+    texture: Option<Texture>,
+    size: (u32, u32),
+}
+
+impl VideoOutput {
+    // Open a window `scale` times the size of the picture, or covering the screen with
+    // `fullscreen`. With `vsync` presenting a frame waits for the display, which paces emulation
+    // when there is no audio device to do it.
+    pub fn open(
+        title: &str,
+        width: u32,
+        height: u32,
+        scale: u32,
+        fullscreen: bool,
+        vsync: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let sdl = sdl2::init()?;
+        let video = sdl.video()?;
+        let mut window = video.window(title, width * scale.max(1), height * scale.max(1));
+        window.position_centered().resizable();
+        if fullscreen {
+            window.fullscreen_desktop();
+        }
+        let window = window.build()?;
+        let mut canvas = window.into_canvas();
+        if vsync {
+            canvas = canvas.present_vsync();
+        }
+
+        Ok(Self {
+            canvas: canvas.build()?,
+            events: sdl.event_pump()?,
+            gamepad_subsystem: sdl.game_controller()?,
+            gamepads: Vec::new(),
+            texture: None,
+            size: (0, 0),
+        })
+    }
+
+    // Handle pending window events, passing key presses and releases to `on_input` by SDL
+    // scancode name, so bindings follow key positions rather than the keyboard layout, and
+    // gamepad buttons by SDL game controller button name, and files dropped on the window to
+    // `on_drop`. Returns false once the window has been closed.
+    pub fn handle_events(
+        &mut self,
+        mut on_input: impl FnMut(Input, bool),
+        mut on_drop: impl FnMut(PathBuf),
+    ) -> bool {
+        let events: Vec<Event> = self.events.poll_iter().collect();
+        for event in events {
+            match event {
+                Event::Quit { .. } => return false,
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    repeat: false,
+                    ..
+                } => on_input(Input::Key(scancode.name().to_string()), true),
+                Event::KeyUp {
+                    scancode: Some(scancode),
+                    ..
+                } => on_input(Input::Key(scancode.name().to_string()), false),
+                Event::DropFile { filename, .. } => on_drop(PathBuf::from(filename)),
+                // Sent for every gamepad already plugged in at startup too
+                Event::ControllerDeviceAdded { which, .. } => {
+                    match self.gamepad_subsystem.open(which) {
+                        Ok(gamepad) => self.gamepads.push(gamepad),
+                        Err(e) => eprintln!("Could not open gamepad {}: {}", which, e),
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    self.gamepads
+                        .retain(|gamepad| gamepad.instance_id() != which);
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    if let Some(pad) = self.gamepad_index(which) {
+                        on_input(Input::Gamepad(pad, button.string()), true);
+                    }
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    if let Some(pad) = self.gamepad_index(which) {
+                        on_input(Input::Gamepad(pad, button.string()), false);
+                    }
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        if let Err(e) = self.canvas.window_mut().set_title(title) {
+            eprintln!("Error setting the window title: {}", e);
+        }
+    }
+
+    fn gamepad_index(&self, instance_id: u32) -> Option<usize> {
+        self.gamepads
+            .iter()
+            .position(|gamepad| gamepad.instance_id() == instance_id)
+    }
+
+    // Show a frame of `width` by `height` RGBA pixels
+    pub fn present(&mut self, frame: &[u8], width: u32, height: u32) -> Result<(), Box<dyn Error>> {
+        if self.texture.is_none() || self.size != (width, height) {
+            let texture = self.canvas.texture_creator().create_texture_streaming(
+                PixelFormatEnum::RGBA32,
+                width,
+                height,
+            )?;
+            // The renderer scales this logical size to the window as it is resized
+            self.canvas.set_logical_size(width, height)?;
+            self.texture = Some(texture);
+            self.size = (width, height);
+        }
+        let Some(texture) = self.texture.as_mut() else {
+            return Ok(());
+        };
+
+        texture.update(None, frame, width as usize * 4)?;
+        self.canvas.clear();
+        self.canvas.copy(texture, None, None)?;
+        self.canvas.present();
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use pixels::{Pixels, PixelsBuilder, SurfaceTexture};
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, Event, KeyEvent, WindowEvent};
+use winit::event_loop::EventLoop;
+use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::platform::pump_events::{EventLoopExtPumpEvents, PumpStatus};
+use winit::window::{Fullscreen, Window, WindowBuilder};
+
+use crate::egui_overlay::{Command, Overlay, Snapshot};
+use crate::input::Input;
+
+// The pure-Rust alternative to the SDL2 window: winit for the window and events and pixels
+// (wgpu) for drawing. It has the same interface, so the main loop doesn't care which it gets,
+// and draws the debug overlay's egui windows over the picture.
+pub struct VideoOutput {
+    overlay: Overlay,        // Holds GPU resources of pixels', so it goes first
+    pixels: Pixels<'static>, // Draws into the window, so it is declared (and dropped) before it
+    window: Arc<Window>,
+    event_loop: EventLoop<()>,
+    size: (u32, u32),
+}
+
+impl VideoOutput {
+    // Open a window `scale` times the size of the picture, or covering the screen with
+    // `fullscreen`. With `vsync` presenting a frame waits for the display, which paces emulation
+    // when there is no audio device to do it.
+    pub fn open(
+        title: &str,
+        width: u32,
+        height: u32,
+        scale: u32,
+        fullscreen: bool,
+        vsync: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let event_loop = EventLoop::new()?;
+        let scale = scale.max(1);
+        let window = Arc::new(
+            WindowBuilder::new()
+                .with_title(title)
+                .with_inner_size(LogicalSize::new(width * scale, height * scale))
+                .with_min_inner_size(LogicalSize::new(width, height))
+                .with_fullscreen(fullscreen.then_some(Fullscreen::Borderless(None)))
+                .build(&event_loop)?,
+        );
+        let surface = window.inner_size();
+        let pixels = PixelsBuilder::new(
+            width,
+            height,
+            SurfaceTexture::new(surface.width, surface.height, Arc::clone(&window)),
+        )
+        .enable_vsync(vsync)
+        .build()?;
+
+        Ok(Self {
+            overlay: Overlay::new(&window, &pixels),
+            pixels,
+            window,
+            event_loop,
+            size: (width, height),
+        })
+    }
+
+    // Handle pending window events, passing key presses and releases to `on_input` by physical
+    // key name ("KeyX" becomes "X", "ArrowUp" "Up"), and files dropped on the window to
+    // `on_drop`. Gamepads aren't read, and neither are keys the overlay's windows take. Returns
+    // false once the window has been closed.
+    pub fn handle_events(
+        &mut self,
+        mut on_input: impl FnMut(Input, bool),
+        mut on_drop: impl FnMut(PathBuf),
+    ) -> bool {
+        let mut closed = false;
+        let mut resized = None;
+        let (overlay, window) = (&mut self.overlay, &self.window);
+        let status = self
+            .event_loop
+            .pump_events(Some(Duration::ZERO), |event, _| {
+                if let Event::WindowEvent { event, .. } = event {
+                    if overlay.handle_event(window, &event) {
+                        return;
+                    }
+                    match event {
+                        WindowEvent::CloseRequested => closed = true,
+                        WindowEvent::Resized(size) => resized = Some(size),
+                        WindowEvent::DroppedFile(path) => on_drop(path),
+                        WindowEvent::KeyboardInput {
+                            event:
+                                KeyEvent {
+                                    physical_key: PhysicalKey::Code(code),
+                                    state,
+                                    repeat: false,
+                                    ..
+                                },
+                            ..
+                        } => on_input(Input::Key(key_name(code)), state == ElementState::Pressed),
+                        _ => {}
+                    }
+                }
+            });
+
+        // pixels scales the picture to the new surface, keeping its aspect ratio
+        if let Some(size) = resized {
+            if let Err(e) = self.pixels.resize_surface(size.width, size.height) {
+                eprintln!("Error resizing the window surface: {}", e);
+            }
+        }
+        !closed && matches!(status, PumpStatus::Continue)
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.window.set_title(title);
+    }
+
+    // Show a frame of `width` by `height` RGBA pixels
+    pub fn present(&mut self, frame: &[u8], width: u32, height: u32) -> Result<(), Box<dyn Error>> {
+        if self.size != (width, height) {
+            self.pixels.resize_buffer(width, height)?;
+            self.size = (width, height);
+        }
+        self.pixels.frame_mut().copy_from_slice(frame);
+        self.overlay.prepare(&self.window);
+        let overlay = &mut self.overlay;
+        self.pixels.render_with(|encoder, target, context| {
+            context.scaling_renderer.render(encoder, target);
+            overlay.render(encoder, target, context);
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    // Show the debug overlay's windows with what `snapshot` holds, or hide them with None
+    pub fn show_overlay(&mut self, snapshot: Option<Snapshot>) {
+        self.overlay.show(snapshot);
+    }
+
+    // The changes made in the overlay's windows since the last call
+    pub fn take_overlay_commands(&mut self) -> Vec<Command> {
+        self.overlay.take_commands()
+    }
+}
+
+// The name of a key in the config's terms, from winit's name for it
+fn key_name(code: KeyCode) -> String {
+    let name = format!("{:?}", code);
+    ["Key", "Digit", "Arrow"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(&name)
+        .to_string()
+}
@@ -0,0 +1,230 @@
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use crate::config::Config;
+#[cfg(any(feature = "video", feature = "winit"))]
+use crate::config::KeyBindings;
+#[cfg(any(feature = "video", feature = "winit"))]
+use crate::input;
+
+// Interactive first-run setup. Every prompt shows its default in brackets and an empty answer keeps it.
+// `audio_devices` are the output devices to choose from, none to leave the system default.
+pub fn run(
+    mut input: impl BufRead,
+    mut output: impl Write,
+    audio_devices: &[String],
+) -> Result<Config, Box<dyn Error>> {
+    let mut config = Config::default();
+
+    writeln!(
+        output,
+        "Welcome to rustendo! Let's set up a few things first."
+    )?;
+    writeln!(output, "Press Enter to accept the value shown in brackets.")?;
+    writeln!(output)?;
+
+    writeln!(
+        output,
+        "ROMs given by a relative path are also looked for in the ROM directory."
+    )?;
+    let rom_directory = ask(&mut input, &mut output, "ROM directory", "")?;
+    if !rom_directory.is_empty() {
+        let path = PathBuf::from(rom_directory);
+        if !path.is_dir() {
+            writeln!(output, "Note: {} does not exist yet", path.display())?;
+        }
+        config.rom_directory = Some(path);
+    }
+
+    #[cfg(any(feature = "video", feature = "winit"))]
+    {
+        controller(&mut input, &mut output, 1, &mut config.input.player1)?;
+        writeln!(output)?;
+        if ask(&mut input, &mut output, "Set up controller 2 (y/n)", "n")?.starts_with(['y', 'Y']) {
+            controller(&mut input, &mut output, 2, &mut config.input.player2)?;
+        }
+    }
+
+    writeln!(output)?;
+    config.video.scale = loop {
+        let answer = ask(
+            &mut input,
+            &mut output,
+            "Video scale (1-8)",
+            &config.video.scale.to_string(),
+        )?;
+        match answer.parse::<u32>() {
+            Ok(scale @ 1..=8) => break scale,
+            _ => writeln!(output, "Please enter a number between 1 and 8")?,
+        }
+    };
+
+    if !audio_devices.is_empty() {
+        writeln!(output)?;
+        writeln!(output, "Audio devices:")?;
+        writeln!(output, "  0. System default")?;
+        for (number, name) in audio_devices.iter().enumerate() {
+            writeln!(output, "  {}. {}", number + 1, name)?;
+        }
+        let prompt = format!("Audio device (0-{})", audio_devices.len());
+        config.audio.device = loop {
+            match ask(&mut input, &mut output, &prompt, "0")?.parse::<usize>() {
+                Ok(0) => break None,
+                Ok(number) if number <= audio_devices.len() => {
+                    break Some(audio_devices[number - 1].clone())
+                }
+                _ => writeln!(
+                    output,
+                    "Please enter a number between 0 and {}",
+                    audio_devices.len()
+                )?,
+            }
+        };
+    }
+
+    Ok(config)
+}
+
+// One player's keys and, with SDL, gamepad buttons
+#[cfg(any(feature = "video", feature = "winit"))]
+fn controller(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    player: usize,
+    bindings: &mut KeyBindings,
+) -> io::Result<()> {
+    writeln!(output)?;
+    writeln!(
+        output,
+        "Controller {}: type the key to use for each button, e.g. X, Space, Up or Right Shift.",
+        player
+    )?;
+    bind(
+        input,
+        output,
+        bindings.keys_mut(),
+        input::is_key_name,
+        "key",
+    )?;
+
+    #[cfg(feature = "video")]
+    {
+        writeln!(output)?;
+        writeln!(
+            output,
+            "Controller {} on gamepad {}: type the button to use for each, e.g. a, x, start or dpup.",
+            player, player
+        )?;
+        bind(
+            input,
+            output,
+            bindings.gamepad.buttons_mut(),
+            input::is_gamepad_button,
+            "gamepad button",
+        )?;
+    }
+    Ok(())
+}
+
+// Ask for each button's binding, again until the answer is a name `known` accepts. An empty
+// binding leaves the button unbound.
+#[cfg(any(feature = "video", feature = "winit"))]
+fn bind(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    bindings: [&mut String; 10],
+    known: fn(&str) -> bool,
+    kind: &str,
+) -> io::Result<()> {
+    for (button, binding) in KeyBindings::BUTTONS.iter().zip(bindings) {
+        *binding = loop {
+            let answer = ask(input, output, button, binding)?;
+            if answer.is_empty() || known(&answer) {
+                break answer;
+            }
+            writeln!(output, "There is no {} called \"{}\"", kind, answer)?;
+        };
+    }
+    Ok(())
+}
+
+fn ask(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    prompt: &str,
+    default: &str,
+) -> io::Result<String> {
+    write!(output, "{} [{}]: ", prompt, default)?;
+    output.flush()?;
+
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "setup was interrupted",
+        ));
+    }
+
+    let answer = line.trim();
+    Ok(if answer.is_empty() {
+        default.to_string()
+    } else {
+        answer.to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Enter pressed at each of the controller prompts this build asks
+    fn keep_controllers() -> String {
+        let prompts = if cfg!(feature = "video") {
+            21
+        } else if cfg!(feature = "winit") {
+            11
+        } else {
+            0
+        };
+        "\n".repeat(prompts)
+    }
+
+    fn setup(answers: &str, audio_devices: &[String]) -> (Config, String) {
+        let mut output = Vec::new();
+        let config = run(answers.as_bytes(), &mut output, audio_devices).unwrap();
+        (config, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn audio_devices_are_picked_by_number() {
+        let devices = ["Speakers".to_string(), "Headphones".to_string()];
+        let answers = format!("\n{}\n3\n2\n", keep_controllers());
+        let (config, output) = setup(&answers, &devices);
+        assert!(output.contains("  2. Headphones"));
+        assert!(output.contains("Please enter a number between 0 and 2"));
+        assert_eq!(config.audio.device.as_deref(), Some("Headphones"));
+
+        let answers = format!("\n{}\n\n", keep_controllers());
+        assert_eq!(setup(&answers, &devices).0.audio.device, None);
+    }
+
+    #[test]
+    fn an_empty_answer_keeps_the_default() {
+        let answers = format!("roms\n{}\n", keep_controllers());
+        let (config, _) = setup(&answers, &[]);
+        assert_eq!(config.rom_directory, Some(PathBuf::from("roms")));
+        assert_eq!(config.video.scale, Config::default().video.scale);
+        assert_eq!(config.audio.device, None);
+    }
+
+    #[cfg(any(feature = "video", feature = "winit"))]
+    #[test]
+    fn unknown_keys_are_asked_for_again() {
+        let answers = format!("\nNope\nSpace\n{}\n", keep_controllers());
+        let (config, output) = setup(&answers, &[]);
+        assert!(output.contains("There is no key called \"Nope\""));
+        assert_eq!(config.input.player1.a, "Space");
+        assert_eq!(config.input.player1.b, "Z");
+    }
+}
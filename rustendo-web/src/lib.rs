@@ -0,0 +1,93 @@
+use rustendo_core::{Nes, Rom};
+use wasm_bindgen::prelude::*;
+
+// The emulator as the page's script sees it. The page owns everything platform-specific: it
+// reads the ROM file, calls `step_frame` on its own schedule, draws `frame` on a canvas, queues
+// `audio_samples` with WebAudio and passes key presses to `set_button`.
+#[wasm_bindgen]
+pub struct Emulator {
+    nes: Nes,
+    rom_crc32: u32,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    // Load an iNES or NES 2.0 image, producing sound at `sample_rate` (the AudioContext's)
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8], sample_rate: u32) -> Result<Emulator, JsError> {
+        let rom = Rom::from_bytes(rom).map_err(|e| JsError::new(&e.to_string()))?;
+        let mut nes = Nes::new(&rom).map_err(|e| JsError::new(&e.to_string()))?;
+        nes.set_audio_sample_rate(Some(sample_rate));
+        Ok(Self {
+            nes,
+            rom_crc32: rom.crc32(),
+        })
+    }
+
+    // Identifies the game, e.g. to key its saves in local storage
+    pub fn rom_crc32(&self) -> u32 {
+        self.rom_crc32
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        self.nes.region().frame_rate()
+    }
+
+    pub fn width(&self) -> usize {
+        self.nes.overscan().width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.nes.overscan().height()
+    }
+
+    pub fn step_frame(&mut self) {
+        self.nes.step_frame();
+    }
+
+    // The last frame as RGBA, `width` by `height`, ready for an ImageData
+    pub fn frame(&mut self) -> Vec<u8> {
+        self.nes.output_frame().to_vec()
+    }
+
+    // Mono samples made since the last call
+    pub fn audio_samples(&mut self) -> Vec<f32> {
+        let mut samples = Vec::new();
+        self.nes.take_audio_samples(&mut samples);
+        samples
+    }
+
+    // `button` is 0-7: A, B, Select, Start, Up, Down, Left, Right
+    pub fn set_button(&mut self, port: usize, button: usize, pressed: bool) {
+        let Some(mut controller) = self.nes.controller_mut(port).filter(|_| button < 8) else {
+            return;
+        };
+        if pressed {
+            controller.press_button(button);
+        } else {
+            controller.release_button(button);
+        }
+    }
+
+    // Battery-backed RAM, when it changed since the last call
+    pub fn take_save_data(&mut self) -> Option<Vec<u8>> {
+        if !self.nes.take_save_changed() {
+            return None;
+        }
+        self.nes.save_data()
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        self.nes.load_save_data(data);
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        self.nes.save_state()
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), JsError> {
+        self.nes
+            .load_state(data)
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+}
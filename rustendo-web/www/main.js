@@ -0,0 +1,119 @@
+import init, { Emulator } from "./pkg/rustendo_web.js";
+
+// KeyboardEvent.code for each button, in the order Emulator.set_button numbers them: the same
+// keys as the desktop front end's defaults
+const KEYS = ["KeyX", "KeyZ", "ShiftRight", "Enter", "ArrowUp", "ArrowDown", "ArrowLeft", "ArrowRight"];
+
+// Seconds of sound queued ahead of the playback position, enough to ride out a late frame
+const AUDIO_LEAD = 0.05;
+
+const canvas = document.getElementById("screen");
+const context = canvas.getContext("2d");
+const status = document.getElementById("status");
+
+let emulator = null;
+let audio = null;
+let audioTime = 0;
+let state = null;
+
+await init();
+
+document.getElementById("rom").addEventListener("change", async (event) => {
+  const file = event.target.files[0];
+  if (!file) {
+    return;
+  }
+  // Browsers only allow sound to start from a user action like this one
+  audio ??= new AudioContext();
+  await audio.resume();
+  try {
+    emulator?.free();
+    emulator = new Emulator(new Uint8Array(await file.arrayBuffer()), audio.sampleRate);
+  } catch (e) {
+    emulator = null;
+    status.textContent = `Error loading ROM: ${e.message}`;
+    return;
+  }
+  const save = localStorage.getItem(saveKey());
+  if (save) {
+    emulator.load_save_data(Uint8Array.from(atob(save), (c) => c.charCodeAt(0)));
+  }
+  state = null;
+  canvas.width = emulator.width();
+  canvas.height = emulator.height();
+  canvas.style.width = `${emulator.width() * 3}px`;
+  status.textContent = file.name;
+  event.target.blur();
+});
+
+function saveKey() {
+  return `rustendo-save-${emulator.rom_crc32().toString(16).padStart(8, "0")}`;
+}
+
+function handleKey(event, pressed) {
+  if (!emulator) {
+    return;
+  }
+  const button = KEYS.indexOf(event.code);
+  if (button >= 0) {
+    emulator.set_button(0, button, pressed);
+  } else if (pressed && event.code === "F5") {
+    state = emulator.save_state();
+    status.textContent = "State saved";
+  } else if (pressed && event.code === "F8" && state) {
+    emulator.load_state(state);
+    status.textContent = "State loaded";
+  } else {
+    return;
+  }
+  event.preventDefault();
+}
+
+document.addEventListener("keydown", (event) => handleKey(event, true));
+document.addEventListener("keyup", (event) => handleKey(event, false));
+
+function queueAudio(samples) {
+  if (samples.length === 0) {
+    return;
+  }
+  const buffer = audio.createBuffer(1, samples.length, audio.sampleRate);
+  buffer.copyToChannel(samples, 0);
+  const source = audio.createBufferSource();
+  source.buffer = buffer;
+  source.connect(audio.destination);
+  // Start over from just ahead of now after falling behind, rather than playing catch-up
+  audioTime = Math.max(audioTime, audio.currentTime + AUDIO_LEAD);
+  source.start(audioTime);
+  audioTime += buffer.duration;
+}
+
+// Display refresh rates vary, so frames are emulated as the console's frame rate comes due
+// rather than one per animation frame
+let last = null;
+let owed = 0;
+function tick(now) {
+  if (emulator) {
+    if (last !== null) {
+      // Don't try to make up for time spent in a background tab
+      owed = Math.min(owed + ((now - last) / 1000) * emulator.frame_rate(), 4);
+    }
+    let stepped = false;
+    while (owed >= 1) {
+      emulator.step_frame();
+      queueAudio(emulator.audio_samples());
+      owed -= 1;
+      stepped = true;
+    }
+    if (stepped) {
+      const frame = new Uint8ClampedArray(emulator.frame());
+      context.putImageData(new ImageData(frame, emulator.width(), emulator.height()), 0, 0);
+      const save = emulator.take_save_data();
+      if (save) {
+        localStorage.setItem(saveKey(), btoa(String.fromCharCode(...save)));
+      }
+    }
+  }
+  last = now;
+  requestAnimationFrame(tick);
+}
+requestAnimationFrame(tick);
@@ -1,44 +0,0 @@
-use crate::memory::Memory;
-use std::cell::RefCell;
-
-pub struct APU<'a> {
-    pulse_1: u8,                 // Pulse 1 register
-    pulse_2: u8,                 // Pulse 2 register
-    triangle: u8,                // Triangle register
-    noise: u8,                   // Noise register
-    dmc: u8,                     // DMC register
-    status: u8,                  // APU status register
-    frame_counter: u8,           // Frame counter register
-    memory: &'a RefCell<Memory>, // Reference to the shared Memory struct
-    audio_buffer: Vec<f32>,      // Audio buffer to store generated audio samples
-}
-
-impl<'a> APU<'a> {
-    pub fn new(memory: &'a RefCell<Memory>) -> Self {
-        Self {
-            pulse_1: 0,
-            pulse_2: 0,
-            triangle: 0,
-            noise: 0,
-            dmc: 0,
-            status: 0,
-            frame_counter: 0,
-            memory,
-            audio_buffer: Vec::new(),
-        }
-    }
-
-    pub fn reset(&mut self) {
-        self.pulse_1 = 0;
-        self.pulse_2 = 0;
-        self.triangle = 0;
-        self.noise = 0;
-        self.dmc = 0;
-        self.status = 0;
-        self.frame_counter = 0;
-    }
-
-    pub fn tick(&mut self) {
-        // Update the state of the APU (e.g., update oscillators, mix channels, handle timing, etc.)
-    }
-}